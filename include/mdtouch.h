@@ -0,0 +1,103 @@
+#ifndef MDTOUCH_H
+#define MDTOUCH_H
+
+/* Generated with cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result codes returned by the FFI functions.
+ */
+typedef enum {
+  /**
+   * The operation succeeded.
+   */
+  MDTOUCH_STATUS_OK = 0,
+  /**
+   * A pointer was null or a path was not valid UTF-16.
+   */
+  MDTOUCH_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * The path, or one of its parent directories, does not exist.
+   */
+  MDTOUCH_STATUS_NOT_FOUND = 2,
+  /**
+   * Access to the path was denied.
+   */
+  MDTOUCH_STATUS_PERMISSION_DENIED = 3,
+  /**
+   * Any other I/O failure.
+   */
+  MDTOUCH_STATUS_IO_ERROR = 4,
+} MdtouchStatus;
+
+/**
+ * Timestamps to apply, mirroring [`TouchOptions`]. Times are seconds and nanoseconds
+ * since the Unix epoch.
+ */
+typedef struct {
+  /**
+   * Non-zero to change the access time.
+   */
+  uint8_t set_atime;
+  /**
+   * Non-zero to change the modification time.
+   */
+  uint8_t set_mtime;
+  /**
+   * Access time, whole seconds since the Unix epoch.
+   */
+  int64_t atime_seconds;
+  /**
+   * Access time, nanoseconds past `atime_seconds`.
+   */
+  uint32_t atime_nanoseconds;
+  /**
+   * Modification time, whole seconds since the Unix epoch.
+   */
+  int64_t mtime_seconds;
+  /**
+   * Modification time, nanoseconds past `mtime_seconds`.
+   */
+  uint32_t mtime_nanoseconds;
+} MdtouchOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Touches the file at `path`, creating it if it does not exist.
+ *
+ * `path` is a NUL-terminated UTF-16 string. When `opts` is null both timestamps are
+ * set to the current time.
+ *
+ * # Safety
+ *
+ * `path` must point to a valid NUL-terminated UTF-16 string, and `opts` must be null
+ * or point to a valid `MdtouchOptions`.
+ */
+MdtouchStatus mdtouch_touch_utf16(const uint16_t *path, const MdtouchOptions *opts);
+
+/**
+ * Copies the calling thread's last error message into `buffer` as NUL-terminated
+ * UTF-16, truncating it to fit `capacity` units.
+ *
+ * Returns the number of units needed for the whole message including the terminator,
+ * so a caller can pass a null `buffer` first to size its allocation.
+ *
+ * # Safety
+ *
+ * `buffer` must be null or valid for writes of `capacity` UTF-16 units.
+ */
+size_t mdtouch_last_error_utf16(uint16_t *buffer, size_t capacity);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MDTOUCH_H */
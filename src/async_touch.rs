@@ -0,0 +1,123 @@
+//! Async wrappers around the blocking touch API, enabled by the `async` feature.
+//!
+//! Filesystem metadata calls block, so each touch runs on tokio's blocking thread pool
+//! rather than on the async runtime's worker threads.
+
+use std::io;
+use std::path::PathBuf;
+
+use tokio::task;
+
+use crate::{touch_file, TouchOptions, TouchOutcome};
+
+/// Converts a failed blocking task into the I/O error type the sync API returns.
+fn join_error(e: task::JoinError) -> io::Error {
+    io::Error::other(format!("touch task failed: {}", e))
+}
+
+/// Touches a file like [`touch_file`], without blocking the async runtime.
+///
+/// Must be called from within a tokio runtime.
+pub async fn touch_file_async<P: Into<PathBuf>>(
+    path: P,
+    options: &TouchOptions,
+) -> io::Result<TouchOutcome> {
+    let path = path.into();
+    let options = *options;
+    task::spawn_blocking(move || touch_file(path, &options))
+        .await
+        .map_err(join_error)?
+}
+
+/// Touches every path concurrently and returns each path with its result, in the order
+/// the paths were given. A failure for one path does not stop the others.
+///
+/// Must be called from within a tokio runtime.
+pub async fn touch_many_async<I, P>(
+    paths: I,
+    options: &TouchOptions,
+) -> Vec<(PathBuf, io::Result<TouchOutcome>)>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    let options = *options;
+    let tasks: Vec<_> = paths
+        .into_iter()
+        .map(|path| {
+            let path = path.into();
+            let task_path = path.clone();
+            (
+                path,
+                task::spawn_blocking(move || touch_file(task_path, &options)),
+            )
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for (path, handle) in tasks {
+        let result = handle.await.map_err(join_error).and_then(|result| result);
+        results.push((path, result));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    /// Builds a runtime with a blocking pool so the tests don't need tokio's macros.
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("Failed to build runtime")
+    }
+
+    /// Creates an empty scratch directory for a single test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mdtouch_async_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_touch_file_async_creates_file() {
+        let dir = scratch_dir("single");
+        let path = dir.join("file.txt");
+
+        runtime()
+            .block_on(touch_file_async(&path, &TouchOptions::now()))
+            .unwrap();
+        assert!(path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_touch_many_async_concurrent_with_errors() {
+        let dir = scratch_dir("many");
+        let mut paths: Vec<PathBuf> = (0..50)
+            .map(|i| dir.join(format!("file{}.txt", i)))
+            .collect();
+        let bad = dir.join("missing").join("file.txt");
+        paths.insert(10, bad.clone());
+
+        let results = runtime().block_on(touch_many_async(paths.clone(), &TouchOptions::now()));
+
+        assert_eq!(results.len(), paths.len());
+        for ((path, result), expected) in results.iter().zip(&paths) {
+            assert_eq!(path, expected, "Results should keep the input order.");
+            if *path == bad {
+                assert!(result.is_err());
+            } else {
+                assert!(result.is_ok());
+                assert!(path.exists());
+            }
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! File attribute filters (`--attr-filter`).
+//!
+//! A filter is a list of terms in the style of `attrib`: `+X` requires attribute `X` and
+//! `-X` excludes files that have it, as in `+A -R`. The attributes are `R` (read-only),
+//! `H` (hidden), `S` (system), `A` (archive), `T` (temporary) and `I` (not content
+//! indexed), using the Windows attribute bits. Elsewhere only `R` (no write permission)
+//! and `H` (a name starting with `.`) can be set.
+
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+
+/// `FILE_ATTRIBUTE_READONLY`.
+pub const READONLY: u32 = 0x1;
+/// `FILE_ATTRIBUTE_HIDDEN`.
+pub const HIDDEN: u32 = 0x2;
+/// `FILE_ATTRIBUTE_SYSTEM`.
+pub const SYSTEM: u32 = 0x4;
+/// `FILE_ATTRIBUTE_ARCHIVE`.
+pub const ARCHIVE: u32 = 0x20;
+/// `FILE_ATTRIBUTE_TEMPORARY`.
+pub const TEMPORARY: u32 = 0x100;
+/// `FILE_ATTRIBUTE_NOT_CONTENT_INDEXED`.
+pub const NOT_INDEXED: u32 = 0x2000;
+
+/// Attributes a file must have and must not have.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AttrFilter {
+    required: u32,
+    forbidden: u32,
+}
+
+impl AttrFilter {
+    /// Parses a filter expression such as `+A`, `-R` or `+A -H -S`. Letters are
+    /// case-insensitive and spaces between terms are optional.
+    pub fn parse(expression: &str) -> io::Result<Self> {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid --attr-filter '{}': {}", expression, reason),
+            )
+        };
+        let mut filter = AttrFilter::default();
+        let mut chars = expression.chars().filter(|c| !c.is_whitespace());
+        while let Some(sign) = chars.next() {
+            if sign != '+' && sign != '-' {
+                return Err(invalid(&format!("expected + or - before '{}'", sign)));
+            }
+            let letter = chars
+                .next()
+                .ok_or_else(|| invalid(&format!("missing attribute after '{}'", sign)))?;
+            let bit = attribute_bit(letter).ok_or_else(|| {
+                invalid(&format!(
+                    "unknown attribute '{}' (expected R, H, S, A, T or I)",
+                    letter
+                ))
+            })?;
+            if sign == '+' {
+                filter.required |= bit;
+            } else {
+                filter.forbidden |= bit;
+            }
+        }
+        if filter == AttrFilter::default() {
+            return Err(invalid("no attributes given"));
+        }
+        // Combining with the empty filter rejects terms like `+A -A`.
+        AttrFilter::default().combine(filter)
+    }
+
+    /// A filter that excludes files with any of the attribute bits in `forbidden`.
+    pub fn excluding(forbidden: u32) -> Self {
+        AttrFilter {
+            required: 0,
+            forbidden,
+        }
+    }
+
+    /// Combines two filters, so that a file must pass both. Fails if one requires an
+    /// attribute the other excludes.
+    pub fn combine(self, other: AttrFilter) -> io::Result<Self> {
+        let combined = AttrFilter {
+            required: self.required | other.required,
+            forbidden: self.forbidden | other.forbidden,
+        };
+        if combined.required & combined.forbidden != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid --attr-filter: an attribute is both required and excluded",
+            ));
+        }
+        Ok(combined)
+    }
+
+    /// Whether a file with the given attribute bits passes the filter.
+    pub fn matches(&self, attributes: u32) -> bool {
+        attributes & self.required == self.required && attributes & self.forbidden == 0
+    }
+}
+
+/// Maps an attribute letter to its bit.
+fn attribute_bit(letter: char) -> Option<u32> {
+    match letter.to_ascii_uppercase() {
+        'R' => Some(READONLY),
+        'H' => Some(HIDDEN),
+        'S' => Some(SYSTEM),
+        'A' => Some(ARCHIVE),
+        'T' => Some(TEMPORARY),
+        'I' => Some(NOT_INDEXED),
+        _ => None,
+    }
+}
+
+/// Returns the attribute bits of a file.
+#[cfg(windows)]
+pub fn attributes(_path: &Path, metadata: &Metadata) -> u32 {
+    use std::os::windows::fs::MetadataExt;
+    metadata.file_attributes()
+}
+
+/// Returns the attribute bits of a file: read-only without write permission, and hidden
+/// for a name starting with `.`.
+#[cfg(not(windows))]
+pub fn attributes(path: &Path, metadata: &Metadata) -> u32 {
+    let mut attributes = 0;
+    if metadata.permissions().readonly() {
+        attributes |= READONLY;
+    }
+    if path
+        .file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+    {
+        attributes |= HIDDEN;
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_terms() {
+        let filter = AttrFilter::parse("+A").unwrap();
+        assert_eq!(
+            filter,
+            AttrFilter {
+                required: ARCHIVE,
+                forbidden: 0
+            }
+        );
+        let filter = AttrFilter::parse("+a -R-h").unwrap();
+        assert_eq!(
+            filter,
+            AttrFilter {
+                required: ARCHIVE,
+                forbidden: READONLY | HIDDEN
+            }
+        );
+        for bad in ["", "A", "+", "+X", "+A-A", "*R"] {
+            assert!(AttrFilter::parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let filter = AttrFilter::parse("+A -R").unwrap();
+        assert!(filter.matches(ARCHIVE));
+        assert!(filter.matches(ARCHIVE | HIDDEN));
+        assert!(!filter.matches(ARCHIVE | READONLY));
+        assert!(!filter.matches(0));
+        assert!(AttrFilter::parse("-S").unwrap().matches(0));
+        let skipping = AttrFilter::excluding(HIDDEN | SYSTEM);
+        assert_eq!(skipping, AttrFilter::parse("-H -S").unwrap());
+        assert!(!skipping.matches(SYSTEM | ARCHIVE));
+    }
+
+    #[test]
+    fn test_combine() {
+        let archived = AttrFilter::parse("+A").unwrap();
+        let writable = AttrFilter::parse("-R").unwrap();
+        let both = archived.combine(writable).unwrap();
+        assert_eq!(both, AttrFilter::parse("+A-R").unwrap());
+        assert!(archived.combine(AttrFilter::parse("-A").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! The append-only record of modified files (`--audit-log`).
+//!
+//! Each modified file gets one line of JSON, written after the change succeeded:
+//!
+//! ```text
+//! {"time":"2025-02-03T10:00:00.000000000Z","path":"C:\\logs\\app.log","outcome":"updated","old_atime":"...","old_mtime":"...","new_atime":"...","new_mtime":"..."}
+//! ```
+//!
+//! `outcome` is `created` or `updated`; a created file has `null` old times. All times are
+//! UTC. The log is opened for appending only and shared for reading and writing, so
+//! several mdtouch processes can log to the same file: every line is a single write to the
+//! end of the file, which the OS never interleaves with another appender's.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use mdtouch::date::format_iso8601;
+use mdtouch::{normalize_path, FileTime};
+
+/// An access and modification time pair.
+pub type Times = (FileTime, FileTime);
+
+/// The access and modification times a file had before it was touched, each if known.
+pub type OldTimes = (Option<FileTime>, Option<FileTime>);
+
+/// An audit log open for appending.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(AuditLog {
+            file: open_for_appending(path)?,
+        })
+    }
+
+    /// Appends the line for one modified file. `before` is `None` for a file the touch
+    /// created, as the touch itself reported.
+    pub fn record(&self, path: &Path, before: Option<OldTimes>, after: Times) -> io::Result<()> {
+        let line = format_entry(FileTime::now(), path, before, after);
+        // One write per line, so that lines from concurrent writers stay whole.
+        (&self.file).write_all(line.as_bytes())
+    }
+}
+
+/// Opens the log file at `path` for appending, creating it if needed, in a way that lets
+/// other processes append to it too.
+pub fn open_for_appending(path: &Path) -> io::Result<File> {
+    // std already opens with FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE on
+    // Windows, and an append-only handle (FILE_APPEND_DATA without FILE_WRITE_DATA)
+    // makes every write land at the current end of the file.
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+/// Formats the line for one modified file, including the trailing newline.
+fn format_entry(now: FileTime, path: &Path, before: Option<OldTimes>, after: Times) -> String {
+    let time = |time: Option<FileTime>| match time {
+        Some(time) => format!("\"{}\"", format_iso8601(time)),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"time\":\"{}\",\"path\":{},\"outcome\":\"{}\",\"old_atime\":{},\"old_mtime\":{},\"new_atime\":{},\"new_mtime\":{}}}\n",
+        format_iso8601(now),
+        json_string(&normalize_path(path).to_string_lossy()),
+        if before.is_some() { "updated" } else { "created" },
+        time(before.and_then(|times| times.0)),
+        time(before.and_then(|times| times.1)),
+        time(Some(after.0)),
+        time(Some(after.1)),
+    )
+}
+
+/// Quotes a string for JSON.
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_format_entry() {
+        let at = |seconds| FileTime::from_unix_time(seconds, 0);
+        let path = normalize_path(Path::new("a \"quoted\"\tname"));
+        let line = format_entry(
+            at(60),
+            &path,
+            Some((Some(at(0)), Some(at(1)))),
+            (at(2), at(3)),
+        );
+        assert_eq!(
+            line,
+            format!(
+                "{{\"time\":\"1970-01-01T00:01:00.000000000Z\",\"path\":{},\"outcome\":\"updated\",\
+                 \"old_atime\":\"1970-01-01T00:00:00.000000000Z\",\"old_mtime\":\"1970-01-01T00:00:01.000000000Z\",\
+                 \"new_atime\":\"1970-01-01T00:00:02.000000000Z\",\"new_mtime\":\"1970-01-01T00:00:03.000000000Z\"}}\n",
+                json_string(&path.to_string_lossy())
+            )
+        );
+        assert!(json_string(&path.to_string_lossy()).ends_with("a \\\"quoted\\\"\\tname\""));
+
+        let created = format_entry(at(0), &path, None, (at(2), at(3)));
+        assert!(created.contains("\"outcome\":\"created\",\"old_atime\":null,\"old_mtime\":null"));
+
+        // An updated file whose times could not be read beforehand is still updated.
+        let unknown = format_entry(at(0), &path, Some((None, None)), (at(2), at(3)));
+        assert!(unknown.contains("\"outcome\":\"updated\",\"old_atime\":null,\"old_mtime\":null"));
+    }
+
+    #[test]
+    fn test_record_appends_one_line_per_entry() {
+        let path = std::env::temp_dir().join(format!("mdtouch_audit_{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let times = (FileTime::zero(), FileTime::zero());
+        AuditLog::open(&path)
+            .unwrap()
+            .record(Path::new("first"), None, times)
+            .unwrap();
+        // A second, independent handle appends rather than overwriting.
+        AuditLog::open(&path)
+            .unwrap()
+            .record(Path::new("second"), Some((None, Some(times.1))), times)
+            .unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("first\",\"outcome\":\"created\""));
+        assert!(lines[1].contains("second\",\"outcome\":\"updated\""));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! Copying a file aside before it is touched (`--backup`).
+//!
+//! The copy sits next to the file, named after it with a timestamp and a suffix added:
+//! `notes.txt` is copied to `notes.txt.20250203T100000Z.bak`. The timestamp is when the
+//! copy was made, in UTC to the second, so backups sort in the order they were made and
+//! the name has nothing Windows refuses. Should that name be taken, as when a file is
+//! backed up twice in a second, `-2`, `-3` and so on are added before the suffix; an
+//! existing file is never written over. The copy keeps the file's permissions and times,
+//! so it still shows when the original was last changed.
+//!
+//! Only files that exist and have something in them are copied. A file about to be
+//! created, an empty one or a directory has nothing that touching it could lose.
+
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{is_separator, Path, PathBuf};
+
+use mdtouch::date::format_iso8601;
+use mdtouch::FileTime;
+
+/// What a backup's name ends with, unless `--backup=SUFFIX` says otherwise.
+pub const DEFAULT_SUFFIX: &str = ".bak";
+
+/// The highest number added to a backup's name before giving up.
+const NUMBER_LIMIT: u32 = 99;
+
+/// Checks a `--backup` suffix: it must keep the backup in the file's directory.
+pub fn check_suffix(suffix: &str) -> io::Result<()> {
+    if suffix.chars().any(is_separator) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid --backup suffix '{}': it cannot contain a path separator",
+                suffix
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Copies the file at `path` to a backup named for `now` with `suffix`, and returns the
+/// backup's path, or `None` if there was nothing to back up.
+pub fn backup(path: &Path, suffix: &str, now: FileTime) -> io::Result<Option<PathBuf>> {
+    let metadata = match fs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+        Ok(metadata) if !metadata.is_file() || metadata.len() == 0 => return Ok(None),
+        Ok(metadata) => metadata,
+    };
+    for backup in names(path, suffix, now) {
+        let mut copy = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(copy) => copy,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let copied = copy_into(path, &metadata, &mut copy);
+        if copied.is_err() {
+            // Half a copy would pass for a backup.
+            drop(copy);
+            let _ = fs::remove_file(&backup);
+        }
+        return copied.map(|()| Some(backup));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("every backup name numbered up to {} is taken", NUMBER_LIMIT),
+    ))
+}
+
+/// The names a backup of `path` made at `now` may take, in the order they are tried.
+fn names(path: &Path, suffix: &str, now: FileTime) -> impl Iterator<Item = PathBuf> {
+    // 2025-02-03T10:00:00 becomes 20250203T100000.
+    let stamp: String = format_iso8601(now)[..19]
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    let mut stamped = OsString::from(path.as_os_str());
+    stamped.push(format!(".{}Z", stamp));
+    let suffix = suffix.to_string();
+    (1..=NUMBER_LIMIT).map(move |n| {
+        let mut name = stamped.clone();
+        if n > 1 {
+            name.push(format!("-{}", n));
+        }
+        name.push(&suffix);
+        PathBuf::from(name)
+    })
+}
+
+/// Copies the contents of the file at `path`, whose metadata is given, into `copy`, then
+/// gives the copy its times and permissions.
+fn copy_into(path: &Path, metadata: &Metadata, copy: &mut File) -> io::Result<()> {
+    io::copy(&mut File::open(path)?, copy)?;
+    filetime::set_file_handle_times(
+        copy,
+        Some(FileTime::from_last_access_time(metadata)),
+        Some(FileTime::from_last_modification_time(metadata)),
+    )?;
+    copy.set_permissions(metadata.permissions())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_backup_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        let now = FileTime::from_unix_time(1_738_576_800, 500);
+        assert_eq!(backup(&path, DEFAULT_SUFFIX, now).unwrap(), None);
+        fs::write(&path, b"").unwrap();
+        assert_eq!(backup(&path, DEFAULT_SUFFIX, now).unwrap(), None);
+
+        fs::write(&path, b"draft").unwrap();
+        let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        let first = backup(&path, DEFAULT_SUFFIX, now).unwrap().unwrap();
+        assert_eq!(first, dir.join("notes.txt.20250203T100000Z.bak"));
+        assert_eq!(fs::read(&first).unwrap(), b"draft");
+        assert_eq!(
+            FileTime::from_last_modification_time(&fs::metadata(&first).unwrap()),
+            mtime
+        );
+        // The same second again is numbered, never written over.
+        let second = backup(&path, "~", now).unwrap().unwrap();
+        assert_eq!(second, dir.join("notes.txt.20250203T100000Z~"));
+        let third = backup(&path, "~", now).unwrap().unwrap();
+        assert_eq!(third, dir.join("notes.txt.20250203T100000Z-2~"));
+        assert!(check_suffix("/x").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
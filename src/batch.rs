@@ -0,0 +1,258 @@
+//! Touching many files in one call, with a result for each and counts for the lot.
+//!
+//! The files are touched up to [`TouchOptions::jobs`] at once and the results still come
+//! back in the order the paths were given. [`touch_each`] does the same with a touch of
+//! the caller's own and hands each result over as it comes; the command line runs every
+//! operand through it.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{jobs, touch_file, FileTime, TouchOptions, TouchOutcome};
+
+/// The results of [`touch_many`].
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Every path with what became of it, in the order the paths were given.
+    pub outcomes: Vec<(PathBuf, io::Result<TouchOutcome>)>,
+    /// The access time applied to every file, if it was changed.
+    pub atime: Option<FileTime>,
+    /// The modification time applied to every file, if it was changed.
+    pub mtime: Option<FileTime>,
+    /// How many files were created.
+    pub created: usize,
+    /// How many existing files were updated.
+    pub updated: usize,
+    /// How many paths were skipped.
+    pub skipped: usize,
+    /// How many paths could not be touched.
+    pub failed: usize,
+}
+
+impl BatchResult {
+    /// Whether every path was touched or skipped without an error.
+    pub fn all_ok(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+/// Touches every path as [`touch_file`](crate::touch_file) would, with the same times
+/// for all of them, and reports what became of each. A failure for one path does not
+/// stop the others.
+///
+/// Up to `options.jobs` paths are touched at once, except on WASI, which has no
+/// threads. The paths are pulled from the iterator as they are needed.
+pub fn touch_many<I>(paths: I, options: &TouchOptions) -> BatchResult
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    let mut result = BatchResult {
+        outcomes: Vec::new(),
+        atime: options.atime,
+        mtime: options.mtime,
+        created: 0,
+        updated: 0,
+        skipped: 0,
+        failed: 0,
+    };
+    let outcome = touch_each(
+        paths,
+        options,
+        |path| touch_file(path, options),
+        |path, outcome| {
+            *match &outcome {
+                Ok(TouchOutcome::Created) => &mut result.created,
+                Ok(TouchOutcome::Updated { .. }) => &mut result.updated,
+                Ok(TouchOutcome::SkippedMissing | TouchOutcome::SkippedFilter) => {
+                    &mut result.skipped
+                }
+                Err(_) => &mut result.failed,
+            } += 1;
+            result.outcomes.push((path, outcome));
+            Ok(true)
+        },
+    );
+    // Reporting never fails, so neither does the run.
+    debug_assert!(outcome.is_ok());
+    result
+}
+
+/// Runs `touch` on every path as [`touch_many`] touches them, up to `options.jobs` at
+/// once, and hands each result to `report` in the order the paths were given rather than
+/// keeping it. Once `report` returns `false` or fails, no more paths are started; its
+/// error is returned.
+pub fn touch_each<I, O, T, R>(
+    paths: I,
+    options: &TouchOptions,
+    touch: T,
+    report: R,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = PathBuf>,
+    O: Send,
+    T: Fn(&Path) -> io::Result<O> + Sync,
+    R: FnMut(PathBuf, io::Result<O>) -> io::Result<bool>,
+{
+    let jobs = if cfg!(target_os = "wasi") {
+        1
+    } else {
+        options.jobs
+    };
+    jobs::for_each_in_order(paths, jobs, touch, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    /// Creates an empty scratch directory for a single test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mdtouch_batch_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_outcomes_are_counted() {
+        let dir = scratch_dir("counts");
+        fs::write(dir.join("existing"), "").unwrap();
+        let time = FileTime::from_unix_time(1_700_000_000, 0);
+        let options = TouchOptions {
+            atime: Some(time),
+            mtime: Some(time),
+            ..TouchOptions::default()
+        };
+        let paths = vec![
+            dir.join("new"),
+            dir.join("existing"),
+            dir.join("no-such-dir").join("file"),
+            dir.join("new"),
+        ];
+        let written = fs::metadata(dir.join("existing")).unwrap();
+        let written = FileTime::from_last_modification_time(&written);
+        let result = touch_many(paths.clone(), &options);
+        let kinds: Vec<_> = result
+            .outcomes
+            .iter()
+            .map(|(_, outcome)| outcome.as_ref().map_err(|e| e.kind()).copied())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Ok(TouchOutcome::Created),
+                Ok(TouchOutcome::Updated {
+                    previous_mtime: Some(written),
+                    previous_len: Some(0),
+                }),
+                Err(io::ErrorKind::NotFound),
+                Ok(TouchOutcome::Updated {
+                    previous_mtime: Some(time),
+                    previous_len: Some(0),
+                }),
+            ]
+        );
+        assert_eq!(
+            (
+                result.created,
+                result.updated,
+                result.skipped,
+                result.failed
+            ),
+            (1, 2, 0, 1)
+        );
+        assert!(!result.all_ok());
+        assert_eq!((result.atime, result.mtime), (Some(time), Some(time)));
+        let metadata = fs::metadata(dir.join("existing")).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+
+        // Under no_create the missing file is skipped rather than created.
+        let no_create = TouchOptions {
+            no_create: true,
+            ..options
+        };
+        let result = touch_many(vec![dir.join("absent"), dir.join("new")], &no_create);
+        assert_eq!(
+            (
+                result.created,
+                result.updated,
+                result.skipped,
+                result.failed
+            ),
+            (0, 1, 1, 0)
+        );
+        assert!(result.all_ok());
+        assert!(!dir.join("absent").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_outcomes_follow_the_order_of_the_paths() {
+        let dir = scratch_dir("order");
+        // Every third path is in a missing directory, so failures are spread throughout.
+        let paths: Vec<_> = (0..60)
+            .map(|i| match i % 3 {
+                0 => dir.join("missing").join(i.to_string()),
+                _ => dir.join(i.to_string()),
+            })
+            .collect();
+        for jobs in [0, 1, 8] {
+            let options = TouchOptions {
+                jobs,
+                ..TouchOptions::now()
+            };
+            let result = touch_many(paths.clone(), &options);
+            let touched: Vec<_> = result.outcomes.iter().map(|(path, _)| path).collect();
+            assert_eq!(touched, paths.iter().collect::<Vec<_>>(), "jobs = {}", jobs);
+            for (i, (_, outcome)) in result.outcomes.iter().enumerate() {
+                assert_eq!(
+                    outcome.is_err(),
+                    i.is_multiple_of(3),
+                    "jobs = {}, path {}",
+                    jobs,
+                    i
+                );
+            }
+            assert_eq!(result.failed, 20, "jobs = {}", jobs);
+            assert_eq!(result.created + result.updated, 40, "jobs = {}", jobs);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_touch_each_stops_when_asked() {
+        let dir = scratch_dir("each");
+        let paths: Vec<_> = (0..40).map(|i| dir.join(i.to_string())).collect();
+        let options = TouchOptions {
+            jobs: 4,
+            ..TouchOptions::now()
+        };
+        let mut reported = Vec::new();
+        touch_each(
+            paths.clone(),
+            &options,
+            |path| touch_file(path, &options).map(|_| path.to_path_buf()),
+            |path, touched| {
+                assert_eq!(touched.unwrap(), path);
+                reported.push(path);
+                Ok(reported.len() < 10)
+            },
+        )
+        .unwrap();
+        assert_eq!(reported, paths[..10]);
+        // Those in flight may still be touched, but no more are started.
+        assert!(!paths[30].exists());
+
+        let error = touch_each(
+            paths.clone(),
+            &options,
+            |path| touch_file(path, &options),
+            |_, _| Err::<bool, _>(io::Error::other("stop")),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "stop");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Resuming interrupted batches (`--checkpoint`).
+//!
+//! The checkpoint file lists the operands already touched successfully, as absolute paths
+//! in the `--files-from` format: one per line, or NUL-separated with `-0`. Each path is
+//! appended as soon as its file has been touched, so the list stays current however the
+//! process ends. A run given an existing checkpoint skips the operands listed in it, and a
+//! run that touches every remaining operand successfully deletes it. Should the process be
+//! killed in the middle of appending, the last entry may be cut short; that operand is then
+//! simply touched again.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use mdtouch::normalize_path;
+
+use crate::{list_separator, path_to_bytes, read_operand_list};
+
+/// An open checkpoint file and the operands it already lists.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    /// The file opened for appending, unless it was only read.
+    file: Option<File>,
+    done: HashSet<PathBuf>,
+    separator: u8,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, reading the operands an earlier run finished. The
+    /// file is created if it does not exist yet.
+    pub fn open(path: &Path, null_separated: bool) -> io::Result<Self> {
+        let mut checkpoint = Checkpoint::read(path, null_separated)?;
+        checkpoint.file = Some(OpenOptions::new().append(true).create(true).open(path)?);
+        Ok(checkpoint)
+    }
+
+    /// Reads the operands the checkpoint at `path` lists, if it exists, without opening it
+    /// for recording more (`--preflight`).
+    pub fn read(path: &Path, null_separated: bool) -> io::Result<Self> {
+        let done = match read_operand_list(path, null_separated) {
+            Ok(listed) => listed.into_iter().collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            file: None,
+            done,
+            separator: list_separator(null_separated),
+        })
+    }
+
+    /// Whether an earlier run already touched `operand`.
+    pub fn is_done(&self, operand: &Path) -> bool {
+        self.done.contains(&normalize_path(operand))
+    }
+
+    /// Appends `operand` to the checkpoint as finished. One that was only read records
+    /// nothing.
+    pub fn record(&self, operand: &Path) -> io::Result<()> {
+        let Some(mut file) = self.file.as_ref() else {
+            return Ok(());
+        };
+        let mut entry = path_to_bytes(&normalize_path(operand));
+        entry.push(self.separator);
+        file.write_all(&entry)
+    }
+
+    /// Deletes the checkpoint once the whole batch is done.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.file);
+        std::fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_recorded_operands_are_done_on_reopening() {
+        let path = std::env::temp_dir().join(format!("mdtouch_checkpoint_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let checkpoint = Checkpoint::open(&path, false).unwrap();
+        assert!(!checkpoint.is_done(Path::new("a.txt")));
+        checkpoint.record(Path::new("a.txt")).unwrap();
+        checkpoint.record(Path::new("dir/../b.txt")).unwrap();
+        drop(checkpoint);
+
+        // Entries are absolute, so they match however the operand is spelled.
+        let checkpoint = Checkpoint::open(&path, false).unwrap();
+        assert!(checkpoint.is_done(&normalize_path(Path::new("a.txt"))));
+        assert!(checkpoint.is_done(Path::new("./b.txt")));
+        assert!(!checkpoint.is_done(Path::new("c.txt")));
+        checkpoint.finish().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,35 @@
+//! Where "now" comes from.
+//!
+//! Anything that stamps files with the current time can take a [`TimeSource`] instead of
+//! calling [`FileTime::now`] itself, so that a test can pin the clock to a known time and
+//! a caller can supply a clock of its own.
+
+use std::fmt::Debug;
+
+use filetime::FileTime;
+
+/// A clock that tells the current time.
+pub trait TimeSource: Debug + Send + Sync {
+    /// The current time by this clock.
+    fn now(&self) -> FileTime;
+}
+
+/// The system's real-time clock.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> FileTime {
+        FileTime::now()
+    }
+}
+
+/// A clock stopped at one time, for tests and reproducible runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedClock(pub FileTime);
+
+impl TimeSource for FixedClock {
+    fn now(&self) -> FileTime {
+        self.0
+    }
+}
@@ -0,0 +1,267 @@
+//! Touching a file only when its content changed since the last run
+//! (`--if-content-changed`).
+//!
+//! Each file's content is hashed with SHA-256 and compared with the hash stored for it
+//! the last time it was touched this way. When they match the file is passed over;
+//! otherwise the new hash is stored and the file touched. A file with no hash stored yet
+//! counts as changed. The hash is written as `sha256:` and 64 hexadecimal digits.
+//!
+//! On Windows the hash is kept in an alternate data stream of the file itself,
+//! `FILE:mdtouch.hash`, so it moves and is deleted with the file. Where the filesystem has
+//! no streams, as FAT and exFAT don't, and everywhere else than Windows, it is kept in a
+//! sidecar next to the file, named after it with `.mdtouch-hash` added. Sidecars are never
+//! touched or hashed themselves.
+//!
+//! Only regular files are hashed: a directory, or a file about to be created, is touched
+//! as usual and nothing is stored for it.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The name added to a file's own to name the sidecar holding its hash.
+pub const SIDECAR_EXTENSION: &str = ".mdtouch-hash";
+
+/// The alternate data stream holding a file's hash on Windows.
+#[cfg(windows)]
+const STREAM_NAME: &str = "mdtouch.hash";
+
+/// The sidecar holding the hash of `path`.
+pub fn sidecar(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Whether `path` is itself a sidecar holding a hash.
+pub fn is_sidecar(path: &Path) -> bool {
+    path.as_os_str()
+        .to_string_lossy()
+        .ends_with(SIDECAR_EXTENSION)
+}
+
+/// The alternate data stream holding the hash of `path`.
+#[cfg(windows)]
+fn stream(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(":");
+    name.push(STREAM_NAME);
+    PathBuf::from(name)
+}
+
+/// Hashes the content of the file at `path`, or returns `None` if there is no regular
+/// file there.
+pub fn hash_file(path: &Path) -> io::Result<Option<String>> {
+    match fs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+        Ok(metadata) if !metadata.is_file() => return Ok(None),
+        Ok(_) => {}
+    }
+    let digest = sha256(File::open(path)?)?;
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(Some(format!("sha256:{}", hex)))
+}
+
+/// The hash stored for `path`, if there is one.
+pub fn stored(path: &Path) -> Option<String> {
+    #[cfg(windows)]
+    if let Ok(hash) = fs::read_to_string(stream(path)) {
+        return Some(hash.trim().to_string());
+    }
+    fs::read_to_string(sidecar(path))
+        .ok()
+        .map(|hash| hash.trim().to_string())
+}
+
+/// Stores `hash` for `path`, in its stream where the filesystem has them and otherwise
+/// in its sidecar.
+pub fn store(path: &Path, hash: &str) -> io::Result<()> {
+    let line = format!("{}\n", hash);
+    #[cfg(windows)]
+    if fs::write(stream(path), &line).is_ok() {
+        return Ok(());
+    }
+    fs::write(sidecar(path), line)
+}
+
+/// Removes the hash stored for `path`, so the file counts as changed next time.
+pub fn forget(path: &Path) {
+    #[cfg(windows)]
+    let _ = fs::remove_file(stream(path));
+    let _ = fs::remove_file(sidecar(path));
+}
+
+/// The SHA-256 round constants: the first 32 bits of the fractional parts of the cube
+/// roots of the first 64 primes.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Hashes everything `reader` gives with SHA-256 (FIPS 180-4).
+fn sha256<R: Read>(mut reader: R) -> io::Result<[u8; 32]> {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut buffer = vec![0; 64 * 1024];
+    let mut pending = Vec::with_capacity(64);
+    let mut length: u64 = 0;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        length += read as u64;
+        let mut input = &buffer[..read];
+        if !pending.is_empty() {
+            let wanted = (64 - pending.len()).min(input.len());
+            pending.extend_from_slice(&input[..wanted]);
+            input = &input[wanted..];
+            if pending.len() < 64 {
+                continue;
+            }
+            compress(&mut state, &pending);
+            pending.clear();
+        }
+        let mut blocks = input.chunks_exact(64);
+        for block in &mut blocks {
+            compress(&mut state, block);
+        }
+        pending.extend_from_slice(blocks.remainder());
+    }
+    // A 1 bit, zeros up to 8 bytes short of a block, then the length in bits.
+    pending.push(0x80);
+    while pending.len() % 64 != 56 {
+        pending.push(0);
+    }
+    pending.extend_from_slice(&(length * 8).to_be_bytes());
+    for block in pending.chunks_exact(64) {
+        compress(&mut state, block);
+    }
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    Ok(digest)
+}
+
+/// Runs one 64-byte block through the compression function.
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let choice = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(choice)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let majority = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(majority);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(add);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(input: &[u8]) -> String {
+        sha256(input)
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        let million = vec![b'a'; 1_000_000];
+        assert_eq!(
+            hex(&million),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn test_sha256_across_short_reads() {
+        /// Gives at most 7 bytes a read, so blocks are put together across reads.
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                let read = self.0.len().min(buffer.len()).min(7);
+                buffer[..read].copy_from_slice(&self.0[..read]);
+                self.0 = &self.0[read..];
+                Ok(read)
+            }
+        }
+        let input: Vec<u8> = (0..1000u32).map(|n| n as u8).collect();
+        assert_eq!(
+            sha256(Trickle(&input)).unwrap(),
+            sha256(&input[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_store_and_read_back() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_hash_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("data.bin");
+        assert_eq!(hash_file(&path).unwrap(), None);
+        assert_eq!(hash_file(&dir).unwrap(), None);
+        fs::write(&path, b"abc").unwrap();
+        let hash = hash_file(&path).unwrap().unwrap();
+        assert_eq!(
+            hash,
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(stored(&path), None);
+        store(&path, &hash).unwrap();
+        assert_eq!(stored(&path), Some(hash));
+        forget(&path);
+        assert_eq!(stored(&path), None);
+        assert!(is_sidecar(&sidecar(&path)));
+        assert!(!is_sidecar(&path));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1748 @@
+//! Parsing of the date strings accepted by `-d`/`--date`, and formatting of times for
+//! output.
+//!
+//! Supported forms:
+//!
+//! * `@SECONDS[.FRACTION]` - seconds since the Unix epoch.
+//! * ISO 8601: `YYYY-MM-DD`, optionally followed by `T` or a space and
+//!   `HH:MM[:SS[.FRACTION]]`, and optionally a zone.
+//! * RFC 2822 and other month-name forms, such as `Mon, 03 Feb 2025 10:00:00 +0000`,
+//!   `3 March 2024 10:30`, `March 3, 2024` or `3. März 2024`.
+//! * `now`.
+//! * Any single form given as a [`DateFormat`] pattern (`--date-format`), read with that
+//!   pattern alone.
+//!
+//! A zone is `Z`, `UTC`, `GMT` or a numeric offset (`+HH:MM`, `+HHMM` or `+HH`).
+//! Without one the time is read as local time, unless the context asks for UTC or gives
+//! a [`TimeZone`] to read it in.
+//!
+//! Month names are matched case-insensitively, in full or by an unambiguous prefix of
+//! at least three letters (`Feb`, `févr.`). English names are always recognised; the
+//! context's locale adds the names of one other language.
+//!
+//! The parsers are pure functions of their input and a [`ParseContext`]: given a fixed
+//! `now` and local offset, they read the system neither for the time nor for the zone,
+//! so other tools can read dates exactly as the command line does.
+
+use std::fmt;
+use std::io;
+
+use filetime::FileTime;
+
+use crate::TimeZone;
+
+/// Languages with month-name tables. Anything else falls back to English only.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Locale {
+    #[default]
+    English,
+    French,
+    German,
+    Spanish,
+    Italian,
+    Portuguese,
+    Dutch,
+}
+
+impl Locale {
+    /// Maps a locale name such as `fr`, `de-AT` or `es_ES.UTF-8` onto a month table by its
+    /// language code. Unknown names give `English`.
+    pub fn from_name(name: &str) -> Self {
+        let language = name
+            .split(['-', '_', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "fr" | "french" => Locale::French,
+            "de" | "german" => Locale::German,
+            "es" | "spanish" => Locale::Spanish,
+            "it" | "italian" => Locale::Italian,
+            "pt" | "portuguese" => Locale::Portuguese,
+            "nl" | "dutch" => Locale::Dutch,
+            _ => Locale::English,
+        }
+    }
+
+    /// Picks up the system locale from the environment (`LC_ALL`, `LC_TIME`, then
+    /// `LANG`), falling back to `English`.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map(|value| Locale::from_name(&value))
+            .unwrap_or_default()
+    }
+
+    /// The full month names, January first, in lowercase.
+    fn month_names(self) -> [&'static str; 12] {
+        match self {
+            Locale::English => [
+                "january",
+                "february",
+                "march",
+                "april",
+                "may",
+                "june",
+                "july",
+                "august",
+                "september",
+                "october",
+                "november",
+                "december",
+            ],
+            Locale::French => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Locale::German => [
+                "januar",
+                "februar",
+                "märz",
+                "april",
+                "mai",
+                "juni",
+                "juli",
+                "august",
+                "september",
+                "oktober",
+                "november",
+                "dezember",
+            ],
+            Locale::Spanish => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+            Locale::Italian => [
+                "gennaio",
+                "febbraio",
+                "marzo",
+                "aprile",
+                "maggio",
+                "giugno",
+                "luglio",
+                "agosto",
+                "settembre",
+                "ottobre",
+                "novembre",
+                "dicembre",
+            ],
+            Locale::Portuguese => [
+                "janeiro",
+                "fevereiro",
+                "março",
+                "abril",
+                "maio",
+                "junho",
+                "julho",
+                "agosto",
+                "setembro",
+                "outubro",
+                "novembro",
+                "dezembro",
+            ],
+            Locale::Dutch => [
+                "januari",
+                "februari",
+                "maart",
+                "april",
+                "mei",
+                "juni",
+                "juli",
+                "augustus",
+                "september",
+                "oktober",
+                "november",
+                "december",
+            ],
+        }
+    }
+
+    /// Looks up a month name (1-12) in this locale's table, accepting the full name or a
+    /// prefix of at least three letters that matches only one month.
+    fn month_number(self, word: &str) -> Option<u32> {
+        let names = self.month_names();
+        if let Some(index) = names.iter().position(|name| *name == word) {
+            return Some(index as u32 + 1);
+        }
+        if word.chars().count() < 3 {
+            return None;
+        }
+        let mut matches = names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| name.starts_with(word));
+        match (matches.next(), matches.next()) {
+            (Some((index, _)), None) => Some(index as u32 + 1),
+            _ => None,
+        }
+    }
+}
+
+/// English weekday names, which may prefix a date and are otherwise ignored.
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Settings that affect how a date string is interpreted.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseContext {
+    /// The time `now` refers to.
+    pub now: FileTime,
+    /// Read times without a zone as UTC instead of local time.
+    pub utc: bool,
+    /// The offset of local time from UTC, in seconds east. `None` asks the system for the
+    /// offset in force at each date read, following daylight saving time.
+    pub local_offset: Option<i32>,
+    /// The zone to read times without one in instead of local time (`--tz`). Takes the
+    /// place of `local_offset`, but not of `utc`.
+    pub zone: Option<TimeZone>,
+    /// The locale whose month names are recognised alongside English.
+    pub locale: Locale,
+}
+
+impl Default for ParseContext {
+    fn default() -> Self {
+        ParseContext {
+            now: FileTime::now(),
+            utc: false,
+            local_offset: None,
+            zone: None,
+            locale: Locale::English,
+        }
+    }
+}
+
+impl ParseContext {
+    /// The offset from UTC, in seconds, of times without a zone at the instant
+    /// `unix_seconds`.
+    fn offset_at(&self, unix_seconds: i64) -> i32 {
+        if self.utc {
+            return 0;
+        }
+        if let Some(zone) = self.zone {
+            return zone.offset_at(unix_seconds);
+        }
+        self.local_offset
+            .unwrap_or_else(|| local_offset_at(unix_seconds))
+    }
+
+    /// Converts a civil time without a zone (as seconds since the epoch, ignoring zones)
+    /// into UTC, reading it as local time unless `utc` or `zone` is set.
+    ///
+    /// The offset is looked up twice so that a date on the other side of a daylight
+    /// saving change from the first guess still gets the offset in force at that moment.
+    pub fn local_to_utc(&self, civil: i64) -> i64 {
+        let guess = civil - i64::from(self.offset_at(civil));
+        civil - i64::from(self.offset_at(guess))
+    }
+}
+
+/// A string that could not be read as a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// What the string was meant to be, such as `date` or `--filetime value`.
+    what: &'static str,
+    input: String,
+    reason: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(what: &'static str, input: &str, reason: impl Into<String>) -> Self {
+        ParseError {
+            what,
+            input: input.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// The string that could not be read.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Why it could not be read, such as `no such day`.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid {} '{}': {}", self.what, self.input, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(err: ParseError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// Returns the error for a date string that could not be understood.
+fn invalid(input: &str, reason: &str) -> ParseError {
+    ParseError::new("date", input, reason)
+}
+
+/// Parses a date string into the instant it names.
+pub fn parse_date(input: &str, context: ParseContext) -> Result<FileTime, ParseError> {
+    let trimmed = input.trim();
+    if trimmed.eq_ignore_ascii_case("now") {
+        return Ok(context.now);
+    }
+    if let Some(epoch) = trimmed.strip_prefix('@') {
+        let (seconds, nanos) = parse_seconds(epoch).ok_or_else(|| invalid(input, "bad epoch"))?;
+        return Ok(FileTime::from_unix_time(seconds, nanos));
+    }
+
+    let mut fields = Fields::default();
+    let tokens: Vec<&str> = trimmed
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .collect();
+    for (index, token) in tokens.iter().enumerate() {
+        fields
+            .take(token, index, context.locale)
+            .map_err(|reason| invalid(input, reason))?;
+    }
+
+    let date = match (fields.year, fields.month, fields.day) {
+        (Some(year), Some(month), Some(day)) => (year, month, day),
+        _ => return Err(invalid(input, "expected a year, month and day")),
+    };
+    let time = fields.time.unwrap_or_default();
+    to_file_time(input, date, time, fields.offset, &context)
+}
+
+/// Parses a `--times` list: the access, modification and creation times, separated by
+/// commas, each in any form [`parse_date`] accepts (or as `format` reads it, if given) or
+/// `-` (or nothing) to leave that time unchanged.
+pub fn parse_time_slots(
+    input: &str,
+    context: ParseContext,
+    format: Option<&DateFormat>,
+) -> Result<[Option<FileTime>; 3], ParseError> {
+    let slots: Vec<&str> = input.split(',').map(str::trim).collect();
+    let [atime, mtime, created] = slots[..] else {
+        return Err(ParseError::new(
+            "--times",
+            input,
+            "expected access, modification and creation times separated by commas (- leaves one unchanged)",
+        ));
+    };
+    let slot = |text: &str| match text {
+        "" | "-" => Ok(None),
+        text => match format {
+            Some(format) => format.parse(text, context),
+            None => parse_date(text, context),
+        }
+        .map(Some),
+    };
+    Ok([slot(atime)?, slot(mtime)?, slot(created)?])
+}
+
+/// Parses a `-t` timestamp, `[[CC]YY]MMDDhhmm[.ss]`, as a local time (or UTC if the
+/// context asks for it).
+///
+/// A two-digit year from 69 to 99 is in the 1900s and anything lower in the 2000s, as in
+/// POSIX touch. Without a year, the current year is used.
+pub fn parse_touch_stamp(input: &str, context: ParseContext) -> Result<FileTime, ParseError> {
+    let (digits, seconds) = match input.split_once('.') {
+        Some((digits, seconds)) => (digits, Some(seconds)),
+        None => (input, None),
+    };
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid(input, "expected [[CC]YY]MMDDhhmm[.ss]"));
+    }
+    let second = match seconds {
+        Some(seconds) if seconds.len() == 2 => {
+            parse_number(Some(seconds)).ok_or_else(|| invalid(input, "bad seconds"))?
+        }
+        Some(_) => return Err(invalid(input, "bad seconds")),
+        None => 0,
+    };
+    // Every field is two ASCII digits, so slicing and parsing cannot fail.
+    let field = |start: usize| digits[start..start + 2].parse::<u32>().unwrap_or_default();
+    let (year, rest) = match digits.len() {
+        8 => {
+            let offset = context.offset_at(context.now.unix_seconds());
+            let local_now = context.now.unix_seconds() + i64::from(offset);
+            (civil_from_days(local_now.div_euclid(86_400)).0, 0)
+        }
+        10 => {
+            let year = i64::from(field(0));
+            (if year >= 69 { 1900 + year } else { 2000 + year }, 2)
+        }
+        12 => (i64::from(field(0)) * 100 + i64::from(field(2)), 4),
+        _ => return Err(invalid(input, "expected [[CC]YY]MMDDhhmm[.ss]")),
+    };
+    let date = (year, field(rest), field(rest + 2));
+    let time = (field(rest + 4), field(rest + 6), second, 0);
+    to_file_time(input, date, time, None, &context)
+}
+
+/// A pattern given to `--date-format`, such as `%d.%m.%Y %H:%M`, for reading dates in one
+/// known form instead of working out which form they are in.
+///
+/// The specifiers are those of `strftime`: `%Y` the year, `%y` a two-digit year (69 to 99
+/// in the 1900s and anything lower in the 2000s, as with `-t`), `%m` the month, `%b`, `%B`
+/// or `%h` its name, `%d` or `%e` the day, `%H` the hour, `%I` the hour on a 12-hour clock
+/// with `%p` for AM or PM, `%M` minutes, `%S` seconds, `%f` a fraction of a second, `%z`
+/// a numeric zone and `%Z` a zone name such as `UTC`. `%a` or `%A` skip a weekday name,
+/// `%s` reads seconds since the epoch and `%%` is a percent sign. `%F`, `%T`, `%R` and `%D`
+/// stand for `%Y-%m-%d`, `%H:%M:%S`, `%H:%M` and `%m/%d/%y`. Numbers may have fewer digits
+/// than their field holds. Whitespace in the pattern matches any amount of whitespace,
+/// and anything else must appear as it is, ignoring case.
+///
+/// Parts of the date the pattern leaves out are filled in: those larger than any it has
+/// come from today, the others are the first, so `%H:%M` is today and `%m/%Y` is the
+/// first of the month. A time left out is midnight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateFormat {
+    pattern: String,
+    items: Vec<FormatItem>,
+}
+
+/// One piece of a [`DateFormat`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FormatItem {
+    /// Any amount of whitespace, including none.
+    Space,
+    /// A character that must appear as it is.
+    Literal(char),
+    /// A specifier, by its letter. Synonyms are stored as one letter: `%B` and `%h` as
+    /// `b`, `%A` as `a`.
+    Field(char),
+}
+
+/// What a [`DateFormat`] has read from a date so far.
+#[derive(Debug, Default)]
+struct FormatFields {
+    year: Option<i64>,
+    month: Option<u32>,
+    day: Option<u32>,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32,
+    pm: Option<bool>,
+    offset: Option<i32>,
+    epoch: Option<i64>,
+}
+
+impl DateFormat {
+    /// Reads a pattern, rejecting unknown specifiers.
+    pub fn new(pattern: &str) -> Result<Self, ParseError> {
+        let invalid = |reason: String| ParseError::new("--date-format", pattern, reason);
+        let mut items = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                if items.last() != Some(&FormatItem::Space) {
+                    items.push(FormatItem::Space);
+                }
+                continue;
+            }
+            if c != '%' {
+                items.push(FormatItem::Literal(c));
+                continue;
+            }
+            let spec = chars
+                .next()
+                .ok_or_else(|| invalid("ends with a lone %".to_string()))?;
+            let shorthand = match spec {
+                'F' => "%Y-%m-%d",
+                'T' => "%H:%M:%S",
+                'R' => "%H:%M",
+                'D' => "%m/%d/%y",
+                _ => "",
+            };
+            if !shorthand.is_empty() {
+                items.extend(DateFormat::new(shorthand)?.items);
+                continue;
+            }
+            items.push(match spec {
+                '%' => FormatItem::Literal('%'),
+                'n' | 't' => FormatItem::Space,
+                'b' | 'B' | 'h' => FormatItem::Field('b'),
+                'a' | 'A' => FormatItem::Field('a'),
+                'Y' | 'y' | 'm' | 'd' | 'e' | 'H' | 'I' | 'p' | 'M' | 'S' | 'f' | 'z' | 'Z'
+                | 's' => FormatItem::Field(spec),
+                _ => return Err(invalid(format!("unknown specifier %{}", spec))),
+            });
+        }
+        let has = |spec: char| items.contains(&FormatItem::Field(spec));
+        if !items
+            .iter()
+            .any(|item| matches!(item, FormatItem::Field(_)))
+        {
+            return Err(invalid(
+                "no specifiers to read a date or time with".to_string(),
+            ));
+        }
+        if has('I') != has('p') {
+            return Err(invalid("%I and %p go together".to_string()));
+        }
+        Ok(DateFormat {
+            pattern: pattern.to_string(),
+            items,
+        })
+    }
+
+    /// Reads `input`, which must match the pattern in full.
+    pub fn parse(&self, input: &str, context: ParseContext) -> Result<FileTime, ParseError> {
+        let text: Vec<char> = input.trim().chars().collect();
+        let mut position = 0;
+        let mut fields = FormatFields::default();
+        let mismatch = |reason: String, position: usize| {
+            invalid(
+                input,
+                &format!(
+                    "does not match --date-format '{}': {} at character {}",
+                    self.pattern,
+                    reason,
+                    position + 1
+                ),
+            )
+        };
+        for item in &self.items {
+            match *item {
+                FormatItem::Space => {
+                    while text.get(position).is_some_and(|c| c.is_whitespace()) {
+                        position += 1;
+                    }
+                }
+                FormatItem::Literal(expected) => match text.get(position) {
+                    Some(c) if c.to_lowercase().eq(expected.to_lowercase()) => position += 1,
+                    _ => return Err(mismatch(format!("expected '{}'", expected), position)),
+                },
+                FormatItem::Field(spec) => {
+                    let start = position;
+                    fields
+                        .take(spec, &text, &mut position, context.locale)
+                        .ok_or_else(|| {
+                            mismatch(format!("expected {}", describe_field(spec)), start)
+                        })?;
+                }
+            }
+        }
+        if position < text.len() {
+            let rest: String = text[position..].iter().collect();
+            return Err(mismatch(format!("unexpected '{}'", rest), position));
+        }
+        fields.finish(input, &context)
+    }
+}
+
+impl FormatFields {
+    /// Reads the field `spec` from `text` at `position`, moving past it. Returns `None`
+    /// when the text there is not that field.
+    fn take(
+        &mut self,
+        spec: char,
+        text: &[char],
+        position: &mut usize,
+        locale: Locale,
+    ) -> Option<()> {
+        let mut run = |accept: fn(char) -> bool, max: usize| -> Option<String> {
+            let length = text[*position..]
+                .iter()
+                .take(max)
+                .take_while(|c| accept(**c))
+                .count();
+            let taken: String = text[*position..*position + length].iter().collect();
+            *position += length;
+            (length > 0).then_some(taken)
+        };
+        let digit = |c: char| c.is_ascii_digit();
+        match spec {
+            'Y' => self.year = Some(run(digit, 4)?.parse().ok()?),
+            'y' => {
+                let year: i64 = run(digit, 2)?.parse().ok()?;
+                self.year = Some(if year >= 69 { 1900 + year } else { 2000 + year });
+            }
+            'm' => self.month = Some(run(digit, 2)?.parse().ok()?),
+            'd' => self.day = Some(run(digit, 2)?.parse().ok()?),
+            'e' => {
+                run(|c| c == ' ', usize::MAX);
+                self.day = Some(run(digit, 2)?.parse().ok()?);
+            }
+            'H' | 'I' => self.hour = run(digit, 2)?.parse().ok()?,
+            'M' => self.minute = run(digit, 2)?.parse().ok()?,
+            'S' => self.second = run(digit, 2)?.parse().ok()?,
+            'f' => {
+                let fraction = run(digit, 9)?;
+                self.nanos = fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32);
+            }
+            'p' => {
+                self.pm = match run(char::is_alphabetic, usize::MAX)?
+                    .to_lowercase()
+                    .as_str()
+                {
+                    "am" => Some(false),
+                    "pm" => Some(true),
+                    _ => None,
+                };
+                self.pm?;
+            }
+            'b' => {
+                let word = run(char::is_alphabetic, usize::MAX)?.to_lowercase();
+                self.month = Some(
+                    locale
+                        .month_number(&word)
+                        .or_else(|| Locale::English.month_number(&word))?,
+                );
+            }
+            'a' => {
+                run(char::is_alphabetic, usize::MAX)?;
+            }
+            'z' => {
+                let zone = run(
+                    |c| matches!(c, '+' | '-' | ':' | 'Z' | 'z') || c.is_ascii_digit(),
+                    6,
+                )?;
+                self.offset = Some(parse_zone(&zone)?);
+            }
+            'Z' => self.offset = Some(parse_zone(&run(char::is_alphabetic, usize::MAX)?)?),
+            's' => {
+                let negative = run(|c| c == '-', 1).is_some();
+                let seconds: i64 = run(digit, 19)?.parse().ok()?;
+                self.epoch = Some(if negative { -seconds } else { seconds });
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Fills in what the pattern left out and converts the result to an instant.
+    fn finish(self, input: &str, context: &ParseContext) -> Result<FileTime, ParseError> {
+        if let Some(seconds) = self.epoch {
+            return Ok(FileTime::from_unix_time(seconds, self.nanos));
+        }
+        let offset = context.offset_at(context.now.unix_seconds());
+        let local_now = context.now.unix_seconds() + i64::from(offset);
+        let today = civil_from_days(local_now.div_euclid(86_400));
+        let date = (
+            self.year.unwrap_or(today.0),
+            self.month
+                .unwrap_or(if self.year.is_some() { 1 } else { today.1 }),
+            self.day
+                .unwrap_or(if self.year.is_some() || self.month.is_some() {
+                    1
+                } else {
+                    today.2
+                }),
+        );
+        let hour = match self.pm {
+            Some(_) if !(1..=12).contains(&self.hour) => {
+                return Err(invalid(input, "time out of range"))
+            }
+            Some(pm) => self.hour % 12 + if pm { 12 } else { 0 },
+            None => self.hour,
+        };
+        let time = (hour, self.minute, self.second, self.nanos);
+        to_file_time(input, date, time, self.offset, context)
+    }
+}
+
+/// Says what a specifier reads, for saying what a date is missing.
+fn describe_field(spec: char) -> &'static str {
+    match spec {
+        'Y' => "a year (%Y)",
+        'y' => "a two-digit year (%y)",
+        'm' => "a month (%m)",
+        'b' => "a month name (%b)",
+        'd' | 'e' => "a day (%d)",
+        'H' => "an hour (%H)",
+        'I' => "an hour from 1 to 12 (%I)",
+        'p' => "AM or PM (%p)",
+        'M' => "minutes (%M)",
+        'S' => "seconds (%S)",
+        'f' => "a fraction of a second (%f)",
+        'z' => "a zone offset (%z)",
+        'Z' => "a zone name (%Z)",
+        'a' => "a weekday name (%a)",
+        's' => "seconds since the epoch (%s)",
+        _ => "a field",
+    }
+}
+
+/// Checks a date and time read from `input` and converts them to an instant. Without an
+/// `offset` the time is local, unless the context asks for UTC.
+fn to_file_time(
+    input: &str,
+    (year, month, day): CivilDate,
+    (hour, minute, second, nanos): (u32, u32, u32, u32),
+    offset: Option<i32>,
+    context: &ParseContext,
+) -> Result<FileTime, ParseError> {
+    if !(0..=9999).contains(&year) {
+        return Err(invalid(input, "year out of range"));
+    }
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid(input, "no such day"));
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid(input, "time out of range"));
+    }
+    // A leap second is folded into the following second, as most systems do.
+    let civil = days_from_civil(year, month, day) * 86_400
+        + i64::from(hour) * 3_600
+        + i64::from(minute) * 60
+        + i64::from(second);
+
+    let seconds = match offset {
+        Some(offset) => civil - i64::from(offset),
+        None => context.local_to_utc(civil),
+    };
+    Ok(FileTime::from_unix_time(seconds, nanos))
+}
+
+/// The pieces of a date collected while scanning its tokens.
+#[derive(Debug, Default)]
+struct Fields {
+    year: Option<i64>,
+    month: Option<u32>,
+    day: Option<u32>,
+    time: Option<(u32, u32, u32, u32)>,
+    offset: Option<i32>,
+}
+
+impl Fields {
+    /// Records one whitespace-separated token.
+    fn take(&mut self, token: &str, index: usize, locale: Locale) -> Result<(), &'static str> {
+        // ISO 8601 date, possibly with the time attached by a `T`.
+        if let Some((date, rest)) = split_iso_date(token) {
+            if self.year.is_some() {
+                return Err("more than one date");
+            }
+            (self.year, self.month, self.day) = (Some(date.0), Some(date.1), Some(date.2));
+            return match rest {
+                Some(time) => self.take_time(time),
+                None => Ok(()),
+            };
+        }
+        if token.contains(':') && token.starts_with(|c: char| c.is_ascii_digit()) {
+            return self.take_time(token);
+        }
+        if let Some(offset) = parse_zone(token) {
+            if self.offset.is_some()
+                || (self.time.is_none() && !token.starts_with(char::is_alphabetic))
+            {
+                return Err("unexpected zone");
+            }
+            self.offset = Some(offset);
+            return Ok(());
+        }
+        // Numbers may carry a trailing dot, as in the German `3. März`.
+        let number = token.strip_suffix('.').unwrap_or(token);
+        if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) {
+            return self.take_number(number);
+        }
+
+        let word = token.trim_end_matches('.').to_lowercase();
+        let month = locale
+            .month_number(&word)
+            .or_else(|| Locale::English.month_number(&word));
+        if let Some(month) = month {
+            if self.month.is_some() {
+                return Err("more than one month");
+            }
+            self.month = Some(month);
+            return Ok(());
+        }
+        let is_weekday = WEEKDAYS
+            .iter()
+            .any(|day| *day == word || (word.len() >= 3 && day.starts_with(&word)));
+        // A leading word followed by a comma is taken to be a weekday in any language.
+        if is_weekday || (index == 0 && token.ends_with(',')) {
+            return Ok(());
+        }
+        Err("unrecognised word")
+    }
+
+    /// Records a bare number as the day or year of a month-name date.
+    fn take_number(&mut self, number: &str) -> Result<(), &'static str> {
+        let value: i64 = number.parse().map_err(|_| "number too large")?;
+        if number.len() <= 2 && self.day.is_none() {
+            self.day = Some(value as u32);
+        } else if self.year.is_none() {
+            self.year = Some(value);
+        } else {
+            return Err("too many numbers");
+        }
+        Ok(())
+    }
+
+    /// Records a `HH:MM[:SS[.FRACTION]]` time, with an optional zone glued on.
+    fn take_time(&mut self, token: &str) -> Result<(), &'static str> {
+        if self.time.is_some() {
+            return Err("more than one time");
+        }
+        let zone_start = token.find(['Z', 'z', '+', '-']).unwrap_or(token.len());
+        let (time, zone) = token.split_at(zone_start);
+        if !zone.is_empty() {
+            self.offset = Some(parse_zone(zone).ok_or("bad zone")?);
+        }
+
+        let mut parts = time.split(':');
+        let hour = parse_number(parts.next()).ok_or("bad hour")?;
+        let minute = parse_number(parts.next()).ok_or("bad minute")?;
+        let (second, nanos) = match parts.next() {
+            Some(seconds) => {
+                let (second, nanos) = parse_seconds(seconds).ok_or("bad seconds")?;
+                (u32::try_from(second).map_err(|_| "bad seconds")?, nanos)
+            }
+            None => (0, 0),
+        };
+        if parts.next().is_some() {
+            return Err("bad time");
+        }
+        self.time = Some((hour, minute, second, nanos));
+        Ok(())
+    }
+}
+
+/// Parses a field of one or two digits.
+fn parse_number(field: Option<&str>) -> Option<u32> {
+    let field = field?;
+    if field.is_empty() || field.len() > 2 || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    field.parse().ok()
+}
+
+/// Parses `SECONDS[.FRACTION]` into whole seconds and nanoseconds. The fraction may have
+/// up to nine digits.
+fn parse_seconds(text: &str) -> Option<(i64, u32)> {
+    let (whole, fraction) = match text.split_once(['.', ',']) {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (text, ""),
+    };
+    let digits = whole.strip_prefix('-').unwrap_or(whole);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let seconds: i64 = whole.parse().ok()?;
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32)
+    };
+    if whole.starts_with('-') && nanos > 0 {
+        // -1.5 is one and a half seconds before the epoch.
+        Some((seconds.checked_sub(1)?, 1_000_000_000 - nanos))
+    } else {
+        Some((seconds, nanos))
+    }
+}
+
+/// A calendar date as year, month (1-12) and day of the month.
+type CivilDate = (i64, u32, u32);
+
+/// Splits a token starting with an ISO 8601 `YYYY-MM-DD` date into the date and any time
+/// attached with `T`.
+fn split_iso_date(token: &str) -> Option<(CivilDate, Option<&str>)> {
+    let (date, time) = match token.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (token, None),
+    };
+    let mut parts = date.split('-');
+    let year = parts.next()?;
+    let month = parts.next()?;
+    let day = parts.next()?;
+    if parts.next().is_some() || year.len() != 4 || !year.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let month = parse_number(Some(month))?;
+    let day = parse_number(Some(day))?;
+    Some(((year.parse().ok()?, month, day), time))
+}
+
+/// Parses a zone designator into its offset from UTC in seconds.
+pub(crate) fn parse_zone(zone: &str) -> Option<i32> {
+    if ["z", "utc", "gmt", "ut"].contains(&zone.to_ascii_lowercase().as_str()) {
+        return Some(0);
+    }
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3_600 + minutes * 60))
+}
+
+/// Whether `year` is a leap year in the proleptic Gregorian calendar.
+pub(crate) fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// The number of days in a month.
+pub(crate) fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date for a number of days since 1970-01-01; the inverse of
+/// [`days_from_civil`].
+pub(crate) fn civil_from_days(days: i64) -> CivilDate {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Formats a time as ISO 8601 in UTC, with nanoseconds: `2025-02-03T10:00:00.000000000Z`.
+pub fn format_iso8601(time: FileTime) -> String {
+    let seconds = time.unix_seconds();
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let of_day = seconds.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        of_day / 3_600,
+        of_day % 3_600 / 60,
+        of_day % 60,
+        time.nanoseconds()
+    )
+}
+
+/// Formats a time as seconds since the Unix epoch, in the `@1738576800.000001500` form
+/// `-d` reads back to the same time.
+pub fn format_epoch(time: FileTime) -> String {
+    let (seconds, nanos) = (time.unix_seconds(), time.nanoseconds());
+    match nanos {
+        0 => format!("@{}", seconds),
+        // -1.5 is one and a half seconds before the epoch, kept as -2 and a half.
+        _ if seconds < 0 => format!("@-{}.{:09}", -(seconds + 1), 1_000_000_000 - nanos),
+        _ => format!("@{}.{:09}", seconds, nanos),
+    }
+}
+
+/// Describes how long before or after `now` a time is, in its largest whole unit: `just
+/// now`, `2 minutes ago`, `in 3 days`. Months are 30 days and years 365.
+pub fn humanize(time: FileTime, now: FileTime) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 86_400, "year"),
+        (30 * 86_400, "month"),
+        (86_400, "day"),
+        (3_600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+    let seconds = now.unix_seconds() - time.unix_seconds();
+    if seconds.abs() < 5 {
+        return "just now".to_string();
+    }
+    let (size, unit) = UNITS
+        .into_iter()
+        .find(|&(size, _)| seconds.abs() >= size)
+        .unwrap_or((1, "second"));
+    let count = seconds.abs() / size;
+    let plural = if count == 1 { "" } else { "s" };
+    if seconds > 0 {
+        format!("{} {}{} ago", count, unit, plural)
+    } else {
+        format!("in {} {}{}", count, unit, plural)
+    }
+}
+
+/// Seconds from the FILETIME epoch, 1601-01-01, to the Unix epoch.
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// FILETIME ticks (100 ns intervals) in a second.
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// Parses a raw Windows FILETIME (`--filetime`): a count of 100 ns ticks since
+/// 1601-01-01 UTC, in decimal or `0x`-prefixed hexadecimal.
+///
+/// Windows only accepts values up to `0x7FFFFFFFFFFFFFFF`, and reads 0 as "leave
+/// unchanged", so anything else is rejected.
+pub fn parse_filetime(input: &str) -> Result<FileTime, ParseError> {
+    let invalid = |reason: &str| ParseError::new("--filetime value", input, reason);
+    let trimmed = input.trim();
+    let ticks = match trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => trimmed.parse(),
+    }
+    .map_err(|_| invalid("expected a decimal or 0x-prefixed hexadecimal tick count"))?;
+    if ticks == 0 {
+        return Err(invalid(
+            "0 is not a time (Windows reads it as \"unchanged\")",
+        ));
+    }
+    filetime_from_ticks(ticks)
+        .ok_or_else(|| invalid("out of range (the largest FILETIME is 0x7FFFFFFFFFFFFFFF)"))
+}
+
+/// Converts a raw Windows FILETIME, 100 ns ticks since 1601-01-01 UTC, into a time.
+/// Returns `None` for 0, which Windows reads as "unchanged", and for anything above
+/// `0x7FFFFFFFFFFFFFFF`, which Windows does not accept.
+pub fn filetime_from_ticks(ticks: u64) -> Option<FileTime> {
+    if ticks == 0 || ticks > i64::MAX as u64 {
+        return None;
+    }
+    let seconds = (ticks / TICKS_PER_SECOND) as i64 - FILETIME_EPOCH_OFFSET;
+    let nanos = (ticks % TICKS_PER_SECOND) as u32 * 100;
+    Some(FileTime::from_unix_time(seconds, nanos))
+}
+
+/// .NET ticks (100 ns intervals since 0001-01-01) at the Unix epoch.
+const DOTNET_UNIX_EPOCH: u64 = 621_355_968_000_000_000;
+
+/// .NET ticks at the FILETIME epoch, 1601-01-01, the earliest time Windows can store.
+const DOTNET_FILETIME_EPOCH: u64 = 504_911_232_000_000_000;
+
+/// .NET ticks at `DateTime.MaxValue`, the last tick of 9999-12-31.
+const DOTNET_MAX_TICKS: u64 = 3_155_378_975_999_999_999;
+
+/// Parses a .NET `DateTime.Ticks` value (`--dotnet-ticks`): a count of 100 ns ticks since
+/// 0001-01-01, optionally followed by `:utc` or `:local` for the `DateTime.Kind` it came
+/// from. Without a kind it is UTC; a local time is local unless the context asks for UTC.
+pub fn parse_dotnet_ticks(input: &str, context: ParseContext) -> Result<FileTime, ParseError> {
+    let invalid = |reason: &str| ParseError::new("--dotnet-ticks value", input, reason);
+    let (digits, local) = match input.trim().split_once(':') {
+        Some((digits, kind)) if kind.eq_ignore_ascii_case("utc") => (digits, false),
+        Some((digits, kind)) if kind.eq_ignore_ascii_case("local") => (digits, true),
+        Some(_) => return Err(invalid("the kind must be utc or local")),
+        None => (input.trim(), false),
+    };
+    let ticks: u64 = digits
+        .parse()
+        .map_err(|_| invalid("expected a decimal tick count"))?;
+    if ticks < DOTNET_FILETIME_EPOCH {
+        return Err(invalid(
+            "before 1601-01-01, the earliest time a file can carry",
+        ));
+    }
+    if ticks > DOTNET_MAX_TICKS {
+        return Err(invalid("beyond DateTime.MaxValue (9999-12-31)"));
+    }
+    // Both epochs fall on whole seconds, so the sub-second ticks carry over unchanged.
+    let seconds = (ticks / TICKS_PER_SECOND) as i64 - (DOTNET_UNIX_EPOCH / TICKS_PER_SECOND) as i64;
+    let nanos = (ticks % TICKS_PER_SECOND) as u32 * 100;
+    let seconds = if local {
+        context.local_to_utc(seconds)
+    } else {
+        seconds
+    };
+    Ok(FileTime::from_unix_time(seconds, nanos))
+}
+
+/// The system's local offset from UTC, in seconds, at the given instant.
+#[cfg(unix)]
+fn local_offset_at(unix_seconds: i64) -> i32 {
+    let time = unix_seconds as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm we pass and reads the time we pass.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i32
+    }
+}
+
+/// The system's local offset from UTC, in seconds, at the given instant.
+#[cfg(windows)]
+fn local_offset_at(unix_seconds: i64) -> i32 {
+    use windows_sys::Win32::Foundation::{FILETIME, SYSTEMTIME};
+    use windows_sys::Win32::System::Time::{
+        FileTimeToSystemTime, SystemTimeToFileTime, SystemTimeToTzSpecificLocalTime,
+    };
+
+    let ticks = (unix_seconds + FILETIME_EPOCH_OFFSET) * 10_000_000;
+    let utc_file_time = FILETIME {
+        dwLowDateTime: ticks as u32,
+        dwHighDateTime: (ticks >> 32) as u32,
+    };
+    // SAFETY: every pointer refers to a live local of the expected type.
+    unsafe {
+        let mut utc: SYSTEMTIME = std::mem::zeroed();
+        let mut local: SYSTEMTIME = std::mem::zeroed();
+        let mut local_file_time: FILETIME = std::mem::zeroed();
+        if FileTimeToSystemTime(&utc_file_time, &mut utc) == 0
+            || SystemTimeToTzSpecificLocalTime(std::ptr::null(), &utc, &mut local) == 0
+            || SystemTimeToFileTime(&local, &mut local_file_time) == 0
+        {
+            return 0;
+        }
+        let local_ticks = (i64::from(local_file_time.dwHighDateTime) << 32)
+            | i64::from(local_file_time.dwLowDateTime);
+        ((local_ticks - ticks) / 10_000_000) as i32
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn local_offset_at(_unix_seconds: i64) -> i32 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A context that reads zoneless times as UTC, so results don't depend on the host.
+    fn utc_context(locale: Locale) -> ParseContext {
+        ParseContext {
+            now: FileTime::from_unix_time(1_700_000_000, 0),
+            utc: true,
+            local_offset: None,
+            zone: None,
+            locale,
+        }
+    }
+
+    fn parse(input: &str) -> FileTime {
+        parse_date(input, utc_context(Locale::English)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_iso_forms() {
+        let expected = FileTime::from_unix_time(1_738_576_800, 0);
+        assert_eq!(parse("2025-02-03T10:00:00Z"), expected);
+        assert_eq!(parse("2025-02-03 10:00"), expected);
+        assert_eq!(parse("2025-02-03T12:00:00+02:00"), expected);
+        assert_eq!(parse("2025-02-03 05:00 -0500"), expected);
+        assert_eq!(
+            parse("2025-02-03"),
+            FileTime::from_unix_time(1_738_540_800, 0)
+        );
+        assert_eq!(
+            parse("2025-02-03T10:00:00.25Z"),
+            FileTime::from_unix_time(1_738_576_800, 250_000_000)
+        );
+    }
+
+    #[test]
+    fn test_parse_rfc2822_and_month_names() {
+        let expected = FileTime::from_unix_time(1_738_576_800, 0);
+        assert_eq!(parse("Mon, 03 Feb 2025 10:00:00 +0000"), expected);
+        assert_eq!(parse("3 February 2025 10:00"), expected);
+        assert_eq!(parse("February 3, 2025 10:00 UTC"), expected);
+        assert_eq!(parse("feb. 3 2025 10:00:00 GMT"), expected);
+    }
+
+    #[test]
+    fn test_parse_epoch_and_now() {
+        assert_eq!(
+            parse("@1234567890"),
+            FileTime::from_unix_time(1_234_567_890, 0)
+        );
+        assert_eq!(parse("@1.5"), FileTime::from_unix_time(1, 500_000_000));
+        assert_eq!(parse("@-1.5"), FileTime::from_unix_time(-2, 500_000_000));
+        assert_eq!(parse("now"), FileTime::from_unix_time(1_700_000_000, 0));
+    }
+
+    #[test]
+    fn test_parse_localized_month_names() {
+        let expected = FileTime::from_unix_time(1_706_918_400, 0); // 2024-02-03
+        let french = utc_context(Locale::French);
+        assert_eq!(parse_date("3 Février 2024", french).unwrap(), expected);
+        assert_eq!(parse_date("3 févr. 2024", french).unwrap(), expected);
+
+        let german = utc_context(Locale::German);
+        let march = FileTime::from_unix_time(1_709_467_200, 0); // 2024-03-03 12:00
+        assert_eq!(parse_date("3. März 2024 12:00", german).unwrap(), march);
+        assert_eq!(parse_date("3. MÄRZ 2024 12:00", german).unwrap(), march);
+
+        // English still works whatever the locale.
+        assert_eq!(parse_date("3 February 2024", german).unwrap(), expected);
+        // Without the locale, localized names are rejected.
+        assert!(parse_date("3. März 2024", utc_context(Locale::English)).is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_month_prefix_is_rejected() {
+        // "jui" could be juin or juillet.
+        let french = utc_context(Locale::French);
+        assert!(parse_date("3 jui 2024", french).is_err());
+        assert!(parse_date("3 juil 2024", french).is_ok());
+    }
+
+    #[test]
+    fn test_locale_from_name() {
+        assert_eq!(Locale::from_name("fr"), Locale::French);
+        assert_eq!(Locale::from_name("de-AT"), Locale::German);
+        assert_eq!(Locale::from_name("es_ES.UTF-8"), Locale::Spanish);
+        assert_eq!(Locale::from_name("pt_BR"), Locale::Portuguese);
+        assert_eq!(Locale::from_name("ja-JP"), Locale::English);
+        assert_eq!(Locale::from_name("C"), Locale::English);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_dates() {
+        let context = utc_context(Locale::English);
+        for input in [
+            "",
+            "2025-02-30",
+            "2025-13-01",
+            "2025-02-03 24:00",
+            "3 Smarch 2025",
+            "2025-02-03 10:00 +2500",
+            "@abc",
+            "10:00",
+        ] {
+            let err = io::Error::from(parse_date(input, context).unwrap_err());
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(1601, 1, 1), -134_774);
+    }
+
+    #[test]
+    fn test_local_time_round_trips_through_offset() {
+        let context = ParseContext {
+            utc: false,
+            ..utc_context(Locale::English)
+        };
+        let local = parse_date("2024-07-01 12:00", context).unwrap();
+        let offset = local_offset_at(local.unix_seconds());
+        let utc = parse("2024-07-01 12:00");
+        assert_eq!(local.unix_seconds() + i64::from(offset), utc.unix_seconds());
+    }
+
+    #[test]
+    fn test_civil_from_days_inverts_days_from_civil() {
+        for date in [
+            (1970, 1, 1),
+            (2000, 2, 29),
+            (1969, 12, 31),
+            (1601, 1, 1),
+            (2024, 3, 1),
+        ] {
+            assert_eq!(
+                civil_from_days(days_from_civil(date.0, date.1, date.2)),
+                date
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_iso8601() {
+        assert_eq!(
+            format_iso8601(FileTime::from_unix_time(0, 0)),
+            "1970-01-01T00:00:00.000000000Z"
+        );
+        assert_eq!(
+            format_iso8601(FileTime::from_unix_time(1_738_576_800, 1_500)),
+            "2025-02-03T10:00:00.000001500Z"
+        );
+        assert_eq!(
+            format_iso8601(FileTime::from_unix_time(-1, 0)),
+            "1969-12-31T23:59:59.000000000Z"
+        );
+    }
+
+    #[test]
+    fn test_format_epoch_reads_back() {
+        for (seconds, nanos, text) in [
+            (1_738_576_800, 1_500, "@1738576800.000001500"),
+            (1_738_576_800, 0, "@1738576800"),
+            (-2, 500_000_000, "@-1.500000000"),
+            (-1, 250_000_000, "@-0.750000000"),
+        ] {
+            let time = FileTime::from_unix_time(seconds, nanos);
+            assert_eq!(format_epoch(time), text);
+            assert_eq!(parse_date(text, ParseContext::default()).unwrap(), time);
+        }
+    }
+
+    #[test]
+    fn test_parse_touch_stamp() {
+        let context = ParseContext {
+            now: parse("2025-06-15 12:00"),
+            ..utc_context(Locale::English)
+        };
+        let stamp = |input: &str| parse_touch_stamp(input, context).map(|t| t.unix_seconds());
+        assert_eq!(
+            stamp("202501020304").unwrap(),
+            parse("2025-01-02 03:04").unix_seconds()
+        );
+        assert_eq!(
+            stamp("202501020304.05").unwrap(),
+            parse("2025-01-02 03:04:05").unix_seconds()
+        );
+        assert_eq!(
+            stamp("6901020304").unwrap(),
+            parse("1969-01-02 03:04").unix_seconds()
+        );
+        assert_eq!(
+            stamp("6801020304").unwrap(),
+            parse("2068-01-02 03:04").unix_seconds()
+        );
+        assert_eq!(
+            stamp("01020304").unwrap(),
+            parse("2025-01-02 03:04").unix_seconds()
+        );
+        for input in [
+            "2025",
+            "202513020304",
+            "202501020304.5",
+            "2025-1020304",
+            "0102030a",
+        ] {
+            assert!(stamp(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_date_format() {
+        let context = ParseContext {
+            now: parse("2025-06-15 12:34:56"),
+            ..utc_context(Locale::German)
+        };
+        let read = |pattern: &str, input: &str| {
+            DateFormat::new(pattern)
+                .unwrap()
+                .parse(input, context)
+                .map(|t| (t.unix_seconds(), t.nanoseconds()))
+        };
+        let at = |input: &str| {
+            let time = parse(input);
+            (time.unix_seconds(), time.nanoseconds())
+        };
+        assert_eq!(
+            read("%d.%m.%Y %H:%M", "03.02.2025 10:00"),
+            Ok(at("2025-02-03 10:00"))
+        );
+        assert_eq!(
+            read("%d.%m.%Y %H:%M", "3.2.2025  9:05"),
+            Ok(at("2025-02-03 09:05"))
+        );
+        // Two-digit years split at 69, as -t does.
+        assert_eq!(read("%m/%d/%y", "12/31/99"), Ok(at("1999-12-31")));
+        assert_eq!(read("%D", "01/02/68"), Ok(at("2068-01-02")));
+        // Literal text, matched ignoring case.
+        assert_eq!(
+            read(
+                "Exported on %Y-%m-%d at %I:%M %p",
+                "exported ON 2024-03-01 AT 12:15 am"
+            ),
+            Ok(at("2024-03-01 00:15"))
+        );
+        assert_eq!(
+            read("backup_%Y%m%d_%H%M%S.%f", "backup_20240301_131415.25"),
+            Ok((at("2024-03-01 13:14:15").0, 250_000_000))
+        );
+        assert_eq!(
+            read("%a, %e %B %Y %T %z", "Mon,  3 März 2025 10:00:00 +0100"),
+            Ok(at("2025-03-03 09:00"))
+        );
+        assert_eq!(read("%s", "-86400"), Ok((-86_400, 0)));
+        assert_eq!(
+            read("100%% at %F", "100% at 2024-01-01"),
+            Ok(at("2024-01-01"))
+        );
+        // What the pattern leaves out: today's date, the first of a month or year, midnight.
+        assert_eq!(read("%H:%M", "08:30"), Ok(at("2025-06-15 08:30")));
+        assert_eq!(read("%d", "20"), Ok(at("2025-06-20")));
+        assert_eq!(read("%m/%Y", "02/2024"), Ok(at("2024-02-01")));
+        assert_eq!(read("%Y", "2030"), Ok(at("2030-01-01")));
+
+        let err = read("%d.%m.%Y %H:%M", "03/02/2025 10:00").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid date '03/02/2025 10:00': does not match --date-format '%d.%m.%Y %H:%M': expected '.' at character 3"
+        );
+        let err = read("%d.%m.%Y", "03.Feb.2025").unwrap_err();
+        assert!(
+            err.reason()
+                .ends_with("expected a month (%m) at character 4"),
+            "{}",
+            err
+        );
+        let err = read("%Y-%m-%d", "2025-02-03 10:00").unwrap_err();
+        assert!(
+            err.reason()
+                .ends_with("unexpected ' 10:00' at character 11"),
+            "{}",
+            err
+        );
+        for (pattern, input) in [
+            ("%Y-%m-%d", "2025-02-30"),
+            ("%H:%M", "24:00"),
+            ("%I:%M %p", "13:00 PM"),
+            ("%d %b %Y", "3 Foo 2025"),
+            ("%Y", ""),
+        ] {
+            assert!(read(pattern, input).is_err(), "{} {}", pattern, input);
+        }
+        for pattern in ["%d.%m.%Q", "%Y-%m-%", "on the day", "%I:%M", "%H %p"] {
+            assert!(DateFormat::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_parse_filetime() {
+        // 2020-01-01T00:00:00Z is 132223104000000000 ticks.
+        let time = parse_filetime("132223104001234567").unwrap();
+        assert_eq!(time.unix_seconds(), 1_577_836_800);
+        assert_eq!(time.nanoseconds(), 123_456_700);
+        assert_eq!(
+            parse_filetime("0x1D5C03669050000").unwrap().unix_seconds(),
+            1_577_836_800
+        );
+        // Before 1970 the Unix seconds are negative, but the ticks still come through whole.
+        let early = parse_filetime("1").unwrap();
+        assert_eq!(early.unix_seconds(), -FILETIME_EPOCH_OFFSET);
+        assert_eq!(early.nanoseconds(), 100);
+        assert!(parse_filetime("0x7FFFFFFFFFFFFFFF").is_ok());
+        for input in [
+            "",
+            "0",
+            "-1",
+            "0x8000000000000000",
+            "18446744073709551616",
+            "12.5",
+            "0xZZ",
+        ] {
+            assert!(parse_filetime(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_dotnet_ticks() {
+        let context = utc_context(Locale::English);
+        let ticks = |input| parse_dotnet_ticks(input, context);
+        // new DateTime(2020, 1, 1, 0, 0, 0, DateTimeKind.Utc).Ticks
+        let time = ticks("637134336000000000").unwrap();
+        assert_eq!(
+            (time.unix_seconds(), time.nanoseconds()),
+            (1_577_836_800, 0)
+        );
+        // DateTime.UnixEpoch.Ticks, plus 1234567 ticks.
+        let time = ticks("621355968001234567:utc").unwrap();
+        assert_eq!((time.unix_seconds(), time.nanoseconds()), (0, 123_456_700));
+        // DateTime.FromFileTimeUtc(0).Ticks
+        let time = ticks("504911232000000000").unwrap();
+        assert_eq!(time.unix_seconds(), -FILETIME_EPOCH_OFFSET);
+        // The context reads local times as UTC here.
+        assert_eq!(
+            ticks("637134336000000000:local").unwrap(),
+            ticks("637134336000000000").unwrap()
+        );
+        assert!(ticks("3155378975999999999").is_ok());
+        for input in [
+            "504911231999999999",
+            "3155378976000000000",
+            "637134336000000000:est",
+            "-1",
+            "",
+        ] {
+            assert!(ticks(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_time_slots() {
+        let context = utc_context(Locale::English);
+        let at = |input| parse_date(input, context).unwrap();
+        assert_eq!(
+            parse_time_slots("2024-01-01,2024-02-01,2023-12-01", context, None).unwrap(),
+            [
+                Some(at("2024-01-01")),
+                Some(at("2024-02-01")),
+                Some(at("2023-12-01"))
+            ]
+        );
+        assert_eq!(
+            parse_time_slots("-, 2024-02-01 10:00 ,", context, None).unwrap(),
+            [None, Some(at("2024-02-01 10:00")), None]
+        );
+        for input in [
+            "2024-01-01",
+            "2024-01-01,2024-02-01",
+            "-,-,-,-",
+            "-,someday,-",
+        ] {
+            assert!(parse_time_slots(input, context, None).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_humanize() {
+        let now = FileTime::from_unix_time(1_700_000_000, 0);
+        let ago =
+            |seconds: i64| humanize(FileTime::from_unix_time(1_700_000_000 - seconds, 0), now);
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(-4), "just now");
+        assert_eq!(ago(45), "45 seconds ago");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(150), "2 minutes ago");
+        assert_eq!(ago(3 * 3_600 + 59), "3 hours ago");
+        assert_eq!(ago(86_400), "1 day ago");
+        assert_eq!(ago(65 * 86_400), "2 months ago");
+        assert_eq!(ago(800 * 86_400), "2 years ago");
+        assert_eq!(ago(-7_200), "in 2 hours");
+    }
+
+    /// A small deterministic generator (xorshift64*), so the property tests below cover
+    /// thousands of cases, the same ones on every run.
+    struct Cases(u64);
+
+    impl Cases {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        /// A number in `range`.
+        fn within(&mut self, range: std::ops::RangeInclusive<i64>) -> i64 {
+            let span = (range.end() - range.start()) as u64 + 1;
+            range.start() + (self.next() % span) as i64
+        }
+
+        fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+            items[self.next() as usize % items.len()]
+        }
+    }
+
+    #[test]
+    fn test_formatted_times_parse_back() {
+        let mut cases = Cases(0x5EED_0001);
+        let earliest = days_from_civil(0, 1, 1) * 86_400;
+        let latest = days_from_civil(9999, 12, 31) * 86_400 + 86_399;
+        for _ in 0..10_000 {
+            let time = FileTime::from_unix_time(
+                cases.within(earliest..=latest),
+                cases.within(0..=999_999_999) as u32,
+            );
+            let formatted = format_iso8601(time);
+            // The zone in the string wins over whatever the context says.
+            let context = ParseContext {
+                utc: cases.next().is_multiple_of(2),
+                local_offset: Some(cases.within(-50_400..=50_400) as i32),
+                ..utc_context(Locale::English)
+            };
+            assert_eq!(parse_date(&formatted, context), Ok(time), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn test_civil_times_parse_back_in_every_form() {
+        let mut cases = Cases(0x5EED_0002);
+        for _ in 0..10_000 {
+            let year = cases.within(1000..=9999);
+            let month = cases.within(1..=12) as u32;
+            let day = cases.within(1..=i64::from(days_in_month(year, month))) as u32;
+            let (hour, minute, second) = (
+                cases.within(0..=23),
+                cases.within(0..=59),
+                cases.within(0..=59),
+            );
+            let offset = cases.within(-50_400..=50_400) as i32;
+            let context = ParseContext {
+                utc: false,
+                local_offset: Some(offset),
+                ..utc_context(Locale::English)
+            };
+            let expected = FileTime::from_unix_time(
+                days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second
+                    - i64::from(offset),
+                0,
+            );
+            let name = Locale::English.month_names()[month as usize - 1];
+            let forms = [
+                format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    year, month, day, hour, minute, second
+                ),
+                format!(
+                    "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    year, month, day, hour, minute, second
+                ),
+                format!(
+                    "{} {} {} {:02}:{:02}:{:02}",
+                    day, name, year, hour, minute, second
+                ),
+                format!(
+                    "{}, {} {} {:02}:{:02}:{:02}",
+                    name, day, year, hour, minute, second
+                ),
+            ];
+            for form in forms {
+                assert_eq!(parse_date(&form, context), Ok(expected), "{}", form);
+            }
+            let stamp = format!(
+                "{:04}{:02}{:02}{:02}{:02}.{:02}",
+                year, month, day, hour, minute, second
+            );
+            assert_eq!(
+                parse_touch_stamp(&stamp, context),
+                Ok(expected),
+                "{}",
+                stamp
+            );
+        }
+    }
+
+    #[test]
+    fn test_parsers_never_panic() {
+        let context = ParseContext {
+            local_offset: Some(0),
+            ..utc_context(Locale::German)
+        };
+        let parse_all = |input: &str| {
+            let _ = parse_date(input, context);
+            let _ = parse_touch_stamp(input, context);
+            let _ = parse_time_slots(input, context, None);
+            for pattern in ["%d.%m.%Y %H:%M:%S.%f%z", "%e %b %y %I %p", "%s"] {
+                let _ = DateFormat::new(pattern).unwrap().parse(input, context);
+            }
+            let _ = parse_filetime(input);
+            let _ = parse_dotnet_ticks(input, context);
+        };
+        // Inputs at the edges of the number types.
+        for input in [
+            "@-9223372036854775808.5",
+            "@9223372036854775807.999999999",
+            "@99999999999999999999",
+            "1 January 9223372036854775807",
+            "99999999999999999999 March",
+            "9999-12-31T23:59:60.999999999-23:59",
+            "0000-01-01T00:00:00+23:59",
+            "999999999999.99",
+            "0x7FFFFFFFFFFFFFFF:local",
+        ] {
+            parse_all(input);
+        }
+        // Random strings over the characters the parsers look for, and valid dates with
+        // random damage.
+        let alphabet = [
+            '0',
+            '1',
+            '2',
+            '5',
+            '9',
+            '-',
+            '+',
+            ':',
+            '.',
+            ',',
+            '@',
+            'T',
+            'Z',
+            'x',
+            ' ',
+            'a',
+            'M',
+            'ä',
+            'é',
+            '\u{0}',
+            '\u{1F600}',
+        ];
+        let valid = [
+            "2024-02-29T23:59:59.5+01:00",
+            "3. März 2024 10:30",
+            "202402291030.59",
+        ];
+        let mut cases = Cases(0x5EED_0003);
+        for _ in 0..20_000 {
+            let mut input: Vec<char> = if cases.next().is_multiple_of(2) {
+                cases.pick(&valid).chars().collect()
+            } else {
+                Vec::new()
+            };
+            for _ in 0..cases.within(0..=8) {
+                let at = cases.within(0..=input.len() as i64) as usize;
+                let c = cases.pick(&alphabet);
+                match cases.next() % 3 {
+                    0 if at < input.len() => input[at] = c,
+                    1 if at < input.len() => {
+                        input.remove(at);
+                    }
+                    _ => input.insert(at, c),
+                }
+            }
+            parse_all(&input.into_iter().collect::<String>());
+        }
+    }
+}
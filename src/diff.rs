@@ -0,0 +1,359 @@
+//! Comparing the timestamps of two trees (`--diff-trees`).
+//!
+//! Both trees are walked in full and their entries matched up by their path below each
+//! root. An entry found in both is compared on its access, modification and creation
+//! times; a creation time that either side can't report is left out of the comparison.
+//! A difference no larger than the fuzz counts as none, so a copy on a filesystem that
+//! keeps coarser times can still match its original. Neither tree is changed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use mdtouch::FileTime;
+
+use crate::audit::json_string;
+use crate::probe::format_duration;
+use crate::walk::{Found, Walk};
+
+/// One path's place in the comparison.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// In both trees. Each difference is the second tree's time less the first's, in
+    /// nanoseconds; the creation difference is `None` when it couldn't be compared.
+    Both {
+        path: PathBuf,
+        atime: i128,
+        mtime: i128,
+        created: Option<i128>,
+    },
+    /// Only in the first tree.
+    OnlyInFirst(PathBuf),
+    /// Only in the second tree.
+    OnlyInSecond(PathBuf),
+}
+
+/// The comparison of two trees, with entries sorted by path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeDiff {
+    pub first: PathBuf,
+    pub second: PathBuf,
+    pub entries: Vec<Entry>,
+    /// The largest difference that still counts as none, in nanoseconds.
+    pub fuzz: i128,
+}
+
+/// The times of one entry: access, modification and creation.
+type Times = (FileTime, FileTime, Option<FileTime>);
+
+/// Walks and compares the trees at `first` and `second`.
+pub fn diff_trees(first: &Path, second: &Path, fuzz: Duration) -> io::Result<TreeDiff> {
+    let mut first_times = read_tree(first)?;
+    let second_times = read_tree(second)?;
+    let mut entries = Vec::new();
+    for (path, theirs) in second_times {
+        match first_times.remove(&path) {
+            Some(ours) => entries.push(Entry::Both {
+                atime: nanos_between(ours.0, theirs.0),
+                mtime: nanos_between(ours.1, theirs.1),
+                created: ours.2.zip(theirs.2).map(|(a, b)| nanos_between(a, b)),
+                path,
+            }),
+            None => entries.push(Entry::OnlyInSecond(path)),
+        }
+    }
+    entries.extend(first_times.into_keys().map(Entry::OnlyInFirst));
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(TreeDiff {
+        first: first.to_path_buf(),
+        second: second.to_path_buf(),
+        entries,
+        fuzz: fuzz.as_nanos() as i128,
+    })
+}
+
+/// Reads the times of everything below `root`, keyed by path relative to it.
+fn read_tree(root: &Path) -> io::Result<BTreeMap<PathBuf, Times>> {
+    if !fs::metadata(root)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", root.display()),
+        ));
+    }
+    let mut times = BTreeMap::new();
+    // The first path is the root itself, which is not compared.
+    for found in Walk::new(root.to_path_buf()).skip(1) {
+        let found = found.map_err(|(path, e)| {
+            io::Error::new(e.kind(), format!("cannot read {}: {}", path.display(), e))
+        })?;
+        // Links are compared as entries; what they lead to is not walked.
+        let Found::Path(path) = found else {
+            continue;
+        };
+        let metadata = fs::symlink_metadata(&path)?;
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        times.insert(
+            relative,
+            (
+                FileTime::from_last_access_time(&metadata),
+                FileTime::from_last_modification_time(&metadata),
+                FileTime::from_creation_time(&metadata),
+            ),
+        );
+    }
+    Ok(times)
+}
+
+/// How much later `later` is than `earlier`, in nanoseconds.
+fn nanos_between(earlier: FileTime, later: FileTime) -> i128 {
+    (i128::from(later.unix_seconds()) - i128::from(earlier.unix_seconds())) * 1_000_000_000
+        + i128::from(later.nanoseconds())
+        - i128::from(earlier.nanoseconds())
+}
+
+impl Entry {
+    /// The entry's path below each root.
+    pub fn path(&self) -> &Path {
+        match self {
+            Entry::Both { path, .. } | Entry::OnlyInFirst(path) | Entry::OnlyInSecond(path) => path,
+        }
+    }
+}
+
+impl TreeDiff {
+    /// The differences in one entry that go beyond the fuzz, by name.
+    fn differences(&self, entry: &Entry) -> Vec<(&'static str, i128)> {
+        let Entry::Both {
+            atime,
+            mtime,
+            created,
+            ..
+        } = entry
+        else {
+            return Vec::new();
+        };
+        [
+            ("atime", Some(*atime)),
+            ("mtime", Some(*mtime)),
+            ("created", *created),
+        ]
+        .into_iter()
+        .filter_map(|(name, difference)| Some((name, difference?)))
+        .filter(|(_, difference)| difference.abs() > self.fuzz)
+        .collect()
+    }
+
+    /// Whether the entry is in both trees with the same times.
+    fn is_identical(&self, entry: &Entry) -> bool {
+        matches!(entry, Entry::Both { .. }) && self.differences(entry).is_empty()
+    }
+
+    /// Whether every path is in both trees with the same times.
+    pub fn trees_match(&self) -> bool {
+        self.entries.iter().all(|entry| self.is_identical(entry))
+    }
+
+    /// Formats the differences for reading, one path per line and a count at the end.
+    /// Identical paths are only counted.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let (mut identical, mut different, mut only_first, mut only_second) = (0, 0, 0, 0);
+        for entry in &self.entries {
+            let path = entry.path().display();
+            match entry {
+                Entry::Both { .. } => {
+                    let differences = self.differences(entry);
+                    if differences.is_empty() {
+                        identical += 1;
+                        continue;
+                    }
+                    different += 1;
+                    let differences: Vec<String> = differences
+                        .into_iter()
+                        .map(|(name, difference)| {
+                            format!("{} {}", name, format_difference(difference))
+                        })
+                        .collect();
+                    text.push_str(&format!("~ {}: {}\n", path, differences.join(", ")));
+                }
+                Entry::OnlyInFirst(_) => {
+                    only_first += 1;
+                    text.push_str(&format!("- {} (only in {})\n", path, self.first.display()));
+                }
+                Entry::OnlyInSecond(_) => {
+                    only_second += 1;
+                    text.push_str(&format!("+ {} (only in {})\n", path, self.second.display()));
+                }
+            }
+        }
+        text.push_str(&format!(
+            "{} identical, {} different, {} only in {}, {} only in {}\n",
+            identical,
+            different,
+            only_first,
+            self.first.display(),
+            only_second,
+            self.second.display()
+        ));
+        text
+    }
+
+    /// Formats every path as a line of JSON. `status` is `identical`, `different`,
+    /// `only_in_first` or `only_in_second`, and the differences are in nanoseconds, with
+    /// `null` for a creation time that couldn't be compared.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        for entry in &self.entries {
+            let path = json_string(&entry.path().to_string_lossy());
+            match entry {
+                Entry::Both {
+                    atime,
+                    mtime,
+                    created,
+                    ..
+                } => json.push_str(&format!(
+                    "{{\"path\":{},\"status\":\"{}\",\"atime_diff_ns\":{},\"mtime_diff_ns\":{},\"created_diff_ns\":{}}}\n",
+                    path,
+                    if self.is_identical(entry) { "identical" } else { "different" },
+                    atime,
+                    mtime,
+                    created.map_or("null".to_string(), |created| created.to_string()),
+                )),
+                Entry::OnlyInFirst(_) => json.push_str(&format!(
+                    "{{\"path\":{},\"status\":\"only_in_first\"}}\n",
+                    path
+                )),
+                Entry::OnlyInSecond(_) => json.push_str(&format!(
+                    "{{\"path\":{},\"status\":\"only_in_second\"}}\n",
+                    path
+                )),
+            }
+        }
+        json
+    }
+}
+
+/// Formats a difference in nanoseconds with its sign, such as `+2 s`.
+fn format_difference(difference: i128) -> String {
+    let size = u64::try_from(difference.unsigned_abs()).unwrap_or(u64::MAX);
+    let sign = if difference < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_duration(size))
+}
+
+/// Parses a `--fuzz` tolerance: a number of seconds, optionally followed by `s`, `ms`,
+/// `us` or `ns`.
+pub fn parse_fuzz(text: &str) -> io::Result<Duration> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid --fuzz '{}' (expected a number followed by s, ms, us or ns)",
+                text
+            ),
+        )
+    };
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => text.split_at(split),
+        None => (text, "s"),
+    };
+    let count: u64 = number.parse().map_err(|_| invalid())?;
+    match unit {
+        "s" => Ok(Duration::from_secs(count)),
+        "ms" => Ok(Duration::from_millis(count)),
+        "us" => Ok(Duration::from_micros(count)),
+        "ns" => Ok(Duration::from_nanos(count)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_trees_finds_differences_and_missing_paths() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_diff_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (first, second) = (dir.join("original"), dir.join("copy"));
+        for root in [&first, &second] {
+            fs::create_dir_all(root).unwrap();
+            for name in ["same.txt", "changed.txt"] {
+                fs::write(root.join(name), b"").unwrap();
+            }
+        }
+        fs::write(first.join("missing.txt"), b"").unwrap();
+        let time = FileTime::from_unix_time(1_700_000_000, 0);
+        let later = FileTime::from_unix_time(1_700_000_002, 0);
+        for root in [&first, &second] {
+            filetime::set_file_times(root.join("same.txt"), time, time).unwrap();
+            filetime::set_file_times(root.join("changed.txt"), time, time).unwrap();
+        }
+        filetime::set_file_times(second.join("changed.txt"), time, later).unwrap();
+
+        // Creation times can't be set everywhere, so the fuzz covers the moments between
+        // creating the two trees' files.
+        let diff = diff_trees(&first, &second, Duration::from_secs(1)).unwrap();
+        let paths: Vec<&Path> = diff.entries.iter().map(Entry::path).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("changed.txt"),
+                Path::new("missing.txt"),
+                Path::new("same.txt")
+            ]
+        );
+        assert!(matches!(
+            diff.entries[0],
+            Entry::Both {
+                atime: 0,
+                mtime: 2_000_000_000,
+                ..
+            }
+        ));
+        assert_eq!(diff.entries[1], Entry::OnlyInFirst("missing.txt".into()));
+        assert!(diff.is_identical(&diff.entries[2]));
+        assert!(!diff.trees_match());
+
+        let text = diff.to_text();
+        assert!(text.contains("~ changed.txt: mtime +2 s\n"), "{}", text);
+        assert!(text.contains("- missing.txt (only in "), "{}", text);
+        assert!(!text.contains("same.txt"), "{}", text);
+        assert!(
+            text.contains("1 identical, 1 different, 1 only in"),
+            "{}",
+            text
+        );
+        let json = diff.to_json();
+        assert!(json.contains(
+            "{\"path\":\"changed.txt\",\"status\":\"different\",\"atime_diff_ns\":0,\"mtime_diff_ns\":2000000000,"
+        ));
+        assert!(json.contains("{\"path\":\"missing.txt\",\"status\":\"only_in_first\"}\n"));
+        assert!(
+            json.contains("{\"path\":\"same.txt\",\"status\":\"identical\",\"atime_diff_ns\":0,")
+        );
+
+        // Within the fuzz the changed file matches, but the missing one still differs.
+        let fuzzy = diff_trees(&first, &second, Duration::from_secs(2)).unwrap();
+        assert!(fuzzy.is_identical(&fuzzy.entries[0]));
+        assert!(!fuzzy.trees_match());
+        fs::remove_file(first.join("missing.txt")).unwrap();
+        assert!(diff_trees(&first, &second, Duration::from_secs(2))
+            .unwrap()
+            .trees_match());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_fuzz() {
+        assert_eq!(parse_fuzz("2").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_fuzz("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_fuzz("100ms").unwrap(), Duration::from_millis(100));
+        assert_eq!(parse_fuzz("10us").unwrap(), Duration::from_micros(10));
+        assert_eq!(parse_fuzz("100ns").unwrap(), Duration::from_nanos(100));
+        for text in ["", "s", "2h", "1.5s", "-1"] {
+            assert!(parse_fuzz(text).is_err(), "{}", text);
+        }
+    }
+}
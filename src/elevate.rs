@@ -0,0 +1,262 @@
+//! Touching files only an administrator may change.
+//!
+//! A file under a protected directory, such as Program Files, fails to be touched with a
+//! bare "Access is denied" unless the process is elevated. That failure is explained with
+//! a hint to elevate, and `--elevate` does it: the same command line is run again in an
+//! elevated process, through the UAC prompt, and its exit status becomes this one's.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// The exit status of the elevated run, carried back to `main` as the inner error of an
+/// [`io::Error`]. It has no message of its own: the elevated run printed its own.
+#[derive(Debug)]
+pub struct ChildExit(pub u8);
+
+impl fmt::Display for ChildExit {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl Error for ChildExit {}
+
+/// Returns the hint to add to the error `e` from touching a file, if elevating would
+/// likely help: access was denied, the file is under the `protected` directory, and the
+/// process is not `elevated` already.
+pub fn hint(e: &io::Error, protected: Option<&Path>, elevated: bool) -> Option<String> {
+    if e.kind() != io::ErrorKind::PermissionDenied || elevated {
+        return None;
+    }
+    let protected = protected?;
+    Some(if cfg!(windows) {
+        format!(
+            " ({} needs administrator rights: run mdtouch from an elevated prompt, or add --elevate)",
+            protected.display()
+        )
+    } else {
+        format!(
+            " ({} needs root: run mdtouch with sudo)",
+            protected.display()
+        )
+    })
+}
+
+/// Drops `--elevate` from the arguments, so the elevated run does not relaunch itself in
+/// turn. Operands after `--` are kept as they are.
+pub fn without_elevate(args: Vec<OsString>) -> Vec<OsString> {
+    let mut options = true;
+    args.into_iter()
+        .filter(|arg| {
+            options &= arg != "--";
+            !(options && arg == "--elevate")
+        })
+        .collect()
+}
+
+/// Joins arguments into a Windows command line, quoting them so that the runtime splits
+/// them back into the same arguments.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn command_line(args: &[OsString]) -> String {
+    let mut line = String::new();
+    for arg in args {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        let arg = arg.to_string_lossy();
+        if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\u{b}', '"']) {
+            line.push_str(&arg);
+            continue;
+        }
+        // Backslashes are only special before a quote, where each must be doubled.
+        line.push('"');
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => backslashes += 1,
+                '"' => {
+                    line.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                    line.push('"');
+                    backslashes = 0;
+                }
+                _ => {
+                    line.extend(std::iter::repeat_n('\\', backslashes));
+                    line.push(c);
+                    backslashes = 0;
+                }
+            }
+        }
+        line.extend(std::iter::repeat_n('\\', backslashes * 2));
+        line.push('"');
+    }
+    line
+}
+
+/// Runs mdtouch again, elevated, with `args`, in the current directory, and waits for it.
+/// Fails with [`ChildExit`] inside when the elevated run did not succeed.
+pub fn relaunch(args: &[OsString]) -> io::Result<()> {
+    match platform::relaunch(args)? {
+        0 => Ok(()),
+        code => Err(io::Error::other(ChildExit(code))),
+    }
+}
+
+/// Whether this process already runs with administrator rights (root, outside Windows).
+pub fn is_elevated() -> bool {
+    platform::is_elevated()
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::{OsStr, OsString};
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, GetExitCodeProcess, OpenProcessToken, WaitForSingleObject, INFINITE,
+    };
+    use windows_sys::Win32::UI::Shell::{
+        ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW,
+    };
+
+    fn wide(text: &OsStr) -> Vec<u16> {
+        text.encode_wide().chain(Some(0)).collect()
+    }
+
+    pub fn is_elevated() -> bool {
+        let mut token: HANDLE = std::ptr::null_mut();
+        // SAFETY: the pseudo handle needs no closing; the token is closed below.
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut size = 0;
+        // SAFETY: the buffer is a TOKEN_ELEVATION, passed with its size.
+        let ok = unsafe {
+            GetTokenInformation(
+                token,
+                TokenElevation,
+                (&mut elevation as *mut TOKEN_ELEVATION).cast(),
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut size,
+            )
+        } != 0;
+        // SAFETY: the token was opened above and is not used after this.
+        unsafe { CloseHandle(token) };
+        ok && elevation.TokenIsElevated != 0
+    }
+
+    pub fn relaunch(args: &[OsString]) -> io::Result<u8> {
+        let program = wide(std::env::current_exe()?.as_os_str());
+        let parameters = wide(OsStr::new(&super::command_line(args)));
+        // An elevated process starts in the system directory unless told otherwise.
+        let directory = wide(std::env::current_dir()?.as_os_str());
+        let verb = wide(OsStr::new("runas"));
+        // SAFETY: all-zero is a valid SHELLEXECUTEINFOW; the fields used are set below.
+        let mut info: SHELLEXECUTEINFOW = unsafe { std::mem::zeroed() };
+        info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as u32;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = program.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.lpDirectory = directory.as_ptr();
+        // SW_SHOWNORMAL: the elevated run gets a console window of its own.
+        info.nShow = 1;
+        // SAFETY: the strings are NUL-terminated and outlive the call. Declining the UAC
+        // prompt fails here with ERROR_CANCELLED.
+        if unsafe { ShellExecuteExW(&mut info) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let process = info.hProcess;
+        // SAFETY: SEE_MASK_NOCLOSEPROCESS hands over the process handle, closed below.
+        let code = unsafe {
+            WaitForSingleObject(process, INFINITE);
+            let mut code = 1;
+            GetExitCodeProcess(process, &mut code);
+            CloseHandle(process);
+            code
+        };
+        Ok(u8::try_from(code).unwrap_or(1))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::ffi::OsString;
+    use std::io;
+
+    pub fn is_elevated() -> bool {
+        #[cfg(unix)]
+        {
+            // SAFETY: plain call with no arguments.
+            unsafe { libc::geteuid() == 0 }
+        }
+        #[cfg(not(unix))]
+        {
+            false
+        }
+    }
+
+    pub fn relaunch(_args: &[OsString]) -> io::Result<u8> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Option --elevate is only supported on Windows; run mdtouch with sudo instead",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_hint_only_when_elevating_would_help() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let protected = Some(Path::new("protected"));
+        let hint = hint(&denied, protected, false).unwrap();
+        assert!(hint.contains("protected needs"), "{}", hint);
+        // Already elevated, outside a protected directory, or failing for another reason:
+        // elevating would not help.
+        assert!(super::hint(&denied, protected, true).is_none());
+        assert!(super::hint(&denied, None, false).is_none());
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(super::hint(&missing, protected, false).is_none());
+    }
+
+    #[test]
+    fn test_without_elevate() {
+        assert_eq!(
+            without_elevate(args(&["--elevate", "-d", "now", "a", "--", "--elevate"])),
+            args(&["-d", "now", "a", "--", "--elevate"])
+        );
+    }
+
+    #[test]
+    fn test_command_line_quoting() {
+        assert_eq!(
+            command_line(&args(&[
+                "-d",
+                "2024-01-01 10:00",
+                "",
+                r"C:\Program Files\",
+                r#"a"b"#
+            ])),
+            r#"-d "2024-01-01 10:00" "" "C:\Program Files\\" "a\"b""#
+        );
+        assert_eq!(
+            command_line(&args(&[r"C:\dir\file.txt"])),
+            r"C:\dir\file.txt"
+        );
+    }
+}
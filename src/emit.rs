@@ -0,0 +1,196 @@
+//! Writing out the command a run comes to (`--emit-command`).
+//!
+//! Before touching anything, the run writes a command line that does the same to the same
+//! files with nothing left to work out, so it can be kept and run again exactly. The files
+//! are listed as wildcards, file lists and `-R` found them, each made absolute, with
+//! `--no-glob` so none is expanded again. The times are given with `--times` as seconds
+//! since the epoch, so `-d now` or `-r FILE` become the times they stood for, and `-` for
+//! a time left alone. Of the other options, those that change what is done to each file,
+//! such as `--owner` or `--exec`, are kept; those that only choose, order or report files,
+//! or say how the run goes, such as `--exclude`, `-v` or `--jobs`, are left out. Options
+//! that give each file times of its own, or need the reference file the command no
+//! longer names, can't be written this way, so the run refuses them.
+//!
+//! A file whose name isn't valid Unicode can't be written on the command line as it is,
+//! so no command is written for a run that has one.
+//!
+//! Words are quoted only where the shell needs it: in single quotes elsewhere than
+//! Windows, in double quotes on Windows.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use mdtouch::date::format_epoch;
+use mdtouch::{BrokenLinkPolicy, FileTime, TouchOptions};
+
+use crate::Options;
+
+/// Where `--emit-command` writes the command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Destination {
+    /// Standard error, so the command is kept apart from what the run prints.
+    Stderr,
+    /// A file, replaced if it exists.
+    File(PathBuf),
+}
+
+/// The words of the command that touches `operands` with `times`, and `created` as the
+/// creation time, as `options` would. Fails if an operand's name isn't valid Unicode.
+pub fn equivalent_command(
+    options: &Options,
+    times: &TouchOptions,
+    created: Option<FileTime>,
+    operands: &[PathBuf],
+) -> io::Result<Vec<String>> {
+    let flags = [
+        (options.no_create, "-c"),
+        (options.exclusive, "--exclusive"),
+        (options.empty, "--empty"),
+        (options.no_dereference, "--no-dereference"),
+        (options.flush, "--flush"),
+        (options.atomic_create, "--atomic-create"),
+        (options.mkdir, "--mkdir"),
+        (options.parents, "--parents"),
+        (options.lockfile, "--lockfile"),
+        (options.real_creation, "--real-creation"),
+        (options.creation_from_mtime, "--creation-from-mtime"),
+        (
+            options.create_time_monotonic,
+            "--force-create-time-monotonic",
+        ),
+        (options.only_newer, "--only-newer"),
+        (options.if_content_changed, "--if-content-changed"),
+        (options.owner_existing, "--owner-existing"),
+        (options.exec_shell, "--exec-shell"),
+    ];
+    let mut words = vec!["mdtouch".to_string()];
+    words.extend(
+        flags
+            .into_iter()
+            .filter(|(given, _)| *given)
+            .map(|(_, flag)| flag.to_string()),
+    );
+    if let Some(suffix) = &options.backup {
+        words.push(format!("--backup={}", suffix));
+    }
+    if let Some(age) = options.steal_stale {
+        words.push(format!("--steal-stale={}s", age.as_secs()));
+    }
+    if let Some(owner) = &options.owner {
+        words.extend(["--owner".to_string(), owner.clone()]);
+    }
+    if let Some(command) = &options.exec {
+        words.extend(["--exec".to_string(), command.clone()]);
+    }
+    match options.broken_link {
+        BrokenLinkPolicy::Error => {}
+        BrokenLinkPolicy::Skip => words.push("--broken-link=skip".to_string()),
+        BrokenLinkPolicy::CreateTarget => words.push("--broken-link=create-target".to_string()),
+    }
+    let slot = |time: Option<FileTime>| time.map_or("-".to_string(), format_epoch);
+    words.push("--times".to_string());
+    words.push(format!(
+        "{},{},{}",
+        slot(times.atime),
+        slot(times.mtime),
+        slot(created)
+    ));
+    words.push("--no-glob".to_string());
+    for operand in operands {
+        let absolute = std::path::absolute(operand).unwrap_or_else(|_| operand.clone());
+        match absolute.into_os_string().into_string() {
+            Ok(word) => words.push(word),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                    "Cannot write a command for {}: its name is not valid Unicode (--emit-command)",
+                    operand.display()
+                ),
+                ))
+            }
+        }
+    }
+    Ok(words)
+}
+
+/// Writes the command made of `words` to `destination`, on one line.
+pub fn write(destination: &Destination, words: &[String]) -> io::Result<()> {
+    let line = words
+        .iter()
+        .map(|word| quote(word))
+        .collect::<Vec<_>>()
+        .join(" ");
+    match destination {
+        Destination::Stderr => {
+            eprintln!("{}", line);
+            Ok(())
+        }
+        Destination::File(path) => fs::write(path, line + "\n").map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Error writing the command to {}: {}", path.display(), e),
+            )
+        }),
+    }
+}
+
+/// Quotes `word` for the shell, if it has anything the shell would read differently.
+fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    if cfg!(windows) {
+        // Quotes cannot appear in Windows file names, so surrounding the word is enough.
+        format!("\"{}\"", word)
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equivalent_command() {
+        let options = Options {
+            no_create: true,
+            broken_link: BrokenLinkPolicy::Skip,
+            owner: Some("www-data".to_string()),
+            owner_existing: true,
+            ..Options::default()
+        };
+        let time = FileTime::from_unix_time(1_700_000_000, 5);
+        let times = TouchOptions {
+            mtime: Some(time),
+            ..TouchOptions::default()
+        };
+        let operand = std::env::temp_dir().join("a.txt");
+        assert_eq!(
+            equivalent_command(&options, &times, None, std::slice::from_ref(&operand)).unwrap(),
+            [
+                "mdtouch",
+                "-c",
+                "--owner-existing",
+                "--owner",
+                "www-data",
+                "--broken-link=skip",
+                "--times",
+                "-,@1700000000.000000005,-",
+                "--no-glob",
+                &operand.to_string_lossy(),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("/tmp/a-1.txt"), "/tmp/a-1.txt");
+        assert_eq!(quote("/tmp/it's here"), r"'/tmp/it'\''s here'");
+        assert_eq!(quote(""), "''");
+    }
+}
@@ -0,0 +1,285 @@
+//! Decoding file lists that are not UTF-8 (`--input-encoding`).
+//!
+//! Lists piped from other Windows tools are often UTF-16, and older ones write the ANSI
+//! code page. Either way the list is turned into UTF-8 as it is read, so the entries are
+//! then split and converted to paths exactly as for a UTF-8 list. A byte order mark at
+//! the start is dropped; without an encoding given, it is also what tells UTF-16 apart.
+
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+/// The encoding of a file list.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum InputEncoding {
+    /// Told by the byte order mark, if there is one, and otherwise UTF-8.
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// The ANSI code page on Windows. Elsewhere the bytes are taken as they are, as for
+    /// any other list.
+    Ansi,
+}
+
+impl InputEncoding {
+    /// Parses the value of `--input-encoding`.
+    pub fn parse(value: &str) -> io::Result<Self> {
+        match value.to_ascii_lowercase().replace('-', "").as_str() {
+            "auto" => Ok(InputEncoding::Auto),
+            "utf8" => Ok(InputEncoding::Utf8),
+            "utf16le" => Ok(InputEncoding::Utf16Le),
+            "utf16be" => Ok(InputEncoding::Utf16Be),
+            "ansi" => Ok(InputEncoding::Ansi),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --input-encoding '{}' (expected utf8, utf16le, utf16be or ansi)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+/// The byte order marks, longest first, with the encoding each one marks.
+const BOMS: [(&[u8], InputEncoding); 3] = [
+    (b"\xEF\xBB\xBF", InputEncoding::Utf8),
+    (b"\xFF\xFE", InputEncoding::Utf16Le),
+    (b"\xFE\xFF", InputEncoding::Utf16Be),
+];
+
+/// Wraps `reader` so that it yields the list as UTF-8, without a byte order mark.
+pub fn decode(
+    mut reader: Box<dyn BufRead>,
+    encoding: InputEncoding,
+) -> io::Result<Box<dyn BufRead>> {
+    // A pipe may hand over the mark a byte at a time, so read until there are enough.
+    let mut start = Vec::with_capacity(3);
+    while start.len() < 3 {
+        let read = reader
+            .by_ref()
+            .take(3 - start.len() as u64)
+            .read_to_end(&mut start)?;
+        if read == 0 {
+            break;
+        }
+    }
+    let bom = BOMS
+        .iter()
+        .find(|(bom, marked)| {
+            start.starts_with(bom) && (encoding == InputEncoding::Auto || encoding == *marked)
+        })
+        .copied();
+    let (skip, encoding) = match (bom, encoding) {
+        (Some((bom, marked)), _) => (bom.len(), marked),
+        (None, InputEncoding::Auto) => (0, InputEncoding::Utf8),
+        (None, encoding) => (0, encoding),
+    };
+    start.drain(..skip);
+    let reader = Cursor::new(start).chain(reader);
+    Ok(match encoding {
+        InputEncoding::Utf16Le => Box::new(BufReader::new(Utf16Reader::new(reader, false))),
+        InputEncoding::Utf16Be => Box::new(BufReader::new(Utf16Reader::new(reader, true))),
+        #[cfg(windows)]
+        InputEncoding::Ansi => Box::new(BufReader::new(AnsiReader::new(reader))),
+        _ => Box::new(BufReader::new(reader)),
+    })
+}
+
+/// Turns UTF-16 into UTF-8. Unpaired surrogates become U+FFFD, as does a stray byte at
+/// the very end.
+struct Utf16Reader<R> {
+    inner: R,
+    big_endian: bool,
+    /// A byte left over from the last read, waiting for the other half of its unit.
+    odd_byte: Option<u8>,
+    /// A high surrogate left over from the last read, waiting for its low half.
+    high_surrogate: Option<u16>,
+    /// Decoded text not yet handed out.
+    decoded: Cursor<Vec<u8>>,
+}
+
+impl<R: Read> Utf16Reader<R> {
+    fn new(inner: R, big_endian: bool) -> Self {
+        Utf16Reader {
+            inner,
+            big_endian,
+            odd_byte: None,
+            high_surrogate: None,
+            decoded: Cursor::new(Vec::new()),
+        }
+    }
+
+    /// Decodes the next chunk of input into `decoded`. Returns `false` at the end.
+    fn decode_more(&mut self) -> io::Result<bool> {
+        let mut bytes = Vec::with_capacity(8 * 1024 + 1);
+        bytes.extend(self.odd_byte.take());
+        let read = (&mut self.inner).take(8 * 1024).read_to_end(&mut bytes)?;
+        let mut units: Vec<u16> = Vec::with_capacity(bytes.len() / 2 + 1);
+        units.extend(self.high_surrogate.take());
+        let mut pairs = bytes.chunks_exact(2);
+        units.extend(pairs.by_ref().map(|pair| {
+            let pair = [pair[0], pair[1]];
+            if self.big_endian {
+                u16::from_be_bytes(pair)
+            } else {
+                u16::from_le_bytes(pair)
+            }
+        }));
+        let at_end = read == 0;
+        self.odd_byte = pairs.remainder().first().copied().filter(|_| !at_end);
+        if !at_end
+            && units
+                .last()
+                .is_some_and(|unit| (0xD800..0xDC00).contains(unit))
+        {
+            self.high_surrogate = units.pop();
+        }
+        let mut text: String = char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+        if at_end && !pairs.remainder().is_empty() {
+            text.push(char::REPLACEMENT_CHARACTER);
+        }
+        self.decoded = Cursor::new(text.into_bytes());
+        Ok(!at_end || !self.decoded.get_ref().is_empty())
+    }
+}
+
+impl<R: Read> Read for Utf16Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.decoded.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            if !self.decode_more()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Turns text in the ANSI code page into UTF-8, a line at a time. Newlines and NULs
+/// never occur inside a multibyte character in a Windows code page, so lines can be
+/// converted on their own.
+#[cfg(windows)]
+struct AnsiReader<R> {
+    inner: BufReader<R>,
+    decoded: Cursor<Vec<u8>>,
+}
+
+#[cfg(windows)]
+impl<R: Read> AnsiReader<R> {
+    fn new(inner: R) -> Self {
+        AnsiReader {
+            inner: BufReader::new(inner),
+            decoded: Cursor::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl<R: Read> Read for AnsiReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use windows_sys::Win32::Globalization::{MultiByteToWideChar, CP_ACP};
+
+        let read = self.decoded.read(buf)?;
+        if read > 0 || buf.is_empty() {
+            return Ok(read);
+        }
+        let mut line = Vec::new();
+        if self.inner.read_until(b'\n', &mut line)? == 0 {
+            return Ok(0);
+        }
+        let len = i32::try_from(line.len()).map_err(|_| io::Error::other("line too long"))?;
+        // Every byte becomes at most one UTF-16 unit.
+        let mut wide = vec![0u16; line.len()];
+        // SAFETY: both buffers are passed with their lengths.
+        let units =
+            unsafe { MultiByteToWideChar(CP_ACP, 0, line.as_ptr(), len, wide.as_mut_ptr(), len) };
+        if units == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        wide.truncate(units as usize);
+        self.decoded = Cursor::new(String::from_utf16_lossy(&wide).into_bytes());
+        self.decoded.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(bytes: &[u8], encoding: InputEncoding) -> String {
+        let reader: Box<dyn BufRead> = Box::new(Cursor::new(bytes.to_vec()));
+        let mut text = String::new();
+        decode(reader, encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = if big_endian {
+            vec![0xFE, 0xFF]
+        } else {
+            vec![0xFF, 0xFE]
+        };
+        for unit in text.encode_utf16() {
+            bytes.extend(if big_endian {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            });
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_a_bom_tells_the_encoding() {
+        let text = "a.txt\r\nrésumé 😀.txt\r\n";
+        for encoding in [InputEncoding::Auto, InputEncoding::Utf16Le] {
+            assert_eq!(decoded(&utf16(text, false), encoding), text);
+        }
+        for encoding in [InputEncoding::Auto, InputEncoding::Utf16Be] {
+            assert_eq!(decoded(&utf16(text, true), encoding), text);
+        }
+        let mut utf8 = b"\xEF\xBB\xBF".to_vec();
+        utf8.extend(text.as_bytes());
+        assert_eq!(decoded(&utf8, InputEncoding::Auto), text);
+        assert_eq!(decoded(&utf8, InputEncoding::Utf8), text);
+        // Without a BOM, UTF-8 is assumed unless told otherwise.
+        assert_eq!(decoded(text.as_bytes(), InputEncoding::Auto), text);
+        let bare = &utf16(text, false)[2..];
+        assert_eq!(decoded(bare, InputEncoding::Utf16Le), text);
+        assert_eq!(decoded(b"", InputEncoding::Auto), "");
+        assert_eq!(decoded(b"x", InputEncoding::Auto), "x");
+    }
+
+    #[test]
+    fn test_utf16_split_across_reads() {
+        // Long enough that units, and the surrogate pairs of the emoji, straddle chunks.
+        let text = "x😀".repeat(5_000);
+        assert_eq!(decoded(&utf16(&text, false), InputEncoding::Auto), text);
+        // A lone surrogate and a dangling byte are replaced rather than failing.
+        let mut broken = utf16("a", false);
+        broken.extend([0x00, 0xD8, b'b', 0x00, 0x41]);
+        assert_eq!(decoded(&broken, InputEncoding::Auto), "a\u{FFFD}b\u{FFFD}");
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(
+            InputEncoding::parse("utf16le").unwrap(),
+            InputEncoding::Utf16Le
+        );
+        assert_eq!(
+            InputEncoding::parse("UTF-16BE").unwrap(),
+            InputEncoding::Utf16Be
+        );
+        assert_eq!(InputEncoding::parse("ansi").unwrap(), InputEncoding::Ansi);
+        assert!(InputEncoding::parse("latin1").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+//! The record of operands that could not be touched (`--error-log`).
+//!
+//! Each failure gets one line, the time it was reported in UTC followed by the same
+//! message standard error shows:
+//!
+//! ```text
+//! 2025-02-03T10:00:00.000000000Z Error touching C:\logs\app.log: Access is denied. (os error 5)
+//! ```
+//!
+//! The log is opened like the audit log, for appending and shared with other writers, but
+//! buffered: lines are written as failures come in and flushed when the run ends.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use mdtouch::date::format_iso8601;
+use mdtouch::FileTime;
+
+use crate::audit::open_for_appending;
+
+/// An error log open for appending.
+#[derive(Debug)]
+pub struct ErrorLog {
+    file: BufWriter<File>,
+}
+
+impl ErrorLog {
+    /// Opens the log at `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(ErrorLog {
+            file: BufWriter::new(open_for_appending(path)?),
+        })
+    }
+
+    /// Adds the line for one failure. A message that runs over several lines is joined
+    /// into one.
+    pub fn record(&mut self, message: &str) -> io::Result<()> {
+        let message = message.replace(['\r', '\n'], " ");
+        writeln!(self.file, "{} {}", format_iso8601(FileTime::now()), message)
+    }
+
+    /// Writes out any lines still buffered.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_record_appends_one_line_per_failure() {
+        let path = std::env::temp_dir().join(format!("mdtouch_errors_{}.log", std::process::id()));
+        fs::write(&path, "earlier\n").unwrap();
+        let mut log = ErrorLog::open(&path).unwrap();
+        log.record("Error touching a: denied").unwrap();
+        log.record("Error touching b:\nsplit").unwrap();
+        log.finish().unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "earlier");
+        let (time, message) = lines[1].split_once(' ').unwrap();
+        assert!(time.ends_with('Z') && time.len() == 30, "{}", time);
+        assert_eq!(message, "Error touching a: denied");
+        assert!(lines[2].ends_with(" Error touching b: split"));
+        fs::remove_file(&path).unwrap();
+    }
+}
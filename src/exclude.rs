@@ -0,0 +1,126 @@
+//! Leaving paths out of wildcard and `-R` expansion by pattern (`--exclude`,
+//! `--exclude-from`).
+//!
+//! A pattern uses the same wildcards as operands: `*`, `?` and `[...]` classes, matching
+//! within one path component, with case ignored on Windows unless `--case-sensitive` is
+//! given. A pattern without a `/`, such as `*.tmp` or `node_modules`, is matched against
+//! the name of every path below the operand, however deep. One with a `/` before its end,
+//! such as `build/cache` or `/docs`, is matched against the path below the operand from
+//! its start, a component at a time, with `/` between components on every platform. A
+//! trailing `/` makes no difference. Either way, a directory that is excluded is left out
+//! along with everything below it. An operand named outright is never excluded.
+//!
+//! `--exclude-from` reads patterns from a file, one per line. Blank lines and lines
+//! starting with `#` are skipped, and Windows line endings are fine.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+use crate::glob::name_matches;
+
+/// One exclusion pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    /// Matched against the name of each path, at any depth.
+    Name(String),
+    /// Matched against the path below the operand, one pattern per component.
+    Path(Vec<String>),
+}
+
+/// The patterns that leave paths out of an expansion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Excludes {
+    patterns: Vec<Pattern>,
+    ignore_case: bool,
+}
+
+impl Excludes {
+    /// Reads `patterns`, to be matched ignoring case if `ignore_case` is set.
+    pub fn new(patterns: &[String], ignore_case: bool) -> Self {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let separators = ['/', std::path::MAIN_SEPARATOR];
+                let pattern = pattern.trim_end_matches(separators);
+                if !pattern.contains(separators) {
+                    return Pattern::Name(pattern.to_string());
+                }
+                Pattern::Path(
+                    pattern
+                        .split(separators)
+                        .filter(|component| !component.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            })
+            .collect();
+        Excludes {
+            patterns,
+            ignore_case,
+        }
+    }
+
+    /// Returns whether `path`, found by expanding `root`, is left out: it, or a directory
+    /// between it and `root`, matches one of the patterns.
+    pub fn excludes(&self, path: &Path, root: &Path) -> bool {
+        let below: Vec<String> = path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let matches = |pattern: &str, name: &str| name_matches(pattern, name, self.ignore_case);
+        self.patterns.iter().any(|pattern| match pattern {
+            Pattern::Name(pattern) => below.iter().any(|name| matches(pattern, name)),
+            Pattern::Path(patterns) => {
+                patterns.len() <= below.len()
+                    && patterns
+                        .iter()
+                        .zip(&below)
+                        .all(|(pattern, name)| matches(pattern, name))
+            }
+        })
+    }
+}
+
+/// Reads the patterns in the `--exclude-from` file `path`.
+pub fn read_patterns(path: &Path) -> io::Result<Vec<String>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excludes() {
+        let patterns = ["*.tmp", "build/cache", "/docs/"].map(String::from);
+        let excludes = Excludes::new(&patterns, false);
+        let root = Path::new("project");
+        let excluded = |below: &str| excludes.excludes(&root.join(below), root);
+        assert!(excluded("a.tmp"));
+        assert!(excluded("src/deep/a.tmp"));
+        assert!(excluded("build/cache"));
+        assert!(excluded("build/cache/objects/1.o"));
+        assert!(excluded("docs/index.md"));
+        assert!(!excluded("src/build/cache"));
+        assert!(!excluded("build/cached"));
+        assert!(!excluded("src/docs"));
+        assert!(!excluded("a.TMP"));
+        assert!(!excludes.excludes(root, root));
+
+        let excludes = Excludes::new(&patterns, true);
+        assert!(excludes.excludes(&root.join("a.TMP"), root));
+    }
+}
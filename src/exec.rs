@@ -0,0 +1,258 @@
+//! Running a command for each touched file (`--exec`).
+//!
+//! The command is split into words as a command line would be, with double or single
+//! quotes around words containing spaces, and every `{}` in a word is replaced by the
+//! path. The first word is the program, started directly with the rest as its arguments,
+//! so a path with spaces or quotes reaches it as one argument without any quoting. With
+//! `--exec-shell` the command goes to the shell instead (`cmd /C` on Windows, `sh -c`
+//! elsewhere), with `{}` replaced by the path quoted for that shell, so redirections and
+//! pipes work. cmd expands `%NAME%`, and `!NAME!` where delayed expansion is on, even
+//! inside quotes, and has no escape for them there, so a path with `%` or `!` in it fails
+//! its file rather than being handed to cmd.
+//!
+//! A command that exits with a non-zero status fails the file it ran for.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// The placeholder replaced by the path.
+const PLACEHOLDER: &str = "{}";
+
+/// A command to run for each touched file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exec {
+    /// The words of the command, started without a shell.
+    Direct(Vec<String>),
+    /// The command line, run by the shell.
+    Shell(String),
+}
+
+impl Exec {
+    /// Reads the command given to `--exec`, to be run by the shell with `shell`.
+    pub fn parse(command: &str, shell: bool) -> io::Result<Self> {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid --exec command '{}': {}", command, reason),
+            )
+        };
+        if command.trim().is_empty() {
+            return Err(invalid("no command given"));
+        }
+        if shell {
+            return Ok(Exec::Shell(command.to_string()));
+        }
+        split_words(command)
+            .map(Exec::Direct)
+            .ok_or_else(|| invalid("unclosed quote"))
+    }
+
+    /// Builds the command to run for `path`.
+    fn command(&self, path: &Path) -> io::Result<Command> {
+        let path = path.as_os_str();
+        Ok(match self {
+            Exec::Direct(words) => {
+                let mut words = words.iter().map(|word| substitute(word, path.to_owned()));
+                let mut command = Command::new(words.next().unwrap_or_default());
+                command.args(words);
+                command
+            }
+            Exec::Shell(line) => shell_command(&substitute(line, shell_quote(path)?)),
+        })
+    }
+
+    /// Runs the command for `path` and waits for it, failing unless it exits with status 0.
+    pub fn run(&self, path: &Path) -> io::Result<()> {
+        let program = match self {
+            Exec::Direct(words) => words[0].as_str(),
+            Exec::Shell(_) => "the shell",
+        };
+        let status = self.command(path)?.status().map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot run {} for --exec: {}", program, e),
+            )
+        })?;
+        if status.success() {
+            return Ok(());
+        }
+        Err(io::Error::other(match status.code() {
+            Some(code) => format!("--exec command exited with status {}", code),
+            None => "--exec command was killed".to_string(),
+        }))
+    }
+}
+
+/// Replaces each `{}` in `word` with `path`.
+fn substitute(word: &str, path: OsString) -> OsString {
+    let mut parts = word.split(PLACEHOLDER);
+    let mut result = OsString::from(parts.next().unwrap_or_default());
+    for part in parts {
+        result.push(&path);
+        result.push(part);
+    }
+    result
+}
+
+/// Splits a command into words at unquoted whitespace. Double or single quotes keep
+/// whitespace in a word and are removed. Returns `None` for an unclosed quote.
+fn split_words(command: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Quotes `path` so the shell reads it back as one word. Fails for a path cmd would
+/// expand variables in.
+#[cfg(windows)]
+fn shell_quote(path: &std::ffi::OsStr) -> io::Result<OsString> {
+    check_cmd_safe(path)?;
+    // Quotes cannot appear in Windows file names, so surrounding the path is enough.
+    let mut quoted = OsString::from("\"");
+    quoted.push(path);
+    quoted.push("\"");
+    Ok(quoted)
+}
+
+/// Fails if `path` has a `%` or `!` in it, which cmd would read as the start of a
+/// variable even between quotes.
+#[cfg(any(windows, test))]
+fn check_cmd_safe(path: &std::ffi::OsStr) -> io::Result<()> {
+    if !path.to_string_lossy().contains(['%', '!']) {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "cannot pass {} to cmd for --exec-shell: cmd would expand the % or ! in its name (use --exec without --exec-shell)",
+            Path::new(path).display()
+        ),
+    ))
+}
+
+/// Quotes `path` so the shell reads it back as one word.
+#[cfg(unix)]
+fn shell_quote(path: &std::ffi::OsStr) -> io::Result<OsString> {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let mut quoted = vec![b'\''];
+    for &byte in path.as_bytes() {
+        if byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    Ok(OsString::from_vec(quoted))
+}
+
+/// Quotes `path` so the shell reads it back as one word.
+#[cfg(not(any(unix, windows)))]
+fn shell_quote(path: &std::ffi::OsStr) -> io::Result<OsString> {
+    Ok(format!("'{}'", path.to_string_lossy().replace('\'', r"'\''")).into())
+}
+
+/// The shell command that runs `line`.
+#[cfg(windows)]
+fn shell_command(line: &std::ffi::OsStr) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("cmd");
+    // cmd.exe does its own parsing of the line after /C, so it goes through as it is. With
+    // /S, only the outer quotes added here are taken off, whatever quotes the line has.
+    let mut quoted = OsString::from("\"");
+    quoted.push(line);
+    quoted.push("\"");
+    command.raw_arg("/S /C").raw_arg(quoted);
+    command
+}
+
+/// The shell command that runs `line`.
+#[cfg(not(windows))]
+fn shell_command(line: &std::ffi::OsStr) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#"notify --file "{}" 'a b' ""  x"#).unwrap(),
+            ["notify", "--file", "{}", "a b", "", "x"]
+        );
+        assert_eq!(split_words("  ").unwrap(), Vec::<String>::new());
+        assert!(split_words("echo \"open").is_none());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let path = OsString::from("dir/with space.txt");
+        assert_eq!(substitute("{}", path.clone()), path);
+        assert_eq!(
+            substitute("--file={}.bak", path),
+            OsString::from("--file=dir/with space.txt.bak")
+        );
+        assert_eq!(
+            substitute("plain", OsString::new()),
+            OsString::from("plain")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(
+            shell_quote(std::ffi::OsStr::new("it's here.txt")).unwrap(),
+            OsString::from(r"'it'\''s here.txt'")
+        );
+    }
+
+    #[test]
+    fn test_check_cmd_safe() {
+        assert!(check_cmd_safe(std::ffi::OsStr::new(r"C:\logs\a b^c.txt")).is_ok());
+        for name in [r"C:\logs\%PATH%.txt", r"C:\logs\!x!.txt", "50%.txt"] {
+            let err = check_cmd_safe(std::ffi::OsStr::new(name)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains("--exec-shell"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Exec::parse("echo {}", false).unwrap(),
+            Exec::Direct(vec!["echo".into(), "{}".into()])
+        );
+        assert_eq!(
+            Exec::parse("echo {} > log", true).unwrap(),
+            Exec::Shell("echo {} > log".into())
+        );
+        assert!(Exec::parse(" ", false).is_err());
+        assert!(Exec::parse("echo 'open", false).is_err());
+    }
+}
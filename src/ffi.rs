@@ -0,0 +1,260 @@
+//! C-compatible interface to the touch API, enabled by the `ffi` feature.
+//!
+//! Paths cross the boundary as NUL-terminated UTF-16, matching Windows `wchar_t`
+//! strings. Every function returns an [`MdtouchStatus`] code; the message for the most
+//! recent failure on the calling thread is available from [`mdtouch_last_error_utf16`].
+//! The C declarations live in `include/mdtouch.h`, generated with cbindgen.
+
+use std::cell::RefCell;
+use std::io;
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+
+use crate::{touch_file, FileTime, TouchOptions};
+
+/// Result codes returned by the FFI functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MdtouchStatus {
+    /// The operation succeeded.
+    Ok = 0,
+    /// A pointer was null or a path was not valid UTF-16.
+    InvalidArgument = 1,
+    /// The path, or one of its parent directories, does not exist.
+    NotFound = 2,
+    /// Access to the path was denied.
+    PermissionDenied = 3,
+    /// Any other I/O failure.
+    IoError = 4,
+}
+
+/// Timestamps to apply, mirroring [`TouchOptions`]. Times are seconds and nanoseconds
+/// since the Unix epoch.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MdtouchOptions {
+    /// Non-zero to change the access time.
+    pub set_atime: u8,
+    /// Non-zero to change the modification time.
+    pub set_mtime: u8,
+    /// Access time, whole seconds since the Unix epoch.
+    pub atime_seconds: i64,
+    /// Access time, nanoseconds past `atime_seconds`.
+    pub atime_nanoseconds: u32,
+    /// Modification time, whole seconds since the Unix epoch.
+    pub mtime_seconds: i64,
+    /// Modification time, nanoseconds past `mtime_seconds`.
+    pub mtime_nanoseconds: u32,
+}
+
+impl From<&MdtouchOptions> for TouchOptions {
+    fn from(options: &MdtouchOptions) -> Self {
+        TouchOptions {
+            atime: (options.set_atime != 0).then(|| {
+                FileTime::from_unix_time(options.atime_seconds, options.atime_nanoseconds)
+            }),
+            mtime: (options.set_mtime != 0).then(|| {
+                FileTime::from_unix_time(options.mtime_seconds, options.mtime_nanoseconds)
+            }),
+            ..TouchOptions::default()
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Records `message` as the calling thread's last error and returns `status`.
+fn fail(status: MdtouchStatus, message: String) -> MdtouchStatus {
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// Maps an I/O error onto the closest status code.
+fn status_for(e: &io::Error) -> MdtouchStatus {
+    match e.kind() {
+        io::ErrorKind::NotFound => MdtouchStatus::NotFound,
+        io::ErrorKind::PermissionDenied => MdtouchStatus::PermissionDenied,
+        io::ErrorKind::InvalidInput => MdtouchStatus::InvalidArgument,
+        _ => MdtouchStatus::IoError,
+    }
+}
+
+/// Decodes UTF-16 into a path, keeping unpaired surrogates on Windows where they are
+/// legal in file names.
+#[cfg(windows)]
+fn path_from_utf16(units: &[u16]) -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_wide(units)))
+}
+
+#[cfg(not(windows))]
+fn path_from_utf16(units: &[u16]) -> Option<PathBuf> {
+    String::from_utf16(units).ok().map(PathBuf::from)
+}
+
+/// Touches the file at `path`, creating it if it does not exist.
+///
+/// `path` is a NUL-terminated UTF-16 string. When `opts` is null both timestamps are
+/// set to the current time.
+///
+/// # Safety
+///
+/// `path` must point to a valid NUL-terminated UTF-16 string, and `opts` must be null
+/// or point to a valid `MdtouchOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn mdtouch_touch_utf16(
+    path: *const u16,
+    opts: *const MdtouchOptions,
+) -> MdtouchStatus {
+    if path.is_null() {
+        return fail(MdtouchStatus::InvalidArgument, "path is null".to_string());
+    }
+    let mut len = 0;
+    while *path.add(len) != 0 {
+        len += 1;
+    }
+    let Some(path) = path_from_utf16(slice::from_raw_parts(path, len)) else {
+        return fail(
+            MdtouchStatus::InvalidArgument,
+            "path is not valid UTF-16".to_string(),
+        );
+    };
+
+    let options = match opts.as_ref() {
+        Some(opts) => TouchOptions::from(opts),
+        None => TouchOptions::now(),
+    };
+    match touch_file(&path, &options) {
+        Ok(_) => MdtouchStatus::Ok,
+        Err(e) => fail(
+            status_for(&e),
+            format!("Error touching {}: {}", path.display(), e),
+        ),
+    }
+}
+
+/// Copies the calling thread's last error message into `buffer` as NUL-terminated
+/// UTF-16, truncating it to fit `capacity` units.
+///
+/// Returns the number of units needed for the whole message including the terminator,
+/// so a caller can pass a null `buffer` first to size its allocation.
+///
+/// # Safety
+///
+/// `buffer` must be null or valid for writes of `capacity` UTF-16 units.
+#[no_mangle]
+pub unsafe extern "C" fn mdtouch_last_error_utf16(buffer: *mut u16, capacity: usize) -> usize {
+    let units: Vec<u16> = LAST_ERROR.with(|last| last.borrow().encode_utf16().collect());
+    if !buffer.is_null() && capacity > 0 {
+        let copied = units.len().min(capacity - 1);
+        ptr::copy_nonoverlapping(units.as_ptr(), buffer, copied);
+        *buffer.add(copied) = 0;
+    }
+    units.len() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    /// Encodes a path as a NUL-terminated UTF-16 string.
+    fn to_utf16(path: &Path) -> Vec<u16> {
+        path.to_str()
+            .unwrap()
+            .encode_utf16()
+            .chain(Some(0))
+            .collect()
+    }
+
+    /// Reads the calling thread's last error through the FFI.
+    fn last_error() -> String {
+        unsafe {
+            let needed = mdtouch_last_error_utf16(ptr::null_mut(), 0);
+            let mut buffer = vec![0u16; needed];
+            mdtouch_last_error_utf16(buffer.as_mut_ptr(), buffer.len());
+            String::from_utf16(&buffer[..needed - 1]).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_touch_round_trip_through_ffi() {
+        let path = env::temp_dir().join(format!("mdtouch_ffi_{}.tmp", std::process::id()));
+        let options = MdtouchOptions {
+            set_atime: 1,
+            set_mtime: 1,
+            atime_seconds: 1_100_000_000,
+            atime_nanoseconds: 0,
+            mtime_seconds: 1_200_000_000,
+            mtime_nanoseconds: 500_000_000,
+        };
+
+        let status = unsafe { mdtouch_touch_utf16(to_utf16(&path).as_ptr(), &options) };
+        assert_eq!(status, MdtouchStatus::Ok);
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(
+            FileTime::from_last_access_time(&metadata),
+            FileTime::from_unix_time(1_100_000_000, 0)
+        );
+        assert_eq!(
+            FileTime::from_last_modification_time(&metadata),
+            FileTime::from_unix_time(1_200_000_000, 500_000_000)
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ffi_reports_errors_per_thread() {
+        let bad = env::temp_dir()
+            .join("non_existent_dir_xyz_123")
+            .join("file.txt");
+
+        let status = unsafe { mdtouch_touch_utf16(to_utf16(&bad).as_ptr(), ptr::null()) };
+        assert_eq!(status, MdtouchStatus::NotFound);
+        assert!(last_error().contains("Error touching"));
+
+        // Another thread has its own, empty, last error.
+        let other = std::thread::spawn(last_error).join().unwrap();
+        assert!(other.is_empty());
+
+        let status = unsafe { mdtouch_touch_utf16(ptr::null(), ptr::null()) };
+        assert_eq!(status, MdtouchStatus::InvalidArgument);
+        assert_eq!(last_error(), "path is null");
+    }
+
+    #[test]
+    fn test_last_error_truncates_to_buffer() {
+        fail(MdtouchStatus::IoError, "abcdef".to_string());
+        let mut buffer = [0xffffu16; 4];
+        let needed = unsafe { mdtouch_last_error_utf16(buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(needed, 7);
+        assert_eq!(buffer, [b'a' as u16, b'b' as u16, b'c' as u16, 0]);
+    }
+
+    #[test]
+    fn test_header_declares_ffi_surface() {
+        let header = include_str!("../include/mdtouch.h");
+        for declaration in [
+            "} MdtouchStatus;",
+            "MDTOUCH_STATUS_NOT_FOUND = 2",
+            "} MdtouchOptions;",
+            "int64_t atime_seconds;",
+            "uint32_t mtime_nanoseconds;",
+            "MdtouchStatus mdtouch_touch_utf16(const uint16_t *path, const MdtouchOptions *opts);",
+            "size_t mdtouch_last_error_utf16(uint16_t *buffer, size_t capacity);",
+        ] {
+            assert!(
+                header.contains(declaration),
+                "include/mdtouch.h is out of date; missing `{}`. Regenerate it with cbindgen.",
+                declaration
+            );
+        }
+    }
+}
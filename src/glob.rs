@@ -0,0 +1,267 @@
+//! Wildcard expansion of file operands.
+//!
+//! cmd.exe and PowerShell pass wildcards through to the program unexpanded, so mdtouch
+//! expands them itself. A pattern may use `*` (any run of characters), `?` (any single
+//! character) and `[...]` classes such as `[abc]`, `[a-z]` or `[!0-9]`, in any path
+//! component. As in POSIX shells, a wildcard only matches a leading `.` when the pattern
+//! spells it out. Brackets are only read as a class where no file is named by them as
+//! written, so an existing `report[1].txt`, or a directory `build[1]` on the way to a
+//! `*`, is taken as it is. Whether case matters is up to the caller: mdtouch ignores it on Windows,
+//! as Windows does, unless `--case-sensitive` is given.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Returns whether a path contains any wildcard characters.
+pub fn has_wildcards(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expands a single operand.
+///
+/// An operand without wildcards, or that names an existing file as written, is returned
+/// unchanged. Otherwise the matching paths are returned in sorted order, or the operand
+/// itself if nothing matches, as a shell without `nullglob` would. With `ignore_case`,
+/// names match whatever their case.
+pub fn expand_operand(operand: PathBuf, ignore_case: bool) -> Vec<PathBuf> {
+    if !has_wildcards(&operand) || fs::symlink_metadata(&operand).is_ok() {
+        return vec![operand];
+    }
+    let matches = expand(&operand, ignore_case);
+    if matches.is_empty() {
+        vec![operand]
+    } else {
+        matches
+    }
+}
+
+/// Returns the existing paths that match `pattern`, sorted.
+pub fn expand(pattern: &Path, ignore_case: bool) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let text = match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        };
+        candidates = match text {
+            Some(text) if has_wildcards(Path::new(text)) => candidates
+                .iter()
+                .flat_map(|base| {
+                    // A name such as `report[1]` is taken as written if it is there.
+                    let literal = base.join(text);
+                    if !text.contains(['*', '?']) && fs::symlink_metadata(&literal).is_ok() {
+                        vec![literal]
+                    } else {
+                        matching_entries(base, text, ignore_case)
+                    }
+                })
+                .collect(),
+            _ => candidates
+                .into_iter()
+                .map(|base| base.join(component))
+                .filter(|path| fs::symlink_metadata(path).is_ok())
+                .collect(),
+        };
+    }
+    candidates.sort();
+    candidates
+}
+
+/// Returns the entries of the directory `base` whose names match `pattern`.
+fn matching_entries(base: &Path, pattern: &str, ignore_case: bool) -> Vec<PathBuf> {
+    let directory = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base
+    };
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name_matches(pattern, name, ignore_case))
+        })
+        .map(|entry| base.join(entry.file_name()))
+        .collect()
+}
+
+/// Returns whether a file name matches a single-component pattern, ignoring case if
+/// `ignore_case` is set.
+pub fn name_matches(pattern: &str, name: &str, ignore_case: bool) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let fold = |text: &str| -> Vec<char> {
+        if ignore_case {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        }
+    };
+    matches(&fold(pattern), &fold(name))
+}
+
+/// Matches `name` against `pattern`, both already case-folded as needed.
+///
+/// Only the last `*` is ever gone back to: whatever an earlier one matched, a later one
+/// can match as well, so this takes time in proportion to the pattern's length times the
+/// name's, however many stars there are.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Just past the last `*` seen, and where in the name its match currently ends.
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+        } else if let Some(next) = match_one(pattern, p, name[n]) {
+            p = next;
+            n += 1;
+        } else if let Some((after, end)) = star {
+            // Let the last star take one more character, and carry on from there.
+            p = after;
+            n = end + 1;
+            star = Some((after, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Where the pattern goes on from if the element at `p`, other than `*`, matches `c`.
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match class_end(&pattern[p + 1..]) {
+            Some(end) => class_contains(&pattern[p + 1..p + 1 + end], c).then_some(p + end + 2),
+            // An unclosed bracket is an ordinary character.
+            None => (c == '[').then_some(p + 1),
+        },
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Finds the `]` closing a class whose body starts at `body`. A `]` straight after the
+/// opening bracket (or after a negating `!`/`^`) is a member rather than the end.
+fn class_end(body: &[char]) -> Option<usize> {
+    let start = match body.first() {
+        Some('!' | '^') => 1,
+        _ => 0,
+    };
+    body.iter()
+        .skip(start + 1)
+        .position(|c| *c == ']')
+        .map(|offset| start + 1 + offset)
+}
+
+/// Returns whether a class body such as `a-z0` or `!abc` contains `c`.
+fn class_contains(body: &[char], c: char) -> bool {
+    let (negated, members) = match body.first() {
+        Some('!' | '^') => (true, &body[1..]),
+        _ => (false, body),
+    };
+    let mut found = false;
+    let mut index = 0;
+    while index < members.len() {
+        if index + 2 < members.len() && members[index + 1] == '-' {
+            found |= (members[index]..=members[index + 2]).contains(&c);
+            index += 3;
+        } else {
+            found |= members[index] == c;
+            index += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_name_matches() {
+        for ignore_case in [false, true] {
+            assert!(name_matches("*.log", "app.log", ignore_case));
+            assert!(!name_matches("*.log", "app.txt", ignore_case));
+            assert!(name_matches("app-?.log", "app-1.log", ignore_case));
+            assert!(!name_matches("app-?.log", "app-10.log", ignore_case));
+            assert!(name_matches("app-[0-9].log", "app-7.log", ignore_case));
+            assert!(!name_matches("app-[!0-9].log", "app-7.log", ignore_case));
+            assert!(name_matches("[]x]", "]", ignore_case));
+            assert!(name_matches("a[b", "a[b", ignore_case));
+            assert!(!name_matches("*", ".hidden", ignore_case));
+            assert!(name_matches(".*", ".hidden", ignore_case));
+            assert!(name_matches("*a*[bc]*", "xaxxc", ignore_case));
+            assert!(!name_matches("*a*[bc]*", "xaxxd", ignore_case));
+            assert!(name_matches("a*", "a", ignore_case));
+            assert!(!name_matches("a*b", "a", ignore_case));
+        }
+    }
+
+    #[test]
+    fn test_name_matches_many_stars_quickly() {
+        // Going back into every star, failing this takes time exponential in their number.
+        let pattern = "*a".repeat(20) + "b";
+        let name = "a".repeat(40);
+        let start = std::time::Instant::now();
+        assert!(!name_matches(&pattern, &name, false));
+        assert!(name_matches(&pattern, &(name + "b"), false));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_name_matches_case() {
+        assert!(name_matches("Make*", "makefile", true));
+        assert!(name_matches("[M]AKEFILE", "Makefile", true));
+        assert!(!name_matches("Make*", "makefile", false));
+        assert!(name_matches("Make*", "Makefile", false));
+        assert!(!name_matches("[m]akefile", "Makefile", false));
+    }
+
+    #[test]
+    fn test_expand_across_components() {
+        let dir = env::temp_dir().join(format!("mdtouch_glob_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["logs1", "logs2", "other"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join("app.log"), b"").unwrap();
+        }
+
+        let found = expand(&dir.join("logs*").join("app.log"), false);
+        assert_eq!(
+            found,
+            vec![
+                dir.join("logs1").join("app.log"),
+                dir.join("logs2").join("app.log")
+            ]
+        );
+
+        // Unmatched patterns are kept as written; literal names are left alone.
+        let unmatched = dir.join("*.nothing");
+        assert_eq!(expand_operand(unmatched.clone(), false), vec![unmatched]);
+        let literal = dir.join("new.txt");
+        assert_eq!(expand_operand(literal.clone(), false), vec![literal]);
+
+        // Brackets naming an existing directory are not a class, even before a `*`.
+        fs::create_dir(dir.join("build[1]")).unwrap();
+        fs::create_dir(dir.join("build1")).unwrap();
+        fs::write(dir.join("build[1]").join("out.o"), b"").unwrap();
+        fs::write(dir.join("build1").join("out.o"), b"").unwrap();
+        assert_eq!(
+            expand(&dir.join("build[1]").join("*.o"), false),
+            vec![dir.join("build[1]").join("out.o")]
+        );
+        assert_eq!(
+            expand(&dir.join("build[12]").join("*.o"), false),
+            vec![dir.join("build1").join("out.o")]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,446 @@
+//! Strict GNU `touch` compatibility, turned on with `--posix`, `MDTOUCH_COMPAT=gnu` or by
+//! running mdtouch under the name `touch`.
+//!
+//! In this mode only the options GNU touch accepts are recognised, under the same short
+//! and long names (including unambiguous long-option prefixes and bundled short flags),
+//! and usage errors and per-file failures are reported in GNU's words. Any failure exits
+//! with status 1. mdtouch's own extensions are rejected as unrecognised options so that
+//! scripts cannot come to rely on them by accident.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use mdtouch::{
+    parse_date, parse_touch_stamp, touch_file, BrokenLinkPolicy, FileTime, ParseContext,
+    TimeSource, TouchOptions,
+};
+
+use crate::Options;
+
+/// Environment variable that turns on compatibility mode when set to `gnu`.
+const COMPAT_VARIABLE: &str = "MDTOUCH_COMPAT";
+
+/// The long options GNU touch accepts, and whether each takes an argument.
+const LONG_OPTIONS: [(&str, bool); 8] = [
+    ("date", true),
+    ("help", false),
+    ("no-create", false),
+    ("no-dereference", false),
+    ("posix", false),
+    ("reference", true),
+    ("time", true),
+    ("version", false),
+];
+
+/// The hint GNU prints after every usage error.
+const TRY_HELP: &str = "Try 'touch --help' for more information.";
+
+/// What the command line asks for.
+#[derive(Debug)]
+enum Command {
+    Help,
+    Version,
+    Touch(Box<Options>, Vec<PathBuf>),
+}
+
+/// Returns whether compatibility mode was asked for, by a `--posix` option or through
+/// the environment.
+pub fn requested(args: &[OsString]) -> bool {
+    let from_env = env::var(COMPAT_VARIABLE).is_ok_and(|value| value.eq_ignore_ascii_case("gnu"));
+    from_env
+        || args
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == "--posix")
+}
+
+/// Returns whether the program was run under the name `touch` (or `touch.exe`, in any
+/// case), as when it is installed or linked in place of GNU touch.
+pub fn invoked_as_touch(program: &OsStr) -> bool {
+    let Some(name) = Path::new(program).file_name().and_then(OsStr::to_str) else {
+        return false;
+    };
+    let name = name.to_ascii_lowercase();
+    name == "touch" || name == "touch.exe"
+}
+
+/// Returns GNU touch's help text.
+fn help_message() -> String {
+    let mut msg = String::new();
+    msg.push_str("Usage: touch [OPTION]... FILE...\n");
+    msg.push_str("Update the access and modification times of each FILE to the current time.\n\n");
+    msg.push_str("A FILE argument that does not exist is created empty, unless -c or -h\n");
+    msg.push_str("is supplied.\n\n");
+    msg.push_str("  -a                     change only the access time\n");
+    msg.push_str("  -c, --no-create        do not create any files\n");
+    msg.push_str("  -d, --date=STRING      parse STRING and use it instead of current time\n");
+    msg.push_str("  -f                     (ignored)\n");
+    msg.push_str("  -h, --no-dereference   affect each symbolic link instead of any referenced\n");
+    msg.push_str("                         file\n");
+    msg.push_str("  -m                     change only the modification time\n");
+    msg.push_str("  -r, --reference=FILE   use this file's times instead of current time\n");
+    msg.push_str("  -t STAMP               use [[CC]YY]MMDDhhmm[.ss] instead of current time\n");
+    msg.push_str("      --time=WORD        change the specified time:\n");
+    msg.push_str("                           WORD is access, atime, or use: equivalent to -a\n");
+    msg.push_str("                           WORD is modify or mtime: equivalent to -m\n");
+    msg.push_str("      --help             display this help and exit\n");
+    msg.push_str("      --version          output version information and exit");
+    msg
+}
+
+/// Builds a usage error, followed by GNU's pointer to `--help`.
+fn usage_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("touch: {}\n{}", message, TRY_HELP),
+    )
+}
+
+/// Quotes a file name the way GNU does in diagnostics.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.display())
+}
+
+/// Describes an error as the C library's `strerror` would, without Rust's
+/// ` (os error N)` suffix. The common cases use the POSIX wording on every platform.
+fn describe(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::NotFound => "No such file or directory".to_string(),
+        io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        _ => {
+            let text = e.to_string();
+            match text.find(" (os error ") {
+                Some(end) => text[..end].to_string(),
+                None => text,
+            }
+        }
+    }
+}
+
+/// Finds the long option `name` names, exactly or as an unambiguous prefix. `arg` is the
+/// whole argument, for the error message.
+fn long_option(name: &str, arg: &str) -> io::Result<(&'static str, bool)> {
+    if let Some(exact) = LONG_OPTIONS.iter().find(|(long, _)| *long == name) {
+        return Ok(*exact);
+    }
+    let candidates: Vec<&(&str, bool)> = LONG_OPTIONS
+        .iter()
+        .filter(|(long, _)| long.starts_with(name))
+        .collect();
+    match candidates.as_slice() {
+        [] => Err(usage_error(&format!("unrecognized option '{}'", arg))),
+        [only] => Ok(**only),
+        _ => {
+            let possibilities: Vec<String> = candidates
+                .iter()
+                .map(|(long, _)| format!("'--{}'", long))
+                .collect();
+            Err(usage_error(&format!(
+                "option '--{}' is ambiguous; possibilities: {}",
+                name,
+                possibilities.join(" ")
+            )))
+        }
+    }
+}
+
+/// Applies a `--time` argument.
+fn set_time_word(options: &mut Options, word: &str) -> io::Result<()> {
+    match word {
+        "access" | "atime" | "use" => options.access_only = true,
+        "modify" | "mtime" => options.modify_only = true,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "touch: invalid argument '{}' for '--time'\n\
+                     Valid arguments are:\n  - 'atime', 'access', 'use'\n  - 'mtime', 'modify'\n{}",
+                    word, TRY_HELP
+                ),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Parses the command line as GNU touch would.
+///
+/// Options and operands may be mixed, `--` ends the options, and a lone `-` is an
+/// operand. `--help` and `--version` take effect as soon as they are seen.
+fn parse_args(args: Vec<OsString>) -> io::Result<Command> {
+    let mut options = Options::default();
+    let mut operands = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let text = match arg.to_str() {
+            Some(text) if text.len() > 1 && text.starts_with('-') => text.to_string(),
+            _ => {
+                operands.push(PathBuf::from(arg));
+                continue;
+            }
+        };
+        if text == "--" {
+            operands.extend(args.by_ref().map(PathBuf::from));
+            break;
+        }
+
+        if let Some(long) = text.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let (name, takes_value) = long_option(name, &text)?;
+            let value = match (takes_value, inline) {
+                (false, Some(_)) => {
+                    return Err(usage_error(&format!(
+                        "option '--{}' doesn't allow an argument",
+                        name
+                    )))
+                }
+                (false, None) => OsString::new(),
+                (true, Some(value)) => OsString::from(value),
+                (true, None) => args.next().ok_or_else(|| {
+                    usage_error(&format!("option '--{}' requires an argument", name))
+                })?,
+            };
+            match name {
+                "date" => options.date = Some(value.to_string_lossy().into_owned()),
+                "help" => return Ok(Command::Help),
+                "no-create" => options.no_create = true,
+                "no-dereference" => options.no_dereference = true,
+                "reference" => options.reference = Some(PathBuf::from(value)),
+                "time" => set_time_word(&mut options, &value.to_string_lossy())?,
+                "version" => return Ok(Command::Version),
+                _ => {} // --posix only selects this mode.
+            }
+            continue;
+        }
+
+        // A bundle of short flags such as `-am`, where an option taking a value uses the
+        // rest of the bundle or, if there is none, the next argument (`-d2025-01-01`).
+        for (index, flag) in text.char_indices().skip(1) {
+            match flag {
+                'a' => options.access_only = true,
+                'c' => options.no_create = true,
+                'f' => {}
+                'h' => options.no_dereference = true,
+                'm' => options.modify_only = true,
+                'd' | 'r' | 't' => {
+                    let rest = &text[index + 1..];
+                    let value = if rest.is_empty() {
+                        args.next().ok_or_else(|| {
+                            usage_error(&format!("option requires an argument -- '{}'", flag))
+                        })?
+                    } else {
+                        OsString::from(rest)
+                    };
+                    match flag {
+                        'd' => options.date = Some(value.to_string_lossy().into_owned()),
+                        'r' => options.reference = Some(PathBuf::from(value)),
+                        _ => options.stamp = Some(value.to_string_lossy().into_owned()),
+                    }
+                    break;
+                }
+                _ => return Err(usage_error(&format!("invalid option -- '{}'", flag))),
+            }
+        }
+    }
+    Ok(Command::Touch(Box::new(options), operands))
+}
+
+/// Works out the times to apply as GNU touch does, with "now" read from `clock`. A date
+/// given with `-r` is read relative to the reference file's times.
+fn resolve_times(options: &Options, clock: &dyn TimeSource) -> io::Result<TouchOptions> {
+    if options.stamp.is_some() && (options.date.is_some() || options.reference.is_some()) {
+        return Err(usage_error(
+            "cannot specify times from more than one source",
+        ));
+    }
+
+    let now = clock.now();
+    let (mut atime, mut mtime) = (now, now);
+    if let Some(reference) = &options.reference {
+        let metadata = if options.no_dereference {
+            fs::symlink_metadata(reference)
+        } else {
+            fs::metadata(reference)
+        }
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "touch: failed to get attributes of {}: {}",
+                    quote(reference),
+                    describe(&e)
+                ),
+            )
+        })?;
+        atime = FileTime::from_last_access_time(&metadata);
+        mtime = FileTime::from_last_modification_time(&metadata);
+    }
+    let invalid_date = |input: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("touch: invalid date format '{}'", input),
+        )
+    };
+    if let Some(date) = &options.date {
+        let relative_to = |base| ParseContext {
+            now: base,
+            ..ParseContext::default()
+        };
+        atime = parse_date(date, relative_to(atime)).map_err(|_| invalid_date(date))?;
+        mtime = parse_date(date, relative_to(mtime)).map_err(|_| invalid_date(date))?;
+    }
+    if let Some(stamp) = &options.stamp {
+        let time =
+            parse_touch_stamp(stamp, ParseContext::default()).map_err(|_| invalid_date(stamp))?;
+        (atime, mtime) = (time, time);
+    }
+
+    let both = options.access_only == options.modify_only;
+    Ok(TouchOptions {
+        atime: (both || options.access_only).then_some(atime),
+        mtime: (both || options.modify_only).then_some(mtime),
+        no_create: options.no_create,
+        no_dereference: options.no_dereference,
+        // GNU touch opens dangling links with O_CREAT, creating their targets.
+        broken_link: BrokenLinkPolicy::CreateTarget,
+        exclusive: false,
+        flush: false,
+        atomic_create: false,
+        empty: false,
+        jobs: 1,
+    })
+}
+
+/// Runs a GNU-compatible invocation, reading the current time from `clock`. Every operand
+/// is attempted, and the returned error holds one GNU-style line per failure.
+pub fn run<W: Write>(args: Vec<OsString>, mut writer: W, clock: &dyn TimeSource) -> io::Result<()> {
+    let (options, operands) = match parse_args(args)? {
+        Command::Help => return writeln!(writer, "{}", help_message()),
+        Command::Version => {
+            return writeln!(writer, "touch (mdtouch) {}", env!("CARGO_PKG_VERSION"))
+        }
+        Command::Touch(options, operands) => (options, operands),
+    };
+    if operands.is_empty() {
+        return Err(usage_error("missing file operand"));
+    }
+    let times = resolve_times(&options, clock)?;
+
+    let messages: Vec<String> = operands
+        .iter()
+        .filter_map(|path| {
+            let e = touch_file(path, &times).err()?;
+            Some(format!(
+                "touch: cannot touch {}: {}",
+                quote(path),
+                describe(&e)
+            ))
+        })
+        .collect();
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(messages.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> io::Result<Command> {
+        parse_args(args.iter().map(OsString::from).collect())
+    }
+
+    fn parse_touch(args: &[&str]) -> (Options, Vec<PathBuf>) {
+        match parse(args).unwrap() {
+            Command::Touch(options, operands) => (*options, operands),
+            other => panic!("expected a touch command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invoked_as_touch() {
+        for program in [
+            "touch",
+            "TOUCH.EXE",
+            "/usr/local/bin/touch",
+            r"C:\tools\Touch.exe",
+        ] {
+            let program = program.replace('\\', std::path::MAIN_SEPARATOR_STR);
+            assert!(invoked_as_touch(OsStr::new(&program)), "{}", program);
+        }
+        for program in [
+            "mdtouch",
+            "mdtouch.exe",
+            "touch.com",
+            "touchy",
+            "touch/mdtouch",
+        ] {
+            assert!(!invoked_as_touch(OsStr::new(program)), "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_bundled_short_flags_and_attached_values() {
+        let (options, operands) = parse_touch(&["-acm", "-d2025-01-02", "a", "-rref", "b"]);
+        assert!(options.access_only && options.modify_only && options.no_create);
+        assert_eq!(options.date.as_deref(), Some("2025-01-02"));
+        assert_eq!(options.reference, Some(PathBuf::from("ref")));
+        assert_eq!(operands, vec![PathBuf::from("a"), PathBuf::from("b")]);
+    }
+
+    #[test]
+    fn test_long_option_prefixes_and_double_dash() {
+        let (options, operands) = parse_touch(&["--no-c", "--ref=r", "--time", "use", "--", "-a"]);
+        assert!(options.no_create && options.access_only && !options.modify_only);
+        assert_eq!(options.reference, Some(PathBuf::from("r")));
+        assert_eq!(operands, vec![PathBuf::from("-a")]);
+    }
+
+    #[test]
+    fn test_help_and_version_win_over_operands() {
+        assert!(matches!(parse(&["x", "--help"]).unwrap(), Command::Help));
+        assert!(matches!(parse(&["--vers"]).unwrap(), Command::Version));
+    }
+
+    #[test]
+    fn test_extensions_are_rejected() {
+        for (args, message) in [
+            (&["--verbose"][..], "touch: unrecognized option '--verbose'"),
+            (&["--json=1"][..], "touch: unrecognized option '--json=1'"),
+            (&["-v"][..], "touch: invalid option -- 'v'"),
+            (&["-0"][..], "touch: invalid option -- '0'"),
+        ] {
+            let err = parse(args).unwrap_err();
+            assert_eq!(err.to_string(), format!("{}\n{}", message, TRY_HELP));
+        }
+    }
+
+    #[test]
+    fn test_times_from_the_clock() {
+        let clock = mdtouch::FixedClock(FileTime::from_unix_time(1_000_000_000, 0));
+        let (options, _) = parse_touch(&["a"]);
+        let times = resolve_times(&options, &clock).unwrap();
+        assert_eq!((times.atime, times.mtime), (Some(clock.0), Some(clock.0)));
+
+        // So does a date of "now".
+        let (options, _) = parse_touch(&["-m", "-d", "now", "a"]);
+        let times = resolve_times(&options, &clock).unwrap();
+        assert_eq!((times.atime, times.mtime), (None, Some(clock.0)));
+    }
+
+    #[test]
+    fn test_describe_drops_os_error_suffix() {
+        let not_found = io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(describe(&not_found), "No such file or directory");
+        assert_eq!(describe(&io::Error::other("Disk full")), "Disk full");
+    }
+}
@@ -0,0 +1,175 @@
+//! Protection for operating-system directories against bulk operations.
+//!
+//! Paths produced by wildcard expansion, and those `-R` finds below an operand, are
+//! checked against a guard list before they are touched, so that a pattern such as
+//! `%DIR%\*` with an empty variable, or `-R C:\`, cannot churn the timestamps of the OS. Operands given explicitly are never checked: naming a single
+//! file inside a protected tree is clearly intentional. `--allow-system` turns the guard
+//! off.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mdtouch::normalize_path;
+
+/// The directories that bulk operations must not reach into.
+#[derive(Debug, Clone, Default)]
+pub struct SystemGuard {
+    /// Directories protected along with everything below them.
+    trees: Vec<PathBuf>,
+    /// Directories protected along with their immediate entries only, such as the root of
+    /// the system drive.
+    roots: Vec<PathBuf>,
+}
+
+impl SystemGuard {
+    /// Builds the guard list for this system: the Windows directory, the Program Files
+    /// directories and the root of the system drive.
+    #[cfg(windows)]
+    pub fn for_system() -> Self {
+        let trees = [
+            "SystemRoot",
+            "windir",
+            "ProgramFiles",
+            "ProgramFiles(x86)",
+            "ProgramW6432",
+        ]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .collect();
+        let drive = std::env::var_os("SystemDrive").unwrap_or_else(|| "C:".into());
+        let mut root = PathBuf::from(drive);
+        root.push("\\");
+        SystemGuard::new(trees, vec![root])
+    }
+
+    /// Builds the guard list for this system: the directories holding the OS and its
+    /// programs, and the filesystem root.
+    #[cfg(not(windows))]
+    pub fn for_system() -> Self {
+        let trees = ["/bin", "/boot", "/etc", "/lib", "/sbin", "/usr"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        SystemGuard::new(trees, vec![PathBuf::from("/")])
+    }
+
+    /// Builds a guard from the protected trees and roots, resolving each to its
+    /// canonical form.
+    pub fn new(trees: Vec<PathBuf>, roots: Vec<PathBuf>) -> Self {
+        SystemGuard {
+            trees: trees.iter().map(|path| canonical(path)).collect(),
+            roots: roots.iter().map(|path| canonical(path)).collect(),
+        }
+    }
+
+    /// Returns the protected directory that `path` falls under, if any.
+    ///
+    /// The path is canonicalized first, so `..` components and symbolic links cannot be
+    /// used to slip past the guard.
+    pub fn blocks(&self, path: &Path) -> Option<&Path> {
+        let path = canonical(path);
+        let tree = self
+            .trees
+            .iter()
+            .find(|tree| starts_with_folded(&path, tree));
+        let root = self.roots.iter().find(|root| {
+            starts_with_folded(&path, root)
+                && path.components().count() <= root.components().count() + 1
+        });
+        tree.or(root).map(PathBuf::as_path)
+    }
+}
+
+/// Resolves a path to its canonical form. For a path that does not exist yet, the
+/// deepest existing ancestor is canonicalized and the rest appended to it.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(resolved) = fs::canonicalize(path) {
+        return resolved;
+    }
+    let absolute = normalize_path(path);
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    while let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) {
+        missing.push(name);
+        existing = parent;
+        if let Ok(resolved) = fs::canonicalize(existing) {
+            return missing
+                .iter()
+                .rev()
+                .fold(resolved, |path, name| path.join(name));
+        }
+    }
+    absolute
+}
+
+/// Whether `path` is `base` or lies below it, ignoring case on Windows.
+fn starts_with_folded(path: &Path, base: &Path) -> bool {
+    if cfg!(windows) {
+        let fold = |path: &Path| PathBuf::from(path.to_string_lossy().to_lowercase());
+        fold(path).starts_with(fold(base))
+    } else {
+        path.starts_with(base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// Creates a scratch layout with a protected `system` tree, a `root` protected one
+    /// level deep and an ordinary `work` directory.
+    fn layout(name: &str) -> (PathBuf, SystemGuard) {
+        let dir = env::temp_dir().join(format!("mdtouch_guard_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["system/deep", "root/child/grandchild", "work/nested"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let guard = SystemGuard::new(vec![dir.join("system")], vec![dir.join("root")]);
+        (dir, guard)
+    }
+
+    #[test]
+    fn test_blocks_paths_inside_protected_trees() {
+        let (dir, guard) = layout("trees");
+        assert!(guard.blocks(&dir.join("system")).is_some());
+        assert!(guard.blocks(&dir.join("system/deep/file.txt")).is_some());
+        assert!(guard.blocks(&dir.join("work/file.txt")).is_none());
+        // A sibling that merely shares the prefix is not inside the tree.
+        assert!(guard.blocks(&dir.join("system2")).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_blocks_sneaky_relative_forms() {
+        let (dir, guard) = layout("relative");
+        for sneaky in [
+            "work/../system/file.txt",
+            "work/nested/../../system/deep",
+            "./work/./../system",
+            "work/nested/../../work/../system/missing/new.txt",
+        ] {
+            assert!(guard.blocks(&dir.join(sneaky)).is_some(), "{}", sneaky);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_blocks_symlinks_into_protected_trees() {
+        let (dir, guard) = layout("symlink");
+        std::os::unix::fs::symlink(dir.join("system"), dir.join("work/link")).unwrap();
+        assert!(guard.blocks(&dir.join("work/link/deep")).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_root_protects_only_its_immediate_entries() {
+        let (dir, guard) = layout("root");
+        assert!(guard.blocks(&dir.join("root")).is_some());
+        assert!(guard.blocks(&dir.join("work/../root/child")).is_some());
+        assert!(guard.blocks(&dir.join("root/child/grandchild")).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Keeping files open between touches of the same file ([`HandlePool`]).
+//!
+//! A touch opens the file, sets its times through the handle and closes it again. When
+//! one file comes up many times in a run, as it can with overlapping wildcards, a pool
+//! keeps its handle open instead, so the later touches only set the times through it.
+//! Handles are keyed by the file's canonical path, so two spellings of a file share one,
+//! and once the pool holds [`HANDLE_POOL_SIZE`] of them the least recently used is closed
+//! to make room. The rest are closed when the pool is dropped.
+//!
+//! A handle is only reused while the path still names the file it was opened on: the
+//! file's ID is read through the path each time and compared with the handle's (the
+//! device and inode on Unix, the volume serial number and file index on Windows), so a
+//! file deleted and made again in the meantime is opened afresh, even with the same size
+//! and creation time. Where there is no such ID, handles are never reused.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::{
+    file_id, flush_if_asked, set_handle_times, touch_through_handle, FileId, FileTime,
+    TouchOptions, TouchOutcome,
+};
+
+/// How many handles a pool made for the command line's `--wrap-handle-reuse` keeps open.
+pub const HANDLE_POOL_SIZE: usize = 32;
+
+/// An open file, and the path it was opened through.
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    file: File,
+    id: Option<FileId>,
+}
+
+/// Handles kept open across touches, most recently used first.
+#[derive(Debug)]
+pub struct HandlePool {
+    capacity: usize,
+    entries: Mutex<VecDeque<Entry>>,
+    reused: AtomicUsize,
+}
+
+impl HandlePool {
+    /// A pool keeping up to `capacity` handles open. With a capacity of zero, nothing is
+    /// kept and every touch opens the file.
+    pub fn new(capacity: usize) -> Self {
+        HandlePool {
+            capacity,
+            entries: Mutex::default(),
+            reused: AtomicUsize::new(0),
+        }
+    }
+
+    /// Touches a file as [`crate::touch_file`] does, through the handle kept for it if
+    /// there is one. A handle opened for it is kept for next time.
+    pub fn touch_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &TouchOptions,
+    ) -> io::Result<TouchOutcome> {
+        self.touch(path.as_ref(), options, false)
+            .map(|(outcome, _)| outcome)
+    }
+
+    /// Touches a file as [`crate::touch_file_with_id`] does, through the handle kept for
+    /// it if there is one.
+    pub fn touch_file_with_id<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &TouchOptions,
+    ) -> io::Result<(TouchOutcome, Option<FileId>)> {
+        self.touch(path.as_ref(), options, true)
+    }
+
+    /// How many touches went through a handle that was already open.
+    pub fn reused(&self) -> usize {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// How many handles are open.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether no handle is open.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn touch(
+        &self,
+        path: &Path,
+        options: &TouchOptions,
+        with_id: bool,
+    ) -> io::Result<(TouchOutcome, Option<FileId>)> {
+        // Only a file opened for its own times can be kept: not one that must be new, a
+        // link touched itself, nor one to be emptied, which a kept handle may not allow.
+        let poolable = !options.exclusive && !options.no_dereference && !options.empty;
+        if let Some(entry) = poolable.then(|| self.take(path)).flatten() {
+            if let Some(outcome) = reuse(&entry, options)? {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                let id = entry.id.filter(|_| with_id);
+                self.keep(entry);
+                return Ok((outcome, id));
+            }
+        }
+        let (outcome, file) = touch_through_handle(path, options)?;
+        let id = file.as_ref().and_then(file_id);
+        if let Some(file) = file.filter(|_| poolable) {
+            if let Ok(path) = fs::canonicalize(path) {
+                self.keep(Entry { path, file, id });
+            }
+        }
+        Ok((outcome, id.filter(|_| with_id)))
+    }
+
+    /// Takes the entry kept for `path` out of the pool, if there is one.
+    fn take(&self, path: &Path) -> Option<Entry> {
+        let path = fs::canonicalize(path).ok()?;
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.iter().position(|entry| entry.path == path)?;
+        entries.remove(index)
+    }
+
+    /// Puts `entry` first in the pool, closing the least recently used handle if that
+    /// makes one too many.
+    fn keep(&self, entry: Entry) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|kept| kept.path != entry.path);
+        entries.push_front(entry);
+        entries.truncate(self.capacity);
+    }
+}
+
+/// Sets the times through a kept handle, if its path still names the file it was opened
+/// on. Returns `None` if it does not, so the file is to be opened again.
+fn reuse(entry: &Entry, options: &TouchOptions) -> io::Result<Option<TouchOutcome>> {
+    let Ok(metadata) = fs::metadata(&entry.path) else {
+        return Ok(None);
+    };
+    if !same_file(entry, &metadata) {
+        return Ok(None);
+    }
+    set_handle_times(&entry.file, options.atime, options.mtime)?;
+    flush_if_asked(&entry.file, &entry.path, options, false)?;
+    Ok(Some(TouchOutcome::Updated {
+        previous_mtime: Some(FileTime::from_last_modification_time(&metadata)),
+        previous_len: Some(metadata.len()),
+    }))
+}
+
+/// Whether the file at the entry's path, whose metadata is given, is the one it holds
+/// open.
+#[cfg(unix)]
+fn same_file(entry: &Entry, metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    entry
+        .id
+        .is_some_and(|id| id.volume == metadata.dev() && id.index == metadata.ino())
+}
+
+/// Whether the file at the entry's path is the one it holds open. A path's metadata has
+/// no file index here, so the path is opened for nothing but its attributes, sharing
+/// with everyone, and the index read from that handle.
+#[cfg(windows)]
+fn same_file(entry: &Entry, _metadata: &Metadata) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+    };
+
+    let Ok(file) = fs::OpenOptions::new()
+        .access_mode(0)
+        .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+        .open(&entry.path)
+    else {
+        return false;
+    };
+    entry.id.is_some_and(|id| file_id(&file) == Some(id))
+}
+
+/// Without a file ID to tell files apart, a kept handle is never taken to be the path's.
+#[cfg(not(any(unix, windows)))]
+fn same_file(_entry: &Entry, _metadata: &Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mdtouch_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn at(seconds: i64) -> TouchOptions {
+        let time = FileTime::from_unix_time(seconds, 0);
+        TouchOptions {
+            atime: Some(time),
+            mtime: Some(time),
+            ..TouchOptions::default()
+        }
+    }
+
+    fn mtime(path: &Path) -> FileTime {
+        FileTime::from_last_modification_time(&fs::metadata(path).unwrap())
+    }
+
+    #[test]
+    fn test_repeated_touches_reuse_the_handle() {
+        let dir = scratch_dir("handle_pool_reuse");
+        let path = dir.join("log.txt");
+        let pool = HandlePool::new(HANDLE_POOL_SIZE);
+        assert_eq!(
+            pool.touch_file(&path, &at(1_000)).unwrap(),
+            TouchOutcome::Created
+        );
+        let dotted = dir.join(".").join("log.txt");
+        assert_eq!(
+            pool.touch_file(&dotted, &at(2_000)).unwrap(),
+            TouchOutcome::Updated {
+                previous_mtime: Some(FileTime::from_unix_time(1_000, 0)),
+                previous_len: Some(0),
+            }
+        );
+        assert_eq!((pool.reused(), pool.len()), (1, 1));
+        assert_eq!(mtime(&path), FileTime::from_unix_time(2_000, 0));
+
+        // A file made again under the same name is not the one held open.
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, b"new").unwrap();
+        pool.touch_file(&path, &at(3_000)).unwrap();
+        assert_eq!((pool.reused(), pool.len()), (1, 1));
+        assert_eq!(mtime(&path), FileTime::from_unix_time(3_000, 0));
+
+        // Nor is one of the same size moved over it.
+        let other = dir.join("log.tmp");
+        fs::write(&other, b"new").unwrap();
+        fs::rename(&other, &path).unwrap();
+        pool.touch_file(&path, &at(4_000)).unwrap();
+        assert_eq!((pool.reused(), pool.len()), (1, 1));
+        assert_eq!(mtime(&path), FileTime::from_unix_time(4_000, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_least_recently_used_is_closed() {
+        let dir = scratch_dir("handle_pool_evict");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        let pool = HandlePool::new(1);
+        pool.touch_file(&a, &at(1_000)).unwrap();
+        pool.touch_file(&b, &at(1_000)).unwrap();
+        pool.touch_file(&a, &at(2_000)).unwrap();
+        assert_eq!((pool.reused(), pool.len()), (0, 1));
+        pool.touch_file(&a, &at(3_000)).unwrap();
+        assert_eq!(pool.reused(), 1);
+        assert_eq!(mtime(&a), FileTime::from_unix_time(3_000, 0));
+
+        let none = HandlePool::new(0);
+        none.touch_file(&a, &at(1_000)).unwrap();
+        none.touch_file(&a, &at(1_000)).unwrap();
+        assert!(none.is_empty());
+        assert_eq!(none.reused(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
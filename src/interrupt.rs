@@ -0,0 +1,72 @@
+//! Stopping a run cleanly on Ctrl+C.
+//!
+//! Once the handler is installed, Ctrl+C (or Ctrl+Break on Windows) only sets a flag.
+//! The run checks it between operands: no new operand is started, those already being
+//! touched finish, and the run ends as usual, with its reports, logs and checkpoint
+//! written, saying how far it got. A second Ctrl+C is not caught, so it ends the process
+//! at once as it always did.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+/// Set once Ctrl+C has been pressed.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Catches the first Ctrl+C from here on. Installing it again does nothing.
+pub fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(platform::install);
+}
+
+/// Whether Ctrl+C has been pressed since the handler was installed.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::sync::atomic::Ordering;
+
+    use super::REQUESTED;
+
+    pub fn install() {
+        extern "C" fn handler(_signal: libc::c_int) {
+            REQUESTED.store(true, Ordering::Relaxed);
+            // SAFETY: signal is async-signal-safe; the next SIGINT ends the process.
+            unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+        }
+        // SAFETY: the handler only stores to an atomic and resets itself.
+        unsafe { libc::signal(libc::SIGINT, handler as *const () as libc::sighandler_t) };
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::Ordering;
+
+    use windows_sys::Win32::Foundation::{BOOL, TRUE};
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+    };
+
+    use super::REQUESTED;
+
+    pub fn install() {
+        unsafe extern "system" fn handler(event: u32) -> BOOL {
+            match event {
+                // Handled the first time only; after that the default handler ends the
+                // process.
+                CTRL_C_EVENT | CTRL_BREAK_EVENT if !REQUESTED.swap(true, Ordering::Relaxed) => TRUE,
+                _ => 0,
+            }
+        }
+        // SAFETY: the handler runs on its own thread and only touches an atomic.
+        unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) };
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    /// There is no Ctrl+C to catch.
+    pub fn install() {}
+}
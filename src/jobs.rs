@@ -0,0 +1,176 @@
+//! Touching several files at once, for [`touch_many`](crate::touch_many) and the command
+//! line's `--jobs`.
+//!
+//! On a high-latency share each touch spends most of its time waiting for the server, so
+//! keeping a window of them in flight hides that latency. Setting times always completes
+//! synchronously, so the window is kept full by a small pool of worker threads.
+//! Results are handed back in operand order, so output, error messages and `--failed-to`
+//! lists are the same as for a sequential run.
+//!
+//! Operands are pulled from their source only as the window has room, so however long
+//! the source is, only a bounded number of operands and results are held at once.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Runs `touch` on every operand with up to `jobs` in flight at once, passing each result
+/// to `report` in operand order.
+///
+/// The operands are taken from the iterator on the calling thread, a few at a time, so it
+/// need not be `Send`. Once `report` returns `false` (or fails) no further operands are
+/// started, although ones already in flight still finish and their results are discarded.
+/// With one job, operands are simply touched one after another.
+pub fn for_each_in_order<I, O, T, R>(
+    operands: I,
+    jobs: usize,
+    touch: T,
+    mut report: R,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = PathBuf>,
+    O: Send,
+    T: Fn(&Path) -> io::Result<O> + Sync,
+    R: FnMut(PathBuf, io::Result<O>) -> io::Result<bool>,
+{
+    let mut operands = operands.into_iter();
+    if jobs <= 1 {
+        for operand in operands {
+            let result = touch(&operand);
+            if !report(operand, result)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    // Enough operands are handed out for every worker to have the next one ready, and no
+    // more: results that arrive early wait here until those before them are reported.
+    let window = jobs * 2;
+    let (work_sender, work_receiver) = mpsc::channel::<(usize, PathBuf)>();
+    let work_receiver = Mutex::new(work_receiver);
+    let (done_sender, done_receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let done_sender = done_sender.clone();
+            let (work_receiver, touch) = (&work_receiver, &touch);
+            scope.spawn(move || loop {
+                let next = work_receiver.lock().unwrap().recv();
+                let Ok((index, path)) = next else {
+                    break;
+                };
+                let result = touch(&path);
+                if done_sender.send((index, path, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(done_sender);
+
+        let mut pending = BTreeMap::new();
+        let (mut started, mut reported) = (0, 0);
+        let mut stopped = false;
+        let mut outcome = Ok(());
+        loop {
+            while !stopped && started - reported < window {
+                let Some(operand) = operands.next() else {
+                    break;
+                };
+                // The workers only stop once this sender is dropped, so the send succeeds.
+                let _ = work_sender.send((started, operand));
+                started += 1;
+            }
+            if reported == started {
+                break;
+            }
+            let Ok((index, path, result)) = done_receiver.recv() else {
+                break;
+            };
+            pending.insert(index, (path, result));
+            while let Some((path, result)) = pending.remove(&reported) {
+                reported += 1;
+                if stopped {
+                    continue;
+                }
+                match report(path, result) {
+                    Ok(true) => {}
+                    Ok(false) => stopped = true,
+                    Err(e) => {
+                        stopped = true;
+                        outcome = Err(e);
+                    }
+                }
+            }
+        }
+        drop(work_sender);
+        outcome
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn operands(count: usize) -> Vec<PathBuf> {
+        (0..count).map(|i| PathBuf::from(i.to_string())).collect()
+    }
+
+    #[test]
+    fn test_results_are_reported_in_operand_order() {
+        // Earlier operands take longer, so they complete last.
+        let touch = |path: &Path| {
+            let index: u64 = path.to_str().unwrap().parse().unwrap();
+            thread::sleep(Duration::from_millis(20 - index));
+            Ok(())
+        };
+        let mut seen = Vec::new();
+        for_each_in_order(operands(20), 8, touch, |path, result| {
+            result?;
+            seen.push(path);
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(seen, operands(20));
+    }
+
+    #[test]
+    fn test_stopping_reports_nothing_further() {
+        let touch = |path: &Path| match path.to_str() {
+            Some("3") => Err(io::Error::other("boom")),
+            _ => Ok(()),
+        };
+        for jobs in [1, 4] {
+            let mut seen = Vec::new();
+            for_each_in_order(operands(50), jobs, touch, |path, result| {
+                seen.push(path);
+                Ok(result.is_ok())
+            })
+            .unwrap();
+            assert_eq!(seen, operands(4), "jobs = {}", jobs);
+        }
+    }
+
+    #[test]
+    fn test_operands_are_pulled_only_as_needed() {
+        // An endless source still finishes once reporting stops.
+        for jobs in [1, 4] {
+            let mut seen = 0;
+            let endless = (0..).map(|i: u64| PathBuf::from(i.to_string()));
+            for_each_in_order(
+                endless,
+                jobs,
+                |_: &Path| Ok(()),
+                |_, _| {
+                    seen += 1;
+                    Ok(seen < 10)
+                },
+            )
+            .unwrap();
+            assert_eq!(seen, 10, "jobs = {}", jobs);
+        }
+    }
+}
@@ -0,0 +1,1262 @@
+//! Core logic for `mdtouch`, a tool that mimics the behaviour of the Unix `touch` command.
+//!
+//! The command line tool is a thin layer over this library, so other programs can create
+//! files and update their timestamps in exactly the same way without spawning a process.
+//!
+//! The library also builds for `wasm32-wasip2`, where timestamps are set through the
+//! standard library's WASI support instead of `filetime`. Paths must then lie inside a
+//! directory preopened by the host.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub use filetime::FileTime;
+
+mod batch;
+mod clock;
+pub mod date;
+mod handle_pool;
+pub mod jobs;
+mod temp;
+mod tz;
+
+pub use batch::{touch_each, touch_many, BatchResult};
+pub use clock::{FixedClock, SystemClock, TimeSource};
+pub use date::{parse_date, parse_touch_stamp, DateFormat, Locale, ParseContext, ParseError};
+pub use handle_pool::{HandlePool, HANDLE_POOL_SIZE};
+pub use temp::{create_temp_file, create_unique_file, UNIQUE_PATTERN};
+pub use tz::TimeZone;
+
+#[cfg(feature = "async")]
+mod async_touch;
+
+#[cfg(feature = "async")]
+pub use async_touch::{touch_file_async, touch_many_async};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+/// How to touch a file: the timestamps to apply, where a `None` leaves that timestamp
+/// unchanged, and what to do about missing files and symbolic links.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TouchOptions {
+    /// The access time to set.
+    pub atime: Option<FileTime>,
+    /// The modification time to set.
+    pub mtime: Option<FileTime>,
+    /// Skip files that do not exist instead of creating them.
+    pub no_create: bool,
+    /// Change the times of a symbolic link itself rather than the file it points to.
+    /// Missing files are never created in this mode.
+    pub no_dereference: bool,
+    /// What to do with a symbolic link whose target does not exist.
+    pub broken_link: BrokenLinkPolicy,
+    /// Only create the file: fail with [`io::ErrorKind::AlreadyExists`] if anything,
+    /// even a symbolic link, is already there, leaving it untouched. The check and the
+    /// creation are a single open, so when several callers race to create the same path
+    /// exactly one succeeds. Takes precedence over `no_create` and `no_dereference`.
+    pub exclusive: bool,
+    /// Flush the new times to disk before returning, and for a file that was created,
+    /// its directory entry too where the platform allows. A symbolic link touched
+    /// itself under `no_dereference` is not flushed.
+    pub flush: bool,
+    /// Create a missing file under a temporary name in the same directory and rename it
+    /// into place, replacing anything that appeared there meanwhile, so that no other
+    /// process ever sees it half made. The times are set once it is in place. Files that
+    /// already exist are touched as usual.
+    pub atomic_create: bool,
+    /// Empty a file that already exists, as `: > file` does, through the handle its times
+    /// are set through and just before they are set, so it stays the same file for
+    /// whatever has it open. The file is opened for writing, and anything but a regular
+    /// file is an error. A file that is created is empty already. Not done to a symbolic
+    /// link touched itself under `no_dereference`.
+    pub empty: bool,
+    /// How many files [`touch_many`] touches at once. Zero and one both mean one after
+    /// another; [`touch_file`] ignores it.
+    pub jobs: usize,
+}
+
+/// What touching one path did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchOutcome {
+    /// The file did not exist and was created.
+    Created,
+    /// The file already existed and its times were set. Its modification time and length
+    /// from before are read through the handle the times were set through, so they are
+    /// `None` for a file whose times could only be set by path. Under `empty` the length
+    /// is how much was discarded.
+    Updated {
+        previous_mtime: Option<FileTime>,
+        previous_len: Option<u64>,
+    },
+    /// Nothing was done: the file was missing under `no_create`, or was a broken link
+    /// left alone by [`BrokenLinkPolicy::Skip`].
+    SkippedMissing,
+    /// Nothing was done, because a filter passed the file over. The library never gives
+    /// this itself; it is for callers that choose which files to touch, as the command
+    /// line's `--apply-to` does, to report those the same way.
+    SkippedFilter,
+}
+
+/// What [`touch_file`] does with a symbolic link whose target does not exist, when
+/// following links.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BrokenLinkPolicy {
+    /// Fail with an error of kind [`io::ErrorKind::NotFound`].
+    #[default]
+    Error,
+    /// Leave the link alone and report success.
+    Skip,
+    /// Create the missing target as an empty file and touch it, unless `no_create` is set.
+    CreateTarget,
+}
+
+impl TouchOptions {
+    /// Sets both timestamps to the current time, as a plain `touch` does.
+    pub fn now() -> Self {
+        TouchOptions::now_from(&SystemClock)
+    }
+
+    /// Sets both timestamps to the current time by `clock`. The clock is read once, here;
+    /// the options keep the time, not the clock.
+    pub fn now_from(clock: &dyn TimeSource) -> Self {
+        let now = clock.now();
+        TouchOptions {
+            atime: Some(now),
+            mtime: Some(now),
+            ..TouchOptions::default()
+        }
+    }
+}
+
+/// Makes `path` absolute against the current directory and removes `.` and `..`
+/// components lexically, without consulting the filesystem.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Resolves operands made up only of `.` and `..` components (such as `.`, `..` or
+/// `../..`) to the absolute directory they name, so they are touched as directories.
+/// Any other path is returned unchanged.
+fn resolve_dot_operand(path: &Path) -> PathBuf {
+    let only_dots = path.components().next().is_some()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::CurDir | Component::ParentDir));
+    if only_dots {
+        normalize_path(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Opens an existing file or directory with just enough access to read and set its
+/// timestamps.
+///
+/// On Windows this asks only for attribute access, so read-only files can still be
+/// touched, and uses backup semantics so directories can be opened too.
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_READ_ATTRIBUTES, FILE_WRITE_ATTRIBUTES,
+    };
+
+    OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
+
+/// Opens an existing file or directory with just enough access to read and set its
+/// timestamps.
+///
+/// The open doesn't block, so a FIFO with no writer is opened rather than waited on.
+#[cfg(unix)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).open(path)
+}
+
+/// Opens an existing file as [`open_for_times`] does, with write access to its data as
+/// well so it can be emptied.
+#[cfg(windows)]
+fn open_for_emptying(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_READ_ATTRIBUTES, FILE_WRITE_ATTRIBUTES, FILE_WRITE_DATA,
+    };
+
+    OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES | FILE_WRITE_DATA)
+        .open(path)
+}
+
+/// Opens an existing file for writing so it can be emptied. As in [`open_for_times`],
+/// the open doesn't block on a FIFO.
+#[cfg(unix)]
+fn open_for_emptying(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn open_for_emptying(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).open(path)
+}
+
+/// Touches a file at the given path, mimicking the behaviour of the Unix `touch` command.
+/// If the file does not exist, it is created. In either case, the file's timestamps are
+/// updated to the times in `options`.
+///
+/// The file is opened once, creating it if needed, and its times are set through that
+/// handle. A timestamp left as `None` is passed to the OS as "don't change" (a null
+/// `FILETIME` on Windows, `UTIME_OMIT` on Unix), so `-a`/`-m` style updates never need to
+/// read the existing times first.
+pub fn touch_file<P: AsRef<Path>>(path: P, options: &TouchOptions) -> io::Result<TouchOutcome> {
+    touch_through_handle(path.as_ref(), options).map(|(outcome, _)| outcome)
+}
+
+/// Where a file is kept: its volume and its number there, which stay the same when it is
+/// renamed or moved within the volume.
+///
+/// On Windows these are the volume serial number and the file index, as `fsutil file
+/// queryfileid` shows them; elsewhere the device and inode numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    /// The volume serial number, or the device number.
+    pub volume: u64,
+    /// The file index, or the inode number.
+    pub index: u64,
+}
+
+/// Touches a file as [`touch_file`] does and returns its [`FileId`] along with what was
+/// done, read from the same handle its times were set through. There is none when no
+/// handle was opened: for a file skipped under `no_create` or [`BrokenLinkPolicy::Skip`],
+/// a link touched itself under `no_dereference`, or (outside Windows) a file whose times
+/// could only be set by path.
+pub fn touch_file_with_id<P: AsRef<Path>>(
+    path: P,
+    options: &TouchOptions,
+) -> io::Result<(TouchOutcome, Option<FileId>)> {
+    let (outcome, file) = touch_through_handle(path.as_ref(), options)?;
+    Ok((outcome, file.as_ref().and_then(file_id)))
+}
+
+/// Does the work of [`touch_file`], returning the handle the times were set through, if
+/// any.
+fn touch_through_handle(
+    path: &Path,
+    options: &TouchOptions,
+) -> io::Result<(TouchOutcome, Option<File>)> {
+    let path = &resolve_dot_operand(path);
+    if options.exclusive {
+        let file = create_new(path)?;
+        set_handle_times(&file, options.atime, options.mtime)?;
+        flush_if_asked(&file, path, options, true)?;
+        return Ok((TouchOutcome::Created, Some(file)));
+    }
+    if options.no_dereference {
+        return touch_symlink(path, options).map(|outcome| (outcome, None));
+    }
+    if options.atomic_create
+        && !options.no_create
+        && matches!(path.symlink_metadata(), Err(e) if e.kind() == io::ErrorKind::NotFound)
+    {
+        let file = create_atomically(path, options)?;
+        return Ok((TouchOutcome::Created, Some(file)));
+    }
+    // Through a link, as the times set are its target's. A missing target is made only
+    // under BrokenLinkPolicy::CreateTarget, and otherwise skipped or an error.
+    let (file, created) = match open_or_create(path, options) {
+        Ok(Some(opened)) => opened,
+        Ok(None) => return Ok((TouchOutcome::SkippedMissing, None)),
+        // Without write access the file can't be opened on Unix, but setting its times
+        // by path may still be permitted (for instance when we own it). If there was no
+        // file to begin with, the open's error is the one that explains why.
+        #[cfg(unix)]
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && !options.empty => {
+            set_times_by_path(path, options).map_err(|by_path| {
+                if by_path.kind() == io::ErrorKind::NotFound {
+                    e
+                } else {
+                    by_path
+                }
+            })?;
+            if options.flush {
+                flush_if_asked(&open_for_times(path)?, path, options, false)?;
+            }
+            return Ok((
+                TouchOutcome::Updated {
+                    previous_mtime: None,
+                    previous_len: None,
+                },
+                None,
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+    // Read from the handle, before the times are set, so it is the file's that was opened.
+    let previous = match created {
+        true => None,
+        false if options.empty => Some(file.metadata()?),
+        false => file.metadata().ok(),
+    };
+    if let Some(metadata) = previous.as_ref().filter(|_| options.empty) {
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only a regular file can be emptied",
+            ));
+        }
+        // Before the times are set, as emptying it sets its modification time.
+        file.set_len(0)?;
+    }
+    set_handle_times(&file, options.atime, options.mtime)?;
+    flush_if_asked(&file, path, options, created)?;
+    let outcome = if created {
+        TouchOutcome::Created
+    } else {
+        TouchOutcome::Updated {
+            previous_mtime: previous.as_ref().map(FileTime::from_last_modification_time),
+            previous_len: previous.as_ref().map(|metadata| metadata.len()),
+        }
+    };
+    Ok((outcome, Some(file)))
+}
+
+/// Reads the [`FileId`] of an open file (`GetFileInformationByHandle`).
+#[cfg(windows)]
+fn file_id(file: &File) -> Option<FileId> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    // SAFETY: the handle is open for as long as `file` is borrowed, and the information
+    // is written to a live local of the expected type.
+    unsafe {
+        let mut info: BY_HANDLE_FILE_INFORMATION = std::mem::zeroed();
+        if GetFileInformationByHandle(file.as_raw_handle(), &mut info) == 0 {
+            return None;
+        }
+        Some(FileId {
+            volume: u64::from(info.dwVolumeSerialNumber),
+            index: (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow),
+        })
+    }
+}
+
+/// Reads the [`FileId`] of an open file (`fstat`).
+#[cfg(unix)]
+fn file_id(file: &File) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = file.metadata().ok()?;
+    Some(FileId {
+        volume: metadata.dev(),
+        index: metadata.ino(),
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_id(_file: &File) -> Option<FileId> {
+    None
+}
+
+/// Creates the missing file `path` by renaming an empty file made beside it into place
+/// (`MoveFileEx` with replace semantics on Windows, `rename` elsewhere), then sets its
+/// times, returning the handle they were set through.
+fn create_atomically(path: &Path, options: &TouchOptions) -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no file name to create",
+        ));
+    };
+    // Hidden, and unique to this process and call, so concurrent creations never collide.
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(
+        ".{}-{}.mdtouch-tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp = path.with_file_name(temp_name);
+    drop(create_new(&temp)?);
+    if let Err(e) = std::fs::rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    let file = open_for_times(path)?;
+    set_handle_times(&file, options.atime, options.mtime)?;
+    flush_if_asked(&file, path, options, true)?;
+    Ok(file)
+}
+
+/// Flushes the times just set on `file` to disk if `options.flush` asks for it, and
+/// for a `created` file the directory holding it too.
+///
+/// Only the file's own flush can fail: the directory is flushed where the platform and
+/// filesystem allow it.
+fn flush_if_asked(
+    file: &File,
+    path: &Path,
+    options: &TouchOptions,
+    created: bool,
+) -> io::Result<()> {
+    if !options.flush {
+        return Ok(());
+    }
+    flush_file(file, path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "the times were set but could not be flushed to disk ({})",
+                e
+            ),
+        )
+    })?;
+    if created {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let _ = flush_directory(parent);
+    }
+    Ok(())
+}
+
+/// Flushes an open file's data and metadata to disk (`FlushFileBuffers`).
+///
+/// That needs write access, which the handle the times were set through lacks, so the
+/// file is opened again with it.
+#[cfg(windows)]
+fn flush_file(_file: &File, path: &Path) -> io::Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Foundation::GENERIC_WRITE;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_BACKUP_SEMANTICS;
+
+    OpenOptions::new()
+        .access_mode(GENERIC_WRITE)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?
+        .sync_all()
+}
+
+/// Flushes an open file's data and metadata to disk (`fsync`).
+#[cfg(not(windows))]
+fn flush_file(file: &File, _path: &Path) -> io::Result<()> {
+    file.sync_all()
+}
+
+/// Flushes a directory's entries to disk, so a file created in it survives a crash.
+/// NTFS only allows this to a handle with write access to the directory.
+fn flush_directory(dir: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use windows_sys::Win32::Foundation::GENERIC_WRITE;
+        use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_BACKUP_SEMANTICS;
+
+        OpenOptions::new()
+            .access_mode(GENERIC_WRITE)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(dir)?
+            .sync_all()
+    }
+    #[cfg(not(windows))]
+    {
+        File::open(dir)?.sync_all()
+    }
+}
+
+/// Opens `path` for setting its times, creating it as an empty file if it is missing and
+/// `no_create` is not set, and says whether it was created. Returns `None` when there is
+/// nothing to touch.
+///
+/// The opens are the only lookups of the path for files, and for directories on Windows:
+/// unless `no_create` is set, creating it anew is tried first, and only if something is
+/// there already is it opened as it is, so which of the two happened is never read
+/// beforehand. Nothing is truncated by the open, so existing content is only at risk
+/// under `options.empty`, which asks for a handle that can write. Symbolic links are not
+/// followed, so a link is opened again through its target, which is where a link to a
+/// missing target is found and handled according to `options.broken_link`.
+fn open_or_create(path: &Path, options: &TouchOptions) -> io::Result<Option<(File, bool)>> {
+    let existing = |file| Some((file, false));
+    match open_or_make(path, !options.no_create, options.empty) {
+        Ok((file, created)) if !is_link_handle(&file)? => Ok(Some((file, created))),
+        Ok(_) => open_through_link(path, options),
+        Err(e) if e.kind() == io::ErrorKind::NotFound && options.no_create => Ok(None),
+        // A directory can't be opened for appending outside Windows.
+        Err(e) if e.kind() == io::ErrorKind::IsADirectory => open_for_times(path).map(existing),
+        // Nor a FIFO with no reader, or a device with nothing behind it, but either can
+        // be opened for reading.
+        #[cfg(unix)]
+        Err(e) if e.raw_os_error() == Some(libc::ENXIO) => open_for_times(path).map(existing),
+        Err(e) if is_link_error(&e) => open_through_link(path, options),
+        Err(e) => Err(e),
+    }
+}
+
+/// Opens `path` as [`open_no_follow`] does, first trying to create it anew if `create` is
+/// set, and says whether it was created. Something removed between the failed creation
+/// and the open is created after all.
+fn open_or_make(path: &Path, create: bool, write: bool) -> io::Result<(File, bool)> {
+    if !create {
+        return open_no_follow(path, false, write).map(|file| (file, false));
+    }
+    loop {
+        match open_no_follow(path, true, write) {
+            Ok(file) => return Ok((file, true)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        match open_no_follow(path, false, write) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            result => return result.map(|file| (file, false)),
+        }
+    }
+}
+
+/// Opens `path` through the symbolic link it names, applying the broken-link policy if
+/// the link's target does not exist, and says whether the target was created.
+fn open_through_link(path: &Path, options: &TouchOptions) -> io::Result<Option<(File, bool)>> {
+    let opened = match options.empty {
+        true => open_for_emptying(path),
+        false => open_for_times(path),
+    };
+    match opened {
+        Ok(file) => Ok(Some((file, false))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match options.broken_link {
+            BrokenLinkPolicy::Error => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "broken symbolic link: its target does not exist",
+            )),
+            BrokenLinkPolicy::Skip => Ok(None),
+            BrokenLinkPolicy::CreateTarget if options.no_create => Ok(None),
+            // Opening the link to create it creates its target instead.
+            BrokenLinkPolicy::CreateTarget => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(|file| Some((file, true))),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Opens `path` without following a symbolic link in its last component: an existing
+/// file, or if `create_new` is set a new one, failing if anything is there already.
+///
+/// On Windows a link is opened as itself (its reparse point), so the caller has to check
+/// the handle; only attribute access is requested, and write access to the data too if
+/// `write` is set, and backup semantics let directories be opened too, as in
+/// [`open_for_times`].
+#[cfg(windows)]
+fn open_no_follow(path: &Path, create_new: bool, write: bool) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, FILE_READ_ATTRIBUTES,
+        FILE_WRITE_ATTRIBUTES, FILE_WRITE_DATA,
+    };
+
+    let data = if write { FILE_WRITE_DATA } else { 0 };
+    // With an explicit access mode, append only lets `create_new` through; it grants
+    // nothing.
+    OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES | data)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+        .append(true)
+        .create_new(create_new)
+        .open(path)
+}
+
+/// Opens `path` without following a symbolic link in its last component: an existing
+/// file, or if `create_new` is set a new one (`O_CREAT | O_EXCL`), failing if anything is
+/// there already, even a link. Opening a link fails with `ELOOP` (`EMLINK` on FreeBSD).
+///
+/// The open doesn't block: a FIFO with no reader fails with `ENXIO` instead of waiting
+/// for one. The file is always opened for appending, which is enough to empty it, so
+/// `write` asks for nothing more.
+#[cfg(unix)]
+fn open_no_follow(path: &Path, create_new: bool, _write: bool) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .append(true)
+        .create_new(create_new)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+}
+
+/// Opens `path` for appending, which is enough to empty it: an existing file, or if
+/// `create_new` is set a new one.
+#[cfg(not(any(unix, windows)))]
+fn open_no_follow(path: &Path, create_new: bool, _write: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .append(true)
+        .create_new(create_new)
+        .open(path)
+}
+
+/// Creates `path`, failing if anything exists there already (`CREATE_NEW`).
+///
+/// Only attribute access is requested, as in [`open_no_follow`].
+#[cfg(windows)]
+fn create_new(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_READ_ATTRIBUTES, FILE_WRITE_ATTRIBUTES};
+
+    OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES)
+        .append(true)
+        .create_new(true)
+        .open(path)
+}
+
+/// Creates `path`, failing if anything exists there already (`O_CREAT | O_EXCL`, which
+/// never follows a symbolic link).
+#[cfg(not(windows))]
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().append(true).create_new(true).open(path)
+}
+
+/// Whether an open handle refers to a symbolic link rather than the file it points to.
+#[cfg(windows)]
+fn is_link_handle(file: &File) -> io::Result<bool> {
+    Ok(file.metadata()?.file_type().is_symlink())
+}
+
+#[cfg(not(windows))]
+fn is_link_handle(_file: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Whether an open failed because it met a symbolic link it was told not to follow.
+#[cfg(unix)]
+fn is_link_error(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ELOOP) | Some(libc::EMLINK))
+}
+
+#[cfg(not(unix))]
+fn is_link_error(_e: &io::Error) -> bool {
+    false
+}
+
+/// Sets the timestamps of an open file, leaving any `None` timestamp unchanged.
+#[cfg(not(target_os = "wasi"))]
+fn set_handle_times(
+    file: &File,
+    atime: Option<FileTime>,
+    mtime: Option<FileTime>,
+) -> io::Result<()> {
+    // On Windows a `None` reaches SetFileTime as a null pointer, which leaves that time
+    // unchanged. (An all-ones FILETIME would instead stop later writes through the handle
+    // from updating it, which is not what is wanted here.)
+    filetime::set_file_handle_times(file, atime, mtime)
+}
+
+/// Sets the timestamps of an open file, leaving any `None` timestamp unchanged.
+///
+/// `filetime` has no WASI backend, so this goes through `File::set_times`, which maps
+/// onto `fd_filestat_set_times`.
+#[cfg(target_os = "wasi")]
+fn set_handle_times(
+    file: &File,
+    atime: Option<FileTime>,
+    mtime: Option<FileTime>,
+) -> io::Result<()> {
+    let mut times = std::fs::FileTimes::new();
+    if let Some(atime) = atime {
+        times = times.set_accessed(to_system_time(atime));
+    }
+    if let Some(mtime) = mtime {
+        times = times.set_modified(to_system_time(mtime));
+    }
+    file.set_times(times)
+}
+
+/// Converts a `FileTime` into the equivalent `SystemTime`.
+#[cfg_attr(not(any(windows, target_os = "wasi")), allow(dead_code))]
+fn to_system_time(time: FileTime) -> std::time::SystemTime {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let seconds = time.unix_seconds();
+    let nanos = Duration::from_nanos(u64::from(time.nanoseconds()));
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds.unsigned_abs()) + nanos
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs()) + nanos
+    }
+}
+
+/// Sets the creation time of an existing file or directory.
+///
+/// Only Windows allows a file's creation time to be changed; elsewhere this returns an
+/// error of kind [`io::ErrorKind::Unsupported`].
+pub fn set_creation_time<P: AsRef<Path>>(path: P, created: FileTime) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTimesExt;
+
+        let file = open_for_times(&resolve_dot_operand(path.as_ref()))?;
+        file.set_times(std::fs::FileTimes::new().set_created(to_system_time(created)))
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = (path, created);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this platform cannot change creation times",
+        ))
+    }
+}
+
+/// Sets the times of `path` itself, without following it if it is a symbolic link.
+///
+/// The OS call takes both times, so a timestamp left as `None` is re-applied from the
+/// link's current metadata.
+fn touch_symlink(path: &Path, options: &TouchOptions) -> io::Result<TouchOutcome> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && options.no_create => {
+            return Ok(TouchOutcome::SkippedMissing)
+        }
+        result => result?,
+    };
+    let atime = options
+        .atime
+        .unwrap_or_else(|| FileTime::from_last_access_time(&metadata));
+    let mtime = options
+        .mtime
+        .unwrap_or_else(|| FileTime::from_last_modification_time(&metadata));
+    filetime::set_symlink_file_times(path, atime, mtime)?;
+    Ok(TouchOutcome::Updated {
+        previous_mtime: Some(FileTime::from_last_modification_time(&metadata)),
+        previous_len: Some(metadata.len()),
+    })
+}
+
+/// Sets the timestamps in `options` by path, for files that can't be opened.
+#[cfg(unix)]
+fn set_times_by_path(path: &Path, options: &TouchOptions) -> io::Result<()> {
+    use filetime::{set_file_atime, set_file_mtime, set_file_times};
+
+    match (options.atime, options.mtime) {
+        (Some(atime), Some(mtime)) => set_file_times(path, atime, mtime),
+        (Some(atime), None) => set_file_atime(path, atime),
+        (None, Some(mtime)) => set_file_mtime(path, mtime),
+        (None, None) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_now_from_fixed_clock() {
+        let time = FileTime::from_unix_time(1_234_567_890, 0);
+        let options = TouchOptions::now_from(&FixedClock(time));
+        assert_eq!((options.atime, options.mtime), (Some(time), Some(time)));
+
+        let path = env::temp_dir().join(format!("mdtouch_lib_clock_{}.tmp", std::process::id()));
+        touch_file(&path, &options).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+        std::fs::remove_file(&path).unwrap();
+
+        let before = FileTime::now();
+        assert!(SystemClock.now() >= before);
+    }
+
+    #[test]
+    fn test_resolve_dot_operand() {
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(resolve_dot_operand(Path::new(".")), cwd);
+        assert_eq!(
+            resolve_dot_operand(Path::new("..")),
+            cwd.parent().unwrap().to_path_buf()
+        );
+        assert_eq!(
+            resolve_dot_operand(Path::new("./file.txt")),
+            PathBuf::from("./file.txt")
+        );
+    }
+
+    #[test]
+    fn test_touch_file_through_handle_keeps_unset_time() {
+        let path = env::temp_dir().join(format!("mdtouch_lib_handle_{}.tmp", std::process::id()));
+        std::fs::write(&path, b"content").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_times(&path, old, old).unwrap();
+
+        let new = FileTime::from_unix_time(1_500_000_000, 123_456_700);
+        let options = TouchOptions {
+            mtime: Some(new),
+            ..TouchOptions::default()
+        };
+        assert_eq!(
+            touch_file(&path, &options).unwrap(),
+            TouchOutcome::Updated {
+                previous_mtime: Some(old),
+                previous_len: Some(7),
+            }
+        );
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_access_time(&metadata), old);
+        assert_eq!(FileTime::from_last_modification_time(&metadata), new);
+        assert_eq!(std::fs::read(&path).unwrap(), b"content");
+
+        // The earlier time comes from the open, so under no_create too.
+        let no_create = TouchOptions {
+            no_create: true,
+            ..options
+        };
+        assert_eq!(
+            touch_file(&path, &no_create).unwrap(),
+            TouchOutcome::Updated {
+                previous_mtime: Some(new),
+                previous_len: Some(7),
+            }
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_touch_file_empty() {
+        let dir = env::temp_dir().join(format!("mdtouch_lib_empty_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.txt");
+        std::fs::write(&path, b"old content").unwrap();
+        let (_, id) = touch_file_with_id(&path, &TouchOptions::default()).unwrap();
+
+        // Emptied through the handle the times are then set through, so they stick.
+        let time = FileTime::from_unix_time(1_600_000_000, 0);
+        let options = TouchOptions {
+            atime: Some(time),
+            mtime: Some(time),
+            empty: true,
+            ..TouchOptions::default()
+        };
+        let (outcome, emptied) = touch_file_with_id(&path, &options).unwrap();
+        assert!(
+            matches!(
+                outcome,
+                TouchOutcome::Updated {
+                    previous_len: Some(11),
+                    ..
+                }
+            ),
+            "{:?}",
+            outcome
+        );
+        assert_eq!(emptied, id);
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.len(), 0);
+        assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+
+        assert_eq!(
+            touch_file(dir.join("new"), &options).unwrap(),
+            TouchOutcome::Created
+        );
+        let err = touch_file(&dir, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        #[cfg(unix)]
+        {
+            std::fs::write(&path, b"again").unwrap();
+            let link = dir.join("link");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            touch_file(&link, &options).unwrap();
+            let metadata = std::fs::metadata(&path).unwrap();
+            assert_eq!(metadata.len(), 0);
+            assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_touch_unreadable_file_falls_back_to_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            env::temp_dir().join(format!("mdtouch_lib_unreadable_{}.tmp", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o200)).unwrap();
+
+        let outcome = touch_file(&path, &TouchOptions::now()).unwrap();
+        assert!(
+            matches!(outcome, TouchOutcome::Updated { .. }),
+            "{:?}",
+            outcome
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_touch_fifo_does_not_block() {
+        let path = env::temp_dir().join(format!("mdtouch_lib_fifo_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let name = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).unwrap();
+        // SAFETY: the name is a NUL-terminated path that outlives the call.
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+
+        // With nothing on either end, a blocking open would never return.
+        let time = FileTime::from_unix_time(1_500_000_000, 0);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let fifo = path.clone();
+        std::thread::spawn(move || {
+            let options = TouchOptions {
+                atime: Some(time),
+                mtime: Some(time),
+                ..TouchOptions::default()
+            };
+            let _ = sender.send(touch_file(&fifo, &options).map_err(|e| e.to_string()));
+        });
+        let outcome = receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .expect("touching a FIFO blocked");
+        assert!(
+            matches!(outcome, Ok(TouchOutcome::Updated { .. })),
+            "{:?}",
+            outcome
+        );
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert!(std::os::unix::fs::FileTypeExt::is_fifo(
+            &metadata.file_type()
+        ));
+        assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_to_system_time_matches_file_time() {
+        for time in [
+            FileTime::from_unix_time(1_600_000_000, 123_456_789),
+            FileTime::from_unix_time(0, 0),
+            FileTime::from_unix_time(-86_400, 500),
+        ] {
+            assert_eq!(FileTime::from_system_time(to_system_time(time)), time);
+        }
+    }
+
+    #[test]
+    fn test_no_create_skips_missing_file() {
+        let path = env::temp_dir().join(format!("mdtouch_lib_nocreate_{}.tmp", std::process::id()));
+        let options = TouchOptions {
+            no_create: true,
+            ..TouchOptions::now()
+        };
+        assert_eq!(
+            touch_file(&path, &options).unwrap(),
+            TouchOutcome::SkippedMissing
+        );
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_no_dereference_touches_link_not_target() {
+        let dir = env::temp_dir().join(format!("mdtouch_lib_link_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        std::fs::write(&target, b"").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_times(&target, old, old).unwrap();
+
+        let new = FileTime::from_unix_time(1_500_000_000, 0);
+        let options = TouchOptions {
+            atime: Some(new),
+            mtime: Some(new),
+            no_dereference: true,
+            ..TouchOptions::default()
+        };
+        let link_before = std::fs::symlink_metadata(&link).unwrap();
+        assert_eq!(
+            touch_file(&link, &options).unwrap(),
+            TouchOutcome::Updated {
+                previous_mtime: Some(FileTime::from_last_modification_time(&link_before)),
+                previous_len: Some(link_before.len()),
+            }
+        );
+
+        let link_metadata = std::fs::symlink_metadata(&link).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&link_metadata), new);
+        let target_metadata = std::fs::metadata(&target).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&target_metadata), old);
+
+        // Missing files are not created when not dereferencing.
+        assert!(touch_file(dir.join("missing.txt"), &options).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Creates a scratch directory holding `link`, a symbolic link to the missing file
+    /// `target`.
+    #[cfg(unix)]
+    fn dangling_link(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("mdtouch_lib_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        (dir, link, target)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_broken_link_error_and_skip() {
+        let (dir, link, target) = dangling_link("broken_error");
+        let err = touch_file(&link, &TouchOptions::now()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let skip = TouchOptions {
+            broken_link: BrokenLinkPolicy::Skip,
+            ..TouchOptions::now()
+        };
+        assert_eq!(
+            touch_file(&link, &skip).unwrap(),
+            TouchOutcome::SkippedMissing
+        );
+        assert!(!target.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_broken_link_create_target() {
+        let (dir, link, target) = dangling_link("broken_create");
+        let create = TouchOptions {
+            broken_link: BrokenLinkPolicy::CreateTarget,
+            ..TouchOptions::now()
+        };
+
+        // -c still wins: nothing is created.
+        let no_create = TouchOptions {
+            no_create: true,
+            ..create
+        };
+        assert_eq!(
+            touch_file(&link, &no_create).unwrap(),
+            TouchOutcome::SkippedMissing
+        );
+        assert!(!target.exists());
+
+        assert_eq!(touch_file(&link, &create).unwrap(), TouchOutcome::Created);
+        assert!(target.is_file());
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_touch_preserves_existing_content() {
+        let path = env::temp_dir().join(format!("mdtouch_lib_content_{}.txt", std::process::id()));
+        std::fs::write(&path, b"keep me").unwrap();
+        touch_file(&path, &TouchOptions::now()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_flush_creates_and_updates() {
+        let dir = env::temp_dir().join(format!("mdtouch_lib_flush_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let time = FileTime::from_unix_time(1_600_000_000, 0);
+        let options = TouchOptions {
+            atime: Some(time),
+            mtime: Some(time),
+            flush: true,
+            ..TouchOptions::default()
+        };
+
+        let path = dir.join("stamp");
+        assert_eq!(touch_file(&path, &options).unwrap(), TouchOutcome::Created);
+        assert_eq!(
+            touch_file(&path, &options).unwrap(),
+            TouchOutcome::Updated {
+                previous_mtime: Some(time),
+                previous_len: Some(0),
+            }
+        );
+        let exclusive = TouchOptions {
+            exclusive: true,
+            ..options
+        };
+        assert_eq!(
+            touch_file(dir.join("claimed"), &exclusive).unwrap(),
+            TouchOutcome::Created
+        );
+        for name in ["stamp", "claimed"] {
+            let metadata = std::fs::metadata(dir.join(name)).unwrap();
+            assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_atomic_create() {
+        let dir = env::temp_dir().join(format!("mdtouch_lib_atomic_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let time = FileTime::from_unix_time(1_600_000_000, 0);
+        let options = TouchOptions {
+            atime: Some(time),
+            mtime: Some(time),
+            atomic_create: true,
+            ..TouchOptions::default()
+        };
+
+        let created = dir.join("ready.marker");
+        assert_eq!(
+            touch_file(&created, &options).unwrap(),
+            TouchOutcome::Created
+        );
+        let existing = dir.join("existing");
+        std::fs::write(&existing, b"keep me").unwrap();
+        let outcome = touch_file(&existing, &options).unwrap();
+        assert!(
+            matches!(outcome, TouchOutcome::Updated { .. }),
+            "{:?}",
+            outcome
+        );
+        assert_eq!(std::fs::read(&existing).unwrap(), b"keep me");
+        for path in [&created, &existing] {
+            let metadata = std::fs::metadata(path).unwrap();
+            assert_eq!(FileTime::from_last_modification_time(&metadata), time);
+        }
+        // Nothing is left behind under a temporary name.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let no_create = TouchOptions {
+            no_create: true,
+            ..options
+        };
+        assert_eq!(
+            touch_file(dir.join("absent"), &no_create).unwrap(),
+            TouchOutcome::SkippedMissing
+        );
+        assert!(!dir.join("absent").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_id_survives_a_rename() {
+        let dir = env::temp_dir().join(format!("mdtouch_lib_file_id_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (before, after) = (dir.join("before.txt"), dir.join("after.txt"));
+        let options = TouchOptions::now();
+        let (outcome, id) = touch_file_with_id(&before, &options).unwrap();
+        assert_eq!(outcome, TouchOutcome::Created);
+        if cfg!(any(unix, windows)) {
+            assert!(id.is_some());
+        }
+        std::fs::rename(&before, &after).unwrap();
+        assert_eq!(touch_file_with_id(&after, &options).unwrap().1, id);
+        let other = touch_file_with_id(&before, &options).unwrap().1;
+        assert_ne!(other, id);
+
+        let missing = TouchOptions {
+            no_create: true,
+            ..options
+        };
+        assert_eq!(
+            touch_file_with_id(dir.join("absent"), &missing).unwrap(),
+            (TouchOutcome::SkippedMissing, None)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exclusive_has_exactly_one_winner() {
+        let path = env::temp_dir().join(format!("mdtouch_lib_claim_{}.tmp", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = TouchOptions {
+            exclusive: true,
+            ..TouchOptions::now()
+        };
+
+        let start = std::sync::Barrier::new(2);
+        let results: Vec<io::Result<TouchOutcome>> = std::thread::scope(|scope| {
+            let racers: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        touch_file(&path, &options)
+                    })
+                })
+                .collect();
+            racers
+                .into_iter()
+                .map(|racer| racer.join().unwrap())
+                .collect()
+        });
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Ok(TouchOutcome::Created))));
+        let lost = results.into_iter().find_map(Result::err).unwrap();
+        assert_eq!(lost.kind(), io::ErrorKind::AlreadyExists);
+
+        // The loser leaves the existing file's times alone.
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_times(&path, old, old).unwrap();
+        let err = touch_file(&path, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), old);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,369 @@
+//! Lock files (`--lockfile`, `--steal-stale`).
+//!
+//! A lock is a file created exclusively, holding the ID of the process that took it, the
+//! host it runs on and when it was taken, one per line:
+//!
+//! ```text
+//! 4242
+//! BUILD01
+//! 2025-02-03T10:00:00.000000000Z
+//! ```
+//!
+//! When the file already exists the lock is held, and the error names the holder and says
+//! whether it is still running, which can only be checked on the same host. A stale lock,
+//! whose holder is gone or which is older than a given age, may be taken over. Taking
+//! over renames the old file aside, which only one contender can do, checks that what was
+//! moved is the lock that was judged stale, and then creates a fresh lock exclusively, so
+//! of several processes stealing the same lock exactly one ends up holding it.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use mdtouch::date::format_iso8601;
+use mdtouch::FileTime;
+
+/// The process named in a lock file.
+#[derive(Debug, Clone, PartialEq)]
+struct Holder {
+    pid: u32,
+    host: String,
+}
+
+/// Takes the lock at `path`. If it is held, fails with [`io::ErrorKind::AlreadyExists`]
+/// and a description of the holder, unless `steal_after` is given and the lock is stale:
+/// its holder is no longer running, or the file was last modified at least that long ago.
+pub fn acquire(path: &Path, steal_after: Option<Duration>) -> io::Result<()> {
+    match create(path) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        // Released since the attempt to create it.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return create(path),
+        Err(e) => return Err(e),
+    };
+    let holder = parse(&contents);
+    let running = holder.as_ref().and_then(is_running);
+    let modified = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return create(path),
+        Err(e) => return Err(e),
+    };
+    let age = modified.elapsed().unwrap_or_default();
+    let stale = steal_after.is_some_and(|limit| running == Some(false) || age >= limit);
+    if !stale {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            describe(holder.as_ref(), running),
+        ));
+    }
+    steal(path, &contents)
+}
+
+/// Replaces the stale lock at `path`, whose contents were `inspected`.
+fn steal(path: &Path, inspected: &[u8]) -> io::Result<()> {
+    let aside = aside_path(path);
+    match fs::rename(path, &aside) {
+        Ok(()) => {}
+        // Another contender moved it first; whoever creates the new lock first wins.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return create(path),
+        Err(e) => return Err(e),
+    }
+    if fs::read(&aside)? != inspected {
+        // The stale lock was replaced by a live one between reading and moving it. Put
+        // that back; the hard link fails rather than overwrite a lock taken meanwhile.
+        let restored = fs::hard_link(&aside, path);
+        fs::remove_file(&aside)?;
+        restored?;
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "locked: another process took the lock over first",
+        ));
+    }
+    fs::remove_file(&aside)?;
+    create(path)
+}
+
+/// A name next to `path` that no other contender will use for its moved-aside lock.
+fn aside_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".stale-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+/// Creates the lock file at `path`, failing if it exists, and writes this process's
+/// details into it.
+fn create(path: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let contents = format!(
+        "{}\n{}\n{}\n",
+        std::process::id(),
+        hostname(),
+        format_iso8601(FileTime::now())
+    );
+    file.write_all(contents.as_bytes())
+}
+
+/// Reads the holder from a lock file's contents. A lock still being written, or written
+/// by something else, may not name one.
+fn parse(contents: &[u8]) -> Option<Holder> {
+    let text = String::from_utf8_lossy(contents);
+    let mut lines = text.lines();
+    let pid = lines.next()?.trim().parse().ok()?;
+    let host = lines.next().unwrap_or_default().trim().to_string();
+    Some(Holder { pid, host })
+}
+
+/// Whether the holder is still running, if that can be told from here.
+fn is_running(holder: &Holder) -> Option<bool> {
+    if !holder.host.eq_ignore_ascii_case(&hostname()) {
+        return None;
+    }
+    process_running(holder.pid)
+}
+
+/// Describes who holds a lock, for the error reported when it can't be taken.
+fn describe(holder: Option<&Holder>, running: Option<bool>) -> String {
+    let Some(holder) = holder else {
+        return "locked, but the lock file does not name its holder".to_string();
+    };
+    let mut text = format!("locked by process {}", holder.pid);
+    if !holder.host.is_empty() {
+        text.push_str(&format!(" on {}", holder.host));
+    }
+    match running {
+        Some(true) => text.push_str(", which is still running"),
+        Some(false) => text.push_str(", which is no longer running (use --steal-stale)"),
+        None => {}
+    }
+    text
+}
+
+/// Parses a `--steal-stale` age: a number of seconds, optionally followed by `s`, `m`,
+/// `h` or `d`.
+pub fn parse_age(text: &str) -> io::Result<Duration> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid --steal-stale age '{}' (expected a number followed by s, m, h or d)",
+                text
+            ),
+        )
+    };
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => text.split_at(split),
+        None => (text, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = number.parse().map_err(|_| invalid())?;
+    count
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// The name of this computer.
+#[cfg(windows)]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// The name of this computer.
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer's length is passed along with it, and it is NUL-terminated below
+    // whatever the call wrote.
+    let status = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len() - 1) };
+    if status != 0 {
+        return String::new();
+    }
+    let len = buffer.iter().position(|&byte| byte == 0).unwrap_or(0);
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+#[cfg(not(any(windows, unix)))]
+fn hostname() -> String {
+    String::new()
+}
+
+/// Whether the process with ID `pid` is running, if that can be found out.
+#[cfg(windows)]
+fn process_running(pid: u32) -> Option<bool> {
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_ACCESS_DENIED, ERROR_INVALID_PARAMETER, STILL_ACTIVE,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    // SAFETY: the handle is checked before use and closed once the exit code is read.
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            // There is no process with that ID, or there is one we may not look at.
+            return match GetLastError() {
+                ERROR_INVALID_PARAMETER => Some(false),
+                ERROR_ACCESS_DENIED => Some(true),
+                _ => None,
+            };
+        }
+        let mut code = 0;
+        let known = GetExitCodeProcess(process, &mut code) != 0;
+        CloseHandle(process);
+        known.then_some(code == STILL_ACTIVE as u32)
+    }
+}
+
+/// Whether the process with ID `pid` is running, if that can be found out.
+#[cfg(unix)]
+fn process_running(pid: u32) -> Option<bool> {
+    let pid = libc::pid_t::try_from(pid).ok().filter(|&pid| pid > 0)?;
+    // SAFETY: signal 0 only checks that the process exists and may be signalled.
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return Some(true);
+    }
+    match io::Error::last_os_error().raw_os_error() {
+        // It exists, but belongs to someone else.
+        Some(libc::EPERM) => Some(true),
+        Some(libc::ESRCH) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(windows, unix)))]
+fn process_running(_pid: u32) -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("mdtouch_{}_{}.lock", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// The ID of a process that has exited.
+    fn dead_pid() -> u32 {
+        let program = if cfg!(windows) { "cmd" } else { "true" };
+        let mut child = std::process::Command::new(program)
+            .args(
+                cfg!(windows)
+                    .then_some(["/C", "exit"])
+                    .into_iter()
+                    .flatten(),
+            )
+            .spawn()
+            .unwrap();
+        child.wait().unwrap();
+        child.id()
+    }
+
+    #[test]
+    fn test_fresh_lock_names_this_process() {
+        let path = lock_path("fresh");
+        acquire(&path, None).unwrap();
+        let holder = parse(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            holder,
+            Holder {
+                pid: std::process::id(),
+                host: hostname()
+            }
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_live_holder_keeps_the_lock() {
+        let path = lock_path("live");
+        acquire(&path, None).unwrap();
+        let before = fs::read(&path).unwrap();
+
+        let err = acquire(&path, Some(Duration::from_secs(3600))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(err.to_string().contains("still running"), "{}", err);
+        assert_eq!(fs::read(&path).unwrap(), before);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        // The holder is gone.
+        let path = lock_path("dead");
+        fs::write(&path, format!("{}\n{}\n", dead_pid(), hostname())).unwrap();
+        let err = acquire(&path, None).unwrap_err();
+        assert!(err.to_string().contains("no longer running"), "{}", err);
+        acquire(&path, Some(Duration::from_secs(3600))).unwrap();
+        assert_eq!(
+            parse(&fs::read(&path).unwrap()).unwrap().pid,
+            std::process::id()
+        );
+        fs::remove_file(&path).unwrap();
+
+        // The holder is alive but the lock is old.
+        let path = lock_path("old");
+        fs::write(&path, format!("{}\n{}\n", std::process::id(), hostname())).unwrap();
+        let old = FileTime::from_unix_time(FileTime::now().unix_seconds() - 7200, 0);
+        filetime::set_file_mtime(&path, old).unwrap();
+        assert!(acquire(&path, Some(Duration::from_secs(86_400))).is_err());
+        acquire(&path, Some(Duration::from_secs(3600))).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_one_contender_steals_a_stale_lock() {
+        let path = lock_path("contended");
+        fs::write(&path, format!("{}\n{}\n", dead_pid(), hostname())).unwrap();
+
+        let start = std::sync::Barrier::new(4);
+        let winners = std::thread::scope(|scope| {
+            let contenders: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        acquire(&path, Some(Duration::from_secs(3600)))
+                    })
+                })
+                .collect();
+            contenders
+                .into_iter()
+                .map(|contender| contender.join().unwrap())
+                .filter(Result::is_ok)
+                .count()
+        });
+        assert_eq!(winners, 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_age("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_age("1d").unwrap(), Duration::from_secs(86_400));
+        for bad in ["", "m", "5x", "-5", "1.5h"] {
+            assert!(parse_age(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
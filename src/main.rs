@@ -1,247 +1,8735 @@
+use std::cell::RefCell;
 use std::env;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::Path;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use filetime::{set_file_times, FileTime};
+use mdtouch::date::{
+    format_iso8601, humanize, parse_date, parse_dotnet_ticks, parse_filetime, parse_time_slots,
+    parse_touch_stamp, DateFormat, Locale, ParseContext, ParseError,
+};
+use mdtouch::{
+    normalize_path, set_creation_time, touch_each, touch_file, touch_file_with_id,
+    BrokenLinkPolicy, FileId, FileTime, HandlePool, SystemClock, TimeSource, TimeZone,
+    TouchOptions, TouchOutcome, HANDLE_POOL_SIZE,
+};
+
+mod attrs;
+mod audit;
+mod backup;
+mod checkpoint;
+mod content_hash;
+mod diff;
+mod elevate;
+mod emit;
+mod encoding;
+mod error_log;
+mod exclude;
+mod exec;
+mod glob;
+mod gnu;
+mod guard;
+mod interrupt;
+mod lock;
+mod open_files;
+mod owner;
+mod pe;
+mod probe;
+mod progress;
+mod regex;
+mod report;
+mod saved;
+mod security;
+mod self_test;
+mod serve;
+mod stat_cache;
+mod vars;
+mod volume;
+mod walk;
+mod watch;
+mod zip;
+
+use attrs::AttrFilter;
+use audit::{json_string, AuditLog};
+use checkpoint::Checkpoint;
+use encoding::InputEncoding;
+use error_log::ErrorLog;
+use exclude::Excludes;
+use exec::Exec;
+use guard::SystemGuard;
+use progress::{Progress, ProgressStyle, Tally};
+use regex::Regex;
+use stat_cache::StatCache;
+use volume::{SystemDetector, Volume, Volumes};
+use walk::{Crossing, Found, Order};
+
+/// Compile-time build date and time. To override, set the BUILD_DATETIME environment
+/// variable at compile time. Otherwise, a default value is used.
+const BUILD_DATETIME: &str = match option_env!("BUILD_DATETIME") {
+    Some(val) => val,
+    None => "2025-02-03 10:00:00",
+};
+
+/// Returns a detailed help message describing the usage of the tool.
+fn help_message() -> String {
+    let mut msg = String::new();
+    msg.push_str("Usage: mdtouch [OPTIONS] <file> [file...]\n\n");
+    msg.push_str(
+        "A command line tool to mimic the behaviour of the Unix touch command on Windows.\n",
+    );
+    msg.push_str(
+        "If the file does not exist, it will be created. Otherwise, its access and modification\n",
+    );
+    msg.push_str("times will be updated to the current time.\n\n");
+    msg.push_str("Options:\n");
+    msg.push_str("  -h, -?              Display this help message and exit.\n");
+    msg.push_str("  --files-from FILE   Read additional file operands from FILE, one per line\n");
+    msg.push_str(
+        "                      (or NUL-separated with -0). Use - to read standard input.\n",
+    );
+    msg.push_str(
+        "  --filelist FILE     Read file operands from the manifest FILE, one per line, and\n",
+    );
+    msg.push_str(
+        "                      resolve relative ones against FILE's own directory. Blank\n",
+    );
+    msg.push_str("                      lines and lines starting with # are ignored.\n");
+    msg.push_str(
+        "  --failed-to FILE    Write every operand that could not be touched to FILE in a\n",
+    );
+    msg.push_str(
+        "                      format accepted by --files-from. FILE is only created when\n",
+    );
+    msg.push_str("                      there were failures. Files held back as system ones are\n");
+    msg.push_str("                      not listed.\n");
+    msg.push_str(
+        "  -0, --null          Use NUL instead of newline as the separator for --files-from\n",
+    );
+    msg.push_str("                      and --failed-to lists.\n");
+    msg.push_str("  --input-encoding ENCODING\n");
+    msg.push_str("                      Read --files-from lists and --filelist manifests as\n");
+    msg.push_str("                      utf8, utf16le, utf16be or ansi (the Windows ANSI code\n");
+    msg.push_str("                      page). By default a byte order mark decides, and\n");
+    msg.push_str("                      otherwise UTF-8 is assumed.\n");
+    msg.push_str("  -a                  Change only the access time.\n");
+    msg.push_str("  -m                  Change only the modification time.\n");
+    msg.push_str("  --leave-atime       Leave the access time as it is.\n");
+    msg.push_str("  --leave-mtime       Leave the modification time as it is.\n");
+    msg.push_str("  -r, --reference FILE\n");
+    msg.push_str("                      Use FILE's times instead of the current time. FILE may\n");
+    msg.push_str("                      be a directory.\n");
+    msg.push_str("  --reference-all FILE\n");
+    msg.push_str("                      Like -r, but also copy FILE's creation time where the\n");
+    msg.push_str("                      target filesystem can store it.\n");
+    msg.push_str(
+        "  --mirror-from DIR   Copy the times of the file at the same relative path under\n",
+    );
+    msg.push_str(
+        "                      DIR, for the single operand and (with -R) everything below\n",
+    );
+    msg.push_str(
+        "                      it. Files with no counterpart are skipped with a warning.\n",
+    );
+    msg.push_str("  --reference-pe EXE  Use the link timestamp in the header of the Windows PE\n");
+    msg.push_str(
+        "                      image EXE (an .exe or .dll) instead of the current time.\n",
+    );
+    msg.push_str(
+        "  -d, --date STRING   Use STRING instead of the current time. Accepts ISO 8601\n",
+    );
+    msg.push_str("                      (2025-02-03 10:00:00), RFC 2822, month names (3 March\n");
+    msg.push_str(
+        "                      2024) and @SECONDS since the Unix epoch. Times without a\n",
+    );
+    msg.push_str("                      zone are local.\n");
+    msg.push_str("  --date-format PATTERN\n");
+    msg.push_str("                      Read -d and --times with the strftime PATTERN instead,\n");
+    msg.push_str("                      such as %d.%m.%Y %H:%M. Parts of the date left out are\n");
+    msg.push_str("                      today's (or the first, below those given); a time left\n");
+    msg.push_str("                      out is midnight.\n");
+    msg.push_str(
+        "  --tz ZONE           Read times without a zone in ZONE instead of local time:\n",
+    );
+    msg.push_str("                      an offset such as +05:30, or in builds with the tzdb\n");
+    msg.push_str("                      feature a name such as America/New_York.\n");
+    msg.push_str("  -t STAMP            Use [[CC]YY]MMDDhhmm[.ss] (local time) instead of the\n");
+    msg.push_str("                      current time.\n");
+    msg.push_str(
+        "  --filetime TICKS    Use the raw Windows FILETIME TICKS (100 ns ticks since 1601,\n",
+    );
+    msg.push_str("                      in decimal or 0x hexadecimal) as the time.\n");
+    msg.push_str(
+        "  --times A,M,C       Set the access, modification and creation times, each in\n",
+    );
+    msg.push_str("                      any --date form without commas, or - to leave it alone.\n");
+    msg.push_str("  --force-create-time-monotonic\n");
+    msg.push_str("                      Give each file created a creation time at least 100 ns\n");
+    msg.push_str("                      after the last one's, starting from the creation time\n");
+    msg.push_str("                      --times or --reference-all gives. Files are touched one\n");
+    msg.push_str("                      at a time.\n");
+    msg.push_str(
+        "  --real-creation     Leave files this run creates with the real time of their\n",
+    );
+    msg.push_str(
+        "                      creation. By default, on Windows, they get the time given\n",
+    );
+    msg.push_str("                      (by -d, -t, -r and so on) as their creation time too.\n");
+    msg.push_str("  --min-mtime TIME    Only raise modification times older than TIME to TIME,\n");
+    msg.push_str(
+        "                      leaving newer files alone. TIME is a date as -d reads it,\n",
+    );
+    msg.push_str("                      a -t stamp, or a file whose modification time is taken.\n");
+    msg.push_str("  --fix-future[=MARGIN]\n");
+    msg.push_str("                      Only set back to now the access and modification times\n");
+    msg.push_str(
+        "                      more than MARGIN (0 by default; 30s, 5m, 2h, 1d) ahead of\n",
+    );
+    msg.push_str("                      the clock, leaving every other file and time alone.\n");
+    msg.push_str(
+        "  --detect-clock-skew Warn about each file whose modification time is ahead of\n",
+    );
+    msg.push_str("                      the system clock, and by how much, as on a share whose\n");
+    msg.push_str("                      clock runs fast. Touching it to now moves it back.\n");
+    msg.push_str("  --warn-if-open      With -v, warn about each file another process has open\n");
+    msg.push_str("                      for writing, whose next write may set its times again.\n");
+    msg.push_str("                      The file is touched all the same.\n");
+    msg.push_str("  --only-newer        Leave alone files whose modification time is later than\n");
+    msg.push_str("                      the one given, so no file's is ever moved back.\n");
+    msg.push_str("  --creation-from-mtime\n");
+    msg.push_str("                      Give files created after they were last modified their\n");
+    msg.push_str("                      modification time as their creation time. Alone, it\n");
+    msg.push_str("                      changes nothing else and skips consistent files.\n");
+    msg.push_str("  --dotnet-ticks VALUE[:utc|:local]\n");
+    msg.push_str("                      Use the .NET DateTime.Ticks VALUE (100 ns ticks since\n");
+    msg.push_str("                      0001-01-01, UTC unless :local) as the time.\n");
+    msg.push_str(
+        "  --locale NAME       Also recognise month names in --date for this locale (for\n",
+    );
+    msg.push_str(
+        "                      example fr, de-AT or es_ES.UTF-8). Defaults to the system\n",
+    );
+    msg.push_str(
+        "                      locale from LC_ALL, LC_TIME or LANG. English always works.\n",
+    );
+    msg.push_str("  --atime-from FILE   Take the access time from FILE.\n");
+    msg.push_str("  --mtime-from FILE   Take the modification time from FILE.\n");
+    msg.push_str("  -c, --no-create     Do not create files that do not exist.\n");
+    msg.push_str("  --exclusive         Only create files: fail for a file that already exists,\n");
+    msg.push_str("                      leaving it untouched. Exits with status 3 when that was\n");
+    msg.push_str("                      the only kind of failure.\n");
+    msg.push_str(
+        "  --empty             Truncate each existing file to zero bytes as well, keeping\n",
+    );
+    msg.push_str("                      it in place for whatever has it open. -v says how many\n");
+    msg.push_str("                      bytes were discarded. Directories are an error.\n");
+    msg.push_str(
+        "  --backup[=SUFFIX]   Copy each existing, non-empty file to FILE.TIMESTAMP.bak\n",
+    );
+    msg.push_str("                      (or SUFFIX) before touching it. -v shows the copies.\n");
+    msg.push_str("  --if-content-changed\n");
+    msg.push_str("                      Only touch files whose SHA-256 differs from the one\n");
+    msg.push_str("                      stored when they were last touched this way, in the\n");
+    msg.push_str("                      stream FILE:mdtouch.hash on NTFS, or FILE.mdtouch-hash.\n");
+    msg.push_str("  --touch-mode MODE   create: only create missing files, as --exclusive.\n");
+    msg.push_str("                      update: only update existing ones, as -c. both: do\n");
+    msg.push_str("                      either, the default.\n");
+    msg.push_str(
+        "  --lockfile          Like --exclusive, but write this process's ID, host name\n",
+    );
+    msg.push_str(
+        "                      and the time into each file, and report who holds a lock\n",
+    );
+    msg.push_str("                      that is already taken.\n");
+    msg.push_str(
+        "  --flush             Flush each file's new times to disk before moving on, and\n",
+    );
+    msg.push_str(
+        "                      the directory entry of each file created. Slower, but the\n",
+    );
+    msg.push_str("                      times survive a power loss.\n");
+    msg.push_str(
+        "  --atomic-create     Create each missing file under a temporary name and rename\n",
+    );
+    msg.push_str(
+        "                      it into place, so other processes never see it half made.\n",
+    );
+    msg.push_str(
+        "  --steal-stale AGE   With --lockfile, take over a lock whose holder is no longer\n",
+    );
+    msg.push_str(
+        "                      running or which is older than AGE (seconds, or a number\n",
+    );
+    msg.push_str("                      followed by s, m, h or d).\n");
+    msg.push_str(
+        "  --no-dereference    Change the times of a symbolic link rather than its target.\n",
+    );
+    msg.push_str("                      Missing files are not created.\n");
+    msg.push_str("  --broken-link POLICY\n");
+    msg.push_str(
+        "                      What to do with a symbolic link whose target is missing:\n",
+    );
+    msg.push_str(
+        "                      error (the default) reports it, skip leaves it alone and\n",
+    );
+    msg.push_str("                      create-target creates the target (unless -c is given).\n");
+    msg.push_str(
+        "  --print0            Write each file created or updated to standard output as\n",
+    );
+    msg.push_str("                      it is done, followed by NUL, for piping to xargs -0 and\n");
+    msg.push_str("                      the like. Anything else goes to standard error.\n");
+    msg.push_str("  --print0-errors[=FILE]\n");
+    msg.push_str(
+        "                      After the run, write the operands that failed to standard\n",
+    );
+    msg.push_str(
+        "                      error (or FILE), each followed by NUL, for retrying with\n",
+    );
+    msg.push_str(
+        "                      --null --files-from. Error messages are left out when the\n",
+    );
+    msg.push_str("                      list goes to standard error.\n");
+    msg.push_str("  --emit-command[=FILE]\n");
+    msg.push_str(
+        "                      Before touching anything, write the equivalent command to\n",
+    );
+    msg.push_str(
+        "                      standard error (or FILE): operands expanded and absolute,\n",
+    );
+    msg.push_str("                      with --no-glob, and times as --times @SECONDS.\n");
+    msg.push_str(
+        "  --progress[=STYLE]  Show processed X/Y on standard error as the run goes, and\n",
+    );
+    msg.push_str("                      a summary at the end. STYLE lines (the default) prints\n");
+    msg.push_str("                      a line per update; line rewrites one line in place on\n");
+    msg.push_str("                      a terminal.\n");
+    msg.push_str("  --report-format FILE\n");
+    msg.push_str(
+        "                      After the run, write a report rendered from the template\n",
+    );
+    msg.push_str(
+        "                      in FILE: {#files} and {/files} lines around what is written\n",
+    );
+    msg.push_str(
+        "                      for each file, with {path}, {outcome}, {time} and {reason},\n",
+    );
+    msg.push_str(
+        "                      and {total}, {created}, {updated}, {unchanged}, {skipped}\n",
+    );
+    msg.push_str("                      and {failed} anywhere. {{ and }} are braces.\n");
+    msg.push_str(
+        "  -v, --verbose       Print each path as it is touched. With --json, print a line\n",
+    );
+    msg.push_str("                      of JSON for each, with the time applied and the\n");
+    msg.push_str("                      filesystem it is on.\n");
+    msg.push_str(
+        "  --humanize          With -v, also print the time applied, with how long ago it\n",
+    );
+    msg.push_str("                      is, such as (2 minutes ago).\n");
+    msg.push_str("  --touch-and-print-id\n");
+    msg.push_str(
+        "                      Print each path as it is touched, with its volume serial\n",
+    );
+    msg.push_str(
+        "                      number and file index (device and inode elsewhere), which\n",
+    );
+    msg.push_str("                      stay the same across renames. Works with --json.\n");
+    msg.push_str("  --report-unchanged  Leave alone files that already have the times given, as\n");
+    msg.push_str("                      far as their filesystem keeps them, and list each as\n");
+    msg.push_str("                      unchanged, with or without -v. They are not failures.\n");
+    msg.push_str("  -q, --quiet         Print nothing on standard output, even with -v.\n");
+    msg.push_str("  --quiet-errors      Do not report files that could not be touched; the exit\n");
+    msg.push_str("                      status still shows the failure. Usage errors are still\n");
+    msg.push_str("                      reported. Independent of -q, so -q --quiet-errors is\n");
+    msg.push_str("                      fully silent.\n");
+    msg.push_str("  --relative-to DIR   Show paths in output relative to DIR. Paths outside DIR\n");
+    msg.push_str("                      are shown in full.\n");
+    msg.push_str("  --on-error POLICY   What to do when a file cannot be touched: continue (the\n");
+    msg.push_str(
+        "                      default) carries on with the remaining files, stop halts\n",
+    );
+    msg.push_str(
+        "                      at the first failure, and retry retries transient errors\n",
+    );
+    msg.push_str("                      (such as sharing violations) before carrying on.\n");
+    msg.push_str(
+        "  --retries N         Attempts after the first for --on-error=retry (default 3).\n",
+    );
+    msg.push_str(
+        "  --net-timeout MS    Give up on a file after MS milliseconds, as on a network\n",
+    );
+    msg.push_str("                      share that stopped answering. The abandoned change may\n");
+    msg.push_str("                      still be made in the background.\n");
+    msg.push_str(
+        "  -j, --jobs N        Touch up to N files at once. Output and errors still come\n",
+    );
+    msg.push_str("                      in operand order.\n");
+    msg.push_str(
+        "  --confirm-over N    Ask before touching more than N files (10000 by default)\n",
+    );
+    msg.push_str("                      when standard input is a terminal.\n");
+    msg.push_str("  -y, --yes           Touch a large batch without asking.\n");
+    msg.push_str(
+        "  --max-files N       Touch nothing and fail if there are more than N files once\n",
+    );
+    msg.push_str("                      wildcards, lists and -R are expanded. Never asks.\n");
+    msg.push_str(
+        "  --select WHICH      Touch only the newest or oldest (by modification time) of\n",
+    );
+    msg.push_str(
+        "                      all the operands once wildcards are expanded, ignoring any\n",
+    );
+    msg.push_str("                      that do not exist.\n");
+    msg.push_str(
+        "  --sort ORDER        Process the operands, once expanded, by name, name-desc,\n",
+    );
+    msg.push_str(
+        "                      size or mtime (oldest first), or as-given (the default).\n",
+    );
+    msg.push_str("                      Missing files come first by size or mtime.\n");
+    msg.push_str(
+        "  --apply-to KIND     Touch only files, only dirs, or both (the default). Other\n",
+    );
+    msg.push_str("                      operands are skipped, with a note under -v.\n");
+    msg.push_str(
+        "  -R, --recursive     Touch everything below directory operands as well. Links\n",
+    );
+    msg.push_str(
+        "                      are touched but not descended into, with a note under -v.\n",
+    );
+    msg.push_str("  --follow-links      With -R, descend through links and junctions to\n");
+    msg.push_str("                      directories too, but never into one already walked.\n");
+    msg.push_str("  -x, --one-file-system\n");
+    msg.push_str("                      With -R, leave out directories on another volume than\n");
+    msg.push_str("                      the operand, such as drives mounted into a folder.\n");
+    msg.push_str(
+        "  --sorted            With -R, go through each directory in order of name, ignoring\n",
+    );
+    msg.push_str(
+        "                      case unless --case-sensitive is given, so runs list files\n",
+    );
+    msg.push_str("                      in the same order on any filesystem. Each directory is\n");
+    msg.push_str("                      read whole first, taking memory for all its entries.\n");
+    msg.push_str(
+        "  --attr-filter EXPR  Touch only existing operands whose attributes match EXPR,\n",
+    );
+    msg.push_str("                      terms such as +A (must have) or -R (must not have) over\n");
+    msg.push_str(
+        "                      R, H, S, A, T and I, as with attrib. Others are skipped,\n",
+    );
+    msg.push_str("                      with a note under -v. May be given more than once.\n");
+    msg.push_str(
+        "  --skip-hidden       Leave out hidden files (on Windows, with the hidden attribute;\n",
+    );
+    msg.push_str(
+        "                      elsewhere, named with a leading dot) that a wildcard or -R\n",
+    );
+    msg.push_str("                      expands to, and anything below hidden directories.\n");
+    msg.push_str("  --skip-system       Likewise for files with the Windows system attribute.\n");
+    msg.push_str(
+        "  --regex PATTERN     Of what a wildcard or -R expands to, keep only paths matching\n",
+    );
+    msg.push_str(
+        "                      the regular expression PATTERN, matched against the path\n",
+    );
+    msg.push_str("                      below the operand with / between components. May be\n");
+    msg.push_str("                      given more than once, to keep paths matching any.\n");
+    msg.push_str("  --regex-full-path   Match --regex against the absolute path instead.\n");
+    msg.push_str(
+        "  --exclude PATTERN   Of what a wildcard or -R expands to, leave out paths matching\n",
+    );
+    msg.push_str(
+        "                      the wildcard PATTERN, and everything below them. Without\n",
+    );
+    msg.push_str("                      a /, it matches names at any depth; with one, the path\n");
+    msg.push_str("                      below the operand. May be given more than once.\n");
+    msg.push_str(
+        "  --exclude-from FILE Read --exclude patterns from FILE, one per line, skipping\n",
+    );
+    msg.push_str(
+        "                      blank lines and # comments. May be given more than once.\n",
+    );
+    msg.push_str("  --skip-readonly     Pass over read-only files, operands or not, as skipped\n");
+    msg.push_str("                      rather than failed. Noted under -v.\n");
+    msg.push_str("  --stat-cache        Read each operand's metadata once, however many of the\n");
+    msg.push_str("                      checks above look at it. Under -v, says how many reads\n");
+    msg.push_str("                      that saved.\n");
+    msg.push_str(
+        "  --wrap-handle-reuse Keep the last 32 files touched open, so a file touched again\n",
+    );
+    msg.push_str("                      in the same run has its times set through the handle\n");
+    msg.push_str(
+        "                      it already has. Under -v, says how many opens that saved.\n",
+    );
+    msg.push_str(
+        "  --no-glob           Take *, ? and [ in operands literally instead of expanding\n",
+    );
+    msg.push_str("                      them.\n");
+    msg.push_str(
+        "  --fail-on-zero-matched  Fail if no file is left to process once wildcards, lists\n",
+    );
+    msg.push_str("                      and -R are expanded, naming the patterns that matched\n");
+    msg.push_str("                      nothing. A pattern that matches nothing is then dropped\n");
+    msg.push_str("                      rather than created as written.\n");
+    msg.push_str(
+        "  --case-sensitive    Match wildcards against names in exact case. By default case\n",
+    );
+    msg.push_str("                      is ignored on Windows, as Windows does.\n");
+    msg.push_str(
+        "  --mkdir             Create operands ending in a path separator (out\\cache\\) as\n",
+    );
+    msg.push_str("                      directories if missing. Without it they must already be\n");
+    msg.push_str("                      directories.\n");
+    msg.push_str("  -p, --parents       With --mkdir, also create missing parent directories.\n");
+    msg.push_str(
+        "  --owner ACCOUNT     Make ACCOUNT (a SID or account name; a user name or ID on\n",
+    );
+    msg.push_str("                      Unix) the owner of each file this run creates. Needs\n");
+    msg.push_str("                      SeRestorePrivilege (root on Unix) for other accounts.\n");
+    msg.push_str("  --owner-existing    With --owner, re-own files that already existed too.\n");
+    msg.push_str(
+        "  --copy-security     With -r, give each file this run creates the reference's\n",
+    );
+    msg.push_str("                      owner, group and permissions (its security descriptor).\n");
+    msg.push_str("                      Windows only.\n");
+    msg.push_str(
+        "  --exec CMD          Run CMD for each file touched, with {} replaced by its path,\n",
+    );
+    msg.push_str(
+        "                      as in --exec \"notify --file {}\". A command exiting with\n",
+    );
+    msg.push_str("                      a non-zero status fails the file. At most --jobs run at\n");
+    msg.push_str("                      once.\n");
+    msg.push_str(
+        "  --exec-shell        Run the --exec command through cmd /C (sh -c elsewhere),\n",
+    );
+    msg.push_str("                      with {} replaced by the quoted path.\n");
+    msg.push_str(
+        "  --preflight         Check the whole command line (time options, reference files,\n",
+    );
+    msg.push_str(
+        "                      file lists and operands) and report every problem found,\n",
+    );
+    msg.push_str("                      without touching anything.\n");
+    msg.push_str(
+        "  --fail-if-changes   With --preflight, also fail when any operand would be created\n",
+    );
+    msg.push_str(
+        "                      or get new times, listing each under -v. For checking in\n",
+    );
+    msg.push_str("                      CI that everything is already up to date.\n");
+    msg.push_str(
+        "  --probe             Report each operand's filesystem, the resolution it keeps\n",
+    );
+    msg.push_str("                      times at and whether access times are updated, without\n");
+    msg.push_str("                      touching anything. Add --json for JSON lines.\n");
+    msg.push_str(
+        "  --self-test [DIR]   Set each time of a scratch file in DIR (the current directory\n",
+    );
+    msg.push_str(
+        "                      by default) at several precisions, read them back and report\n",
+    );
+    msg.push_str("                      what the filesystem keeps. Fails if modification times\n");
+    msg.push_str("                      cannot be set.\n");
+    msg.push_str(
+        "  --save-times        Save each file's times to FILE.mdtouch-times beside it, to\n",
+    );
+    msg.push_str("                      keep them across an edit. Nothing else is done.\n");
+    msg.push_str("  --restore-times     Put back the times --save-times saved, and delete the\n");
+    msg.push_str("                      .mdtouch-times file.\n");
+    msg.push_str(
+        "  --temp [DIR]        Create a new file with a unique random name in DIR (or the\n",
+    );
+    msg.push_str("                      temporary directory), touched as usual, and print its\n");
+    msg.push_str("                      absolute path and nothing else.\n");
+    msg.push_str("  --prefix NAME       Start the name of the --temp file with NAME (mdtouch-).\n");
+    msg.push_str("  --suffix .EXT       End the name of the --temp file with .EXT (.tmp).\n");
+    msg.push_str(
+        "  --unique            Create each file new: if the name is taken, number it as\n",
+    );
+    msg.push_str("                      report (2).txt, report (3).txt and so on. Prints the\n");
+    msg.push_str("                      names created.\n");
+    msg.push_str("  --unique-pattern P  Number a taken name by P, from {stem}, {n} and {ext}\n");
+    msg.push_str("                      (\"{stem} ({n}){ext}\").\n");
+    msg.push_str(
+        "  --from-zip ARCHIVE  Set the modification time of each file extracted from the\n",
+    );
+    msg.push_str(
+        "                      zip ARCHIVE into the directory operand to its entry's time.\n",
+    );
+    msg.push_str("                      Entries missing on disk are reported and skipped.\n");
+    msg.push_str("  --diff-trees FIRST SECOND\n");
+    msg.push_str("                      Report the timestamp differences between matching paths\n");
+    msg.push_str(
+        "                      in two trees, and the paths only one of them has, without\n",
+    );
+    msg.push_str(
+        "                      touching anything. Exits with 1 if the trees differ. Add\n",
+    );
+    msg.push_str("                      --json for JSON lines.\n");
+    msg.push_str(
+        "  --fuzz DURATION     With --diff-trees, ignore differences up to DURATION (such\n",
+    );
+    msg.push_str("                      as 2s or 100ms).\n");
+    msg.push_str(
+        "  --serve NAME        Listen on the named pipe NAME (a socket path outside Windows)\n",
+    );
+    msg.push_str(
+        "                      and touch files for clients sending JSON lines, until sent\n",
+    );
+    msg.push_str("                      {\"quit\":true} or interrupted.\n");
+    msg.push_str(
+        "  --send NAME         Have the --serve server on NAME touch the operands, with the\n",
+    );
+    msg.push_str(
+        "                      times the other options give. Without operands, send the\n",
+    );
+    msg.push_str("                      JSON request lines read from standard input.\n");
+    msg.push_str(
+        "  --while-pid PID     Touch the operands again every --every interval for as long\n",
+    );
+    msg.push_str(
+        "                      as process PID runs, then exit. If it isn't running, touch\n",
+    );
+    msg.push_str("                      them once.\n");
+    msg.push_str(
+        "  --every INTERVAL    How often --while-pid touches: seconds, or a number followed\n",
+    );
+    msg.push_str("                      by s, m, h or d (default 1m).\n");
+    msg.push_str(
+        "  --dollar-vars       Expand $VAR and ${VAR} in operands, as well as %VAR% (which\n",
+    );
+    msg.push_str("                      is always expanded on Windows).\n");
+    msg.push_str(
+        "  --strict-env        Fail if an operand names an undefined variable, instead of\n",
+    );
+    msg.push_str("                      leaving the reference as written.\n");
+    msg.push_str(
+        "  --audit-log FILE    Append a line of JSON to FILE for every file modified: when,\n",
+    );
+    msg.push_str(
+        "                      the path, created or updated, and the old and new times.\n",
+    );
+    msg.push_str("                      Problems writing FILE are warnings.\n");
+    msg.push_str(
+        "  --error-log FILE    Append a line to FILE for every operand that could not be\n",
+    );
+    msg.push_str("                      touched: when, and the error. Problems writing FILE are\n");
+    msg.push_str("                      warnings.\n");
+    msg.push_str(
+        "  --checkpoint FILE   Record each operand in FILE once it is touched, and skip the\n",
+    );
+    msg.push_str(
+        "                      operands already in FILE, so an interrupted batch can be\n",
+    );
+    msg.push_str("                      resumed by running it again. FILE is deleted once every\n");
+    msg.push_str("                      operand has been touched.\n");
+    msg.push_str("  --allow-system      Let wildcards and -R reach into system directories (the\n");
+    msg.push_str("                      Windows directory, Program Files and the system drive\n");
+    msg.push_str(
+        "                      root), which are otherwise skipped. Explicit operands are\n",
+    );
+    msg.push_str("                      always allowed.\n");
+    msg.push_str(
+        "  --elevate           Run the same command again as administrator, through the\n",
+    );
+    msg.push_str("                      UAC prompt, in a window of its own, and exit with its\n");
+    msg.push_str(
+        "                      status. Does nothing when already elevated. Windows only.\n",
+    );
+    msg.push_str(
+        "  --posix             Behave exactly like GNU touch: only its options, its error\n",
+    );
+    msg.push_str("                      messages and exit status 1 on any failure. Setting\n");
+    msg.push_str("                      MDTOUCH_COMPAT=gnu does the same, as does running\n");
+    msg.push_str("                      mdtouch under the name touch (or touch.exe), such as\n");
+    msg.push_str("                      through a link named touch in PATH.\n");
+    msg
+}
+
+/// What to do when touching a file fails (`--on-error`).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ErrorPolicy {
+    /// Report the failure and carry on with the remaining files.
+    #[default]
+    Continue,
+    /// Report the failure and stop processing.
+    Stop,
+    /// Retry transient failures, then carry on as with `Continue`.
+    Retry,
+}
+
+impl ErrorPolicy {
+    /// Parses a policy name as given to `--on-error`.
+    fn parse(value: &str) -> std::io::Result<Self> {
+        match value {
+            "continue" => Ok(ErrorPolicy::Continue),
+            "stop" => Ok(ErrorPolicy::Stop),
+            "retry" => Ok(ErrorPolicy::Retry),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --on-error policy '{}' (expected continue, stop or retry)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+/// Which single file `--select` keeps out of all the operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Selection {
+    /// The file with the latest modification time.
+    Newest,
+    /// The file with the earliest modification time.
+    Oldest,
+}
+
+impl Selection {
+    /// Parses a selection name as given to `--select`.
+    fn parse(value: &str) -> std::io::Result<Self> {
+        match value {
+            "newest" => Ok(Selection::Newest),
+            "oldest" => Ok(Selection::Oldest),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --select value '{}' (expected newest or oldest)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+/// The order operands are processed in (`--sort`).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum SortOrder {
+    /// As they were given, listed or found.
+    #[default]
+    AsGiven,
+    /// By path, ignoring case on Windows unless `--case-sensitive` is given.
+    Name,
+    /// By path, last first.
+    NameDescending,
+    /// By size, smallest first.
+    Size,
+    /// By modification time, oldest first.
+    Mtime,
+}
+
+impl SortOrder {
+    /// Parses an order name as given to `--sort`.
+    fn parse(value: &str) -> std::io::Result<Self> {
+        match value {
+            "as-given" => Ok(SortOrder::AsGiven),
+            "name" => Ok(SortOrder::Name),
+            "name-desc" => Ok(SortOrder::NameDescending),
+            "size" => Ok(SortOrder::Size),
+            "mtime" => Ok(SortOrder::Mtime),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --sort order '{}' (expected name, name-desc, size, mtime or as-given)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+/// Whether a run creates missing files, updates existing ones, or both (`--touch-mode`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TouchMode {
+    /// Only create missing files, as `--exclusive` does.
+    Create,
+    /// Only update existing files, as `-c` does.
+    Update,
+    /// Create missing files and update existing ones, as without either.
+    Both,
+}
+
+impl TouchMode {
+    /// Parses a mode name as given to `--touch-mode`.
+    fn parse(value: &str) -> std::io::Result<Self> {
+        match value {
+            "create" => Ok(TouchMode::Create),
+            "update" => Ok(TouchMode::Update),
+            "both" => Ok(TouchMode::Both),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --touch-mode value '{}' (expected create, update or both)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+/// Which kinds of operand to act on (`--apply-to`).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ApplyTo {
+    /// Regular files (and anything else that is not a directory) only.
+    Files,
+    /// Directories only.
+    Dirs,
+    /// Every operand.
+    #[default]
+    Both,
+}
+
+impl ApplyTo {
+    /// Parses a kind as given to `--apply-to`.
+    fn parse(value: &str) -> std::io::Result<Self> {
+        match value {
+            "files" => Ok(ApplyTo::Files),
+            "dirs" => Ok(ApplyTo::Dirs),
+            "both" => Ok(ApplyTo::Both),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --apply-to value '{}' (expected files, dirs or both)",
+                    value
+                ),
+            )),
+        }
+    }
+
+    /// Returns why `path` is skipped, or `None` if it should be touched. An operand that
+    /// does not exist yet counts as a file unless it ends in a path separator.
+    fn skip_reason(self, path: &Path, stats: &StatCache) -> Option<&'static str> {
+        if self == ApplyTo::Both {
+            return None;
+        }
+        let is_dir = match stats.metadata(path) {
+            Ok(metadata) => metadata.is_dir(),
+            Err(_) => ends_with_separator(path),
+        };
+        match (self, is_dir) {
+            (ApplyTo::Files, true) => Some("directory"),
+            (ApplyTo::Dirs, false) => Some("not a directory"),
+            _ => None,
+        }
+    }
+}
+
+/// Where `--print0-errors` writes the NUL-separated list of failed operands.
+#[derive(Debug, Clone, PartialEq)]
+enum ErrorList {
+    /// Standard error, in place of the usual error messages.
+    Stderr,
+    /// A file, leaving the error messages on standard error.
+    File(PathBuf),
+}
+
+/// Parses a policy name as given to `--broken-link`.
+fn parse_broken_link_policy(value: &str) -> std::io::Result<BrokenLinkPolicy> {
+    match value {
+        "error" => Ok(BrokenLinkPolicy::Error),
+        "skip" => Ok(BrokenLinkPolicy::Skip),
+        "create-target" => Ok(BrokenLinkPolicy::CreateTarget),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid --broken-link policy '{}' (expected error, skip or create-target)",
+                value
+            ),
+        )),
+    }
+}
+
+/// Number of retries for `--on-error=retry` when `--retries` is not given.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Delay before the first retry; each later retry waits one more step.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Number of files above which a run asks before touching them, without `--confirm-over`.
+const DEFAULT_CONFIRM_OVER: usize = 10_000;
+
+/// Options controlling a single invocation, parsed from the command line.
+#[derive(Debug, Default)]
+struct Options {
+    /// Files to read additional operands from (`--files-from`).
+    files_from: Vec<PathBuf>,
+    /// Manifests to read operands from, relative to their own directory (`--filelist`).
+    filelists: Vec<PathBuf>,
+    /// Where to write the list of operands that failed (`--failed-to`).
+    failed_to: Option<PathBuf>,
+    /// Whether operand lists are NUL-separated rather than newline-separated (`-0`).
+    null_separated: bool,
+    /// The encoding of operand lists (`--input-encoding`).
+    input_encoding: InputEncoding,
+    /// Whether to report each touched path (`-v`).
+    verbose: bool,
+    /// Whether `-v` also shows the time applied, and how long ago it is (`--humanize`).
+    humanize: bool,
+    /// Whether files that already have the times given are left alone and listed
+    /// (`--report-unchanged`).
+    report_unchanged: bool,
+    /// Whether to suppress informational output, overriding `-v` (`-q`).
+    quiet: bool,
+    /// Whether to leave per-file failures out of the error output (`--quiet-errors`).
+    quiet_errors: bool,
+    /// Base directory that paths in output are shown relative to (`--relative-to`).
+    relative_to: Option<PathBuf>,
+    /// Whether only the access time was requested (`-a`).
+    access_only: bool,
+    /// Whether only the modification time was requested (`-m`).
+    modify_only: bool,
+    /// Whether the access time must be left alone (`--leave-atime`).
+    leave_atime: bool,
+    /// Whether the modification time must be left alone (`--leave-mtime`).
+    leave_mtime: bool,
+    /// File whose times replace the current time (`-r`).
+    reference: Option<PathBuf>,
+    /// Whether to copy the reference file's creation time too (`--reference-all`).
+    reference_all: bool,
+    /// Source tree whose files lend their times to the same paths under the operand
+    /// (`--mirror-from`).
+    mirror_from: Option<PathBuf>,
+    /// PE image whose link timestamp replaces the current time (`--reference-pe`).
+    reference_pe: Option<PathBuf>,
+    /// Date string that replaces the current time (`-d`).
+    date: Option<String>,
+    /// Pattern `-d` and `--times` are read with, rather than guessing (`--date-format`).
+    date_format: Option<DateFormat>,
+    /// Zone times without one are read in, rather than local time (`--tz`).
+    tz: Option<TimeZone>,
+    /// `[[CC]YY]MMDDhhmm[.ss]` timestamp that replaces the current time (`-t`).
+    stamp: Option<String>,
+    /// Raw FILETIME tick count that replaces the current time (`--filetime`).
+    filetime: Option<String>,
+    /// Access, modification and creation times, each optional (`--times`).
+    times: Option<String>,
+    /// Whether files created get strictly increasing creation times
+    /// (`--force-create-time-monotonic`).
+    create_time_monotonic: bool,
+    /// Whether files created keep the real time of their creation, rather than the
+    /// time given (`--real-creation`).
+    real_creation: bool,
+    /// The time older modification times are raised to, as a date, a `-t` stamp or a
+    /// reference file, when only such times are changed (`--min-mtime`).
+    min_mtime: Option<String>,
+    /// How far ahead of the clock a time may be before it is set back to now, when only
+    /// such times are fixed (`--fix-future`).
+    fix_future: Option<Duration>,
+    /// Whether to warn about files whose modification time is ahead of the system clock
+    /// (`--detect-clock-skew`).
+    detect_clock_skew: bool,
+    /// Whether `-v` warns about files another process has open for writing
+    /// (`--warn-if-open`).
+    warn_if_open: bool,
+    /// Whether files whose modification time is later than the one given are left alone
+    /// (`--only-newer`).
+    only_newer: bool,
+    /// Whether files created after they were last modified get their modification time as
+    /// their creation time (`--creation-from-mtime`).
+    creation_from_mtime: bool,
+    /// .NET tick count that replaces the current time (`--dotnet-ticks`).
+    dotnet_ticks: Option<String>,
+    /// Whether to skip files that do not exist instead of creating them (`-c`).
+    no_create: bool,
+    /// Whether to only create files, failing for ones that already exist (`--exclusive`).
+    exclusive: bool,
+    /// Whether to truncate existing files to zero bytes as well (`--empty`).
+    empty: bool,
+    /// The suffix of the copy made of each existing file before it is touched
+    /// (`--backup`).
+    backup: Option<String>,
+    /// Whether files whose content hash matches the one stored for them are passed over
+    /// (`--if-content-changed`).
+    if_content_changed: bool,
+    /// What the run may do to files, mapped to `-c` and `--exclusive` (`--touch-mode`).
+    touch_mode: Option<TouchMode>,
+    /// Whether to flush each file's times to disk once they are set (`--flush`).
+    flush: bool,
+    /// Whether to create missing files by renaming them into place (`--atomic-create`).
+    atomic_create: bool,
+    /// Whether to take each operand as a lock file naming this process (`--lockfile`).
+    lockfile: bool,
+    /// Age after which a held lock may be taken over (`--steal-stale`).
+    steal_stale: Option<Duration>,
+    /// Whether to change the times of symbolic links themselves (`--no-dereference`).
+    no_dereference: bool,
+    /// What to do with symbolic links to missing targets (`--broken-link`).
+    broken_link: BrokenLinkPolicy,
+    /// Locale for month names in `--date` (`--locale`).
+    locale: Option<String>,
+    /// File to take the access time from (`--atime-from`).
+    atime_from: Option<PathBuf>,
+    /// File to take the modification time from (`--mtime-from`).
+    mtime_from: Option<PathBuf>,
+    /// What to do when a file cannot be touched (`--on-error`).
+    on_error: ErrorPolicy,
+    /// Retries for transient errors under `--on-error=retry` (`--retries`).
+    retries: Option<u32>,
+    /// How long to wait for each file before giving up on it (`--net-timeout`).
+    net_timeout: Option<Duration>,
+    /// How many files to touch at once (`--jobs`).
+    jobs: Option<usize>,
+    /// Number of files above which to ask before touching them (`--confirm-over`).
+    confirm_over: Option<usize>,
+    /// Whether to go ahead with a large batch without asking (`--yes`).
+    yes: bool,
+    /// Whether to list the files modified on standard output, NUL-separated (`--print0`).
+    print0: bool,
+    /// Number of files above which to touch nothing and fail (`--max-files`).
+    max_files: Option<usize>,
+    /// Whether to touch only the newest or oldest of the operands (`--select`).
+    select: Option<Selection>,
+    /// The order to process the operands in (`--sort`).
+    sort: SortOrder,
+    /// Which kinds of operand to act on (`--apply-to`).
+    apply_to: ApplyTo,
+    /// Whether to take wildcard characters in operands literally (`--no-glob`).
+    no_glob: bool,
+    /// Whether to fail when expanding the operands leaves nothing to process, rather than
+    /// create a pattern that matched nothing as written (`--fail-on-zero-matched`).
+    fail_on_zero_matched: bool,
+    /// Whether wildcards match names in exact case even on Windows (`--case-sensitive`).
+    case_sensitive: bool,
+    /// Whether wildcards and `-R` may reach into system directories (`--allow-system`).
+    allow_system: bool,
+    /// Whether to run again as administrator, unless already elevated (`--elevate`).
+    elevate: bool,
+    /// Whether to create operands ending in a path separator as directories (`--mkdir`).
+    mkdir: bool,
+    /// Whether `--mkdir` also creates missing parent directories (`-p`).
+    parents: bool,
+    /// Account to make the owner of created files (`--owner`).
+    owner: Option<String>,
+    /// Whether `--owner` also applies to files that already existed (`--owner-existing`).
+    owner_existing: bool,
+    /// Whether files this run creates get the reference's security descriptor
+    /// (`--copy-security`).
+    copy_security: bool,
+    /// Command to run for each touched file (`--exec`).
+    exec: Option<String>,
+    /// Whether `--exec` runs its command through the shell (`--exec-shell`).
+    exec_shell: bool,
+    /// Whether to only check the invocation for problems, touching nothing (`--preflight`).
+    preflight: bool,
+    /// Whether `--preflight` fails when any operand would be created or get new times
+    /// (`--fail-if-changes`).
+    fail_if_changes: bool,
+    /// Whether to report how the operands' volumes keep times, touching nothing
+    /// (`--probe`).
+    probe: bool,
+    /// Whether to test which times a directory's filesystem keeps, and how finely
+    /// (`--self-test`).
+    self_test: bool,
+    /// Whether to save each operand's times to a sidecar for later (`--save-times`).
+    save_times: bool,
+    /// Whether to put back the times `--save-times` saved (`--restore-times`).
+    restore_times: bool,
+    /// Whether to create a uniquely named file and print its path (`--temp`).
+    temp: bool,
+    /// What the name of the `--temp` file starts with (`--prefix`).
+    temp_prefix: Option<String>,
+    /// What the name of the `--temp` file ends with (`--suffix`).
+    temp_suffix: Option<String>,
+    /// Whether to create each operand under a numbered name when it exists (`--unique`).
+    unique: bool,
+    /// How a taken name is numbered (`--unique-pattern`).
+    unique_pattern: Option<String>,
+    /// Whether to compare the times in two trees, touching nothing (`--diff-trees`).
+    diff_trees: bool,
+    /// Zip archive whose entry times are applied to the files extracted from it
+    /// (`--from-zip`).
+    from_zip: Option<PathBuf>,
+    /// The pipe to serve touch requests on (`--serve`).
+    serve: Option<OsString>,
+    /// The pipe of a server to send touch requests to (`--send`).
+    send: Option<OsString>,
+    /// The process whose lifetime the operands are kept touched for (`--while-pid`).
+    while_pid: Option<u32>,
+    /// How often to touch the operands under `--while-pid` (`--every`).
+    every: Option<Duration>,
+    /// The largest difference `--diff-trees` ignores (`--fuzz`).
+    fuzz: Option<Duration>,
+    /// Whether `-v`, `--probe` or `--diff-trees` reports in JSON (`--json`).
+    json: bool,
+    /// Whether to list each file touched with its volume and file index
+    /// (`--touch-and-print-id`).
+    print_id: bool,
+    /// Whether to expand `$VAR` and `${VAR}` in operands too (`--dollar-vars`).
+    dollar_vars: bool,
+    /// Whether an undefined variable in an operand is an error (`--strict-env`).
+    strict_env: bool,
+    /// File to append a line to for every modified file (`--audit-log`).
+    audit_log: Option<PathBuf>,
+    /// File to append a line to for every operand that failed (`--error-log`).
+    error_log: Option<PathBuf>,
+    /// File listing the operands already done, for resuming a batch (`--checkpoint`).
+    checkpoint: Option<PathBuf>,
+    /// Whether directory operands also stand for everything below them (`-R`).
+    recursive: bool,
+    /// Whether `-R` descends through links to directories (`--follow-links`).
+    follow_links: bool,
+    /// Whether `-R` stays on the volume of each operand (`--one-file-system`).
+    one_file_system: bool,
+    /// Whether `-R` lists the entries of each directory by name (`--sorted`).
+    sorted: bool,
+    /// Attributes that operands must have or lack (`--attr-filter`).
+    attr_filter: Option<AttrFilter>,
+    /// Attribute bits that leave a path out of wildcard and `-R` expansion
+    /// (`--skip-hidden`, `--skip-system`).
+    skip_attributes: u32,
+    /// Patterns that paths from wildcard and `-R` expansion must match one of (`--regex`).
+    regex: Vec<Regex>,
+    /// Patterns that leave paths out of wildcard and `-R` expansion, from `--exclude` and
+    /// the files named by `--exclude-from`.
+    exclude: Vec<String>,
+    /// Whether `--regex` matches absolute paths (`--regex-full-path`).
+    regex_full_path: bool,
+    /// Whether read-only files are passed over, wherever they come from (`--skip-readonly`).
+    skip_readonly: bool,
+    /// Whether metadata read before touching is kept for the other checks (`--stat-cache`).
+    stat_cache: bool,
+    /// Whether handles are kept open for files touched again in the run
+    /// (`--wrap-handle-reuse`).
+    handle_reuse: bool,
+    /// Where to write the failed operands, NUL-separated (`--print0-errors`).
+    print0_errors: Option<ErrorList>,
+    /// How to show the progress counter, if at all (`--progress`).
+    progress: Option<ProgressStyle>,
+    /// The template the report written after the run is rendered from (`--report-format`).
+    report_format: Option<report::Template>,
+    /// Where to write the equivalent explicit command before the run (`--emit-command`).
+    emit_command: Option<emit::Destination>,
+    /// The clock "now" is read from, for the times given and the checks against the
+    /// clock; the system's when not set.
+    clock: Option<Arc<dyn TimeSource>>,
+}
+
+impl Options {
+    /// The current time, by the run's clock.
+    fn now(&self) -> FileTime {
+        match &self.clock {
+            Some(clock) => clock.now(),
+            None => FileTime::now(),
+        }
+    }
+}
+
+/// Returns the value for an option that takes an argument, accepting both the
+/// `--opt VALUE` and `--opt=VALUE` forms.
+fn option_value<I: Iterator<Item = OsString>>(
+    name: &str,
+    inline: Option<&str>,
+    rest: &mut I,
+) -> std::io::Result<OsString> {
+    match inline {
+        Some(value) => Ok(OsString::from(value)),
+        None => rest.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Option {} requires an argument", name),
+            )
+        }),
+    }
+}
+
+/// Splits the command line into options and file operands.
+///
+/// Arguments that are not recognised options are treated as file operands. Options are
+/// always plain ASCII, so an argument that is not valid Unicode is always an operand.
+fn parse_args(args: Vec<OsString>) -> std::io::Result<(Options, Vec<PathBuf>)> {
+    let mut options = Options::default();
+    let mut operands = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let Some(text) = arg.to_str() else {
+            operands.push(PathBuf::from(arg));
+            continue;
+        };
+        let (name, inline) = match text.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (text, None),
+        };
+        match name {
+            "--files-from" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.files_from.push(PathBuf::from(value));
+            }
+            "--filelist" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.filelists.push(PathBuf::from(value));
+            }
+            "--input-encoding" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.input_encoding = InputEncoding::parse(&value.to_string_lossy())?;
+            }
+            "--failed-to" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.failed_to = Some(PathBuf::from(value));
+            }
+            "-0" | "--null" => options.null_separated = true,
+            "-v" | "--verbose" => options.verbose = true,
+            "--humanize" => options.humanize = true,
+            "--report-unchanged" => options.report_unchanged = true,
+            "-q" | "--quiet" => options.quiet = true,
+            "--quiet-errors" => options.quiet_errors = true,
+            "--relative-to" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.relative_to = Some(PathBuf::from(value));
+            }
+            "-a" => options.access_only = true,
+            "-m" => options.modify_only = true,
+            "--leave-atime" => options.leave_atime = true,
+            "--leave-mtime" => options.leave_mtime = true,
+            "-r" | "--reference" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.reference = Some(PathBuf::from(value));
+            }
+            "--reference-all" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.reference = Some(PathBuf::from(value));
+                options.reference_all = true;
+            }
+            "--mirror-from" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.mirror_from = Some(PathBuf::from(value));
+            }
+            "--reference-pe" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.reference_pe = Some(PathBuf::from(value));
+            }
+            "-d" | "--date" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.date = Some(value.to_string_lossy().into_owned());
+            }
+            "--date-format" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.date_format = Some(DateFormat::new(&value.to_string_lossy())?);
+            }
+            "--tz" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.tz = Some(TimeZone::from_name(&value.to_string_lossy())?);
+            }
+            "-t" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.stamp = Some(value.to_string_lossy().into_owned());
+            }
+            "--filetime" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.filetime = Some(value.to_string_lossy().into_owned());
+            }
+            "--times" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.times = Some(value.to_string_lossy().into_owned());
+            }
+            "--dotnet-ticks" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.dotnet_ticks = Some(value.to_string_lossy().into_owned());
+            }
+            "-c" | "--no-create" => options.no_create = true,
+            "--exclusive" => options.exclusive = true,
+            "--empty" => options.empty = true,
+            "--if-content-changed" => options.if_content_changed = true,
+            "--backup" => {
+                let suffix = inline.unwrap_or(backup::DEFAULT_SUFFIX);
+                backup::check_suffix(suffix)?;
+                options.backup = Some(suffix.to_string());
+            }
+            "--touch-mode" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.touch_mode = Some(TouchMode::parse(&value.to_string_lossy())?);
+            }
+            "--lockfile" => options.lockfile = true,
+            "--flush" => options.flush = true,
+            "--force-create-time-monotonic" => options.create_time_monotonic = true,
+            "--real-creation" => options.real_creation = true,
+            "--creation-from-mtime" => options.creation_from_mtime = true,
+            "--detect-clock-skew" => options.detect_clock_skew = true,
+            "--warn-if-open" => options.warn_if_open = true,
+            "--min-mtime" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.min_mtime = Some(value.to_string_lossy().into_owned());
+            }
+            "--fix-future" => {
+                options.fix_future = Some(match inline {
+                    Some(margin) => lock::parse_age(margin).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "Invalid --fix-future margin '{}' (expected a number followed by s, m, h or d)",
+                                margin
+                            ),
+                        )
+                    })?,
+                    None => Duration::ZERO,
+                });
+            }
+            "--only-newer" => options.only_newer = true,
+            "--atomic-create" => options.atomic_create = true,
+            "--steal-stale" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.steal_stale = Some(lock::parse_age(&value.to_string_lossy())?);
+            }
+            "--no-dereference" => options.no_dereference = true,
+            "--broken-link" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.broken_link = parse_broken_link_policy(&value.to_string_lossy())?;
+            }
+            "--locale" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.locale = Some(value.to_string_lossy().into_owned());
+            }
+            "--atime-from" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.atime_from = Some(PathBuf::from(value));
+            }
+            "--mtime-from" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.mtime_from = Some(PathBuf::from(value));
+            }
+            "--on-error" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.on_error = ErrorPolicy::parse(&value.to_string_lossy())?;
+            }
+            "--net-timeout" => {
+                let value = option_value(name, inline, &mut args)?;
+                let millis = value
+                    .to_string_lossy()
+                    .parse()
+                    .ok()
+                    .filter(|&millis| millis > 0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "Invalid --net-timeout '{}' (expected a number of milliseconds)",
+                                value.to_string_lossy()
+                            ),
+                        )
+                    })?;
+                options.net_timeout = Some(Duration::from_millis(millis));
+            }
+            "--retries" => {
+                let value = option_value(name, inline, &mut args)?;
+                let retries = value.to_string_lossy().parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid --retries count '{}'", value.to_string_lossy()),
+                    )
+                })?;
+                options.retries = Some(retries);
+            }
+            "-j" | "--jobs" => {
+                let value = option_value(name, inline, &mut args)?;
+                let jobs = value
+                    .to_string_lossy()
+                    .parse()
+                    .ok()
+                    .filter(|jobs| *jobs > 0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid --jobs count '{}'", value.to_string_lossy()),
+                        )
+                    })?;
+                options.jobs = Some(jobs);
+            }
+            "--confirm-over" => {
+                let value = option_value(name, inline, &mut args)?;
+                let count = value
+                    .to_string_lossy()
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid --confirm-over count '{}'", value.to_string_lossy()),
+                        )
+                    })?;
+                options.confirm_over = Some(count);
+            }
+            "-y" | "--yes" => options.yes = true,
+            "--max-files" => {
+                let value = option_value(name, inline, &mut args)?;
+                let count = value
+                    .to_string_lossy()
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid --max-files count '{}'", value.to_string_lossy()),
+                        )
+                    })?;
+                options.max_files = Some(count);
+            }
+            "--select" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.select = Some(Selection::parse(&value.to_string_lossy())?);
+            }
+            "--sort" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.sort = SortOrder::parse(&value.to_string_lossy())?;
+            }
+            "--no-glob" => options.no_glob = true,
+            "--fail-on-zero-matched" => options.fail_on_zero_matched = true,
+            "--case-sensitive" => options.case_sensitive = true,
+            "--apply-to" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.apply_to = ApplyTo::parse(&value.to_string_lossy())?;
+            }
+            "--allow-system" => options.allow_system = true,
+            "--elevate" => options.elevate = true,
+            "--mkdir" => options.mkdir = true,
+            "-p" | "--parents" => options.parents = true,
+            "--owner" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.owner = Some(value.to_string_lossy().into_owned());
+            }
+            "--owner-existing" => options.owner_existing = true,
+            "--copy-security" => options.copy_security = true,
+            "--exec" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.exec = Some(value.to_string_lossy().into_owned());
+            }
+            "--exec-shell" => options.exec_shell = true,
+            "--preflight" => options.preflight = true,
+            "--fail-if-changes" => options.fail_if_changes = true,
+            "--probe" => options.probe = true,
+            "--self-test" => options.self_test = true,
+            "--save-times" => options.save_times = true,
+            "--restore-times" => options.restore_times = true,
+            "--temp" => options.temp = true,
+            "--prefix" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.temp_prefix = Some(value.to_string_lossy().into_owned());
+            }
+            "--suffix" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.temp_suffix = Some(value.to_string_lossy().into_owned());
+            }
+            "--unique" => options.unique = true,
+            "--unique-pattern" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.unique_pattern = Some(value.to_string_lossy().into_owned());
+            }
+            "--json" => options.json = true,
+            "--touch-and-print-id" => options.print_id = true,
+            "--diff-trees" => options.diff_trees = true,
+            "--from-zip" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.from_zip = Some(PathBuf::from(value));
+            }
+            "--serve" => options.serve = Some(option_value(name, inline, &mut args)?),
+            "--send" => options.send = Some(option_value(name, inline, &mut args)?),
+            "--while-pid" => {
+                let value = option_value(name, inline, &mut args)?;
+                let pid = value.to_str().and_then(|value| value.parse().ok());
+                options.while_pid = Some(pid.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid --while-pid '{}'", value.to_string_lossy()),
+                    )
+                })?);
+            }
+            "--every" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.every = Some(watch::parse_interval(&value.to_string_lossy())?);
+            }
+            "--fuzz" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.fuzz = Some(diff::parse_fuzz(&value.to_string_lossy())?);
+            }
+            "--dollar-vars" => options.dollar_vars = true,
+            "--strict-env" => options.strict_env = true,
+            "-R" | "--recursive" => options.recursive = true,
+            "--follow-links" => options.follow_links = true,
+            "-x" | "--one-file-system" => options.one_file_system = true,
+            "--sorted" => options.sorted = true,
+            "--print0" => options.print0 = true,
+            "--print0-errors" => {
+                options.print0_errors = Some(match inline {
+                    Some(path) => ErrorList::File(PathBuf::from(path)),
+                    None => ErrorList::Stderr,
+                });
+            }
+            "--emit-command" => {
+                options.emit_command = Some(match inline {
+                    Some(path) => emit::Destination::File(PathBuf::from(path)),
+                    None => emit::Destination::Stderr,
+                });
+            }
+            "--report-format" => {
+                let path = PathBuf::from(option_value(name, inline, &mut args)?);
+                options.report_format = Some(report::Template::read(&path).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("Error in report template {}: {}", path.display(), e),
+                    )
+                })?);
+            }
+            "--progress" => {
+                options.progress = Some(match inline {
+                    Some(style) => ProgressStyle::parse(style)?,
+                    None => ProgressStyle::Lines,
+                });
+            }
+            "--attr-filter" => {
+                let value = option_value(name, inline, &mut args)?;
+                let filter = AttrFilter::parse(&value.to_string_lossy())?;
+                options.attr_filter = Some(match options.attr_filter {
+                    Some(previous) => previous.combine(filter)?,
+                    None => filter,
+                });
+            }
+            "--skip-hidden" => options.skip_attributes |= attrs::HIDDEN,
+            "--skip-system" => options.skip_attributes |= attrs::SYSTEM,
+            "--regex" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.regex.push(Regex::new(&value.to_string_lossy())?);
+            }
+            "--regex-full-path" => options.regex_full_path = true,
+            "--exclude" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.exclude.push(value.to_string_lossy().into_owned());
+            }
+            "--exclude-from" => {
+                let value = PathBuf::from(option_value(name, inline, &mut args)?);
+                let patterns = exclude::read_patterns(&value).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("Error reading exclude file {}: {}", value.display(), e),
+                    )
+                })?;
+                options.exclude.extend(patterns);
+            }
+            "--skip-readonly" => options.skip_readonly = true,
+            "--stat-cache" => options.stat_cache = true,
+            "--wrap-handle-reuse" => options.handle_reuse = true,
+            "--checkpoint" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.checkpoint = Some(PathBuf::from(value));
+            }
+            "--audit-log" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.audit_log = Some(PathBuf::from(value));
+            }
+            "--error-log" => {
+                let value = option_value(name, inline, &mut args)?;
+                options.error_log = Some(PathBuf::from(value));
+            }
+            // Quotes can't be part of a Windows file name, so any left in an operand are
+            // the shell's.
+            _ if cfg!(windows) => operands.extend(shell_passed_operands(text)),
+            _ => operands.push(PathBuf::from(arg)),
+        }
+    }
+    apply_touch_mode(&mut options)?;
+    Ok((options, operands))
+}
+
+/// Cleans up an operand as Explorer passes it when files are dropped on mdtouch or opened
+/// through a file association, where quoting can go wrong.
+///
+/// Stray quotes and the whitespace next to them are dropped: `"%1"` given a path ending
+/// in a backslash, such as `"C:\dir\"`, arrives as `C:\dir"`, and a second quoted path
+/// can be run into the same argument, as in `C:\dir" C:\file.txt`, which is split back
+/// into two operands. An argument without a quote is taken as it is, since a name may
+/// start with a space. Only used on Windows, where quotes can't be part of a file name.
+fn shell_passed_operands(arg: &str) -> Vec<PathBuf> {
+    if !arg.contains('"') {
+        return vec![PathBuf::from(arg)];
+    }
+    let last = arg.matches('"').count();
+    arg.split('"')
+        .enumerate()
+        .map(|(index, piece)| {
+            let piece = if index > 0 { piece.trim_start() } else { piece };
+            if index < last {
+                piece.trim_end()
+            } else {
+                piece
+            }
+        })
+        .filter(|piece| !piece.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Sets `-c` or `--exclusive` as `--touch-mode` says, refusing a mode that either of
+/// them, given as well, contradicts.
+fn apply_touch_mode(options: &mut Options) -> std::io::Result<()> {
+    let contradicted = match options.touch_mode {
+        Some(TouchMode::Create) => options.no_create.then_some(("create", "-c")),
+        Some(TouchMode::Update) => options.exclusive.then_some(("update", "--exclusive")),
+        Some(TouchMode::Both) if options.no_create => Some(("both", "-c")),
+        Some(TouchMode::Both) => options.exclusive.then_some(("both", "--exclusive")),
+        None => None,
+    };
+    if let Some((mode, option)) = contradicted {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Option --touch-mode={} cannot be combined with {}",
+                mode, option
+            ),
+        ));
+    }
+    match options.touch_mode {
+        Some(TouchMode::Create) => options.exclusive = true,
+        Some(TouchMode::Update) => options.no_create = true,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the separator used between entries of an operand list.
+fn list_separator(null_separated: bool) -> u8 {
+    if null_separated {
+        b'\0'
+    } else {
+        b'\n'
+    }
+}
+
+/// Converts raw bytes read from a list file into a path.
+///
+/// On Unix the bytes are used as-is, so names that are not valid UTF-8 survive a round
+/// trip. Elsewhere list files are expected to be UTF-8 and invalid sequences are replaced.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Converts a path into the bytes written to a list file. The inverse of `path_from_bytes`.
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// The entries of an operand list, read one at a time.
+///
+/// Entries are separated by newlines (with an optional trailing carriage return) or,
+/// when `null_separated` is set, by NUL characters. Empty entries are ignored. Reading
+/// stops at the first error, which is returned as the last item.
+///
+/// A `--filelist` manifest has a `base` directory: its entries are trimmed, blank ones
+/// and `#` comments are ignored, and relative ones are resolved against `base`.
+struct ListEntries {
+    reader: Box<dyn BufRead>,
+    null_separated: bool,
+    base: Option<PathBuf>,
+    failed: bool,
+}
+
+impl Iterator for ListEntries {
+    type Item = std::io::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let separator = list_separator(self.null_separated);
+        let mut entry = Vec::new();
+        while !self.failed {
+            entry.clear();
+            match self.reader.read_until(separator, &mut entry) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    if entry.last() == Some(&separator) {
+                        entry.pop();
+                    }
+                    if !self.null_separated && entry.last() == Some(&b'\r') {
+                        entry.pop();
+                    }
+                    let Some(base) = &self.base else {
+                        if !entry.is_empty() {
+                            return Some(Ok(path_from_bytes(&entry)));
+                        }
+                        continue;
+                    };
+                    let entry = entry.trim_ascii();
+                    if !entry.is_empty() && !entry.starts_with(b"#") {
+                        return Some(Ok(base.join(path_from_bytes(entry))));
+                    }
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Opens a list file (or standard input when `path` is `-`) for reading its entries,
+/// decoding it from `encoding`.
+fn open_operand_list(
+    path: &Path,
+    null_separated: bool,
+    encoding: InputEncoding,
+) -> std::io::Result<ListEntries> {
+    let reader: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(fs::File::open(path)?))
+    };
+    Ok(ListEntries {
+        reader: encoding::decode(reader, encoding)?,
+        null_separated,
+        base: None,
+        failed: false,
+    })
+}
+
+/// Reads all the file operands from a list file (or standard input when `path` is `-`).
+fn read_operand_list(path: &Path, null_separated: bool) -> std::io::Result<Vec<PathBuf>> {
+    open_operand_list(path, null_separated, InputEncoding::Utf8)?.collect()
+}
+
+/// Writes the operands that failed to `path` so they can be retried with `--files-from`.
+///
+/// The list is written to a temporary file in the same directory and then renamed into
+/// place, so an interrupted run never leaves a partial list behind.
+fn write_failed_list(path: &Path, failed: &[PathBuf], null_separated: bool) -> std::io::Result<()> {
+    let mut contents = Vec::new();
+    for operand in failed {
+        contents.extend(path_to_bytes(operand));
+        contents.push(list_separator(null_separated));
+    }
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
+/// Returns the form of `path` to show in output.
+///
+/// With a `base`, paths under it are shown relative to it and anything else is shown as
+/// an absolute path. Without one, the path is shown exactly as it was given.
+fn display_path(path: &Path, base: Option<&Path>) -> PathBuf {
+    let Some(base) = base else {
+        return path.to_path_buf();
+    };
+    let absolute = normalize_path(path);
+    match absolute.strip_prefix(normalize_path(base)) {
+        Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => absolute,
+    }
+}
+
+/// Reads a file's or directory's metadata, naming it in any error so it can be told apart
+/// from the operand being touched.
+fn reference_metadata(path: &Path) -> std::io::Result<fs::Metadata> {
+    read_reference(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Error reading reference {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Finds the `--mirror-from` counterpart of a path at or below the destination root: the
+/// path at the same place relative to the source root.
+fn mirror_source(path: &Path, destination: &Path, source: &Path) -> PathBuf {
+    match path.strip_prefix(destination) {
+        Ok(relative) if !relative.as_os_str().is_empty() => source.join(relative),
+        _ => source.to_path_buf(),
+    }
+}
+
+/// Returns the destination and source roots for `--mirror-from`, if it was given. The
+/// destination is the one operand, which must be given on the command line.
+fn mirror_roots(
+    operands: &[PathBuf],
+    options: &Options,
+) -> std::io::Result<Option<(PathBuf, PathBuf)>> {
+    let Some(source) = &options.mirror_from else {
+        return Ok(None);
+    };
+    match operands {
+        [destination] if options.files_from.is_empty() && options.filelists.is_empty() => {
+            Ok(Some((destination.clone(), source.clone())))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --mirror-from takes exactly one destination operand",
+        )),
+    }
+}
+
+/// Takes the times in `times` from the `--mirror-from` counterpart of `path`. Returns
+/// `None` when there is no counterpart.
+fn mirrored_times(
+    path: &Path,
+    destination: &Path,
+    source: &Path,
+    times: &TouchOptions,
+) -> std::io::Result<Option<TouchOptions>> {
+    let counterpart = mirror_source(path, destination, source);
+    let metadata = match fs::metadata(&counterpart) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("cannot read {}: {}", counterpart.display(), e),
+            ))
+        }
+    };
+    Ok(Some(TouchOptions {
+        atime: times
+            .atime
+            .map(|_| FileTime::from_last_access_time(&metadata)),
+        mtime: times
+            .mtime
+            .map(|_| FileTime::from_last_modification_time(&metadata)),
+        ..*times
+    }))
+}
+
+/// Whether the times to apply were given, rather than being the current time.
+fn explicit_times(options: &Options) -> bool {
+    options.reference.is_some()
+        || options.reference_pe.is_some()
+        || options.date.is_some()
+        || options.stamp.is_some()
+        || options.filetime.is_some()
+        || options.dotnet_ticks.is_some()
+        || options.atime_from.is_some()
+        || options.mtime_from.is_some()
+        || options.mirror_from.is_some()
+        || options.times.is_some()
+}
+
+/// The context `--date` style strings are read in, with `now` as the current time.
+fn parse_context(options: &Options, now: FileTime) -> ParseContext {
+    ParseContext {
+        now,
+        locale: match &options.locale {
+            Some(name) => Locale::from_name(name),
+            None => Locale::from_env(),
+        },
+        zone: options.tz,
+        ..ParseContext::default()
+    }
+}
+
+/// Reads a `-d` date, with the `--date-format` pattern if one was given.
+fn read_date(text: &str, options: &Options, context: ParseContext) -> Result<FileTime, ParseError> {
+    match &options.date_format {
+        Some(format) => format.parse(text, context),
+        None => parse_date(text, context),
+    }
+}
+
+/// Works out which timestamps to apply, and their values, from the time options.
+///
+/// Each timestamp comes from its own `--atime-from`/`--mtime-from` file if given, then
+/// from `-r`, `--reference-pe`, `-d`, `-t`, `--filetime` or `--dotnet-ticks`, and
+/// otherwise is the current time. `-a` and `-m` limit the change to one timestamp, but a
+/// timestamp with its own source file is always changed. `--times` stands alone: it names
+/// each timestamp outright.
+fn resolve_times(options: &Options) -> std::io::Result<TouchOptions> {
+    if options.date_format.is_some() && options.date.is_none() && options.times.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --date-format requires -d or --times",
+        ));
+    }
+    if options.times.is_some()
+        && (options.reference.is_some()
+            || options.reference_pe.is_some()
+            || options.date.is_some()
+            || options.stamp.is_some()
+            || options.filetime.is_some()
+            || options.dotnet_ticks.is_some()
+            || options.atime_from.is_some()
+            || options.mtime_from.is_some()
+            || options.mirror_from.is_some()
+            || options.access_only
+            || options.modify_only
+            || options.leave_atime
+            || options.leave_mtime)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --times cannot be combined with other time options, -a or -m",
+        ));
+    }
+    if options.reference.is_some() && (options.atime_from.is_some() || options.mtime_from.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option -r cannot be combined with --atime-from or --mtime-from",
+        ));
+    }
+    if options.reference.is_some() && options.date.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option -r cannot be combined with -d",
+        ));
+    }
+    if options.reference_pe.is_some()
+        && (options.reference.is_some() || options.date.is_some() || options.stamp.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --reference-pe cannot be combined with -r, -d or -t",
+        ));
+    }
+    if options.leave_atime && options.atime_from.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --leave-atime cannot be combined with --atime-from",
+        ));
+    }
+    if options.leave_mtime && options.mtime_from.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --leave-mtime cannot be combined with --mtime-from",
+        ));
+    }
+    if options.filetime.is_some()
+        && (options.reference.is_some()
+            || options.reference_pe.is_some()
+            || options.date.is_some()
+            || options.stamp.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --filetime cannot be combined with -r, --reference-pe, -d or -t",
+        ));
+    }
+    if options.dotnet_ticks.is_some()
+        && (options.filetime.is_some()
+            || options.reference.is_some()
+            || options.reference_pe.is_some()
+            || options.date.is_some()
+            || options.stamp.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --dotnet-ticks cannot be combined with --filetime, -r, --reference-pe, -d or -t",
+        ));
+    }
+    if options.mirror_from.is_some()
+        && (options.filetime.is_some()
+            || options.dotnet_ticks.is_some()
+            || options.reference.is_some()
+            || options.reference_pe.is_some()
+            || options.date.is_some()
+            || options.stamp.is_some()
+            || options.atime_from.is_some()
+            || options.mtime_from.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --mirror-from cannot be combined with other time sources",
+        ));
+    }
+    if options.stamp.is_some() && (options.reference.is_some() || options.date.is_some()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option -t cannot be combined with -r or -d",
+        ));
+    }
+
+    let now = options.now();
+    let (mut atime, mut mtime) = (now, now);
+    let context = parse_context(options, now);
+    if let Some(slots) = &options.times {
+        let [atime, mtime, _] = parse_time_slots(slots, context, options.date_format.as_ref())?;
+        return Ok(TouchOptions {
+            atime,
+            mtime,
+            no_create: options.no_create,
+            no_dereference: options.no_dereference,
+            broken_link: options.broken_link,
+            exclusive: options.exclusive && !options.lockfile,
+            flush: options.flush,
+            atomic_create: options.atomic_create,
+            empty: options.empty,
+            jobs: job_count(options),
+        });
+    }
+    if let Some(date) = &options.date {
+        let time = read_date(date, options, context)?;
+        (atime, mtime) = (time, time);
+    }
+    if let Some(stamp) = &options.stamp {
+        let time = parse_touch_stamp(stamp, context)?;
+        (atime, mtime) = (time, time);
+    }
+    if let Some(ticks) = &options.filetime {
+        let time = parse_filetime(ticks)?;
+        (atime, mtime) = (time, time);
+    }
+    if let Some(ticks) = &options.dotnet_ticks {
+        let time = parse_dotnet_ticks(ticks, context)?;
+        (atime, mtime) = (time, time);
+    }
+    if let Some(reference) = &options.reference {
+        let metadata = reference_metadata(reference)?;
+        atime = FileTime::from_last_access_time(&metadata);
+        mtime = FileTime::from_last_modification_time(&metadata);
+    }
+    if let Some(image) = &options.reference_pe {
+        let time = pe::read_timestamp(image).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Error reading reference {}: {}", image.display(), e),
+            )
+        })?;
+        (atime, mtime) = (time, time);
+    }
+    if let Some(source) = &options.atime_from {
+        atime = FileTime::from_last_access_time(&reference_metadata(source)?);
+    }
+    if let Some(source) = &options.mtime_from {
+        mtime = FileTime::from_last_modification_time(&reference_metadata(source)?);
+    }
+
+    // --creation-from-mtime alone only changes creation times.
+    let only_creation = options.creation_from_mtime && !explicit_times(options);
+    let both = !options.access_only && !options.modify_only && !only_creation;
+    let change_atime =
+        !options.leave_atime && (both || options.access_only || options.atime_from.is_some());
+    let change_mtime =
+        !options.leave_mtime && (both || options.modify_only || options.mtime_from.is_some());
+    Ok(TouchOptions {
+        atime: change_atime.then_some(atime),
+        mtime: change_mtime.then_some(mtime),
+        no_create: options.no_create,
+        no_dereference: options.no_dereference,
+        broken_link: options.broken_link,
+        // A lock file is created exclusively when it is taken, then touched like any other.
+        exclusive: options.exclusive && !options.lockfile,
+        flush: options.flush,
+        atomic_create: options.atomic_create,
+        empty: options.empty,
+        jobs: job_count(options),
+    })
+}
+
+/// How many files to touch at once (`--jobs`).
+fn job_count(options: &Options) -> usize {
+    match options.jobs {
+        // Creation times are handed out in operand order.
+        _ if options.create_time_monotonic => 1,
+        Some(jobs) => jobs,
+        None => 1,
+    }
+}
+
+/// Expands environment variables in the command-line operands.
+///
+/// Under `--strict-env`, every reference to an undefined variable is reported together as
+/// a single error. Operands that are not valid Unicode are left unchanged.
+fn expand_operand_vars(operands: Vec<PathBuf>, options: &Options) -> std::io::Result<Vec<PathBuf>> {
+    let mut undefined = Vec::new();
+    let mut expanded = Vec::with_capacity(operands.len());
+    for operand in operands {
+        let Some(text) = operand.to_str() else {
+            expanded.push(operand);
+            continue;
+        };
+        if options.strict_env {
+            for name in vars::undefined_vars(text, options.dollar_vars) {
+                undefined.push(format!(
+                    "Undefined environment variable '{}' in operand {}",
+                    name, text
+                ));
+            }
+        }
+        expanded.push(PathBuf::from(vars::expand_env_vars(
+            text,
+            options.dollar_vars,
+        )));
+    }
+    if !undefined.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            undefined.join("\n"),
+        ));
+    }
+    Ok(expanded)
+}
+
+/// The filter `--skip-hidden` and `--skip-system` apply to expanded paths, if any.
+fn skip_filter(options: &Options) -> Option<AttrFilter> {
+    (options.skip_attributes != 0).then(|| AttrFilter::excluding(options.skip_attributes))
+}
+
+/// The patterns `--exclude` and `--exclude-from` leave expanded paths out by, if any.
+fn excludes(options: &Options) -> Option<Excludes> {
+    let ignore_case = cfg!(windows) && !options.case_sensitive;
+    (!options.exclude.is_empty()).then(|| Excludes::new(&options.exclude, ignore_case))
+}
+
+/// Expands wildcards in the command-line operands.
+///
+/// Unless `--allow-system` was given, paths that a wildcard expanded to inside a system
+/// directory are held back and returned separately along with the directory that
+/// protects them. Paths `--skip-hidden` or `--skip-system` exclude are dropped, then
+/// those no `--regex` matches and those an `--exclude` pattern does. Explicit operands are
+/// kept whatever they name.
+///
+/// The patterns that left no path are returned too. Under `--fail-on-zero-matched` they
+/// are dropped; otherwise one that matched nothing at all is kept as written.
+fn expand_operands(
+    operands: Vec<PathBuf>,
+    options: &Options,
+    stats: &StatCache,
+) -> (Vec<PathBuf>, Vec<(PathBuf, PathBuf)>, Vec<PathBuf>) {
+    if options.no_glob {
+        return (operands, Vec::new(), Vec::new());
+    }
+    let guard = (!options.allow_system).then(SystemGuard::for_system);
+    let ignore_case = cfg!(windows) && !options.case_sensitive;
+    let skip = skip_filter(options);
+    let excludes = excludes(options);
+    let mut expanded = Vec::new();
+    let mut blocked = Vec::new();
+    let mut unmatched = Vec::new();
+    for operand in operands {
+        if !glob::has_wildcards(&operand) {
+            expanded.push(operand);
+            continue;
+        }
+        let root: PathBuf = operand
+            .components()
+            .take_while(|component| !glob::has_wildcards(Path::new(component)))
+            .collect();
+        let before = expanded.len() + blocked.len();
+        for path in glob::expand_operand(operand.clone(), ignore_case) {
+            if path == operand && options.fail_on_zero_matched && path.symlink_metadata().is_err() {
+                continue;
+            }
+            if filtered_out(skip, &path, stats)
+                || regex_excludes(&path, &root, options)
+                || excludes
+                    .as_ref()
+                    .is_some_and(|excludes| excludes.excludes(&path, &root))
+            {
+                continue;
+            }
+            match guard.as_ref().and_then(|guard| guard.blocks(&path)) {
+                Some(protected) => blocked.push((path, protected.to_path_buf())),
+                None => expanded.push(path),
+            }
+        }
+        if expanded.len() + blocked.len() == before {
+            unmatched.push(operand);
+        }
+    }
+    (expanded, blocked, unmatched)
+}
+
+/// Fails if `operands` holds nothing, under `--fail-on-zero-matched`, naming the
+/// `unmatched` patterns.
+fn require_operands<'a>(
+    operands: Box<dyn Iterator<Item = PathBuf> + 'a>,
+    unmatched: &[PathBuf],
+    options: &Options,
+) -> std::io::Result<Box<dyn Iterator<Item = PathBuf> + 'a>> {
+    if !options.fail_on_zero_matched {
+        return Ok(operands);
+    }
+    let mut operands = operands.peekable();
+    if operands.peek().is_some() {
+        for pattern in unmatched {
+            eprintln!("Warning: no files matched '{}'", pattern.display());
+        }
+        return Ok(Box::new(operands));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        zero_matched_message(unmatched),
+    ))
+}
+
+/// The message `--fail-on-zero-matched` fails with.
+fn zero_matched_message(unmatched: &[PathBuf]) -> String {
+    if unmatched.is_empty() {
+        return "No files to process (--fail-on-zero-matched)".to_string();
+    }
+    let patterns: Vec<String> = unmatched
+        .iter()
+        .map(|pattern| format!("'{}'", pattern.display()))
+        .collect();
+    format!(
+        "No files matched {} (--fail-on-zero-matched)",
+        patterns.join(", ")
+    )
+}
+
+/// Builds the stream of operands to process: the command-line operands, then the entries
+/// of each `--files-from` list and `--filelist` manifest, with directories expanded into
+/// everything below them under `-R`.
+///
+/// Operands are produced only as they are needed, so a list or tree of any size is never
+/// held in memory. Problems met along the way, such as a directory that can't be read,
+/// are added to `messages`.
+fn operand_stream<'a>(
+    operands: Vec<PathBuf>,
+    lists: Vec<(PathBuf, ListEntries)>,
+    options: &'a Options,
+    messages: &'a RefCell<Vec<String>>,
+) -> Box<dyn Iterator<Item = PathBuf> + 'a> {
+    let listed = lists.into_iter().flat_map(move |(list, entries)| {
+        entries.map_while(move |entry| {
+            entry
+                .map_err(|e| {
+                    messages.borrow_mut().push(format!(
+                        "Error reading file list {}: {}",
+                        list.display(),
+                        e
+                    ));
+                })
+                .ok()
+        })
+    });
+    let operands = operands.into_iter().chain(listed);
+    if !options.recursive {
+        return Box::new(operands);
+    }
+    let guard = (!options.allow_system).then(SystemGuard::for_system);
+    Box::new(operands.flat_map(move |operand| {
+        let root = operand.clone();
+        walk::Walk::new(operand)
+            .filtered(skip_filter(options))
+            .excluding(excludes(options))
+            .guarded(guard.clone())
+            .following_links(options.follow_links)
+            .one_file_system(options.one_file_system)
+            .ordered(walk_order(options))
+            .filter_map(move |entry| match entry {
+                // The operand itself was named outright, so is kept.
+                Ok(Found::Path(path)) if path != root && regex_excludes(&path, &root, options) => {
+                    None
+                }
+                Ok(Found::Path(path)) => Some(path),
+                Ok(Found::Link(link, crossing)) => {
+                    if options.verbose && !options.quiet {
+                        eprintln!("{}", crossing_note(&link, &crossing));
+                    }
+                    None
+                }
+                Ok(Found::Blocked(path, protected)) => {
+                    messages
+                        .borrow_mut()
+                        .push(blocked_message(&path, &protected));
+                    None
+                }
+                Ok(Found::OtherVolume(path)) => {
+                    eprintln!(
+                        "Warning: skipped {} (on another volume; --one-file-system)",
+                        path.display()
+                    );
+                    None
+                }
+                Err((dir, e)) => {
+                    messages.borrow_mut().push(format!(
+                        "Error reading directory {}: {}",
+                        dir.display(),
+                        e
+                    ));
+                    None
+                }
+            })
+    }))
+}
+
+/// Returns whether `--regex` leaves out `path`, found by expanding `root`: no pattern
+/// matches it.
+fn regex_excludes(path: &Path, root: &Path, options: &Options) -> bool {
+    if options.regex.is_empty() {
+        return false;
+    }
+    let text = regex_text(path, root, options.regex_full_path);
+    !options.regex.iter().any(|regex| regex.is_match(&text))
+}
+
+/// The text `--regex` patterns are matched against: the path below `root`, or with
+/// `full_path` the absolute path, with `/` between components on every platform.
+fn regex_text(path: &Path, root: &Path, full_path: bool) -> String {
+    let path = if full_path {
+        std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+    } else {
+        path.strip_prefix(root).unwrap_or(path).to_path_buf()
+    };
+    let text = path.to_string_lossy();
+    if cfg!(windows) {
+        text.replace('\\', "/")
+    } else {
+        text.into_owned()
+    }
+}
+
+/// The order `-R` lists the entries of each directory in: by name ignoring case with
+/// `--sorted`, unless `--case-sensitive` is given too.
+fn walk_order(options: &Options) -> Order {
+    match (options.sorted, options.case_sensitive) {
+        (false, _) => Order::Listed,
+        (true, false) => Order::NameIgnoringCase,
+        (true, true) => Order::Name,
+    }
+}
+
+/// Describes what `-R` did at a link to a directory, for `-v`.
+fn crossing_note(link: &Path, crossing: &Crossing) -> String {
+    match crossing {
+        Crossing::NotFollowed => format!(
+            "not descending into link {} (--follow-links would)",
+            link.display()
+        ),
+        Crossing::Followed(target) => format!(
+            "following link {} into {}",
+            link.display(),
+            target.display()
+        ),
+        Crossing::Walked(target) => format!(
+            "not following link {} into {} again",
+            link.display(),
+            target.display()
+        ),
+    }
+}
+
+/// Returns why an operand is passed over rather than touched, if it is: `--apply-to`, the
+/// `--attr-filter`, `--skip-readonly`, or a `--checkpoint` showing an earlier run already
+/// touched it.
+fn skip_reason(
+    path: &Path,
+    options: &Options,
+    checkpoint: Option<&Checkpoint>,
+    stats: &StatCache,
+) -> Option<&'static str> {
+    if let Some(reason) = options.apply_to.skip_reason(path, stats) {
+        return Some(reason);
+    }
+    if filtered_out(options.attr_filter, path, stats) {
+        return Some("attributes do not match --attr-filter");
+    }
+    if options.skip_readonly && read_only_file(path, stats) {
+        return Some("read-only");
+    }
+    checkpoint
+        .filter(|checkpoint| checkpoint.is_done(path))
+        .map(|_| "done in an earlier run")
+}
+
+/// Why `--min-mtime` leaves a file alone.
+const NOT_BELOW_FLOOR: &str = "not older than --min-mtime";
+
+/// Reads the `--min-mtime` floor: a date as `-d` reads it, a `-t` stamp, or else the
+/// modification time of the file it names, as `-r` takes it.
+fn read_min_mtime(text: &str, options: &Options) -> std::io::Result<FileTime> {
+    let context = parse_context(options, options.now());
+    if let Ok(time) = read_date(text, options, context) {
+        return Ok(time);
+    }
+    if let Ok(time) = parse_touch_stamp(text, context) {
+        return Ok(time);
+    }
+    match read_reference(Path::new(text)) {
+        Ok(metadata) => Ok(FileTime::from_last_modification_time(&metadata)),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid --min-mtime '{}': not a date, a -t stamp or an existing file",
+                text
+            ),
+        )),
+    }
+}
+
+/// What the `--report-format` report tells of the operand `path`, which came to `result`.
+fn report_record(path: &Path, result: &std::io::Result<Step>, options: &Options) -> report::Record {
+    let (outcome, time, reason) = match result {
+        Ok(Step::Touched(TouchOutcome::Created, applied, ..)) => {
+            (report::Outcome::Created, *applied, String::new())
+        }
+        Ok(Step::Touched(_, applied, ..)) => (report::Outcome::Updated, *applied, String::new()),
+        Ok(Step::Unchanged) => (report::Outcome::Unchanged, None, String::new()),
+        Ok(Step::Skipped(reason)) => (report::Outcome::Skipped, None, reason.to_string()),
+        Ok(Step::NoCounterpart(counterpart)) => (
+            report::Outcome::Skipped,
+            None,
+            format!(
+                "no counterpart {} under --mirror-from",
+                counterpart.display()
+            ),
+        ),
+        Ok(Step::Cancelled) => (report::Outcome::Skipped, None, "cancelled".to_string()),
+        Err(e) => (report::Outcome::Failed, None, e.to_string()),
+    };
+    report::Record {
+        path: display_path(path, options.relative_to.as_deref())
+            .to_string_lossy()
+            .into_owned(),
+        outcome,
+        time,
+        reason,
+    }
+}
+
+/// What was done to a file besides setting its times.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Extras {
+    /// Where `--backup` copied it.
+    backup: Option<PathBuf>,
+    /// How many bytes `--empty` discarded from it.
+    discarded: Option<u64>,
+    /// The creation time `--creation-from-mtime` moved back to its modification time.
+    repaired: Option<FileTime>,
+}
+
+/// What became of an operand.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// Its times were set, and whether it was created or updated: the modification time
+    /// given, or the access time when only that was set. Then the volume it is on, if that
+    /// could be found out, its ID under `--touch-and-print-id`, and what else was done to
+    /// it.
+    Touched(
+        TouchOutcome,
+        Option<FileTime>,
+        Option<Arc<Volume>>,
+        Option<FileId>,
+        Extras,
+    ),
+    /// It was passed over, for the given reason.
+    Skipped(&'static str),
+    /// It already had the times given, so was left alone (`--report-unchanged`).
+    Unchanged,
+    /// It has no `--mirror-from` counterpart, which should have been at the given path.
+    NoCounterpart(PathBuf),
+    /// It was not started, because Ctrl+C was pressed.
+    Cancelled,
+}
+
+/// Returns whether `path` is a file with the read-only attribute (elsewhere than Windows,
+/// without write permission). Directories are never taken as read-only: on Windows the
+/// attribute means something else for them.
+fn read_only_file(path: &Path, stats: &StatCache) -> bool {
+    stats.metadata(path).is_ok_and(|metadata| {
+        !metadata.is_dir() && attrs::attributes(path, &metadata) & attrs::READONLY != 0
+    })
+}
+
+/// Returns whether `filter` (`--attr-filter`, or the one `--skip-hidden` makes) excludes
+/// an operand. Operands that do not exist yet have no attributes to check and are never
+/// excluded.
+fn filtered_out(filter: Option<AttrFilter>, path: &Path, stats: &StatCache) -> bool {
+    filter.is_some_and(|filter| {
+        stats
+            .metadata(path)
+            .is_ok_and(|metadata| !filter.matches(attrs::attributes(path, &metadata)))
+    })
+}
+
+/// Asks whether to go on when there are more than `threshold` operands, reading the answer
+/// from `input`. Only up to `threshold + 1` operands are taken from the stream to find
+/// out; all of them are gathered only to say how many there are before asking.
+fn confirm_large_batch<'a, R: BufRead, W: Write>(
+    mut operands: Box<dyn Iterator<Item = PathBuf> + 'a>,
+    threshold: usize,
+    input: &mut R,
+    output: &mut W,
+) -> std::io::Result<Box<dyn Iterator<Item = PathBuf> + 'a>> {
+    let first: Vec<PathBuf> = operands
+        .by_ref()
+        .take(threshold.saturating_add(1))
+        .collect();
+    if first.len() <= threshold {
+        return Ok(Box::new(first.into_iter().chain(operands)));
+    }
+    let all: Vec<PathBuf> = first.into_iter().chain(operands).collect();
+    write!(
+        output,
+        "About to touch {} files, continue? [y/N] ",
+        all.len()
+    )?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+        return Err(io::Error::other(format!(
+            "Nothing touched: the batch of {} files was not confirmed (use --yes to skip asking)",
+            all.len()
+        )));
+    }
+    Ok(Box::new(all.into_iter()))
+}
+
+/// Fails, touching nothing, when there are more than `limit` operands (`--max-files`).
+/// Only up to `limit + 1` operands are held to find out; past that the rest are only
+/// counted, to say how many there are.
+fn limit_batch<'a>(
+    mut operands: Box<dyn Iterator<Item = PathBuf> + 'a>,
+    limit: usize,
+) -> std::io::Result<Box<dyn Iterator<Item = PathBuf> + 'a>> {
+    let first: Vec<PathBuf> = operands.by_ref().take(limit.saturating_add(1)).collect();
+    if first.len() <= limit {
+        return Ok(Box::new(first.into_iter().chain(operands)));
+    }
+    Err(io::Error::other(too_many_files(
+        first.len() + operands.count(),
+        limit,
+    )))
+}
+
+/// The message for a batch over the `--max-files` limit.
+fn too_many_files(count: usize, limit: usize) -> String {
+    format!(
+        "Nothing touched: {} files is more than --max-files allows ({})",
+        count, limit
+    )
+}
+
+/// Picks the operand with the newest or oldest modification time. Operands that do not
+/// exist are passed over, and on a tie the earlier operand wins.
+fn select_operand(
+    operands: impl IntoIterator<Item = PathBuf>,
+    selection: Selection,
+) -> std::io::Result<PathBuf> {
+    let mut best: Option<(FileTime, PathBuf)> = None;
+    for operand in operands {
+        let Ok(metadata) = fs::metadata(&operand) else {
+            continue;
+        };
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        let better = match &best {
+            None => true,
+            Some((best_time, _)) => match selection {
+                Selection::Newest => mtime > *best_time,
+                Selection::Oldest => mtime < *best_time,
+            },
+        };
+        if better {
+            best = Some((mtime, operand));
+        }
+    }
+    best.map(|(_, operand)| operand).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Option --select found no existing files among the operands",
+        )
+    })
+}
+
+/// Puts the operands in `--sort` order. The sort is stable, so operands that compare equal
+/// keep the order they were given in. Unlike the stream it comes from, the whole list is
+/// held in memory to sort it.
+fn sort_operands(mut operands: Vec<PathBuf>, options: &Options, stats: &StatCache) -> Vec<PathBuf> {
+    let ignore_case = cfg!(windows) && !options.case_sensitive;
+    let name = |path: &PathBuf| {
+        let folded = ignore_case.then(|| path.to_string_lossy().to_lowercase());
+        (folded, path.clone())
+    };
+    match options.sort {
+        SortOrder::AsGiven => {}
+        SortOrder::Name => operands.sort_by_cached_key(name),
+        SortOrder::NameDescending => {
+            operands.sort_by_cached_key(|path| std::cmp::Reverse(name(path)))
+        }
+        SortOrder::Size => {
+            operands.sort_by_cached_key(|path| stats.metadata(path).ok().map(|m| m.len()))
+        }
+        SortOrder::Mtime => operands.sort_by_cached_key(|path| {
+            stats
+                .metadata(path)
+                .ok()
+                .map(|metadata| FileTime::from_last_modification_time(&metadata))
+        }),
+    }
+    operands
+}
+
+/// Returns whether a path ends in a path separator, as in `out\cache\`.
+fn ends_with_separator(path: &Path) -> bool {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .last()
+        .is_some_and(|byte| std::path::is_separator(char::from(*byte)))
+}
+
+/// Makes sure an operand ending in a path separator names a directory, returning whether
+/// it had to be created.
+///
+/// Such an operand must be an existing directory. With `--mkdir` a missing one is created
+/// instead (with its parents, given `-p`), unless `-c` says not to create anything.
+fn prepare_directory(path: &Path, options: &Options) -> std::io::Result<bool> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Ok(false),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            "exists but is not a directory",
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if options.no_create {
+                Ok(false)
+            } else if !options.mkdir {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no such directory (use --mkdir to create it)",
+                ))
+            } else if options.parents {
+                fs::create_dir_all(path).map(|()| true)
+            } else {
+                fs::create_dir(path).map(|()| true)
+            }
+        }
+        // A file in place of a directory, as in `file.txt/`.
+        Err(e) if e.kind() == io::ErrorKind::NotADirectory => Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            "exists but is not a directory",
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks, without changing anything, that an operand could be touched (`--preflight`).
+///
+/// Only what can be seen without writing is checked: that an existing operand can be
+/// reached, and that a missing one could be created where it is. Whether the file may be
+/// written is only known once a write is attempted.
+fn check_operand(path: &Path, options: &Options) -> std::io::Result<()> {
+    if ends_with_separator(path) {
+        return match fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => Ok(()),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "exists but is not a directory",
+            )),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if options.no_create || (options.mkdir && options.parents) {
+                    Ok(())
+                } else if !options.mkdir {
+                    Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "no such directory (use --mkdir to create it)",
+                    ))
+                } else {
+                    check_parent(path)
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotADirectory => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "exists but is not a directory",
+            )),
+            Err(e) => Err(e),
+        };
+    }
+    match fs::symlink_metadata(path) {
+        Ok(_) if options.exclusive => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "already exists (--exclusive)",
+        )),
+        Ok(metadata)
+            if metadata.is_symlink()
+                && !options.no_dereference
+                && options.broken_link == BrokenLinkPolicy::Error
+                && fs::metadata(path).is_err() =>
+        {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "broken symbolic link: its target does not exist",
+            ))
+        }
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if options.no_create || options.no_dereference {
+                Ok(())
+            } else {
+                check_parent(path)
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks that the directory a missing operand would be created in exists.
+fn check_parent(path: &Path) -> std::io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match fs::metadata(parent) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            "its parent is not a directory",
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "its parent directory does not exist",
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads the access and modification times of an operand for `--audit-log`, from the
+/// link itself under `--no-dereference`.
+fn current_times(
+    path: &Path,
+    options: &Options,
+    stats: &StatCache,
+) -> std::io::Result<audit::Times> {
+    let metadata = if options.no_dereference {
+        stats.symlink_metadata(path)?
+    } else {
+        stats.metadata(path)?
+    };
+    Ok((
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    ))
+}
+
+/// Touches one operand, preparing it first if it names a directory, through `handles`
+/// when `--wrap-handle-reuse` keeps them.
+fn touch_operand(
+    path: &Path,
+    times: &TouchOptions,
+    retries: u32,
+    options: &Options,
+    handles: Option<&Arc<HandlePool>>,
+) -> std::io::Result<(TouchOutcome, Option<FileId>, Extras)> {
+    let made_directory = ends_with_separator(path)
+        && prepare_directory(path, options).map_err(|e| explain_file_component(path, e))?;
+    // Copied before it is emptied, so the backup has what was there.
+    let backup = match &options.backup {
+        Some(suffix) => backup::backup(path, suffix, options.now())
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot back it up first: {}", e)))?,
+        None => None,
+    };
+    // Refused before the touch, so nothing is done to what can't be emptied.
+    if options.empty {
+        check_emptiable(path).map_err(|e| explain_file_component(path, e))?;
+    }
+    let (outcome, id) = touch_with_retry(
+        path,
+        times,
+        retries,
+        options.net_timeout,
+        options.print_id,
+        handles,
+    )
+    .map_err(|e| explain_file_component(path, e))?;
+    // The touch emptied it through the handle the times were set through; a file just
+    // created had nothing in it.
+    let discarded = match outcome {
+        TouchOutcome::Updated { previous_len, .. } if options.empty => previous_len,
+        _ => None,
+    };
+    // Repaired last, so it matches the modification time the file is left with.
+    let repaired = if options.creation_from_mtime {
+        repair_creation_time(path)?
+    } else {
+        None
+    };
+    let extras = Extras {
+        backup,
+        discarded,
+        repaired,
+    };
+    // The directory was there by the time it was touched, but only because of this run.
+    match outcome {
+        TouchOutcome::Updated { .. } if made_directory => Ok((TouchOutcome::Created, id, extras)),
+        outcome => Ok((outcome, id, extras)),
+    }
+}
+
+/// The times to give `path` under `--fix-future`: now for each of its access and
+/// modification times that is more than `margin` ahead of the clock, leaving the other
+/// alone. Returns `None` if neither is, or there is no file.
+fn clamp_future_times(
+    path: &Path,
+    margin: Duration,
+    times: TouchOptions,
+    options: &Options,
+    stats: &StatCache,
+) -> std::io::Result<Option<TouchOptions>> {
+    let (atime, mtime) = match current_times(path, options, stats) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        result => result?,
+    };
+    let now = options.now();
+    let limit = FileTime::from_unix_time(
+        now.unix_seconds()
+            .saturating_add(margin.as_secs().try_into().unwrap_or(i64::MAX)),
+        now.nanoseconds(),
+    );
+    let clamp = |time: FileTime| (time > limit).then_some(now);
+    let (atime, mtime) = (clamp(atime), clamp(mtime));
+    if atime.is_none() && mtime.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(TouchOptions {
+        atime,
+        mtime,
+        no_create: true,
+        ..times
+    }))
+}
+
+/// Describes how far `ahead` is after `now`, as seconds to the millisecond and in words:
+/// `5400.000 s (in 1 hour)`.
+fn format_skew(ahead: FileTime, now: FileTime) -> String {
+    let nanos =
+        |time: FileTime| time.unix_seconds() as i128 * 1_000_000_000 + time.nanoseconds() as i128;
+    format!(
+        "{:.3} s ({})",
+        (nanos(ahead) - nanos(now)) as f64 / 1e9,
+        humanize(ahead, now)
+    )
+}
+
+/// Reads the creation and modification times of `path`, failing where creation times
+/// cannot be read.
+fn creation_and_mtime(path: &Path) -> std::io::Result<(FileTime, FileTime)> {
+    let metadata = fs::metadata(path)?;
+    let created = FileTime::from_creation_time(&metadata).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "creation times cannot be read here (--creation-from-mtime)",
+        )
+    })?;
+    Ok((created, FileTime::from_last_modification_time(&metadata)))
+}
+
+/// Sets the creation time of `path` to its modification time if it was created after it
+/// was last modified, as files copied by some tools are (`--creation-from-mtime`).
+/// Returns the creation time it had, or `None` if it was left alone.
+fn repair_creation_time(path: &Path) -> std::io::Result<Option<FileTime>> {
+    let (created, mtime) = creation_and_mtime(path)?;
+    if created <= mtime {
+        return Ok(None);
+    }
+    set_creation_time(path, mtime)?;
+    Ok(Some(created))
+}
+
+/// Checks that `path` is a regular file `--empty` can empty, or not there at all.
+fn check_emptiable(path: &Path) -> std::io::Result<()> {
+    match fs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+        Ok(metadata) if metadata.is_dir() => Err(io::Error::new(
+            io::ErrorKind::IsADirectory,
+            "cannot empty a directory (--empty)",
+        )),
+        Ok(metadata) if !metadata.is_file() => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot empty something that is not a regular file (--empty)",
+        )),
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Explains a failure to reach `path` that comes from one of the directories above it
+/// being a file, as in `notes.txt/todo.txt`, which the OS reports only as a missing path
+/// or "not a directory". The error names the file. Any other error is returned as it is.
+fn explain_file_component(path: &Path, e: io::Error) -> io::Error {
+    if !matches!(
+        e.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+    ) {
+        return e;
+    }
+    let ancestors: Vec<&Path> = path
+        .ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .collect();
+    // Nothing below the first file can exist, so look from the top down.
+    let file = ancestors
+        .into_iter()
+        .rev()
+        .find(|ancestor| fs::metadata(ancestor).is_ok_and(|metadata| !metadata.is_dir()));
+    match file {
+        Some(file) => io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!(
+                "a path component is a file, not a directory: {}",
+                file.display()
+            ),
+        ),
+        None => e,
+    }
+}
+
+/// Reads the creation time of the reference for `--reference-all`.
+fn reference_creation_time(path: &Path) -> std::io::Result<FileTime> {
+    let created = read_reference(path)?.created()?;
+    Ok(FileTime::from_system_time(created))
+}
+
+/// Reads the metadata of a reference through a handle to it. Directories can only be
+/// opened with backup semantics, and asking for attribute access alone leaves the
+/// reference's own access time as it was.
+#[cfg(windows)]
+fn read_reference(path: &Path) -> std::io::Result<fs::Metadata> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_READ_ATTRIBUTES,
+    };
+
+    fs::OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?
+        .metadata()
+}
+
+/// Reads the metadata of a reference, file or directory alike.
+#[cfg(not(windows))]
+fn read_reference(path: &Path) -> std::io::Result<fs::Metadata> {
+    fs::metadata(path)
+}
+
+/// Whether `e`, from setting a creation time, says creation times can't be set at all
+/// here, so every other file would fail the same way: the platform or the file system
+/// has no way to, rather than this one file refusing it.
+fn creation_times_unsupported(e: &io::Error) -> bool {
+    // ERROR_INVALID_FUNCTION and ERROR_NOT_SUPPORTED, from file systems without them.
+    e.kind() == io::ErrorKind::Unsupported
+        || (cfg!(windows) && matches!(e.raw_os_error(), Some(1 | 50)))
+}
+
+/// The creation time for the next file created under `--force-create-time-monotonic`:
+/// `time`, or one 100 ns tick after the `previous` file's if that is later.
+fn monotonic_creation_time(previous: &mut Option<FileTime>, time: FileTime) -> FileTime {
+    let time = match *previous {
+        Some(last) => {
+            let nanos = last.nanoseconds() + 100;
+            let next = if nanos < 1_000_000_000 {
+                FileTime::from_unix_time(last.unix_seconds(), nanos)
+            } else {
+                FileTime::from_unix_time(last.unix_seconds() + 1, nanos - 1_000_000_000)
+            };
+            time.max(next)
+        }
+        None => time,
+    };
+    *previous = Some(time);
+    time
+}
+
+/// Returns whether an error is likely to go away if the operation is retried, such as
+/// another process briefly holding the file open.
+fn is_transient(e: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION.
+    #[cfg(windows)]
+    if matches!(e.raw_os_error(), Some(32) | Some(33)) {
+        return true;
+    }
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Touches a file, retrying transient failures up to `retries` more times with a
+/// growing delay between attempts. With a `timeout`, each attempt is given up on after
+/// that long, which counts as a transient failure.
+fn touch_with_retry(
+    path: &Path,
+    times: &TouchOptions,
+    retries: u32,
+    timeout: Option<Duration>,
+    with_id: bool,
+    handles: Option<&Arc<HandlePool>>,
+) -> std::io::Result<(TouchOutcome, Option<FileId>)> {
+    let mut attempt = 0;
+    loop {
+        let result = match timeout {
+            Some(timeout) => {
+                let (path, times, handles) = (path.to_path_buf(), *times, handles.cloned());
+                touch_with_timeout(timeout, move || {
+                    touch_once(&path, &times, with_id, handles.as_deref())
+                })
+            }
+            None => touch_once(path, times, with_id, handles.map(Arc::as_ref)),
+        };
+        match result {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                thread::sleep(RETRY_DELAY * attempt);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs `touch` on a thread of its own, giving up on it after `timeout` (`--net-timeout`).
+///
+/// A blocked open or set-times call can't be cancelled, so a thread that times out is
+/// abandoned rather than stopped: the touch may still complete later, while the process
+/// is running.
+fn touch_with_timeout<T, F>(timeout: Duration, touch: F) -> std::io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // The receiver is gone if we gave up waiting; the result goes nowhere then.
+        let _ = sender.send(touch());
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!(
+                "no response within {} ms (--net-timeout); the change may still be made later",
+                timeout.as_millis()
+            ),
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(io::Error::other("the touch was abandoned unexpectedly"))
+        }
+    }
+}
+
+/// Touches a file once, reading its ID from the handle the times were set through when
+/// `with_id` asks for it (`--touch-and-print-id`), and through the handle kept in
+/// `handles` if there is one (`--wrap-handle-reuse`).
+fn touch_once(
+    path: &Path,
+    times: &TouchOptions,
+    with_id: bool,
+    handles: Option<&HandlePool>,
+) -> std::io::Result<(TouchOutcome, Option<FileId>)> {
+    match (handles, with_id) {
+        (Some(handles), true) => handles.touch_file_with_id(path, times),
+        (Some(handles), false) => handles
+            .touch_file(path, times)
+            .map(|outcome| (outcome, None)),
+        (None, true) => touch_file_with_id(path, times),
+        (None, false) => touch_file(path, times).map(|outcome| (outcome, None)),
+    }
+}
+
+/// Returns the message for a path that a wildcard or `-R` expanded into a system directory.
+fn blocked_message(path: &Path, protected: &Path) -> String {
+    format!(
+        "Refusing to touch {}: it is inside the system directory {} (use --allow-system to override)",
+        path.display(),
+        protected.display()
+    )
+}
+
+/// Opens a `--files-from` list, naming the list in any error.
+fn open_file_list(list: &Path, options: &Options) -> std::io::Result<(PathBuf, ListEntries)> {
+    let entries =
+        open_operand_list(list, options.null_separated, options.input_encoding).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Error reading file list {}: {}", list.display(), e),
+            )
+        })?;
+    Ok((list.to_path_buf(), entries))
+}
+
+/// Opens a `--filelist` manifest, naming it in any error. Its entries are always
+/// newline-separated, whatever `-0` says, and relative ones are resolved against the
+/// manifest's directory rather than the current one (which is still used for `-`).
+fn open_filelist(list: &Path, encoding: InputEncoding) -> std::io::Result<(PathBuf, ListEntries)> {
+    let mut entries = open_operand_list(list, false, encoding).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Error reading file list {}: {}", list.display(), e),
+        )
+    })?;
+    entries.base = Some(list.parent().map(Path::to_path_buf).unwrap_or_default());
+    Ok((list.to_path_buf(), entries))
+}
+
+/// Rejects options that only make sense alongside another one that was not given.
+fn check_option_combinations(options: &Options) -> std::io::Result<()> {
+    if options.retries.is_some() && options.on_error != ErrorPolicy::Retry {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --retries requires --on-error=retry",
+        ));
+    }
+    if options.owner_existing && options.owner.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --owner-existing requires --owner",
+        ));
+    }
+    if options.exec_shell && options.exec.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --exec-shell requires --exec",
+        ));
+    }
+    if let Some(command) = &options.exec {
+        Exec::parse(command, options.exec_shell)?;
+    }
+    if options.copy_security && !cfg!(windows) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Option --copy-security is only supported on Windows",
+        ));
+    }
+    if options.copy_security && options.reference.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --copy-security requires -r or --reference-all",
+        ));
+    }
+    if options.exclusive && options.no_create {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --exclusive cannot be combined with -c",
+        ));
+    }
+    if options.backup.is_some() && options.exclusive {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --backup cannot be combined with --exclusive, which never touches an existing file",
+        ));
+    }
+    if options.min_mtime.is_some()
+        && (explicit_times(options)
+            || options.fix_future.is_some()
+            || options.access_only
+            || options.modify_only
+            || options.leave_mtime)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --min-mtime cannot be combined with other time options",
+        ));
+    }
+    if options.fix_future.is_some()
+        && (explicit_times(options)
+            || options.access_only
+            || options.modify_only
+            || options.leave_atime
+            || options.leave_mtime)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --fix-future cannot be combined with other time options",
+        ));
+    }
+    // --times is only in the way if it gives a creation time, which is checked with it.
+    if options.creation_from_mtime
+        && (options.reference_all || options.real_creation || options.create_time_monotonic)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --creation-from-mtime cannot be combined with other ways of setting creation times",
+        ));
+    }
+    if options.if_content_changed && options.empty {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --if-content-changed cannot be combined with --empty",
+        ));
+    }
+    if options.empty && options.exclusive {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --empty cannot be combined with --exclusive or --touch-mode=create, which never touch an existing file",
+        ));
+    }
+    if options.empty && options.no_dereference {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --empty cannot be combined with --no-dereference",
+        ));
+    }
+    if options.lockfile && options.no_create {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --lockfile cannot be combined with -c",
+        ));
+    }
+    if options.steal_stale.is_some() && !options.lockfile {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --steal-stale requires --lockfile",
+        ));
+    }
+    if options.create_time_monotonic && !options.reference_all && options.times.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --force-create-time-monotonic requires --times or --reference-all",
+        ));
+    }
+    if options.print0
+        && options.print0_errors == Some(ErrorList::Stderr)
+        && (options.verbose || options.report_unchanged)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --print0 moves -v and --report-unchanged output to standard error, which --print0-errors keeps for its list",
+        ));
+    }
+    if options.progress.is_some() && options.print0_errors == Some(ErrorList::Stderr) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --progress cannot be combined with --print0-errors writing to standard error",
+        ));
+    }
+    if options.emit_command.is_some()
+        && (options.mirror_from.is_some()
+            || options.fix_future.is_some()
+            || options.min_mtime.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --emit-command cannot be combined with --mirror-from, --fix-future or --min-mtime, which give each file its own times",
+        ));
+    }
+    if options.emit_command.is_some() && options.copy_security {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --emit-command cannot be combined with --copy-security, which needs the reference file the command no longer names",
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves the `--owner` account, if one was given.
+fn resolve_owner(options: &Options) -> std::io::Result<Option<owner::Owner>> {
+    let Some(name) = &options.owner else {
+        return Ok(None);
+    };
+    owner::resolve(name).map(Some).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid --owner account '{}': {}", name, e),
+        )
+    })
+}
+
+/// Checks a whole invocation without touching anything (`--preflight`).
+///
+/// Unlike a real run, which stops at the first usage error, every check is made and every
+/// problem is reported together: the time options and the files they read, the file lists,
+/// the option combinations and each operand.
+fn preflight<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let problems = RefCell::new(Vec::new());
+    let stats = StatCache::new(options.stat_cache);
+    let operands = match expand_operand_vars(operands.clone(), options) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            problems.borrow_mut().push(e.to_string());
+            operands
+        }
+    };
+    let (operands, blocked, unmatched) = expand_operands(operands, options, &stats);
+    for (path, protected) in blocked {
+        problems
+            .borrow_mut()
+            .push(blocked_message(&path, &protected));
+    }
+    let mirror = mirror_roots(&operands, options).unwrap_or_else(|e| {
+        problems.borrow_mut().push(e.to_string());
+        None
+    });
+    let mut lists = Vec::new();
+    let opened = options
+        .files_from
+        .iter()
+        .map(|list| open_file_list(list, options))
+        .chain(
+            options
+                .filelists
+                .iter()
+                .map(|list| open_filelist(list, options.input_encoding)),
+        );
+    for list in opened {
+        match list {
+            Ok(entries) => lists.push(entries),
+            Err(e) => problems.borrow_mut().push(e.to_string()),
+        }
+    }
+    let times = resolve_times(options)
+        .map_err(|e| problems.borrow_mut().push(e.to_string()))
+        .ok();
+    let floor = options.min_mtime.as_deref().and_then(|text| {
+        read_min_mtime(text, options)
+            .map_err(|e| problems.borrow_mut().push(e.to_string()))
+            .ok()
+    });
+    let created = resolve_creation_time(options)
+        .map_err(|e| problems.borrow_mut().push(e.to_string()))
+        .ok()
+        .flatten();
+    let checkpoint = options.checkpoint.as_ref().and_then(|path| {
+        Checkpoint::read(path, options.null_separated)
+            .map_err(|e| {
+                problems.borrow_mut().push(format!(
+                    "Error reading checkpoint {}: {}",
+                    path.display(),
+                    e
+                ))
+            })
+            .ok()
+    });
+    let checks = [
+        check_option_combinations(options),
+        resolve_owner(options).map(drop),
+    ];
+    problems.borrow_mut().extend(
+        checks
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|e| e.to_string()),
+    );
+    let mut operands = operand_stream(operands, lists, options, &problems);
+    if let Some(selection) = options.select {
+        operands = match select_operand(operands, selection) {
+            Ok(selected) => Box::new(std::iter::once(selected)),
+            Err(e) => {
+                problems.borrow_mut().push(e.to_string());
+                Box::new(std::iter::empty())
+            }
+        };
+    }
+
+    let volumes = Volumes::new(SystemDetector::default());
+    let planner = times.map(|times| Planner {
+        options,
+        times,
+        floor,
+        mirror: mirror.as_ref(),
+        checkpoint: checkpoint.as_ref(),
+        volumes: &volumes,
+        stats: &stats,
+    });
+    let (mut listed, mut checked, mut changes) = (0, 0, 0);
+    for operand in operands {
+        listed += 1;
+        if skip_reason(&operand, options, checkpoint.as_ref(), &stats).is_some() {
+            continue;
+        }
+        checked += 1;
+        let plan = check_operand(&operand, options)
+            .map_err(|e| explain_file_component(&operand, e))
+            .and_then(|()| {
+                planner
+                    .as_ref()
+                    .map(|planner| planner.plan(&operand, created))
+                    .transpose()
+            });
+        let plan = match plan {
+            Ok(plan) => plan,
+            Err(e) => {
+                problems
+                    .borrow_mut()
+                    .push(format!("Cannot touch {}: {}", operand.display(), e));
+                continue;
+            }
+        };
+        let (Some(planner), Some(plan)) =
+            (planner.as_ref().filter(|_| options.fail_if_changes), plan)
+        else {
+            continue;
+        };
+        if let Some(change) = pending_change(&operand, planner, plan, created) {
+            changes += 1;
+            if options.verbose && !options.quiet {
+                let shown = display_path(&operand, options.relative_to.as_deref());
+                writeln!(writer, "{} {}", change, shown.display())?;
+            }
+        }
+    }
+
+    let mut problems = problems.into_inner();
+    if options.fail_on_zero_matched && listed == 0 {
+        problems.push(zero_matched_message(&unmatched));
+    }
+    if let Some(limit) = options.max_files.filter(|limit| listed > *limit) {
+        problems.push(too_many_files(listed, limit));
+    }
+    if !problems.is_empty() {
+        return Err(std::io::Error::other(problems.join("\n")));
+    }
+    if changes > 0 {
+        return Err(std::io::Error::other(format!(
+            "Preflight found {} of {} operand(s) not up to date",
+            changes, checked
+        )));
+    }
+    if !options.quiet {
+        writeln!(
+            writer,
+            "Preflight passed: {} operand(s) checked, nothing was touched",
+            checked
+        )?;
+    }
+    Ok(())
+}
+
+/// What a run does to an operand, decided before anything is done to it.
+#[derive(Debug)]
+enum Plan {
+    /// It is left alone, as the step says.
+    Leave(Step),
+    /// It is touched with the times given. Then the volume it is on, if that could be
+    /// found out, with whether this is the first time that volume has come up, and under
+    /// `--if-content-changed` the hash of its content, to be stored before it is touched.
+    Touch(TouchOptions, Option<(Arc<Volume>, bool)>, Option<String>),
+}
+
+/// Decides what becomes of each operand, for a run and for `--preflight` alike, so a
+/// preflight reports exactly what the run would do.
+struct Planner<'a> {
+    options: &'a Options,
+    /// The times given, before `--mirror-from`, `--fix-future` or `--min-mtime` settle
+    /// each file's own.
+    times: TouchOptions,
+    /// The `--min-mtime` floor.
+    floor: Option<FileTime>,
+    mirror: Option<&'a (PathBuf, PathBuf)>,
+    checkpoint: Option<&'a Checkpoint>,
+    volumes: &'a Volumes<SystemDetector>,
+    stats: &'a StatCache,
+}
+
+impl Planner<'_> {
+    /// Decides what becomes of `path`, which would be given the creation time `created`,
+    /// if any. Warnings about it, such as `--detect-clock-skew` gives, are given here.
+    fn plan(&self, path: &Path, created: Option<FileTime>) -> std::io::Result<Plan> {
+        let (options, stats) = (self.options, self.stats);
+        if let Some(reason) = skip_reason(path, options, self.checkpoint, stats) {
+            return Ok(Plan::Leave(Step::Skipped(reason)));
+        }
+        let mirrored = match self.mirror {
+            Some((destination, source)) => {
+                match mirrored_times(path, destination, source, &self.times)? {
+                    Some(found) => Some(found),
+                    None => {
+                        let counterpart = mirror_source(path, destination, source);
+                        return Ok(Plan::Leave(Step::NoCounterpart(counterpart)));
+                    }
+                }
+            }
+            None => None,
+        };
+        let clamped = match options.fix_future {
+            Some(margin) => match clamp_future_times(path, margin, self.times, options, stats)? {
+                Some(clamped) => Some(clamped),
+                None => return Ok(Plan::Leave(Step::Skipped("not ahead of the clock"))),
+            },
+            None => None,
+        };
+        let raised = match self.floor {
+            Some(floor) => match current_times(path, options, stats) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Ok(Plan::Leave(Step::Skipped("does not exist")))
+                }
+                Err(e) => return Err(e),
+                Ok((_, mtime)) if mtime >= floor => {
+                    return Ok(Plan::Leave(Step::Skipped(NOT_BELOW_FLOOR)))
+                }
+                Ok(_) => Some(TouchOptions {
+                    atime: None,
+                    mtime: Some(floor),
+                    no_create: true,
+                    ..self.times
+                }),
+            },
+            None => None,
+        };
+        let times = clamped.or(raised).or(mirrored).unwrap_or(self.times);
+        let volume = self.volumes.lookup(path);
+        if let Some((volume, first)) = &volume {
+            let setting = [times.atime, times.mtime, created];
+            let notes = volume::check_times(&volume.filesystem, setting)
+                .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+            // Noted once per volume rather than for every file, and only for times that
+            // were asked for: nobody minds the current time being rounded.
+            if *first && explicit_times(options) {
+                for note in notes {
+                    eprintln!("Warning: {}", note);
+                }
+            }
+        }
+        if options.detect_clock_skew || options.only_newer {
+            if let Ok((_, mtime)) = current_times(path, options, stats) {
+                let older = options.only_newer && times.mtime.is_some_and(|given| given < mtime);
+                let now = options.now();
+                if options.detect_clock_skew && mtime > now {
+                    eprintln!(
+                        "Warning: the modification time of {} is {} ahead of the system clock; {}",
+                        path.display(),
+                        format_skew(mtime, now),
+                        if older {
+                            "leaving it alone (--only-newer)"
+                        } else if times.mtime.is_some() {
+                            "touching it moves it back"
+                        } else {
+                            "only its access time is set"
+                        }
+                    );
+                }
+                if older {
+                    return Ok(Plan::Leave(Step::Skipped(
+                        "modification time is newer than the one given",
+                    )));
+                }
+            }
+        }
+        if options.report_unchanged && self.up_to_date(path, &times, volume.as_ref(), created) {
+            return Ok(Plan::Leave(Step::Unchanged));
+        }
+        // With nothing else to do to it, a file created before it was modified is left be.
+        if options.creation_from_mtime && times.atime.is_none() && times.mtime.is_none() {
+            match creation_and_mtime(path) {
+                Ok((created, mtime)) if created <= mtime => {
+                    return Ok(Plan::Leave(Step::Skipped(
+                        "not created after it was modified",
+                    )))
+                }
+                // Missing files are left to the touch, to create or pass over.
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        let hash = match options.if_content_changed {
+            true if content_hash::is_sidecar(path) => {
+                return Ok(Plan::Leave(Step::Skipped("stored hash")))
+            }
+            true => content_hash::hash_file(path)?,
+            false => None,
+        };
+        if let Some(hash) = &hash {
+            if content_hash::stored(path).as_deref() == Some(hash.as_str()) {
+                return Ok(Plan::Leave(Step::Skipped("content unchanged")));
+            }
+        }
+        Ok(Plan::Touch(times, volume, hash))
+    }
+
+    /// Whether touching the existing `path` with `times` would leave it as it is: only its
+    /// access and modification times would be set, and it has them already, allowing for
+    /// the rounding of its filesystem.
+    fn up_to_date(
+        &self,
+        path: &Path,
+        times: &TouchOptions,
+        volume: Option<&(Arc<Volume>, bool)>,
+        created: Option<FileTime>,
+    ) -> bool {
+        let options = self.options;
+        // Without times given, touching sets the current time, so always changes it.
+        if !explicit_times(options)
+            || created.is_some()
+            || (options.owner.is_some() && options.owner_existing)
+            || options.empty
+            || options.backup.is_some()
+        {
+            return false;
+        }
+        let Ok((atime, mtime)) = current_times(path, options, self.stats) else {
+            return false;
+        };
+        let filesystem = volume.map(|(volume, _)| volume.filesystem.as_str());
+        volume::already_set(filesystem, [times.atime, times.mtime], [atime, mtime])
+    }
+}
+
+/// Says how touching an operand as `plan` has it would change it, if at all
+/// (`--fail-if-changes`).
+fn pending_change(
+    path: &Path,
+    planner: &Planner,
+    plan: Plan,
+    created: Option<FileTime>,
+) -> Option<&'static str> {
+    let Plan::Touch(times, volume, _) = plan else {
+        return None;
+    };
+    let options = planner.options;
+    if current_times(path, options, planner.stats).is_err() {
+        return (!options.no_create && !options.no_dereference).then_some("would create");
+    }
+    (!planner.up_to_date(path, &times, volume.as_ref(), created)).then_some("would update")
+}
+
+/// Reports how the volume holding each operand keeps timestamps (`--probe`), touching
+/// nothing.
+fn probe_operands<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    if operands.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --probe needs a file to probe",
+        ));
+    }
+    let mut messages = Vec::new();
+    for (index, operand) in operands.iter().enumerate() {
+        match probe::probe(operand) {
+            Ok(found) if options.json => write!(writer, "{}", found.to_json())?,
+            Ok(found) => {
+                if index > 0 {
+                    writeln!(writer)?;
+                }
+                write!(writer, "{}", found.to_text())?;
+            }
+            Err(e) => messages.push(format!("Error probing {}: {}", operand.display(), e)),
+        }
+    }
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(messages.join("\n")))
+    }
+}
+
+/// Tests which times the filesystem of the directory given, or the current one, keeps
+/// (`--self-test`), and writes the report. Fails if modification times cannot be set.
+fn self_test_operand<W: Write>(operands: Vec<PathBuf>, mut writer: W) -> std::io::Result<()> {
+    let dir = match operands.as_slice() {
+        [] => PathBuf::from("."),
+        [dir] => dir.clone(),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Option --self-test takes at most one directory",
+            ))
+        }
+    };
+    let report = self_test::self_test(&dir)
+        .map_err(|e| io::Error::new(e.kind(), format!("Error testing {}: {}", dir.display(), e)))?;
+    write!(writer, "{}", report.to_text())?;
+    if report.passed() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "Modification times cannot be set in {}",
+            dir.display()
+        )))
+    }
+}
+
+/// Creates a file with a unique name in the directory given, or the temporary directory,
+/// and writes its absolute path (`--temp`). The new file is then touched as an operand
+/// would be, so the time options and the others that change a file apply to it; if that
+/// fails, the file is deleted again.
+fn temp_operand<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    writer: W,
+) -> std::io::Result<()> {
+    let dir = match operands.as_slice() {
+        [] => env::temp_dir(),
+        [dir] => dir.clone(),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Option --temp takes at most one directory",
+            ))
+        }
+    };
+    refuse_with_new_files(options, "--temp")?;
+    let times = resolve_times(options)?;
+    let path = mdtouch::create_temp_file(
+        &dir,
+        options.temp_prefix.as_deref().unwrap_or("mdtouch-"),
+        options.temp_suffix.as_deref().unwrap_or(".tmp"),
+        &times,
+    )
+    .map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Error creating a file in {}: {}", dir.display(), e),
+        )
+    })?;
+    touch_new_files(options, vec![path], writer)
+}
+
+/// Creates each operand, or if its name is taken, a numbered name beside it
+/// (`--unique`), and writes the paths created. As with `--temp`, the new files are then
+/// touched as operands would be.
+fn unique_operands<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    writer: W,
+) -> std::io::Result<()> {
+    if operands.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --unique requires a file",
+        ));
+    }
+    refuse_with_new_files(options, "--unique")?;
+    let times = resolve_times(options)?;
+    let pattern = options
+        .unique_pattern
+        .as_deref()
+        .unwrap_or(mdtouch::UNIQUE_PATTERN);
+    let mut created = Vec::new();
+    for operand in &operands {
+        match mdtouch::create_unique_file(operand, pattern, &times) {
+            Ok(path) => created.push(path),
+            Err(e) => {
+                for path in &created {
+                    let _ = fs::remove_file(path);
+                }
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Error creating {}: {}", operand.display(), e),
+                ));
+            }
+        }
+    }
+    touch_new_files(options, created, writer)
+}
+
+/// Refuses the options that make no sense for files `option` creates itself.
+fn refuse_with_new_files(options: &Options, option: &str) -> std::io::Result<()> {
+    if options.no_create
+        || options.recursive
+        || !options.files_from.is_empty()
+        || !options.filelists.is_empty()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Option {} cannot be combined with -c, -R, --files-from or --filelist",
+                option
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Touches files just created by `--temp` or `--unique` and writes their paths, or
+/// deletes them again if touching fails.
+fn touch_new_files<W: Write>(
+    options: &Options,
+    paths: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    // Their paths are the only output, so what touching them would report is dropped.
+    if let Err(e) = touch_operands(options, paths.clone(), io::sink()) {
+        for path in &paths {
+            let _ = fs::remove_file(path);
+        }
+        return Err(e);
+    }
+    for path in &paths {
+        writeln!(writer, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Saves the times of each operand to its sidecar (`--save-times`), or puts them back from
+/// it (`--restore-times`), noting each under `-v`.
+fn saved_times_operands<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let (option, action, done) = match (options.save_times, options.restore_times) {
+        (true, true) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Options --save-times and --restore-times cannot be combined",
+            ))
+        }
+        (true, false) => ("--save-times", "saving", "saved"),
+        _ => ("--restore-times", "restoring", "restored"),
+    };
+    if operands.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Option {} needs a file", option),
+        ));
+    }
+    let mut messages = Vec::new();
+    for operand in &operands {
+        let result = if options.save_times {
+            saved::save(operand)
+        } else {
+            saved::restore(operand)
+        };
+        match result {
+            Ok(_) if options.verbose && !options.quiet => {
+                writeln!(writer, "{} times of {}", done, operand.display())?;
+            }
+            Ok(_) => {}
+            Err(e) => messages.push(format!(
+                "Error {} times of {}: {}",
+                action,
+                operand.display(),
+                e
+            )),
+        }
+    }
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(messages.join("\n")))
+    }
+}
+
+/// Reports how the times in the two operand trees differ (`--diff-trees`), touching
+/// nothing. Fails, with no message of its own, when they differ.
+fn diff_operands<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let [first, second] = &operands[..] else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --diff-trees needs exactly two directories to compare",
+        ));
+    };
+    let diff = diff::diff_trees(first, second, options.fuzz.unwrap_or_default())?;
+    if options.json {
+        write!(writer, "{}", diff.to_json())?;
+    } else {
+        write!(writer, "{}", diff.to_text())?;
+    }
+    if diff.trees_match() {
+        Ok(())
+    } else {
+        // The report has already said how; the exit status is what's left to say.
+        Err(std::io::Error::other(String::new()))
+    }
+}
+
+/// Sets the modification time of each file extracted from the `--from-zip` archive into
+/// the operand directory to its entry's time, matching them by relative path. Entries
+/// with no file on disk are reported and skipped; files the archive doesn't list are
+/// left alone.
+fn touch_from_zip<W: Write>(
+    options: &Options,
+    archive: &Path,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let [root] = &operands[..] else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --from-zip needs exactly one directory the archive was extracted to",
+        ));
+    };
+    let entries =
+        zip::read_entries(archive, parse_context(options, options.now())).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Error reading archive {}: {}", archive.display(), e),
+            )
+        })?;
+    let mut messages = Vec::new();
+    for entry in entries {
+        let Some(relative) = zip_entry_path(&entry.name) else {
+            eprintln!(
+                "Warning: skipped {} (names a path outside the directory)",
+                entry.name
+            );
+            continue;
+        };
+        let path = root.join(relative);
+        if fs::symlink_metadata(&path).is_err() {
+            eprintln!(
+                "Warning: skipped {} (no {} on disk)",
+                entry.name,
+                path.display()
+            );
+            continue;
+        }
+        let times = TouchOptions {
+            mtime: Some(entry.modified),
+            no_create: true,
+            ..TouchOptions::default()
+        };
+        if let Err(e) = touch_file(&path, &times) {
+            messages.push(format!("Error touching {}: {}", path.display(), e));
+            continue;
+        }
+        if options.verbose && !options.quiet {
+            let precision = if entry.dos_time {
+                " (a DOS time, kept to 2 s)"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "touched {} at {}{}",
+                path.display(),
+                format_iso8601(entry.modified),
+                precision
+            )?;
+        }
+    }
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(messages.join("\n")))
+    }
+}
+
+/// The relative path a zip entry name stands for, or `None` if it would lead out of the
+/// directory the archive was extracted to. Both `/` and `\` separate components.
+fn zip_entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+    {
+        if component == ".." || component.contains(':') {
+            return None;
+        }
+        path.push(component);
+    }
+    Some(path)
+}
+
+/// Has the `--serve` server on `name` touch each operand, with the times the options
+/// give, or passes on the JSON request lines from standard input when there are none.
+/// Each reply is written to `writer`.
+fn send_requests<W: Write>(
+    name: &OsStr,
+    options: &Options,
+    operands: Vec<PathBuf>,
+    writer: W,
+) -> std::io::Result<()> {
+    let failed = if operands.is_empty() {
+        serve::send(name, io::stdin().lock().lines(), writer)?
+    } else {
+        // The server may be running in another directory.
+        let times = resolve_times(options)?;
+        let requests = operands
+            .iter()
+            .map(|path| Ok(serve::request_line(&normalize_path(path), &times)));
+        serve::send(name, requests, writer)?
+    };
+    if failed == 0 {
+        Ok(())
+    } else {
+        // The replies have already said which requests failed, and why.
+        Err(std::io::Error::other(String::new()))
+    }
+}
+
+/// Runs the application logic.
+///
+/// # Arguments
+///
+/// * `args` - A vector of command line arguments (excluding the program name).
+/// * `writer` - A mutable reference to a writer for standard output.
+fn run<W: Write>(args: Vec<OsString>, writer: W) -> std::io::Result<()> {
+    run_with_clock(args, writer, Arc::new(SystemClock))
+}
+
+/// Runs the application logic as [`run`] does, reading the current time from `clock`.
+fn run_with_clock<W: Write>(
+    args: Vec<OsString>,
+    mut writer: W,
+    clock: Arc<dyn TimeSource>,
+) -> std::io::Result<()> {
+    if gnu::requested(&args) {
+        return gnu::run(args, writer, &*clock);
+    }
+
+    // If no arguments are provided, print the version and a short summary.
+    if args.is_empty() {
+        writeln!(writer, "mdtouch  {}", BUILD_DATETIME)?;
+        writeln!(
+            writer,
+            "A tool to update file timestamps or create empty files, mimicking the Unix touch command."
+        )?;
+        return Ok(());
+    }
+
+    // If any argument is a help flag, display help and exit.
+    if args.iter().any(|arg| arg == "-h" || arg == "-?") {
+        writeln!(writer, "{}", help_message())?;
+        return Ok(());
+    }
+
+    let (mut options, operands) = parse_args(args.clone())?;
+    options.clock = Some(Arc::clone(&clock));
+    if options.elevate && !elevate::is_elevated() {
+        return elevate::relaunch(&elevate::without_elevate(args));
+    }
+    if options.probe {
+        return probe_operands(&options, operands, writer);
+    }
+    if options.self_test {
+        return self_test_operand(operands, writer);
+    }
+    if options.save_times || options.restore_times {
+        return saved_times_operands(&options, operands, writer);
+    }
+    if options.diff_trees {
+        return diff_operands(&options, operands, writer);
+    }
+    if let Some(archive) = &options.from_zip {
+        return touch_from_zip(&options, archive, operands, writer);
+    }
+    if let Some(name) = &options.serve {
+        if !operands.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Option --serve takes no operands",
+            ));
+        }
+        return serve::serve(name, clock);
+    }
+    if let Some(name) = &options.send {
+        return send_requests(name, &options, operands, writer);
+    }
+    if options.json && !options.verbose && !options.report_unchanged && !options.print_id {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --json requires -v, --report-unchanged, --touch-and-print-id, --probe or --diff-trees",
+        ));
+    }
+    if options.fuzz.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --fuzz requires --diff-trees",
+        ));
+    }
+    if options.fail_if_changes && !options.preflight {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --fail-if-changes requires --preflight",
+        ));
+    }
+    if options.preflight {
+        return preflight(&options, operands, writer);
+    }
+    if options.every.is_some() && options.while_pid.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --every requires --while-pid",
+        ));
+    }
+    if options.follow_links && !options.recursive {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --follow-links requires -R",
+        ));
+    }
+    if options.sorted && !options.recursive {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --sorted requires -R",
+        ));
+    }
+    if options.one_file_system && !options.recursive {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --one-file-system requires -R",
+        ));
+    }
+    if (options.temp_prefix.is_some() || options.temp_suffix.is_some()) && !options.temp {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Options --prefix and --suffix require --temp",
+        ));
+    }
+    if options.unique_pattern.is_some() && !options.unique {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Option --unique-pattern requires --unique",
+        ));
+    }
+    if options.temp && options.unique {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Options --temp and --unique cannot be combined",
+        ));
+    }
+    if options.temp {
+        return temp_operand(&options, operands, writer);
+    }
+    if options.unique {
+        return unique_operands(&options, operands, writer);
+    }
+    match options.while_pid {
+        Some(pid) => touch_while_running(pid, &options, operands, writer),
+        None => touch_operands(&options, operands, writer),
+    }
+}
+
+/// Touches the operands, then again every `--every` interval for as long as process
+/// `pid` runs (`--while-pid`). A process that isn't running, or can't be watched, gets
+/// them touched just once. A failed round ends the loop with its error.
+fn touch_while_running<W: Write>(
+    pid: u32,
+    options: &Options,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let process = watch::Process::open(pid);
+    let interval = options.every.unwrap_or(watch::DEFAULT_INTERVAL);
+    loop {
+        touch_operands(options, operands.clone(), &mut writer)?;
+        match &process {
+            Some(process) if !process.wait(interval)? => {}
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// The creation time to give each file, if any: the reference's under `--reference-all`,
+/// or the third of `--times`.
+fn resolve_creation_time(options: &Options) -> std::io::Result<Option<FileTime>> {
+    let mut created = None;
+    if let Some(reference) = options
+        .reference
+        .as_deref()
+        .filter(|_| options.reference_all)
+    {
+        match reference_creation_time(reference) {
+            Ok(time) => created = Some(time),
+            Err(e) => eprintln!(
+                "Warning: cannot read the creation time of {} ({}); copying only the access and modification times",
+                reference.display(),
+                e
+            ),
+        }
+    }
+    if let Some(slots) = &options.times {
+        created = parse_time_slots(
+            slots,
+            parse_context(options, options.now()),
+            options.date_format.as_ref(),
+        )?[2];
+        if options.creation_from_mtime && created.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Option --creation-from-mtime cannot be combined with other ways of setting creation times",
+            ));
+        }
+    }
+    Ok(created)
+}
+
+/// Touches the operands, as a run without any of the modes that touch nothing does.
+fn touch_operands<W: Write>(
+    options: &Options,
+    operands: Vec<PathBuf>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let messages = RefCell::new(Vec::new());
+    let stats = StatCache::new(options.stat_cache);
+    let mut handles = options
+        .handle_reuse
+        .then(|| Arc::new(HandlePool::new(HANDLE_POOL_SIZE)));
+    let operands = expand_operand_vars(operands, options)?;
+    let (operands, blocked, unmatched) = expand_operands(operands, options, &stats);
+    for (path, protected) in blocked {
+        messages
+            .borrow_mut()
+            .push(blocked_message(&path, &protected));
+    }
+    let mirror = mirror_roots(&operands, options)?;
+    let lists = options
+        .files_from
+        .iter()
+        .map(|list| open_file_list(list, options))
+        .chain(
+            options
+                .filelists
+                .iter()
+                .map(|list| open_filelist(list, options.input_encoding)),
+        )
+        .collect::<std::io::Result<Vec<_>>>()?;
+    // Only operands given on the command line can be counted before they are touched.
+    let total = (lists.is_empty() && !options.recursive).then_some(operands.len());
+    let mut operands = operand_stream(operands, lists, options, &messages);
+    if options.sort != SortOrder::AsGiven {
+        let sorted = sort_operands(operands.collect(), options, &stats);
+        operands = Box::new(sorted.into_iter());
+    }
+    operands = require_operands(operands, &unmatched, options)?;
+    if let Some(selection) = options.select {
+        operands = Box::new(std::iter::once(select_operand(operands, selection)?));
+    }
+    if let Some(limit) = options.max_files {
+        operands = limit_batch(operands, limit)?;
+    }
+    // A list read from standard input holds it, so there is no asking then.
+    let stdin_list = options
+        .files_from
+        .iter()
+        .chain(&options.filelists)
+        .any(|list| list == Path::new("-"));
+    if !options.yes && !stdin_list && io::stdin().is_terminal() {
+        operands = confirm_large_batch(
+            operands,
+            options.confirm_over.unwrap_or(DEFAULT_CONFIRM_OVER),
+            &mut io::stdin().lock(),
+            &mut io::stderr(),
+        )?;
+    }
+    // Read once for the whole run, rather than all of /proc again for every file.
+    let open_files = (options.warn_if_open && options.verbose && !options.quiet)
+        .then(open_files::OpenFiles::snapshot);
+    let checkpoint = match &options.checkpoint {
+        Some(path) => Some(Checkpoint::open(path, options.null_separated).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Error opening checkpoint {}: {}", path.display(), e),
+            )
+        })?),
+        None => None,
+    };
+
+    let times = resolve_times(options)?;
+    let floor = options
+        .min_mtime
+        .as_deref()
+        .map(|text| read_min_mtime(text, options))
+        .transpose()?;
+    let created = resolve_creation_time(options)?;
+
+    check_option_combinations(options)?;
+    if let Some(destination) = &options.emit_command {
+        let listed: Vec<PathBuf> = operands.collect();
+        emit::write(
+            destination,
+            &emit::equivalent_command(options, &times, created, &listed)?,
+        )?;
+        operands = Box::new(listed.into_iter());
+    }
+    let retries = match options.on_error {
+        ErrorPolicy::Retry => options.retries.unwrap_or(DEFAULT_RETRIES),
+        _ => 0,
+    };
+    let owner = resolve_owner(options)?;
+    let audit = options
+        .audit_log
+        .as_deref()
+        .and_then(|path| match AuditLog::open(path) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot open audit log {} ({}); modifications will not be logged",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        });
+    let mut error_log = options
+        .error_log
+        .as_deref()
+        .and_then(|path| match ErrorLog::open(path) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot open error log {} ({}); failures will not be logged",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        });
+
+    let copy_creation_time = AtomicBool::new(created.is_some());
+    // The creation time given to the last file created, under --force-create-time-monotonic.
+    let last_created = options.create_time_monotonic.then(|| Mutex::new(None));
+    // Files created with a time given would otherwise give away when they were made.
+    // Only Windows can change creation times, so elsewhere this isn't tried.
+    let backdate_creation = AtomicBool::new(
+        cfg!(windows)
+            && created.is_none()
+            && explicit_times(options)
+            && !options.reference_all
+            && options.times.is_none()
+            && !options.real_creation,
+    );
+    let copy_security = AtomicBool::new(options.copy_security);
+    let exec = options
+        .exec
+        .as_deref()
+        .map(|command| Exec::parse(command, options.exec_shell))
+        .transpose()?;
+    let volumes = Volumes::new(SystemDetector::default());
+    let planner = Planner {
+        options,
+        times,
+        floor,
+        mirror: mirror.as_ref(),
+        checkpoint: checkpoint.as_ref(),
+        volumes: &volumes,
+        stats: &stats,
+    };
+    let touch = |path: &Path| -> std::io::Result<Step> {
+        if interrupt::requested() {
+            return Ok(Step::Cancelled);
+        }
+        let created = created.filter(|_| copy_creation_time.load(Ordering::Relaxed));
+        let (times, volume, hash) = match planner.plan(path, created)? {
+            Plan::Leave(step) => return Ok(step),
+            Plan::Touch(times, volume, hash) => (times, volume, hash),
+        };
+        let times = &times;
+        // Stored before the times are set, as writing the stream changes them.
+        if let Some(hash) = &hash {
+            content_hash::store(path, hash)?;
+        }
+        if let Some(open_files) = &open_files {
+            if let Some(writer) = open_files.open_for_writing(path) {
+                eprintln!(
+                    "Warning: {} is open for writing by {}; its times may change again when it is written to or closed",
+                    display_path(path, options.relative_to.as_deref()).display(),
+                    writer
+                );
+            }
+        }
+        let before = audit
+            .as_ref()
+            .and_then(|_| current_times(path, options, &stats).ok());
+        if options.lockfile {
+            lock::acquire(path, options.steal_stale)?;
+        }
+        let touched = touch_operand(path, times, retries, options, handles.as_ref());
+        // Whether or not it worked, what was read about it may no longer hold.
+        stats.forget(path);
+        if touched.is_err() && hash.is_some() {
+            // So it counts as changed next time, and gets the times it missed.
+            content_hash::forget(path);
+        }
+        let (outcome, id, extras) = touched?;
+        if outcome == TouchOutcome::SkippedMissing {
+            return Ok(Step::Skipped("does not exist"));
+        }
+        let made = outcome == TouchOutcome::Created;
+        // Only files this run creates are re-owned, unless --owner-existing says otherwise.
+        if let Some(owner) = &owner {
+            if options.owner_existing || made {
+                owner::set_owner(path, owner)?;
+            }
+        }
+        if let Some(reference) = options
+            .reference
+            .as_deref()
+            .filter(|_| copy_security.load(Ordering::Relaxed))
+        {
+            if made {
+                // Falling back is reported once rather than for every file.
+                if let Err(e) = security::copy_security(reference, path) {
+                    if copy_security.swap(false, Ordering::Relaxed) {
+                        eprintln!(
+                            "Warning: cannot copy the security descriptor of {} ({}); new files keep their default permissions",
+                            reference.display(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(time) = created.filter(|_| copy_creation_time.load(Ordering::Relaxed)) {
+            let time = match &last_created {
+                Some(last) if made => monotonic_creation_time(&mut last.lock().unwrap(), time),
+                _ => time,
+            };
+            // Only where no file could take one is it given up on, and said once; a file
+            // of its own that refuses it is reported and the rest still get theirs.
+            match set_creation_time(path, time) {
+                Err(e) if creation_times_unsupported(&e) => {
+                    if copy_creation_time.swap(false, Ordering::Relaxed) {
+                        eprintln!(
+                            "Warning: cannot set creation times here ({}); setting only the access and modification times",
+                            e
+                        );
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Warning: cannot set the creation time of {} ({})",
+                    path.display(),
+                    e
+                ),
+                Ok(()) => {}
+            }
+        } else if let Some(time) = times
+            .mtime
+            .or(times.atime)
+            .filter(|_| made && backdate_creation.load(Ordering::Relaxed))
+        {
+            match set_creation_time(path, time) {
+                Err(e) if creation_times_unsupported(&e) => {
+                    if backdate_creation.swap(false, Ordering::Relaxed) {
+                        eprintln!(
+                            "Warning: cannot backdate creation times here ({}); new files keep the time they were created",
+                            e
+                        );
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Warning: cannot backdate the creation time of {} ({})",
+                    path.display(),
+                    e
+                ),
+                Ok(()) => {}
+            }
+        }
+        if let Some(log) = &audit {
+            // Whether the file was there, and its modification time, come from the touch
+            // itself; what was read beforehand may be out of date by then.
+            let before = match outcome {
+                TouchOutcome::Updated { previous_mtime, .. } => Some((
+                    before.map(|times| times.0),
+                    previous_mtime.or(before.map(|times| times.1)),
+                )),
+                _ => None,
+            };
+            if let Ok(after) = current_times(path, options, &stats) {
+                if let Err(e) = log.record(path, before, after) {
+                    eprintln!(
+                        "Warning: cannot write to the audit log for {} ({})",
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        // Run on the worker that touched the file, so no more than --jobs run at once.
+        if let Some(exec) = &exec {
+            exec.run(path)?;
+        }
+        let volume = volume.map(|(volume, _)| volume);
+        Ok(Step::Touched(
+            outcome,
+            times.mtime.or(times.atime),
+            volume,
+            id,
+            extras,
+        ))
+    };
+
+    // Process each file argument. Unless the policy says to stop, carry on past failures
+    // so that every operand is attempted and every failure can be reported.
+    let mut failed = Vec::new();
+    // Failures that were --exclusive operands someone else created, and how many of the
+    // messages are about them.
+    let (mut lost_claims, mut lost_claim_messages) = (0, 0);
+    let mut checkpoint_failed = false;
+    // Operands dealt with one way or another, for saying how far an interrupted run got.
+    let mut done = 0;
+    // Files --fix-future set back.
+    let mut clamped = 0;
+    // Files --min-mtime raised, and ones it found no older than its floor.
+    let (mut raised, mut not_below_floor) = (0, 0);
+    // Looked up at the first failure, for telling whether elevating would have helped.
+    let (mut system_guard, mut elevated) = (None, None);
+    let mut records = options.report_format.as_ref().map(|_| Vec::new());
+    let mut progress = options.progress.map(|style| {
+        let total = if options.select.is_some() {
+            Some(1)
+        } else {
+            total
+        };
+        Progress::new(style, total, io::stderr(), io::stderr().is_terminal())
+    });
+    interrupt::install();
+    // Under --print0 standard output carries only the list of files modified, so what
+    // would be reported there goes to standard error instead.
+    let mut stderr = io::stderr();
+    let (report, mut modified): (&mut dyn Write, _) = if options.print0 {
+        (&mut stderr, Some(&mut writer))
+    } else {
+        (&mut writer, None)
+    };
+    touch_each(operands, &times, touch, |filename, result| {
+        if let Ok(Step::Cancelled) = result {
+            return Ok(false);
+        }
+        done += 1;
+        if let Some(progress) = &mut progress {
+            progress.record(match &result {
+                Ok(Step::Touched(..)) => Tally::Touched,
+                Ok(_) => Tally::Skipped,
+                Err(_) => Tally::Failed,
+            })?;
+        }
+        if let Some(records) = &mut records {
+            records.push(report_record(&filename, &result, options));
+        }
+        if let (Some(checkpoint), Ok(Step::Touched(..) | Step::Unchanged)) = (&checkpoint, &result)
+        {
+            // Reported once: a checkpoint that can't be written fails every time.
+            if let Err(e) = checkpoint.record(&filename) {
+                if !std::mem::replace(&mut checkpoint_failed, true) {
+                    eprintln!("Warning: cannot update the checkpoint ({})", e);
+                }
+            }
+        }
+        match result {
+            Ok(Step::Skipped(reason)) => {
+                if reason == NOT_BELOW_FLOOR {
+                    not_below_floor += 1;
+                }
+                if options.verbose && !options.quiet {
+                    let shown = display_path(&filename, options.relative_to.as_deref());
+                    if options.json {
+                        writeln!(
+                            report,
+                            "{{\"path\":{},\"outcome\":\"skipped\",\"reason\":{}}}",
+                            json_string(&shown.to_string_lossy()),
+                            json_string(reason)
+                        )?;
+                    } else {
+                        writeln!(report, "skipped {} ({})", shown.display(), reason)?;
+                    }
+                }
+            }
+            Ok(Step::NoCounterpart(counterpart)) => {
+                eprintln!(
+                    "Warning: skipped {} (no counterpart {} under --mirror-from)",
+                    filename.display(),
+                    counterpart.display()
+                );
+            }
+            Ok(Step::Unchanged) => {
+                if !options.quiet {
+                    let shown = display_path(&filename, options.relative_to.as_deref());
+                    if options.json {
+                        writeln!(
+                            report,
+                            "{{\"path\":{},\"outcome\":\"unchanged\"}}",
+                            json_string(&shown.to_string_lossy())
+                        )?;
+                    } else {
+                        writeln!(report, "unchanged {}", shown.display())?;
+                    }
+                }
+            }
+            Ok(Step::Touched(outcome, applied, volume, id, extras)) => {
+                if options.fix_future.is_some() {
+                    clamped += 1;
+                }
+                if floor.is_some() {
+                    raised += 1;
+                }
+                if let Some(list) = &mut modified {
+                    list.write_all(&path_to_bytes(&filename))?;
+                    list.write_all(b"\0")?;
+                    // Flushed as it goes, for the next program in a pipeline.
+                    list.flush()?;
+                }
+                if (options.verbose || options.print_id) && !options.quiet {
+                    let shown = display_path(&filename, options.relative_to.as_deref());
+                    // Hexadecimal, as fsutil shows them.
+                    let id =
+                        id.map(|id| (format!("{:08X}", id.volume), format!("{:016X}", id.index)));
+                    if options.json {
+                        let text = |value: Option<String>| {
+                            value.map_or("null".to_string(), |value| json_string(&value))
+                        };
+                        let id = match (options.print_id, id) {
+                            (false, _) => String::new(),
+                            (true, id) => {
+                                let (volume, index) = id.unzip();
+                                format!(
+                                    ",\"volume_serial\":{},\"file_index\":{}",
+                                    text(volume),
+                                    text(index)
+                                )
+                            }
+                        };
+                        let (created, previous) = match outcome {
+                            TouchOutcome::Updated { previous_mtime, .. } => (false, previous_mtime),
+                            _ => (true, None),
+                        };
+                        let backup = match (&options.backup, extras.backup) {
+                            (None, _) => String::new(),
+                            (Some(_), backup) => format!(
+                                ",\"backup\":{}",
+                                text(backup.map(|backup| {
+                                    display_path(&backup, options.relative_to.as_deref())
+                                        .to_string_lossy()
+                                        .into_owned()
+                                }))
+                            ),
+                        };
+                        let repaired = match options.creation_from_mtime {
+                            false => String::new(),
+                            true => format!(
+                                ",\"previous_created\":{}",
+                                text(extras.repaired.map(format_iso8601))
+                            ),
+                        };
+                        let discarded = match (options.empty, extras.discarded) {
+                            (false, _) => String::new(),
+                            (true, discarded) => format!(
+                                ",\"discarded_bytes\":{}",
+                                discarded.map_or("null".to_string(), |bytes| bytes.to_string())
+                            ),
+                        };
+                        writeln!(
+                            report,
+                            "{{\"path\":{},\"outcome\":\"touched\",\"time\":{},\"filesystem\":{},\"created\":{},\"previous_mtime\":{}{}{}{}{}}}",
+                            json_string(&shown.to_string_lossy()),
+                            text(applied.map(format_iso8601)),
+                            text(volume.map(|volume| volume.filesystem.clone())),
+                            created,
+                            text(previous.map(format_iso8601)),
+                            backup,
+                            discarded,
+                            repaired,
+                            id,
+                        )?;
+                    } else {
+                        let backup = extras
+                            .backup
+                            .map(|backup| {
+                                let shown = display_path(&backup, options.relative_to.as_deref());
+                                format!(" (backed up to {})", shown.display())
+                            })
+                            .unwrap_or_default();
+                        let discarded = extras
+                            .discarded
+                            .map(|bytes| format!(" (emptied, {} bytes discarded)", bytes))
+                            .unwrap_or_default();
+                        let repaired = extras
+                            .repaired
+                            .map(|created| {
+                                format!(
+                                    " (creation time moved back from {})",
+                                    format_iso8601(created)
+                                )
+                            })
+                            .unwrap_or_default();
+                        let id = id
+                            .map(|(volume, index)| format!(" (volume {}, file {})", volume, index))
+                            .unwrap_or_default();
+                        match applied.filter(|_| options.humanize) {
+                            Some(time) => writeln!(
+                                report,
+                                "touched {} at {} ({}){}{}{}{}",
+                                shown.display(),
+                                format_iso8601(time),
+                                humanize(time, options.now()),
+                                backup,
+                                discarded,
+                                repaired,
+                                id
+                            )?,
+                            None => writeln!(
+                                report,
+                                "touched {}{}{}{}{}",
+                                shown.display(),
+                                backup,
+                                discarded,
+                                repaired,
+                                id
+                            )?,
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let lost_claim = (options.exclusive || options.lockfile)
+                    && e.kind() == io::ErrorKind::AlreadyExists;
+                let message = if !lost_claim {
+                    let hint = elevate::hint(
+                        &e,
+                        system_guard
+                            .get_or_insert_with(SystemGuard::for_system)
+                            .blocks(&filename),
+                        *elevated.get_or_insert_with(elevate::is_elevated),
+                    );
+                    format!(
+                        "Error touching {}: {}{}",
+                        filename.display(),
+                        e,
+                        hint.unwrap_or_default()
+                    )
+                } else if options.lockfile {
+                    format!("Not locking {}: {}", filename.display(), e)
+                } else {
+                    format!("Not creating {}: it already exists", filename.display())
+                };
+                if let Some(log) = &mut error_log {
+                    if let Err(e) = log.record(&message) {
+                        eprintln!("Warning: cannot write to the error log ({})", e);
+                        // Given up on after the first failure, like an unopenable log.
+                        error_log = None;
+                    }
+                }
+                if !options.quiet_errors && options.print0_errors != Some(ErrorList::Stderr) {
+                    lost_claim_messages += usize::from(lost_claim);
+                    messages.borrow_mut().push(message);
+                }
+                lost_claims += usize::from(lost_claim);
+                failed.push(filename);
+                if options.on_error == ErrorPolicy::Stop {
+                    return Ok(false);
+                }
+            }
+            // Stopped on before it was counted, above.
+            Ok(Step::Cancelled) => return Ok(false),
+        }
+        Ok(!interrupt::requested())
+    })?;
+    let interrupted = interrupt::requested();
+
+    if let Some(progress) = progress {
+        progress.finish()?;
+    }
+    if let (Some(template), Some(records)) = (&options.report_format, &records) {
+        report.write_all(template.render(records).as_bytes())?;
+    }
+    if let Some(floor) = floor.filter(|_| !options.quiet) {
+        eprintln!(
+            "min-mtime: {} files raised to {}, {} already as new or newer",
+            raised,
+            format_iso8601(floor),
+            not_below_floor
+        );
+    }
+    if options.fix_future.is_some() && !options.quiet {
+        eprintln!(
+            "fix-future: {} of {} files had times ahead of the clock, set back to now",
+            clamped, done
+        );
+    }
+    if options.stat_cache && options.verbose && !options.quiet {
+        eprintln!(
+            "stat cache: {} metadata lookups, {} read from disk",
+            stats.lookups(),
+            stats.reads()
+        );
+    }
+    // Dropping the pool closes the handles it kept.
+    if let Some(handles) = handles.take() {
+        if options.verbose && !options.quiet {
+            eprintln!(
+                "handle pool: {} touches reused an open handle",
+                handles.reused()
+            );
+        }
+    }
+    let mut messages = messages.into_inner();
+    if let Some(Err(e)) = error_log.map(ErrorLog::finish) {
+        eprintln!("Warning: cannot write to the error log ({})", e);
+    }
+    if let Some(checkpoint) = checkpoint.filter(|_| failed.is_empty() && !interrupted) {
+        if let Err(e) = checkpoint.finish() {
+            eprintln!("Warning: cannot delete the finished checkpoint ({})", e);
+        }
+    }
+    if messages.is_empty() && failed.is_empty() && !interrupted {
+        return Ok(());
+    }
+    let kind = if interrupted {
+        io::ErrorKind::Interrupted
+    } else if lost_claims == failed.len() && lost_claim_messages == messages.len() {
+        io::ErrorKind::AlreadyExists
+    } else {
+        io::ErrorKind::Other
+    };
+    // Operands held back by the system directory guard are not failures: retrying them
+    // would only be refused again, so they are left out of the list.
+    if let Some(path) = options.failed_to.as_ref().filter(|_| !failed.is_empty()) {
+        if let Err(e) = write_failed_list(path, &failed, options.null_separated) {
+            messages.push(format!(
+                "Error writing failed list {}: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+    if interrupted {
+        let plural = |count| if count == 1 { "" } else { "s" };
+        messages.push(match total {
+            Some(total) => format!(
+                "Cancelled after {} of {} operand{}",
+                done,
+                total,
+                plural(total)
+            ),
+            None => format!("Cancelled after {} operand{}", done, plural(done)),
+        });
+    }
+    match &options.print0_errors {
+        Some(ErrorList::Stderr) => {
+            // Standard error carries only the list, so nothing else may be printed there.
+            let mut stderr = io::stderr().lock();
+            for operand in &failed {
+                stderr.write_all(&path_to_bytes(operand))?;
+                stderr.write_all(b"\0")?;
+            }
+            stderr.flush()?;
+            messages.clear();
+        }
+        Some(ErrorList::File(path)) => {
+            if let Err(e) = write_failed_list(path, &failed, true) {
+                messages.push(format!(
+                    "Error writing failed list {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        }
+        None => {}
+    }
+    // main prints the error to stderr, so we fold every failure into a single Error. With
+    // --quiet-errors it may be empty, in which case only the exit status reports failure.
+    Err(std::io::Error::new(kind, messages.join("\n")))
+}
+
+/// Exit status when the only failures were `--exclusive` files that already existed.
+const EXIT_ALREADY_EXISTS: u8 = 3;
+
+/// Exit status when Ctrl+C stopped the run, as shells report for SIGINT.
+const EXIT_INTERRUPTED: u8 = 130;
+
+fn main() -> ExitCode {
+    // args_os keeps operands that are not valid Unicode intact rather than panicking.
+    let mut args = env::args_os();
+    let program = args.next();
+    let args: Vec<OsString> = args.collect();
+
+    // Installed under GNU touch's name, mdtouch behaves like it.
+    let result = if program.is_some_and(|program| gnu::invoked_as_touch(&program)) {
+        gnu::run(args, std::io::stdout(), &SystemClock)
+    } else {
+        run(args, std::io::stdout())
+    };
+    if let Err(e) = result {
+        if let Some(elevate::ChildExit(code)) = e.get_ref().and_then(|e| e.downcast_ref()) {
+            return ExitCode::from(*code);
+        }
+        let message = e.to_string();
+        if !message.is_empty() {
+            eprintln!("{}", message);
+        }
+        match e.kind() {
+            io::ErrorKind::AlreadyExists => return ExitCode::from(EXIT_ALREADY_EXISTS),
+            io::ErrorKind::Interrupted => return ExitCode::from(EXIT_INTERRUPTED),
+            _ => {}
+        }
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::set_file_times;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Generates a unique temporary file path in the system's temporary directory.
+    fn unique_temp_file() -> PathBuf {
+        let mut path = env::temp_dir();
+        // Use the current time in nanoseconds to generate a unique file name.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        path.push(format!("mdtouch_test_{}.tmp", nanos));
+        path
+    }
+
+    #[test]
+    fn test_touch_new_file() {
+        let path = unique_temp_file();
+        // Ensure the file does not exist.
+        if path.exists() {
+            fs::remove_file(&path).expect("Failed to remove pre-existing test file.");
+        }
+        assert!(
+            !path.exists(),
+            "Test file should not exist before touching."
+        );
+
+        touch_file(&path, &TouchOptions::now()).expect("Failed to touch new file.");
+
+        assert!(path.exists(), "File should exist after touching.");
+
+        // Clean up
+        fs::remove_file(&path).expect("Failed to remove test file.");
+    }
+
+    #[test]
+    fn test_touch_existing_file_updates_modification_time() {
+        let path = unique_temp_file();
+        // Create the file initially with some content.
+        fs::write(&path, b"initial content").expect("Failed to create test file.");
+
+        // Set the modification time to a fixed point in the past.
+        let past = FileTime::from_unix_time(1_000_000, 0); // Arbitrary time in the past.
+        set_file_times(&path, past, past).expect("Failed to set file times.");
+
+        let metadata_before = fs::metadata(&path).expect("Failed to get metadata.");
+        let mod_time_before = metadata_before
+            .modified()
+            .expect("Failed to get modified time.");
+
+        // Sleep briefly to ensure that the system clock advances.
+        thread::sleep(Duration::from_secs(1));
+
+        touch_file(&path, &TouchOptions::now()).expect("Failed to touch existing file.");
+
+        let metadata_after = fs::metadata(&path).expect("Failed to get metadata.");
+        let mod_time_after = metadata_after
+            .modified()
+            .expect("Failed to get modified time.");
+
+        assert!(
+            mod_time_after > mod_time_before,
+            "Modification time should be updated."
+        );
+
+        // Clean up
+        fs::remove_file(&path).expect("Failed to remove test file.");
+    }
+
+    #[test]
+    fn test_help_message_contains_usage() {
+        let help = help_message();
+        assert!(
+            help.contains("Usage:"),
+            "Help message should contain 'Usage:'"
+        );
+        assert!(
+            help.contains("-h"),
+            "Help message should mention '-h' option"
+        );
+        assert!(
+            help.contains("-?"),
+            "Help message should mention '-?' option"
+        );
+    }
+
+    #[test]
+    fn test_run_no_args() {
+        let mut output = Vec::new();
+        let result = run(vec![], &mut output);
+        assert!(result.is_ok());
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("mdtouch"));
+        assert!(output_str.contains("A tool to update file timestamps"));
+    }
+
+    #[test]
+    fn test_run_help_arg() {
+        let mut output = Vec::new();
+        let result = run(vec!["-h".into()], &mut output);
+        assert!(result.is_ok());
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Usage:"));
+    }
+
+    #[test]
+    fn test_run_touch_file() {
+        let path = unique_temp_file();
+        let mut output = Vec::new();
+
+        let result = run(vec![path.clone().into_os_string()], &mut output);
+        assert!(result.is_ok());
+        assert!(path.exists());
+
+        // Cleanup
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_error_handling() {
+        // We use a directory path which cannot be created as a file
+        // path variable removed as it was unused
+        let mut output = Vec::new();
+
+        // Trying to 'touch' an existing directory typically updates its timestamp on Unix,
+        // but OpenOptions(...).create(true).write(true).open(dir) fails on Windows with "Access is denied"
+        // or "is a directory" depending on the OS.
+        // Let's force an error by using a path with a non-existent parent directory.
+        // E.g. temp_dir / "non_existent_dir" / "file.txt"
+
+        let mut bad_path = env::temp_dir();
+        bad_path.push("non_existent_dir_xyz_123");
+        bad_path.push("file.txt");
+
+        let result = run(vec![bad_path.into_os_string()], &mut output);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("Error touching"));
+    }
+
+    #[test]
+    fn test_parse_args_options_and_operands() {
+        let args = vec![
+            "a.txt".into(),
+            "--failed-to".into(),
+            "failed.txt".into(),
+            "--files-from=list.txt".into(),
+            "-0".into(),
+            "b.txt".into(),
+        ];
+        let (options, operands) = parse_args(args).unwrap();
+        assert_eq!(
+            operands,
+            vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]
+        );
+        assert_eq!(options.failed_to, Some(PathBuf::from("failed.txt")));
+        assert_eq!(options.files_from, vec![PathBuf::from("list.txt")]);
+        assert!(options.null_separated);
+    }
+
+    #[test]
+    fn test_parse_args_missing_value() {
+        let result = parse_args(vec!["--failed-to".into()]);
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("--failed-to"));
+    }
+
+    #[test]
+    fn test_failed_list_round_trip() {
+        let path = unique_temp_file();
+        let failed = vec![
+            PathBuf::from("with space.txt"),
+            PathBuf::from("dir\\other.txt"),
+            PathBuf::from("résumé 日本.txt"),
+        ];
+
+        for null_separated in [false, true] {
+            write_failed_list(&path, &failed, null_separated).unwrap();
+            let read_back = read_operand_list(&path, null_separated).unwrap();
+            assert_eq!(read_back, failed);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shell_passed_operands() {
+        let paths = |arg: &str| -> Vec<String> {
+            shell_passed_operands(arg)
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(paths(r"C:\dir\file.txt"), [r"C:\dir\file.txt"]);
+        assert_eq!(paths(r"C:\dir\with space.txt"), [r"C:\dir\with space.txt"]);
+        // "%1" given a directory with a trailing backslash.
+        assert_eq!(paths(r#"C:\dir""#), [r"C:\dir"]);
+        assert_eq!(paths(r#"  "C:\a b.txt"  "#), [r"C:\a b.txt"]);
+        // Two dropped paths run into one argument.
+        assert_eq!(
+            paths(r#"C:\dir" C:\other\file.txt"#),
+            [r"C:\dir", r"C:\other\file.txt"]
+        );
+        assert!(paths(" \"\" ").is_empty());
+        // Whitespace is only trimmed next to a stray quote.
+        assert_eq!(paths(" notes.txt"), [" notes.txt"]);
+        assert_eq!(paths(r#" C:\dir" "#), [r" C:\dir"]);
+        assert_eq!(paths(r#""C:\a.txt" tail "#), [r"C:\a.txt", "tail "]);
+    }
+
+    #[test]
+    fn test_read_operand_list_skips_blank_lines_and_crlf() {
+        let path = unique_temp_file();
+        fs::write(&path, "one.txt\r\n\r\ntwo three.txt\n").unwrap();
+
+        let operands = read_operand_list(&path, false).unwrap();
+        assert_eq!(
+            operands,
+            vec![PathBuf::from("one.txt"), PathBuf::from("two three.txt")]
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_follow_links() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("build")).unwrap();
+        let output = dir.join("build").join("out.o");
+        fs::write(&output, b"").unwrap();
+        std::os::unix::fs::symlink(dir.join("build"), dir.join("src").join("build")).unwrap();
+        let old = FileTime::from_unix_time(1_500_000_000, 0);
+        let recurse = |follow: bool| {
+            set_file_times(&output, old, old).unwrap();
+            let mut args = vec![OsString::from("-R"), dir.join("src").into_os_string()];
+            if follow {
+                args.insert(0, OsString::from("--follow-links"));
+            }
+            run(args, Vec::new()).unwrap();
+            read_times(&output).1 != old
+        };
+
+        assert!(!recurse(false));
+        assert!(recurse(true));
+        assert!(run(
+            vec![OsString::from("--follow-links"), dir.into_os_string()],
+            Vec::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_sorted() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join("c")).unwrap();
+        for name in ["D.txt", "a.txt", "c/z.txt", "B.txt", "c/Y.txt", "_.txt"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let listed = |extra: &[&str]| -> Vec<String> {
+            let mut args: Vec<OsString> = ["-v", "-R", "--sorted", "--jobs", "4"]
+                .iter()
+                .chain(extra)
+                .map(OsString::from)
+                .collect();
+            args.push(dir.clone().into_os_string());
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let path = Path::new(line.strip_prefix("touched ").unwrap());
+                    let below = path.strip_prefix(&dir).unwrap();
+                    below.to_string_lossy().replace('\\', "/")
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            listed(&[]),
+            ["", "_.txt", "a.txt", "B.txt", "c", "c/Y.txt", "c/z.txt", "D.txt"]
+        );
+        assert_eq!(
+            listed(&["--case-sensitive"]),
+            ["", "B.txt", "D.txt", "_.txt", "a.txt", "c", "c/Y.txt", "c/z.txt"]
+        );
+        let err = run(
+            vec![OsString::from("--sorted"), dir.clone().into_os_string()],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("requires -R"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_skip_hidden_in_expansion() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join(".cache")).unwrap();
+        let (shown, hidden, below) = (
+            dir.join("shown.txt"),
+            dir.join(".hidden.txt"),
+            dir.join(".cache").join("inner.txt"),
+        );
+        for path in [&shown, &hidden, &below] {
+            fs::write(path, b"").unwrap();
+        }
+        // On Windows hidden is an attribute rather than a leading dot.
+        if cfg!(windows) {
+            for path in [&hidden, &dir.join(".cache")] {
+                let status = std::process::Command::new("attrib")
+                    .arg("+H")
+                    .arg(path)
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+            }
+        }
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let mtime = |path: &Path| read_times(path).1;
+        let touch_with = |operand: PathBuf, recursive: bool| {
+            for path in [&shown, &hidden, &below] {
+                set_file_times(path, old, old).unwrap();
+            }
+            let mut args = vec![OsString::from("--skip-hidden")];
+            if recursive {
+                args.push(OsString::from("-R"));
+            }
+            args.push(operand.into_os_string());
+            run(args, Vec::new()).unwrap();
+        };
+
+        touch_with(dir.clone(), true);
+        assert_ne!(mtime(&shown), old);
+        assert_eq!((mtime(&hidden), mtime(&below)), (old, old));
+        touch_with(dir.join("*.txt"), false);
+        assert_ne!(mtime(&shown), old);
+        assert_eq!(mtime(&hidden), old);
+        // Named outright, a hidden file is still touched.
+        touch_with(hidden.clone(), false);
+        assert_ne!(mtime(&hidden), old);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_regex_in_expansion() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::create_dir_all(dir.join(".cache")).unwrap();
+        let names = [
+            "foo_2024.log",
+            "bar_0001.log",
+            "baz_2024.log",
+            "foo_2024.log.gz",
+            "sub/foo_2025.log",
+            ".cache/foo_2026.log",
+        ];
+        let paths: Vec<PathBuf> = names.iter().map(|name| dir.join(name)).collect();
+        for path in &paths {
+            fs::write(path, b"").unwrap();
+        }
+        if cfg!(windows) {
+            let status = std::process::Command::new("attrib")
+                .arg("+H")
+                .arg(dir.join(".cache"))
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let touched_with = |operand: &Path, extra: &[&str]| -> Vec<&str> {
+            for path in &paths {
+                set_file_times(path, old, old).unwrap();
+            }
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            args.push(operand.as_os_str().to_owned());
+            run(args, Vec::new()).unwrap();
+            names
+                .iter()
+                .zip(&paths)
+                .filter(|(_, path)| read_times(path).1 != old)
+                .map(|(name, _)| *name)
+                .collect()
+        };
+
+        // Anchored, the pattern matches the whole path below the operand.
+        let rotated = r"^(foo|bar)_\d{4}\.log$";
+        assert_eq!(
+            touched_with(&dir, &["-R", "--regex", rotated]),
+            ["foo_2024.log", "bar_0001.log"]
+        );
+        // Components are separated by / whatever the platform.
+        assert_eq!(
+            touched_with(&dir, &["-R", "--regex", r"^sub/foo_\d{4}\.log$"]),
+            ["sub/foo_2025.log"]
+        );
+        // Patterns add up, and --skip-hidden leaves out what it would have anyway.
+        assert_eq!(
+            touched_with(
+                &dir,
+                &[
+                    "-R",
+                    "--skip-hidden",
+                    "--regex",
+                    rotated,
+                    "--regex",
+                    "/foo_"
+                ]
+            ),
+            ["foo_2024.log", "bar_0001.log", "sub/foo_2025.log"]
+        );
+        assert_eq!(
+            touched_with(&dir, &["-R", "--regex", r"(^|/)foo_\d+\.log$"]),
+            ["foo_2024.log", "sub/foo_2025.log", ".cache/foo_2026.log"]
+        );
+        assert_eq!(
+            touched_with(&dir.join("*.log"), &["--regex", rotated]),
+            ["foo_2024.log", "bar_0001.log"]
+        );
+        assert!(touched_with(&dir, &["-R", "--regex-full-path", "--regex", rotated]).is_empty());
+        assert_eq!(
+            touched_with(
+                &dir,
+                &[
+                    "-R",
+                    "--regex-full-path",
+                    "--regex",
+                    r"^(/|[A-Za-z]:/).*/sub/"
+                ]
+            ),
+            ["sub/foo_2025.log"]
+        );
+        // Named outright, an operand is touched whatever the pattern.
+        assert_eq!(
+            touched_with(&paths[2], &["--regex", rotated]),
+            ["baz_2024.log"]
+        );
+
+        let err = run(
+            vec![
+                OsString::from("--regex"),
+                OsString::from("(foo"),
+                dir.into(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("unclosed group"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_exclude_from() {
+        let dir = unique_temp_file();
+        let names = [
+            "keep.txt",
+            "src/main.rs",
+            "src/scratch.tmp",
+            "build/out.o",
+            "build/cache/1.o",
+            "docs/node_modules/x.js",
+            "node_modules/y.js",
+        ];
+        let paths: Vec<PathBuf> = names.iter().map(|name| dir.join(name)).collect();
+        for path in &paths {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        let patterns = dir.with_extension("exclude");
+        fs::write(
+            &patterns,
+            "# Shared exclusions\r\n\r\nnode_modules\r\n  \nbuild/cache/\n",
+        )
+        .unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let touched_with = |extra: &[&str]| -> Vec<&str> {
+            for path in &paths {
+                set_file_times(path, old, old).unwrap();
+            }
+            let mut args: Vec<OsString> = vec!["-R".into()];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(dir.clone().into_os_string());
+            run(args, Vec::new()).unwrap();
+            names
+                .iter()
+                .zip(&paths)
+                .filter(|(_, path)| read_times(path).1 != old)
+                .map(|(name, _)| *name)
+                .collect()
+        };
+
+        let from = patterns.to_str().unwrap();
+        assert_eq!(
+            touched_with(&["--exclude-from", from]),
+            ["keep.txt", "src/main.rs", "src/scratch.tmp", "build/out.o"]
+        );
+        // Inline patterns add to the file's.
+        assert_eq!(
+            touched_with(&["--exclude-from", from, "--exclude", "*.tmp"]),
+            ["keep.txt", "src/main.rs", "build/out.o"]
+        );
+        // The same pattern inline leaves out the same paths, including from a wildcard.
+        assert_eq!(
+            touched_with(&["--exclude", "node_modules", "--exclude", "build/cache"]),
+            touched_with(&["--exclude-from", from])
+        );
+        for path in &paths {
+            set_file_times(path, old, old).unwrap();
+        }
+        run(
+            vec![
+                "--exclude".into(),
+                "scratch*".into(),
+                dir.join("src").join("*").into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert_ne!(read_times(&paths[1]).1, old);
+        assert_eq!(read_times(&paths[2]).1, old);
+
+        let err = run(
+            vec![
+                "--exclude-from".into(),
+                dir.join("missing").into_os_string(),
+                dir.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("Error reading exclude file"),
+            "{}",
+            err
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&patterns).unwrap();
+    }
+
+    #[test]
+    fn test_run_emit_command() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let paths = ["a.txt", "b.txt", "c.log"].map(|name| dir.join(name));
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        for path in &paths {
+            fs::write(path, b"").unwrap();
+            set_file_times(path, old, old).unwrap();
+        }
+        let emitted = dir.with_extension("command");
+        run(
+            vec![
+                OsString::from(format!("--emit-command={}", emitted.display())),
+                "-c".into(),
+                "-d".into(),
+                "@1500000000.25".into(),
+                dir.join("*.txt").into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        let command = fs::read_to_string(&emitted).unwrap();
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let (a, b) = (paths[0].to_str().unwrap(), paths[1].to_str().unwrap());
+        assert_eq!(
+            words,
+            [
+                "mdtouch",
+                "-c",
+                "--times",
+                "@1500000000.250000000,@1500000000.250000000,-",
+                "--no-glob",
+                a,
+                b,
+            ]
+        );
+
+        // Running what was emitted does the same again.
+        let touched = read_times(&paths[0]);
+        for path in &paths {
+            set_file_times(path, old, old).unwrap();
+        }
+        run(words[1..].iter().map(OsString::from).collect(), Vec::new()).unwrap();
+        assert_eq!(read_times(&paths[0]), touched);
+        assert_eq!(read_times(&paths[1]), touched);
+        assert_eq!(read_times(&paths[2]), (old, old));
+
+        // What is done to each file after the touch is kept too.
+        run(
+            vec![
+                OsString::from(format!("--emit-command={}", emitted.display())),
+                "--exec".into(),
+                "echo {}".into(),
+                paths[0].clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        let command = fs::read_to_string(&emitted).unwrap();
+        assert!(
+            command.contains(" --exec 'echo {}' ") || command.contains(" --exec \"echo {}\" "),
+            "{}",
+            command
+        );
+
+        // The reference file --copy-security needs isn't named in the command.
+        let error = run(
+            vec![
+                OsString::from(format!("--emit-command={}", emitted.display())),
+                "--copy-security".into(),
+                "-r".into(),
+                paths[2].clone().into_os_string(),
+                paths[0].clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("--copy-security"), "{}", error);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let odd = dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9.txt"));
+            fs::write(&odd, b"").unwrap();
+            let error = run(
+                vec![
+                    OsString::from(format!("--emit-command={}", emitted.display())),
+                    odd.into_os_string(),
+                ],
+                Vec::new(),
+            )
+            .unwrap_err();
+            assert!(error.to_string().contains("not valid Unicode"), "{}", error);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&emitted).unwrap();
+    }
+
+    #[test]
+    fn test_regex_text_uses_forward_slashes() {
+        let path = Path::new("logs").join("sub").join("app_2024.log");
+        assert_eq!(
+            regex_text(&path, Path::new("logs"), false),
+            "sub/app_2024.log"
+        );
+        let full = regex_text(&path, Path::new("logs"), true);
+        assert!(full.ends_with("/logs/sub/app_2024.log"), "{}", full);
+        assert!(!full.contains('\\'), "{}", full);
+        if cfg!(windows) {
+            let text = regex_text(Path::new(r"logs\sub/app.log"), Path::new("logs"), false);
+            assert_eq!(text, "sub/app.log");
+        }
+    }
+
+    #[test]
+    fn test_run_from_zip_applies_entry_times() {
+        let dir = unique_temp_file();
+        let root = dir.join("extracted");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        for name in ["docs/readme.txt", "bin.exe", "extra.txt"] {
+            fs::write(root.join(name), b"").unwrap();
+        }
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        set_file_times(root.join("extra.txt"), old, old).unwrap();
+        // Every entry has a DOS time; bin.exe also has a precise time in a UT field.
+        let archive = dir.join("release.zip");
+        let mut writer = ::zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        let time = ::zip::DateTime::from_date_and_time(2021, 3, 4, 5, 6, 8).unwrap();
+        for name in [
+            "docs/",
+            "docs/readme.txt",
+            "bin.exe",
+            "missing.txt",
+            "../escape.txt",
+        ] {
+            let mut options = ::zip::write::FullFileOptions::default()
+                .compression_method(::zip::CompressionMethod::Stored)
+                .last_modified_time(time);
+            if name == "bin.exe" {
+                let mut ut = vec![1];
+                ut.extend(1_600_000_000u32.to_le_bytes());
+                options.add_extra_data(0x5455, ut.into(), false).unwrap();
+            }
+            if name.ends_with('/') {
+                writer.add_directory(name, options).unwrap();
+            } else {
+                writer.start_file(name, options).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+
+        let mut output = Vec::new();
+        run(
+            vec![
+                OsString::from("-v"),
+                OsString::from("--from-zip"),
+                archive.clone().into_os_string(),
+                root.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            read_times(&root.join("bin.exe")).1,
+            FileTime::from_unix_time(1_600_000_000, 0)
+        );
+        // DOS times are local, so only the precision can be checked everywhere.
+        let readme = read_times(&root.join("docs/readme.txt")).1;
+        assert!(readme.unix_seconds() % 2 == 0 && readme.unix_seconds() < 1_700_000_000);
+        assert_eq!(read_times(&root.join("docs")).1, readme);
+        assert_eq!(read_times(&root.join("extra.txt")).1, old);
+        assert!(!root.join("missing.txt").exists());
+        assert_eq!(output.lines().count(), 3, "{}", output);
+        assert_eq!(
+            output.matches("(a DOS time, kept to 2 s)").count(),
+            2,
+            "{}",
+            output
+        );
+
+        assert!(run(
+            vec![OsString::from("--from-zip"), archive.into_os_string()],
+            Vec::new()
+        )
+        .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_files_from_utf16_list() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(&dir).unwrap();
+        let names = ["naïve.txt", "日本.txt"];
+        let text: String = names
+            .iter()
+            .map(|name| format!("{}\r\n", dir.join(name).display()))
+            .collect();
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        let list = dir.join("list.txt");
+        fs::write(&list, &utf16).unwrap();
+
+        // The BOM gives the encoding away; naming it reads the same list.
+        for extra in [None, Some("--input-encoding=utf16le")] {
+            let mut args = vec![
+                OsString::from("--files-from"),
+                list.clone().into_os_string(),
+            ];
+            args.extend(extra.map(OsString::from));
+            run(args, Vec::new()).unwrap();
+            for name in names {
+                assert!(dir.join(name).exists(), "{}", name);
+                fs::remove_file(dir.join(name)).unwrap();
+            }
+        }
+        // Read as UTF-8, the same list names nothing that exists.
+        let args = vec![
+            OsString::from("--input-encoding=utf8"),
+            OsString::from("--files-from"),
+            list.into_os_string(),
+        ];
+        assert!(run(args, Vec::new()).is_err());
+        assert!(run(vec![OsString::from("--input-encoding=latin1")], Vec::new()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_filelist_resolves_against_its_directory() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let absolute = unique_temp_file();
+        let manifest = dir.join("files.list");
+        fs::write(
+            &manifest,
+            format!(
+                "# built by CI\r\n\n  a.txt  \nnested/b.txt\n   # indented comment\n{}\n",
+                absolute.display()
+            ),
+        )
+        .unwrap();
+
+        let args = vec!["--filelist".into(), manifest.clone().into_os_string()];
+        run(args, Vec::new()).unwrap();
+        assert!(dir.join("a.txt").exists());
+        assert!(dir.join("nested").join("b.txt").exists());
+        assert!(absolute.exists());
+        assert!(!Path::new("a.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&absolute).unwrap();
+    }
+
+    #[test]
+    fn test_run_failed_to_only_written_on_failure() {
+        let good = unique_temp_file();
+        let list = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "--failed-to".into(),
+            list.clone().into_os_string(),
+            good.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+        assert!(good.exists());
+        assert!(
+            !list.exists(),
+            "No list should be written without failures."
+        );
+
+        let mut bad_path = env::temp_dir();
+        bad_path.push("non_existent_dir_xyz_123");
+        bad_path.push("file.txt");
+
+        let args = vec![
+            "--failed-to".into(),
+            list.clone().into_os_string(),
+            good.clone().into_os_string(),
+            bad_path.clone().into_os_string(),
+        ];
+        let err = run(args, &mut output).unwrap_err();
+        assert!(err.to_string().contains("Error touching"));
+        assert_eq!(
+            read_operand_list(&list, false).unwrap(),
+            vec![bad_path.clone()]
+        );
+
+        // Files the system directory guard holds back are refused, not failed.
+        #[cfg(unix)]
+        {
+            fs::remove_file(&list).unwrap();
+            let args = |extra: &[&Path]| {
+                let mut args = vec![
+                    OsString::from("--failed-to"),
+                    list.clone().into_os_string(),
+                    OsString::from("/etc/host*"),
+                ];
+                args.extend(extra.iter().map(|path| path.as_os_str().to_os_string()));
+                args
+            };
+            let err = run(args(&[&good]), &mut output).unwrap_err();
+            assert!(
+                err.to_string().contains("Refusing to touch /etc/"),
+                "{}",
+                err
+            );
+            assert!(!list.exists());
+            run(args(&[&good, &bad_path]), &mut output).unwrap_err();
+            assert_eq!(read_operand_list(&list, false).unwrap(), vec![bad_path]);
+        }
+
+        fs::remove_file(&good).unwrap();
+        fs::remove_file(&list).unwrap();
+    }
+
+    #[test]
+    fn test_run_touch_non_ascii_file() {
+        let mut path = env::temp_dir();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        path.push(format!(
+            "mdtouch_t\u{e9}st_\u{65e5}\u{672c}_\u{1f600}_{}.tmp",
+            nanos
+        ));
+        let mut output = Vec::new();
+
+        run(vec![path.clone().into_os_string()], &mut output).unwrap();
+        assert!(path.exists());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_touch_non_utf8_file() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut path = unique_temp_file();
+        let mut name = path.file_name().unwrap().as_bytes().to_vec();
+        name.extend_from_slice(b"_\xff\xfe");
+        path.set_file_name(OsStr::from_bytes(&name));
+        let list = unique_temp_file();
+        fs::write(&list, [path.as_os_str().as_bytes(), b"\n"].concat()).unwrap();
+        let mut output = Vec::new();
+
+        let args = vec!["--files-from".into(), list.clone().into_os_string()];
+        run(args, &mut output).unwrap();
+        assert!(path.exists(), "Non-UTF-8 name should be touched verbatim.");
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(list).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_run_touch_unpaired_surrogate_file() {
+        use std::os::windows::ffi::OsStringExt;
+
+        let mut path = unique_temp_file();
+        let mut name: Vec<u16> = "mdtouch_surrogate_".encode_utf16().collect();
+        name.push(0xD800); // An unpaired high surrogate is a legal NTFS name.
+        name.extend(".tmp".encode_utf16());
+        path.set_file_name(OsString::from_wide(&name));
+        let mut output = Vec::new();
+
+        run(vec![path.clone().into_os_string()], &mut output).unwrap();
+        assert!(path.exists());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_display_path_relative_to_base() {
+        let base = env::temp_dir().join("mdtouch_base");
+        let inside = base.join("sub").join("..").join("deep").join("file.txt");
+        let outside = env::temp_dir().join("elsewhere.txt");
+
+        assert_eq!(
+            display_path(&inside, Some(&base)),
+            Path::new("deep").join("file.txt")
+        );
+        assert_eq!(display_path(&base, Some(&base)), PathBuf::from("."));
+        assert_eq!(
+            display_path(&outside, Some(&base)),
+            normalize_path(&outside)
+        );
+        assert!(display_path(&outside, Some(&base)).is_absolute());
+        assert_eq!(
+            display_path(Path::new("as typed.txt"), None),
+            PathBuf::from("as typed.txt")
+        );
+    }
+
+    #[test]
+    fn test_normalize_path_resolves_relative_components() {
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(normalize_path(Path::new("./a/../b")), cwd.join("b"));
+    }
+
+    #[test]
+    fn test_run_verbose_relative_to() {
+        let path = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "-v".into(),
+            "--relative-to".into(),
+            env::temp_dir().into_os_string(),
+            path.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(output_str, format!("touched {}\n", name));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Creates a file with the given access and modification times.
+    fn file_with_times(atime: FileTime, mtime: FileTime) -> PathBuf {
+        let path = unique_temp_file();
+        fs::write(&path, b"reference").unwrap();
+        set_file_times(&path, atime, mtime).unwrap();
+        path
+    }
+
+    /// Reads back a file's access and modification times.
+    fn read_times(path: &Path) -> (FileTime, FileTime) {
+        let metadata = fs::metadata(path).unwrap();
+        (
+            FileTime::from_last_access_time(&metadata),
+            FileTime::from_last_modification_time(&metadata),
+        )
+    }
+
+    #[test]
+    fn test_run_atime_and_mtime_from_separate_files() {
+        let atime_source = file_with_times(
+            FileTime::from_unix_time(1_100_000_000, 100),
+            FileTime::from_unix_time(1_200_000_000, 0),
+        );
+        let mtime_source = file_with_times(
+            FileTime::from_unix_time(1_300_000_000, 0),
+            FileTime::from_unix_time(1_400_000_000, 500),
+        );
+        let target = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "--atime-from".into(),
+            atime_source.clone().into_os_string(),
+            "--mtime-from".into(),
+            mtime_source.clone().into_os_string(),
+            target.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+
+        let (atime, mtime) = read_times(&target);
+        assert_eq!(atime, read_times(&atime_source).0);
+        assert_eq!(mtime, read_times(&mtime_source).1);
+
+        for path in [atime_source, mtime_source, target] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_mtime_from_with_modify_only_keeps_atime() {
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let source = file_with_times(old, FileTime::from_unix_time(1_500_000_000, 0));
+        let target = file_with_times(old, old);
+        let mut output = Vec::new();
+
+        let args = vec![
+            "-m".into(),
+            "--mtime-from".into(),
+            source.clone().into_os_string(),
+            target.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+
+        let (atime, mtime) = read_times(&target);
+        assert_eq!(atime, old, "-m should leave the access time alone.");
+        assert_eq!(mtime, FileTime::from_unix_time(1_500_000_000, 0));
+
+        fs::remove_file(source).unwrap();
+        fs::remove_file(target).unwrap();
+    }
+
+    #[test]
+    fn test_run_reference_copies_both_times() {
+        let atime = FileTime::from_unix_time(1_234_567_890, 0);
+        let mtime = FileTime::from_unix_time(1_234_567_999, 0);
+        let reference = file_with_times(atime, mtime);
+        let target = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "-r".into(),
+            reference.clone().into_os_string(),
+            target.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+        assert_eq!(read_times(&target), (atime, mtime));
+
+        fs::remove_file(reference).unwrap();
+        fs::remove_file(target).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_times_rejects_reference_with_time_sources() {
+        let options = Options {
+            reference: Some(PathBuf::from("ref")),
+            mtime_from: Some(PathBuf::from("other")),
+            ..Options::default()
+        };
+        let err = resolve_times(&options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_resolve_times_access_only() {
+        let options = Options {
+            access_only: true,
+            ..Options::default()
+        };
+        let times = resolve_times(&options).unwrap();
+        assert!(times.atime.is_some());
+        assert!(times.mtime.is_none());
+    }
+
+    #[test]
+    fn test_resolve_times_leave_options() {
+        let options = Options {
+            leave_atime: true,
+            ..Options::default()
+        };
+        let times = resolve_times(&options).unwrap();
+        assert!(times.atime.is_none());
+        assert!(times.mtime.is_some());
+
+        // Leaving both only creates missing files.
+        let options = Options {
+            leave_atime: true,
+            leave_mtime: true,
+            ..Options::default()
+        };
+        let times = resolve_times(&options).unwrap();
+        assert_eq!((times.atime, times.mtime), (None, None));
+
+        let options = Options {
+            leave_mtime: true,
+            mtime_from: Some(PathBuf::from("reference.txt")),
+            ..Options::default()
+        };
+        assert!(resolve_times(&options).is_err());
+    }
+
+    #[test]
+    fn test_touch_parent_directory_operand() {
+        let dir = unique_temp_file();
+        let child = dir.join("child");
+        fs::create_dir_all(&child).unwrap();
+        let past = FileTime::from_unix_time(1_000_000, 0);
+        set_file_times(&dir, past, past).unwrap();
+
+        touch_file(child.join(".."), &TouchOptions::now()).expect("Failed to touch directory.");
+
+        let (_, mtime) = read_times(&dir);
+        assert!(mtime > past, "Directory mtime should advance.");
+        assert!(dir.is_dir());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Returns a path whose parent directory does not exist, so touching it fails.
+    fn missing_parent_path(name: &str) -> PathBuf {
+        let mut bad_path = env::temp_dir();
+        bad_path.push("non_existent_dir_xyz_123");
+        bad_path.push(name);
+        bad_path
+    }
+
+    #[test]
+    fn test_error_policy_parse() {
+        assert_eq!(
+            ErrorPolicy::parse("continue").unwrap(),
+            ErrorPolicy::Continue
+        );
+        assert_eq!(ErrorPolicy::parse("stop").unwrap(), ErrorPolicy::Stop);
+        assert_eq!(ErrorPolicy::parse("retry").unwrap(), ErrorPolicy::Retry);
+        let err = ErrorPolicy::parse("ignore").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_on_error_stop_halts_at_first_failure() {
+        let good = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "--on-error=stop".into(),
+            missing_parent_path("one.txt").into_os_string(),
+            missing_parent_path("two.txt").into_os_string(),
+            good.clone().into_os_string(),
+        ];
+        let err = run(args, &mut output).unwrap_err();
+
+        assert_eq!(err.to_string().matches("Error touching").count(), 1);
+        assert!(
+            !good.exists(),
+            "Files after the failure should not be touched."
+        );
+    }
+
+    #[test]
+    fn test_run_on_error_continue_attempts_everything() {
+        let good = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            missing_parent_path("one.txt").into_os_string(),
+            missing_parent_path("two.txt").into_os_string(),
+            good.clone().into_os_string(),
+        ];
+        let err = run(args, &mut output).unwrap_err();
+
+        assert_eq!(err.to_string().matches("Error touching").count(), 2);
+        assert!(good.exists());
+        fs::remove_file(good).unwrap();
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&io::Error::from(io::ErrorKind::Interrupted)));
+        assert!(is_transient(&io::Error::from(io::ErrorKind::TimedOut)));
+        assert!(!is_transient(&io::Error::from(io::ErrorKind::NotFound)));
+        assert!(!is_transient(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+    }
+
+    #[test]
+    fn test_touch_with_retry_does_not_retry_permanent_errors() {
+        let started = SystemTime::now();
+        let result = touch_with_retry(
+            &missing_parent_path("file.txt"),
+            &TouchOptions::now(),
+            5,
+            None,
+            false,
+            None,
+        );
+        assert!(result.is_err());
+        assert!(started.elapsed().unwrap() < RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_run_retries_requires_retry_policy() {
+        let mut output = Vec::new();
+        let args = vec!["--retries=2".into(), unique_temp_file().into_os_string()];
+        let err = run(args, &mut output).unwrap_err();
+        assert!(err.to_string().contains("--on-error=retry"));
+    }
+
+    #[test]
+    fn test_run_date_with_locale() {
+        let target = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "--locale=fr".into(),
+            "-d".into(),
+            "3 Février 2024 10:00 UTC".into(),
+            target.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+
+        let expected = FileTime::from_unix_time(1_706_954_400, 0);
+        assert_eq!(read_times(&target), (expected, expected));
+        fs::remove_file(target).unwrap();
+    }
+
+    #[test]
+    fn test_run_invalid_date_touches_nothing() {
+        let target = unique_temp_file();
+        let mut output = Vec::new();
+
+        let args = vec![
+            "--date=31 Smarch 2024".into(),
+            target.clone().into_os_string(),
+        ];
+        let err = run(args, &mut output).unwrap_err();
+        assert!(err.to_string().contains("Invalid date"));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_run_no_create_and_stamp() {
+        let missing = unique_temp_file();
+        let existing = file_with_times(
+            FileTime::from_unix_time(1_000_000, 0),
+            FileTime::from_unix_time(1_000_000, 0),
+        );
+        let args = vec![
+            OsString::from("-c"),
+            OsString::from("-t"),
+            OsString::from("200102030405"),
+            missing.clone().into_os_string(),
+            existing.clone().into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+
+        assert!(!missing.exists());
+        let expected = parse_touch_stamp("200102030405", ParseContext::default()).unwrap();
+        assert_eq!(read_times(&existing), (expected, expected));
+        fs::remove_file(&existing).unwrap();
+    }
+
+    #[test]
+    fn test_select_operand_newest_and_oldest() {
+        let old = file_with_times(
+            FileTime::from_unix_time(1_000_000, 0),
+            FileTime::from_unix_time(1_000_000, 0),
+        );
+        let new = file_with_times(
+            FileTime::from_unix_time(2_000_000, 0),
+            FileTime::from_unix_time(2_000_000, 0),
+        );
+        let operands = vec![missing_parent_path("select"), old.clone(), new.clone()];
+        assert_eq!(
+            select_operand(operands.clone(), Selection::Newest).unwrap(),
+            new
+        );
+        assert_eq!(select_operand(operands, Selection::Oldest).unwrap(), old);
+
+        let err =
+            select_operand(vec![missing_parent_path("select")], Selection::Newest).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        fs::remove_file(&old).unwrap();
+        fs::remove_file(&new).unwrap();
+    }
+
+    #[test]
+    fn test_run_select_newest_among_glob_matches() {
+        let dir = env::temp_dir().join(format!("mdtouch_select_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let past = FileTime::from_unix_time(1_000_000, 0);
+        let older = FileTime::from_unix_time(500_000, 0);
+        for (name, time) in [("a.log", older), ("b.log", past), ("c.txt", past)] {
+            fs::write(dir.join(name), b"").unwrap();
+            set_file_times(dir.join(name), time, time).unwrap();
+        }
+
+        let args = vec![
+            OsString::from("--select=newest"),
+            dir.join("*.log").into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+
+        assert_eq!(read_times(&dir.join("a.log")).1, older);
+        assert_ne!(read_times(&dir.join("b.log")).1, past);
+        assert_eq!(read_times(&dir.join("c.txt")).1, past);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_sort() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        // Names, sizes and times each put the files in a different order.
+        let files = [
+            ("b.txt", 1, 3_000),
+            ("c.txt", 3, 1_000),
+            ("a.txt", 2, 2_000),
+        ];
+        let order = |sort: &str| {
+            for (name, size, seconds) in files {
+                fs::write(dir.join(name), vec![b'x'; size]).unwrap();
+                let time = FileTime::from_unix_time(seconds, 0);
+                set_file_times(dir.join(name), time, time).unwrap();
+            }
+            let _ = fs::remove_file(dir.join("new.txt"));
+            let mut args: Vec<OsString> = vec!["-v".into(), "--jobs=1".into()];
+            args.push(format!("--sort={}", sort).into());
+            args.extend(
+                ["b.txt", "c.txt", "a.txt", "new.txt"].map(|name| dir.join(name).into_os_string()),
+            );
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .filter_map(|line| line.strip_prefix("touched "))
+                .map(|path| {
+                    Path::new(path)
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(order("as-given"), "b.txt c.txt a.txt new.txt");
+        assert_eq!(order("name"), "a.txt b.txt c.txt new.txt");
+        assert_eq!(order("name-desc"), "new.txt c.txt b.txt a.txt");
+        assert_eq!(order("size"), "new.txt b.txt a.txt c.txt");
+        assert_eq!(order("mtime"), "new.txt c.txt a.txt b.txt");
+        let err = run(vec!["--sort=random".into()], Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("as-given"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_fail_on_zero_matched() {
+        let dir = env::temp_dir().join(format!("mdtouch_zero_matched_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), b"").unwrap();
+        let typo = dir.join("*.rss");
+        let args = |extra: &[&Path]| {
+            let mut args = vec![OsString::from("--fail-on-zero-matched")];
+            args.extend(extra.iter().map(|path| path.as_os_str().to_owned()));
+            args
+        };
+
+        let err = run(args(&[&typo]), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("No files matched"), "{}", err);
+        assert!(err.to_string().contains("*.rss"), "{}", err);
+        assert!(!typo.exists());
+        let mut preflight = args(&[&typo]);
+        preflight.push(OsString::from("--preflight"));
+        let err = run(preflight, Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("*.rss"), "{}", err);
+
+        // Something left to process is enough; the pattern with no match is dropped.
+        run(args(&[&typo, &dir.join("*.txt")]), Vec::new()).unwrap();
+        assert!(!typo.exists());
+        fs::write(dir.join("empty.list"), b"").unwrap();
+        let err = run(
+            vec![
+                OsString::from("--fail-on-zero-matched"),
+                OsString::from("--files-from"),
+                dir.join("empty.list").into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("No files to process"), "{}", err);
+
+        // Without it, a pattern matching nothing is created as written, where a name can
+        // hold a `*`.
+        if cfg!(unix) {
+            run(vec![typo.clone().into_os_string()], Vec::new()).unwrap();
+            assert!(typo.exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand_operands_guards_system_directories() {
+        let options = Options::default();
+        let (expanded, blocked, _) = expand_operands(
+            vec![PathBuf::from("/etc/host*"), PathBuf::from("/etc/hostname")],
+            &options,
+            &StatCache::default(),
+        );
+        assert!(!blocked.is_empty());
+        assert!(blocked
+            .iter()
+            .all(|(_, protected)| protected.ends_with("etc")));
+        // The explicit operand survives; only the wildcard matches were held back.
+        assert_eq!(expanded, vec![PathBuf::from("/etc/hostname")]);
+
+        let options = Options {
+            allow_system: true,
+            ..Options::default()
+        };
+        let (expanded, blocked, _) = expand_operands(
+            vec![PathBuf::from("/etc/host*")],
+            &options,
+            &StatCache::default(),
+        );
+        assert!(blocked.is_empty());
+        assert!(!expanded.is_empty());
+    }
+
+    #[test]
+    fn test_run_reports_a_file_used_as_a_directory() {
+        let file = unique_temp_file();
+        fs::write(&file, b"").unwrap();
+        for operand in [
+            file.join("sub.txt"),
+            file.join("deeper").join("sub.txt"),
+            file.join("dir").join(""),
+        ] {
+            for preflight in [false, true] {
+                let mut args = vec![operand.clone().into_os_string()];
+                if preflight {
+                    args.insert(0, OsString::from("--preflight"));
+                }
+                let message = run(args, Vec::new()).unwrap_err().to_string();
+                assert!(
+                    message.ends_with(&format!(
+                        "a path component is a file, not a directory: {}",
+                        file.display()
+                    )),
+                    "{}",
+                    message
+                );
+            }
+        }
+        // A plain missing directory is still reported as one.
+        let message = run(
+            vec![missing_parent_path("plain").into_os_string()],
+            Vec::new(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            !message.contains("a path component is a file"),
+            "{}",
+            message
+        );
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_expand_operands_case_sensitive() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("Makefile"), b"").unwrap();
+        fs::write(dir.join("makefile"), b"").unwrap();
+        // Without per-directory case sensitivity both names are the same file.
+        if fs::read_dir(&dir).unwrap().count() == 2 {
+            let expand = |case_sensitive| {
+                let options = Options {
+                    case_sensitive,
+                    ..Options::default()
+                };
+                expand_operands(vec![dir.join("Make*")], &options, &StatCache::default()).0
+            };
+            assert_eq!(expand(true), vec![dir.join("Makefile")]);
+            let mut both = vec![dir.join("Makefile")];
+            if cfg!(windows) {
+                both.push(dir.join("makefile"));
+            }
+            assert_eq!(expand(false), both);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Returns `path` as an operand string with a trailing path separator.
+    fn with_separator(path: &Path) -> OsString {
+        let mut operand = path.as_os_str().to_os_string();
+        operand.push(std::path::MAIN_SEPARATOR_STR);
+        operand
+    }
+
+    #[test]
+    fn test_run_mkdir_creates_directory_with_requested_time() {
+        let dir = unique_temp_file();
+        let stamp = FileTime::from_unix_time(1_234_567_890, 0);
+        let args = vec![
+            OsString::from("--mkdir"),
+            OsString::from("-d"),
+            OsString::from("@1234567890"),
+            with_separator(&dir),
+        ];
+        run(args.clone(), Vec::new()).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(read_times(&dir), (stamp, stamp));
+
+        // An existing directory just has its times updated.
+        set_file_times(&dir, FileTime::zero(), FileTime::zero()).unwrap();
+        run(args, Vec::new()).unwrap();
+        assert_eq!(read_times(&dir), (stamp, stamp));
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_mkdir_parents_and_conflicts() {
+        let base = unique_temp_file();
+        let chain = base.join("a").join("b");
+        let args = |path: &Path, extra: &[&str]| {
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            args.push(with_separator(path));
+            args
+        };
+
+        assert!(run(args(&chain, &["--mkdir"]), Vec::new()).is_err());
+        assert!(!base.exists());
+        run(args(&chain, &["--mkdir", "--parents"]), Vec::new()).unwrap();
+        assert!(chain.is_dir());
+
+        // A file where a directory is expected is an error, with or without --mkdir.
+        let file = base.join("file.txt");
+        fs::write(&file, b"").unwrap();
+        let err = run(args(&file, &["--mkdir"]), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("not a directory"), "{}", err);
+        assert!(file.is_file());
+
+        // Without --mkdir a missing directory operand is not created.
+        let missing = base.join("missing");
+        let err = run(args(&missing, &[]), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("--mkdir"), "{}", err);
+        assert!(!missing.exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_run_reference_pe_rejects_non_pe_file() {
+        let not_pe = file_with_times(FileTime::zero(), FileTime::zero());
+        let target = unique_temp_file();
+        let args = vec![
+            OsString::from("--reference-pe"),
+            not_pe.clone().into_os_string(),
+            target.clone().into_os_string(),
+        ];
+        let err = run(args, Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("not a valid PE image"), "{}", err);
+        assert!(!target.exists());
+        fs::remove_file(&not_pe).unwrap();
+    }
+
+    #[test]
+    fn test_run_quiet_errors_keeps_verbose_output_and_failure() {
+        let good = unique_temp_file();
+        let bad = missing_parent_path("quiet_errors");
+        let args = vec![
+            OsString::from("-v"),
+            OsString::from("--quiet-errors"),
+            good.clone().into_os_string(),
+            bad.into_os_string(),
+        ];
+        let mut output = Vec::new();
+        let err = run(args, &mut output).unwrap_err();
+
+        assert!(err.to_string().is_empty(), "{}", err);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("touched {}\n", good.display())
+        );
+        fs::remove_file(&good).unwrap();
+    }
+
+    #[test]
+    fn test_run_error_log_records_each_failure() {
+        let good = unique_temp_file();
+        let bad = missing_parent_path("error_log");
+        let log = unique_temp_file().with_extension("log");
+        let args = vec![
+            OsString::from("--quiet-errors"),
+            OsString::from(format!("--error-log={}", log.display())),
+            good.clone().into_os_string(),
+            bad.clone().into_os_string(),
+        ];
+        assert!(run(args, Vec::new()).is_err());
+
+        // Logged even though --quiet-errors keeps it off standard error.
+        let contents = fs::read_to_string(&log).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1, "{}", contents);
+        let (time, message) = lines[0].split_once(' ').unwrap();
+        assert!(time.ends_with('Z'), "{}", time);
+        assert!(
+            message.starts_with(&format!("Error touching {}: ", bad.display())),
+            "{}",
+            message
+        );
+        fs::remove_file(&good).unwrap();
+        fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn test_run_quiet_overrides_verbose() {
+        let good = unique_temp_file();
+        let args = vec![
+            OsString::from("-q"),
+            OsString::from("-v"),
+            good.clone().into_os_string(),
+        ];
+        let mut output = Vec::new();
+        run(args, &mut output).unwrap();
+        assert!(output.is_empty());
+        fs::remove_file(&good).unwrap();
+    }
+
+    #[test]
+    fn test_touch_with_timeout_gives_up_on_a_blocked_touch() {
+        let path = unique_temp_file();
+        // The touch waits for the go-ahead, standing in for a share that has stopped
+        // answering.
+        let (go_ahead, wait) = mpsc::channel::<()>();
+        let owned_path = path.clone();
+        let started = std::time::Instant::now();
+        let err = touch_with_timeout(Duration::from_millis(100), move || {
+            let _ = wait.recv();
+            touch_file(&owned_path, &TouchOptions::now())
+        })
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            err.to_string(),
+            "no response within 100 ms (--net-timeout); the change may still be made later"
+        );
+        assert!(!path.exists());
+
+        // Once it goes ahead, the abandoned touch still creates the file.
+        go_ahead.send(()).unwrap();
+        let released = std::time::Instant::now();
+        while !path.exists() && released.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        fs::remove_file(&path).unwrap();
+
+        // A touch that answers in time gives its own result.
+        let outcome = touch_with_timeout(Duration::from_secs(5), || Ok(7));
+        assert_eq!(outcome.unwrap(), 7);
+    }
+
+    #[test]
+    fn test_run_net_timeout() {
+        let path = unique_temp_file();
+        run(
+            vec![
+                OsString::from("--net-timeout=5000"),
+                path.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+
+        let err = run(
+            vec![OsString::from("--net-timeout=0"), path.into_os_string()],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("Invalid --net-timeout '0'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_run_flush_with_jobs() {
+        let existing = unique_temp_file();
+        fs::write(&existing, b"kept").unwrap();
+        let created = unique_temp_file();
+        let args = vec![
+            OsString::from("--flush"),
+            OsString::from("--jobs=2"),
+            OsString::from("-d"),
+            OsString::from("@1600000000"),
+            existing.clone().into_os_string(),
+            created.clone().into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+
+        let time = FileTime::from_unix_time(1_600_000_000, 0);
+        for path in [&existing, &created] {
+            assert_eq!(read_times(path), (time, time));
+        }
+        assert_eq!(fs::read(&existing).unwrap(), b"kept");
+        fs::remove_file(&existing).unwrap();
+        fs::remove_file(&created).unwrap();
+    }
+
+    #[test]
+    fn test_run_reference_directory() {
+        let reference = unique_temp_file();
+        fs::create_dir(&reference).unwrap();
+        let (atime, mtime) = (
+            FileTime::from_unix_time(1_500_000_000, 0),
+            FileTime::from_unix_time(1_600_000_000, 0),
+        );
+        set_file_times(&reference, atime, mtime).unwrap();
+        let path = unique_temp_file();
+        let args = vec![
+            OsString::from("-r"),
+            reference.clone().into_os_string(),
+            path.clone().into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+
+        assert_eq!(read_times(&path), (atime, mtime));
+        // Reading the directory's times left them as they were.
+        assert_eq!(read_times(&reference), (atime, mtime));
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir(&reference).unwrap();
+    }
+
+    #[test]
+    fn test_run_skip_readonly() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        let old = FileTime::from_unix_time(1_500_000_000, 0);
+        for path in &paths {
+            fs::write(path, b"").unwrap();
+            set_file_times(path, old, old).unwrap();
+        }
+        let writable = fs::metadata(&paths[1]).unwrap().permissions();
+        let mut read_only = writable.clone();
+        read_only.set_readonly(true);
+        fs::set_permissions(&paths[1], read_only).unwrap();
+
+        let mut output = Vec::new();
+        let args = vec![
+            OsString::from("--skip-readonly"),
+            OsString::from("-v"),
+            OsString::from("-R"),
+            dir.clone().into_os_string(),
+        ];
+        run(args, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(&format!("skipped {} (read-only)", paths[1].display())));
+        assert_eq!(output.matches("skipped").count(), 1, "{}", output);
+        assert_eq!(read_times(&paths[1]), (old, old));
+        for path in [&paths[0], &paths[2]] {
+            assert_ne!(read_times(path).1, old);
+        }
+        fs::set_permissions(&paths[1], writable).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stat_cache_reads_once_per_operand() {
+        let path = unique_temp_file();
+        fs::write(&path, b"").unwrap();
+        let options = Options {
+            apply_to: ApplyTo::Files,
+            attr_filter: Some(AttrFilter::excluding(attrs::SYSTEM)),
+            skip_readonly: true,
+            ..Options::default()
+        };
+        // The checks made before touching under --apply-to files --attr-filter -S
+        // --skip-readonly --report-unchanged --audit-log.
+        let checks = |stats: &StatCache| {
+            assert_eq!(skip_reason(&path, &options, None, stats), None);
+            assert!(current_times(&path, &options, stats).is_ok());
+            assert!(current_times(&path, &options, stats).is_ok());
+            (stats.lookups(), stats.reads())
+        };
+        assert_eq!(checks(&StatCache::new(false)), (5, 5));
+        assert_eq!(checks(&StatCache::new(true)), (5, 1));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_confirm_large_batch() {
+        let operands = |count: usize| -> Box<dyn Iterator<Item = PathBuf>> {
+            Box::new((0..count).map(|i| PathBuf::from(i.to_string())))
+        };
+        let confirm = |count, answer: &str| {
+            let mut output = Vec::new();
+            let result = confirm_large_batch(
+                operands(count),
+                10,
+                &mut io::Cursor::new(answer.as_bytes()),
+                &mut output,
+            )
+            .map(|operands| operands.count());
+            (result, String::from_utf8(output).unwrap())
+        };
+
+        // Up to the threshold nothing is asked.
+        let (result, asked) = confirm(10, "");
+        assert_eq!((result.unwrap(), asked.as_str()), (10, ""));
+        // Above it, the whole batch is counted and only a yes lets it through.
+        for answer in ["y\n", "YES\r\n"] {
+            let (result, asked) = confirm(25, answer);
+            assert_eq!(result.unwrap(), 25);
+            assert_eq!(asked, "About to touch 25 files, continue? [y/N] ");
+        }
+        for answer in ["\n", "n\n", ""] {
+            let err = confirm(25, answer).0.unwrap_err();
+            assert!(err.to_string().contains("not confirmed"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_run_touch_mode() {
+        let old = FileTime::from_unix_time(1_500_000_000, 0);
+        // Runs in the given mode over a file that exists and one that doesn't, returning
+        // the error kind, if any, and whether each was touched or created.
+        let touch = |mode: &str| {
+            let (present, absent) = (unique_temp_file(), unique_temp_file());
+            fs::write(&present, b"").unwrap();
+            set_file_times(&present, old, old).unwrap();
+            let args = vec![
+                OsString::from(format!("--touch-mode={}", mode)),
+                present.clone().into_os_string(),
+                absent.clone().into_os_string(),
+            ];
+            let result = run(args, Vec::new()).map_err(|e| e.kind());
+            let outcome = (result, read_times(&present).1 != old, absent.exists());
+            fs::remove_file(&present).unwrap();
+            let _ = fs::remove_file(&absent);
+            outcome
+        };
+
+        assert_eq!(
+            touch("create"),
+            (Err(io::ErrorKind::AlreadyExists), false, true)
+        );
+        assert_eq!(touch("update"), (Ok(()), true, false));
+        assert_eq!(touch("both"), (Ok(()), true, true));
+
+        let path = unique_temp_file().into_os_string();
+        for (args, contradiction) in [
+            (["--touch-mode=create", "-c"], "-c"),
+            (["--touch-mode=update", "--exclusive"], "--exclusive"),
+            (["-c", "--touch-mode=both"], "-c"),
+        ] {
+            let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            args.push(path.clone());
+            let err = run(args, Vec::new()).unwrap_err();
+            assert!(
+                err.to_string()
+                    .ends_with(&format!("cannot be combined with {}", contradiction)),
+                "{}",
+                err
+            );
+        }
+        assert!(run(
+            vec![OsString::from("--touch-mode=sometimes"), path],
+            Vec::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_report_unchanged() {
+        let time = FileTime::from_unix_time(1_600_000_000, 0);
+        let correct = unique_temp_file();
+        fs::write(&correct, b"").unwrap();
+        set_file_times(&correct, time, time).unwrap();
+        let stale = unique_temp_file();
+        fs::write(&stale, b"").unwrap();
+        let report = |extra: &[&str]| {
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            args.extend(["--report-unchanged", "-d", "@1600000000"].map(OsString::from));
+            args.extend([
+                correct.clone().into_os_string(),
+                stale.clone().into_os_string(),
+            ]);
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // Listed without -v, and only the file that already had the time.
+        assert_eq!(report(&[]), format!("unchanged {}\n", correct.display()));
+        assert_eq!(read_times(&stale), (time, time));
+        // The second time round neither needs touching.
+        let output = report(&["-v", "--json"]);
+        for path in [&correct, &stale] {
+            assert!(output.contains(&format!(
+                "{{\"path\":{},\"outcome\":\"unchanged\"}}",
+                audit::json_string(&path.to_string_lossy())
+            )));
+        }
+        assert!(!output.contains("touched"), "{}", output);
+        assert_eq!(report(&["-q"]), "");
+        fs::remove_file(&correct).unwrap();
+        fs::remove_file(&stale).unwrap();
+    }
+
+    #[test]
+    fn test_run_touch_and_print_id() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let (before, after) = (dir.join("before.txt"), dir.join("after.txt"));
+        let touch = |extra: &[&str], path: &Path| {
+            let mut args: Vec<OsString> = vec!["--touch-and-print-id".into()];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(path.as_os_str().to_owned());
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let first = touch(&[], &before);
+        let id = first
+            .strip_prefix(&format!("touched {} ", before.display()))
+            .unwrap_or_else(|| panic!("{}", first));
+        assert!(
+            id.starts_with("(volume ") && id.contains(", file "),
+            "{}",
+            id
+        );
+        fs::rename(&before, &after).unwrap();
+        assert_eq!(
+            touch(&[], &after),
+            format!("touched {} {}", after.display(), id)
+        );
+
+        let json = touch(&["--json"], &after);
+        assert!(json.contains("\"outcome\":\"touched\""), "{}", json);
+        let (volume, index) = id
+            .trim_start_matches("(volume ")
+            .trim_end_matches(")\n")
+            .split_once(", file ")
+            .unwrap();
+        assert!(
+            json.contains(&format!(
+                "\"volume_serial\":\"{}\",\"file_index\":\"{}\"",
+                volume, index
+            )),
+            "{}",
+            json
+        );
+        assert_eq!(touch(&["-q"], &after), "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_verbose_json_names_the_filesystem() {
+        let path = unique_temp_file();
+        let mut output = Vec::new();
+        run(
+            vec![
+                OsString::from("-v"),
+                OsString::from("--json"),
+                OsString::from("-d"),
+                OsString::from("@1700000000"),
+                path.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.starts_with(&format!(
+                "{{\"path\":{},\"outcome\":\"touched\",\"time\":\"2023-11-14T22:13:20.000000000Z\",\"filesystem\":",
+                audit::json_string(&path.to_string_lossy())
+            )),
+            "{}",
+            output
+        );
+        #[cfg(any(windows, target_os = "linux"))]
+        assert!(!output.contains("\"filesystem\":null"), "{}", output);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_wrap_handle_reuse() {
+        let path = unique_temp_file();
+        let dotted = path
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(path.file_name().unwrap());
+        let mut output = Vec::new();
+        run(
+            vec![
+                "--wrap-handle-reuse".into(),
+                "-v".into(),
+                "-d".into(),
+                "@1700000000".into(),
+                path.clone().into_os_string(),
+                dotted.clone().into_os_string(),
+                path.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "touched {}\ntouched {}\ntouched {}\n",
+                path.display(),
+                dotted.display(),
+                path.display()
+            )
+        );
+        let (_, mtime) = read_times(&path);
+        assert_eq!(mtime, FileTime::from_unix_time(1_700_000_000, 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_empty() {
+        let path = unique_temp_file();
+        fs::write(&path, vec![b'x'; 1234]).unwrap();
+        let mut output = Vec::new();
+        run(
+            vec![
+                "--empty".into(),
+                "-v".into(),
+                "-d".into(),
+                "@1700000000".into(),
+                path.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        let (_, mtime) = read_times(&path);
+        assert_eq!(mtime, FileTime::from_unix_time(1_700_000_000, 0));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "touched {} (emptied, 1234 bytes discarded)\n",
+                path.display()
+            )
+        );
+
+        // A file it creates has nothing to discard.
+        fs::remove_file(&path).unwrap();
+        let mut output = Vec::new();
+        run(
+            vec![
+                "--empty".into(),
+                "-v".into(),
+                "--json".into(),
+                path.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\"discarded_bytes\":null"), "{}", output);
+        assert!(path.is_file());
+
+        let dir = path.with_extension("dir");
+        fs::create_dir(&dir).unwrap();
+        assert!(run(
+            vec!["--empty".into(), dir.clone().into_os_string()],
+            Vec::new()
+        )
+        .is_err());
+        assert!(run(
+            vec![
+                "--empty".into(),
+                "--no-dereference".into(),
+                path.clone().into_os_string()
+            ],
+            Vec::new()
+        )
+        .is_err());
+        // Refused before anything is done, rather than emptying a file it won't touch.
+        fs::write(&path, b"kept").unwrap();
+        for flag in ["--exclusive", "--touch-mode=create"] {
+            let err = run(
+                vec!["--empty".into(), flag.into(), path.clone().into_os_string()],
+                Vec::new(),
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("--exclusive"), "{}", err);
+            assert_eq!(fs::read(&path).unwrap(), b"kept");
+        }
+        fs::remove_dir(&dir).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_backup() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"draft").unwrap();
+        let mut output = Vec::new();
+        run(
+            vec![
+                "--backup".into(),
+                "--empty".into(),
+                "-v".into(),
+                path.clone().into_os_string(),
+                dir.join("new.txt").into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        // Only the existing file was copied, before it was emptied.
+        let backups: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read(&backups[0]).unwrap(), b"draft");
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "touched {} (backed up to {}) (emptied, 5 bytes discarded)\ntouched {}\n",
+                path.display(),
+                backups[0].display(),
+                dir.join("new.txt").display()
+            )
+        );
+
+        // Now empty, it has nothing to lose, so is not copied again.
+        run(
+            vec!["--backup=~".into(), path.clone().into_os_string()],
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        assert!(run(
+            vec!["--backup=/x".into(), path.clone().into_os_string()],
+            Vec::new()
+        )
+        .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_if_content_changed() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("cache.dat");
+        fs::write(&path, b"first").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let touch = |path: &Path| -> String {
+            set_file_times(path, old, old).unwrap();
+            let mut output = Vec::new();
+            run(
+                vec![
+                    "--if-content-changed".into(),
+                    "-v".into(),
+                    path.to_path_buf().into_os_string(),
+                ],
+                &mut output,
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let hash_of = |content: &[u8]| {
+            let other = dir.join("other");
+            fs::write(&other, content).unwrap();
+            content_hash::hash_file(&other).unwrap()
+        };
+
+        // With no hash stored, it counts as changed, and its hash is stored.
+        assert!(touch(&path).starts_with("touched"));
+        assert_ne!(read_times(&path).1, old);
+        assert_eq!(content_hash::stored(&path), hash_of(b"first"));
+
+        // The same content again is left alone.
+        touch(&path);
+        assert_eq!(read_times(&path).1, old);
+
+        // New content is touched, and the stored hash follows it.
+        fs::write(&path, b"second").unwrap();
+        touch(&path);
+        assert_ne!(read_times(&path).1, old);
+        assert_eq!(content_hash::stored(&path), hash_of(b"second"));
+        touch(&path);
+        assert_eq!(read_times(&path).1, old);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_creation_from_mtime() {
+        let path = unique_temp_file();
+        fs::write(&path, b"").unwrap();
+        let repair = |path: &Path| -> std::io::Result<String> {
+            let mut output = Vec::new();
+            run(
+                vec![
+                    "--creation-from-mtime".into(),
+                    "-v".into(),
+                    path.to_path_buf().into_os_string(),
+                ],
+                &mut output,
+            )?;
+            Ok(String::from_utf8(output).unwrap())
+        };
+
+        // Modified after it was created: nothing to repair, and nothing else is changed.
+        let (atime, later) = (
+            FileTime::from_unix_time(1_000_000_000, 0),
+            FileTime::from_unix_time(4_000_000_000, 0),
+        );
+        set_file_times(&path, atime, later).unwrap();
+        assert_eq!(
+            repair(&path).unwrap(),
+            format!(
+                "skipped {} (not created after it was modified)\n",
+                path.display()
+            )
+        );
+        assert_eq!(read_times(&path), (atime, later));
+
+        // Modified long before it was created, as after some copies.
+        let earlier = FileTime::from_unix_time(1_500_000_000, 0);
+        set_file_times(&path, atime, earlier).unwrap();
+        #[cfg(windows)]
+        {
+            set_creation_time(&path, FileTime::from_unix_time(1_700_000_000, 0)).unwrap();
+            assert_eq!(
+                repair(&path).unwrap(),
+                format!(
+                    "touched {} (creation time moved back from 2023-11-14T22:13:20.000000000Z)\n",
+                    path.display()
+                )
+            );
+            let metadata = fs::metadata(&path).unwrap();
+            assert_eq!(FileTime::from_creation_time(&metadata), Some(earlier));
+        }
+        #[cfg(not(windows))]
+        if fs::metadata(&path).unwrap().created().is_ok() {
+            let err = repair(&path).unwrap_err();
+            assert!(
+                err.to_string().contains("cannot change creation times"),
+                "{}",
+                err
+            );
+        }
+        assert_eq!(read_times(&path), (atime, earlier));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_detect_clock_skew() {
+        let path = unique_temp_file();
+        fs::write(&path, b"").unwrap();
+        let ahead = FileTime::from_unix_time(FileTime::now().unix_seconds() + 86_400, 0);
+        set_file_times(&path, ahead, ahead).unwrap();
+        let mut output = Vec::new();
+        run(
+            vec![
+                "--detect-clock-skew".into(),
+                "--only-newer".into(),
+                "-v".into(),
+                path.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "skipped {} (modification time is newer than the one given)\n",
+                path.display()
+            )
+        );
+        assert_eq!(read_times(&path).1, ahead);
+
+        // Preflight leaves it alone too, so finds nothing to change.
+        let preflight = |extra: &[&str]| {
+            let mut args: Vec<OsString> = ["--preflight", "--fail-if-changes", "-d", "@1700000000"]
+                .iter()
+                .chain(extra)
+                .map(OsString::from)
+                .collect();
+            args.push(path.clone().into_os_string());
+            run(args, Vec::new())
+        };
+        preflight(&["--only-newer"]).unwrap();
+        assert!(preflight(&[]).is_err());
+
+        // Without --only-newer it is only warned about, and moved back to now.
+        run(
+            vec!["--detect-clock-skew".into(), path.clone().into_os_string()],
+            Vec::new(),
+        )
+        .unwrap();
+        assert!(read_times(&path).1 < ahead);
+
+        // A time given later than the file's is still set.
+        run(
+            vec![
+                "--only-newer".into(),
+                "-d".into(),
+                "@4000000000".into(),
+                path.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            read_times(&path).1,
+            FileTime::from_unix_time(4_000_000_000, 0)
+        );
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            format_skew(
+                FileTime::from_unix_time(1_000_005_400, 250_000_000),
+                FileTime::from_unix_time(1_000_000_000, 0)
+            ),
+            "5400.250 s (in 1 hour)"
+        );
+    }
+
+    #[test]
+    fn test_run_fix_future() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let now = FileTime::now().unix_seconds();
+        let (tomorrow, yesterday) = (
+            FileTime::from_unix_time(now + 86_400, 0),
+            FileTime::from_unix_time(now - 86_400, 0),
+        );
+        let (ahead, behind) = (dir.join("ahead.o"), dir.join("behind.o"));
+        let fix = |args: &[&str]| {
+            for path in [&ahead, &behind] {
+                fs::write(path, b"").unwrap();
+                set_file_times(path, yesterday, yesterday).unwrap();
+            }
+            set_file_times(&ahead, yesterday, tomorrow).unwrap();
+            let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            args.push(dir.clone().into_os_string());
+            run(args, Vec::new()).unwrap();
+        };
+
+        fix(&["-R", "--fix-future"]);
+        let (atime, mtime) = read_times(&ahead);
+        assert_eq!(atime, yesterday);
+        assert!(mtime < tomorrow && mtime.unix_seconds() >= now);
+        assert_eq!(read_times(&behind), (yesterday, yesterday));
+
+        // Within the margin, it is left alone too.
+        fix(&["-R", "--fix-future=2d"]);
+        assert_eq!(read_times(&ahead), (yesterday, tomorrow));
+
+        // Preflight sees only the file ahead of the clock as changing.
+        let mut output = Vec::new();
+        let args = ["--preflight", "--fail-if-changes", "-v", "--fix-future"];
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.extend([
+            ahead.clone().into_os_string(),
+            behind.clone().into_os_string(),
+        ]);
+        let err = run(args.clone(), &mut output).unwrap_err();
+        assert!(err.to_string().contains("1 of 2 operand(s)"), "{}", err);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("would update {}\n", ahead.display())
+        );
+        set_file_times(&ahead, yesterday, yesterday).unwrap();
+        run(args, Vec::new()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let args = ["--fix-future", "-d", "now", "x"];
+        let err = run(args.iter().map(OsString::from).collect(), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("--fix-future"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_with_a_frozen_clock() {
+        use mdtouch::FixedClock;
+
+        let frozen = FileTime::from_unix_time(1_000_000_000, 0);
+        let day_before = FileTime::from_unix_time(1_000_000_000 - 86_400, 0);
+        let run_frozen = |args: &[&OsStr], output: &mut Vec<u8>| {
+            let args = args.iter().map(|arg| arg.to_os_string()).collect();
+            run_with_clock(args, output, Arc::new(FixedClock(frozen)))
+        };
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let (ahead, behind) = (dir.join("ahead.o"), dir.join("behind.o"));
+        // By the frozen clock, a file written today is far in the future.
+        fs::write(&ahead, b"").unwrap();
+        set_file_times(&ahead, day_before, FileTime::now()).unwrap();
+        fs::write(&behind, b"").unwrap();
+        set_file_times(&behind, day_before, day_before).unwrap();
+
+        // --fix-future sets it back to exactly the frozen time, and leaves the other be.
+        run_frozen(
+            &[
+                OsStr::new("--fix-future"),
+                ahead.as_os_str(),
+                behind.as_os_str(),
+            ],
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(read_times(&ahead), (day_before, frozen));
+        assert_eq!(read_times(&behind), (day_before, day_before));
+
+        // --detect-clock-skew judges by the same clock: the file ahead is left alone
+        // under --only-newer, and otherwise touched to the frozen now.
+        set_file_times(&ahead, day_before, FileTime::now()).unwrap();
+        let skewed = read_times(&ahead);
+        let mut output = Vec::new();
+        let args = [
+            OsStr::new("--detect-clock-skew"),
+            OsStr::new("--only-newer"),
+            OsStr::new("-v"),
+            OsStr::new("-d"),
+            OsStr::new("now"),
+            ahead.as_os_str(),
+            behind.as_os_str(),
+        ];
+        run_frozen(&args, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "skipped {} (modification time is newer than the one given)\ntouched {}\n",
+                ahead.display(),
+                behind.display()
+            )
+        );
+        assert_eq!(read_times(&ahead), skewed);
+        assert_eq!(read_times(&behind), (frozen, frozen));
+        run_frozen(
+            &[OsStr::new("--detect-clock-skew"), ahead.as_os_str()],
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(read_times(&ahead), (frozen, frozen));
+
+        // GNU mode reads the same clock.
+        set_file_times(&behind, day_before, day_before).unwrap();
+        run_frozen(
+            &[OsStr::new("--posix"), behind.as_os_str()],
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(read_times(&behind), (frozen, frozen));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_report_format() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let template = dir.join("report.tmpl");
+        fs::write(
+            &template,
+            "<ul>\n  {#files}\n<li class=\"{outcome}\">{path} {time}{reason}</li>\n{/files}\n</ul>\n{{{created} of {total} created}}\n",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        run(
+            vec![
+                "--report-format".into(),
+                template.clone().into_os_string(),
+                "-c".into(),
+                "-d".into(),
+                "@1700000000".into(),
+                "--relative-to".into(),
+                dir.clone().into_os_string(),
+                dir.join("report.tmpl").into_os_string(),
+                dir.join("missing.txt").into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<ul>\n<li class=\"updated\">report.tmpl 2023-11-14T22:13:20.000000000Z</li>\n<li class=\"skipped\">missing.txt does not exist</li>\n</ul>\n{0 of 2 created}\n"
+        );
+
+        fs::write(&template, "{#files}\n{path}\n").unwrap();
+        let err = run(
+            vec![
+                OsString::from(format!("--report-format={}", template.display())),
+                dir.join("x").into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("line 1: this {#files} section is never closed"));
+        assert!(!dir.join("x").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_min_mtime() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let time = |seconds| FileTime::from_unix_time(seconds, 0);
+        let floor = time(1_700_000_000);
+        let (older, newer, equal) = (dir.join("older"), dir.join("newer"), dir.join("equal"));
+        for (path, mtime) in [(&older, time(1_600_000_000)), (&newer, time(1_800_000_000))] {
+            fs::write(path, b"").unwrap();
+            set_file_times(path, time(1_500_000_000), mtime).unwrap();
+        }
+        fs::write(&equal, b"").unwrap();
+        set_file_times(&equal, time(1_500_000_000), floor).unwrap();
+
+        let args = ["-R", "--min-mtime", "@1700000000"];
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.push(dir.clone().into_os_string());
+        run(args, Vec::new()).unwrap();
+        assert_eq!(read_times(&older), (time(1_500_000_000), floor));
+        assert_eq!(
+            read_times(&newer),
+            (time(1_500_000_000), time(1_800_000_000))
+        );
+        // A file exactly at the floor is not older than it, so is left alone.
+        assert_eq!(read_times(&equal), (time(1_500_000_000), floor));
+
+        // Preflight finds nothing left to raise, then only the file moved below the floor.
+        let mut args: Vec<OsString> = ["--preflight", "--fail-if-changes", "-v", "--min-mtime"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        args.push("@1700000000".into());
+        args.extend([&older, &newer, &equal].map(|path| path.clone().into_os_string()));
+        run(args.clone(), Vec::new()).unwrap();
+        set_file_times(&equal, time(1_500_000_000), time(1_699_999_999)).unwrap();
+        let mut output = Vec::new();
+        let err = run(args, &mut output).unwrap_err();
+        assert!(err.to_string().contains("1 of 3 operand(s)"), "{}", err);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("would update {}\n", equal.display())
+        );
+        set_file_times(&equal, time(1_500_000_000), floor).unwrap();
+
+        // The floor can be a reference file's modification time.
+        set_file_times(&older, time(1_500_000_000), time(1_600_000_000)).unwrap();
+        let args = vec![
+            "--min-mtime".into(),
+            newer.clone().into_os_string(),
+            older.clone().into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+        assert_eq!(read_times(&older).1, time(1_800_000_000));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let args = ["--min-mtime", "@1700000000", "-m", "x"];
+        let err = run(args.iter().map(OsString::from).collect(), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("--min-mtime"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_verbose_reports_what_was_done() {
+        let path = unique_temp_file();
+        let touch = |extra: &[&str]| {
+            let mut args: Vec<OsString> = ["-v", "--json", "-d", "@1700000000"]
+                .iter()
+                .chain(extra)
+                .map(OsString::from)
+                .collect();
+            args.push(path.clone().into_os_string());
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let created = touch(&[]);
+        assert!(
+            created.contains("\"created\":true,\"previous_mtime\":null"),
+            "{}",
+            created
+        );
+        let updated = touch(&[]);
+        assert!(
+            updated.contains(
+                "\"created\":false,\"previous_mtime\":\"2023-11-14T22:13:20.000000000Z\""
+            ),
+            "{}",
+            updated
+        );
+        fs::remove_file(&path).unwrap();
+
+        // A file -c leaves missing was not touched, so is neither reported nor listed so.
+        let skipped = touch(&["-c"]);
+        assert!(
+            skipped.contains("\"outcome\":\"skipped\",\"reason\":\"does not exist\""),
+            "{}",
+            skipped
+        );
+        let mut listed = Vec::new();
+        run(
+            vec![
+                "-c".into(),
+                "--print0".into(),
+                path.clone().into_os_string(),
+            ],
+            &mut listed,
+        )
+        .unwrap();
+        assert!(listed.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_run_progress_options() {
+        let path = unique_temp_file();
+        let args = |extra: &[&str]| {
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            args.push(path.clone().into_os_string());
+            args
+        };
+        run(args(&["--progress"]), Vec::new()).unwrap();
+        run(args(&["--progress=line", "-c"]), Vec::new()).unwrap();
+        assert!(run(args(&["--progress=bar"]), Vec::new()).is_err());
+        let err = run(args(&["--progress", "--print0-errors"]), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("--progress"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_humanize_shows_the_applied_time() {
+        let path = unique_temp_file();
+        let humanized = |extra: &[&str]| {
+            let mut args: Vec<OsString> = ["-v", "--humanize"].iter().map(OsString::from).collect();
+            args.extend(extra.iter().map(OsString::from));
+            args.push(path.clone().into_os_string());
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let output = humanized(&["-d", "@1700000000"]);
+        assert!(
+            output.contains(" at 2023-11-14T22:13:20.000000000Z (")
+                && output.ends_with(" years ago)\n"),
+            "{}",
+            output
+        );
+        assert!(humanized(&[]).ends_with("(just now)\n"));
+        assert!(humanized(&["-q"]).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_backdates_the_creation_of_new_files() {
+        let past = std::time::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        let created = |path: &Path| fs::metadata(path).unwrap().created().ok();
+        let make = |extra: &[&str]| {
+            let path = unique_temp_file();
+            let mut args = vec![OsString::from("-d"), OsString::from("@1500000000")];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(path.clone().into_os_string());
+            run(args, Vec::new()).unwrap();
+            path
+        };
+
+        // Only Windows can set a creation time; elsewhere the file is still made, quietly.
+        let new = make(&[]);
+        if cfg!(windows) {
+            assert_eq!(created(&new), Some(past));
+        }
+        let real = make(&["--real-creation"]);
+        assert_ne!(created(&real), Some(past));
+        // A file that already existed keeps its creation time.
+        let before = created(&real);
+        run(
+            vec![
+                OsString::from("-d"),
+                OsString::from("@1400000000"),
+                real.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(created(&real), before);
+
+        fs::remove_file(new).unwrap();
+        fs::remove_file(real).unwrap();
+    }
+
+    #[test]
+    fn test_run_reference_all_copies_every_time_it_can() {
+        let atime = FileTime::from_unix_time(1_234_567_890, 0);
+        let mtime = FileTime::from_unix_time(1_234_567_999, 0);
+        let reference = file_with_times(atime, mtime);
+        thread::sleep(Duration::from_millis(20));
+        let target = unique_temp_file();
+        fs::write(&target, b"").unwrap();
+
+        let args = vec![
+            OsString::from("--reference-all"),
+            reference.clone().into_os_string(),
+            target.clone().into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+
+        // Access and modification times are always copied; the creation time only where
+        // the platform can set it.
+        assert_eq!(read_times(&target), (atime, mtime));
+        let created = |path: &Path| fs::metadata(path).and_then(|m| m.created());
+        if cfg!(windows) {
+            assert_eq!(created(&target).unwrap(), created(&reference).unwrap());
+        }
+
+        fs::remove_file(reference).unwrap();
+        fs::remove_file(target).unwrap();
+    }
+
+    #[test]
+    fn test_monotonic_creation_time() {
+        let at = |seconds, nanos| FileTime::from_unix_time(seconds, nanos);
+        let mut previous = None;
+        // The first file gets the time given; later ones a tick after the last, unless
+        // the time given is later still.
+        assert_eq!(
+            monotonic_creation_time(&mut previous, at(100, 0)),
+            at(100, 0)
+        );
+        assert_eq!(
+            monotonic_creation_time(&mut previous, at(100, 0)),
+            at(100, 100)
+        );
+        assert_eq!(
+            monotonic_creation_time(&mut previous, at(50, 0)),
+            at(100, 200)
+        );
+        assert_eq!(
+            monotonic_creation_time(&mut previous, at(200, 0)),
+            at(200, 0)
+        );
+        // A tick can carry into the next second.
+        let mut previous = Some(at(7, 999_999_950));
+        assert_eq!(monotonic_creation_time(&mut previous, at(0, 0)), at(8, 50));
+
+        let err = run(
+            vec![
+                OsString::from("--force-create-time-monotonic"),
+                OsString::from("x"),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("requires --times"), "{}", err);
+    }
+
+    #[test]
+    fn test_creation_times_unsupported() {
+        let unsupported = io::Error::new(io::ErrorKind::Unsupported, "no");
+        assert!(creation_times_unsupported(&unsupported));
+        // One file's refusal says nothing about the others.
+        for kind in [io::ErrorKind::PermissionDenied, io::ErrorKind::NotFound] {
+            assert!(!creation_times_unsupported(&io::Error::from(kind)));
+        }
+        assert_eq!(
+            creation_times_unsupported(&io::Error::from_raw_os_error(50)),
+            cfg!(windows)
+        );
+    }
+
+    #[test]
+    fn test_parse_broken_link_policy() {
+        assert_eq!(
+            parse_broken_link_policy("create-target").unwrap(),
+            BrokenLinkPolicy::CreateTarget
+        );
+        assert_eq!(
+            parse_broken_link_policy("skip").unwrap(),
+            BrokenLinkPolicy::Skip
+        );
+        assert!(parse_broken_link_policy("ignore").is_err());
+    }
+
+    #[test]
+    fn test_run_jobs_matches_sequential_results() {
+        let dir = env::temp_dir().join(format!("mdtouch_jobs_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // Every seventh operand is in a directory that does not exist.
+        let operands: Vec<OsString> = (0..300)
+            .map(|i| match i % 7 {
+                0 => dir.join("missing").join(format!("{}.txt", i)),
+                _ => dir.join(format!("{}.txt", i)),
+            })
+            .map(PathBuf::into_os_string)
+            .collect();
 
-/// Compile-time build date and time. To override, set the BUILD_DATETIME environment
-/// variable at compile time. Otherwise, a default value is used.
-const BUILD_DATETIME: &str = match option_env!("BUILD_DATETIME") {
-    Some(val) => val,
-    None => "2025-02-03 10:00:00",
-};
+        let run_with = |extra: &[&str], list: &str| {
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            args.extend([
+                OsString::from("-v"),
+                OsString::from("--failed-to"),
+                dir.join(list).into_os_string(),
+            ]);
+            args.extend(operands.iter().cloned());
+            let mut output = Vec::new();
+            let err = run(args, &mut output).unwrap_err().to_string();
+            let failed = fs::read_to_string(dir.join(list)).unwrap();
+            (String::from_utf8(output).unwrap(), err, failed)
+        };
 
-/// Returns a detailed help message describing the usage of the tool.
-fn help_message() -> String {
-    let mut msg = String::new();
-    msg.push_str("Usage: mdtouch [OPTIONS] <file> [file...]\n\n");
-    msg.push_str(
-        "A command line tool to mimic the behaviour of the Unix touch command on Windows.\n",
-    );
-    msg.push_str(
-        "If the file does not exist, it will be created. Otherwise, its access and modification\n",
-    );
-    msg.push_str("times will be updated to the current time.\n\n");
-    msg.push_str("Options:\n");
-    msg.push_str("  -h, -?      Display this help message and exit.\n");
-    msg
-}
+        let sequential = run_with(&[], "sequential.lst");
+        let parallel = run_with(&["--jobs", "8"], "parallel.lst");
+        assert_eq!(sequential, parallel);
+        assert_eq!(sequential.2.lines().count(), 43);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_apply_to_each_kind() {
+        let base = unique_temp_file();
+        let dir = base.join("dir");
+        let file = base.join("file.txt");
+        let new = base.join("new.txt");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, b"").unwrap();
+        let old = FileTime::from_unix_time(1_000_000, 0);
+
+        for (kind, touched, skipped) in [
+            ("files", vec![&file, &new], vec![&dir]),
+            ("dirs", vec![&dir], vec![&file, &new]),
+            ("both", vec![&dir, &file, &new], vec![]),
+        ] {
+            let _ = fs::remove_file(&new);
+            set_file_times(&dir, old, old).unwrap();
+            set_file_times(&file, old, old).unwrap();
+            let args = vec![
+                OsString::from(format!("--apply-to={}", kind)),
+                OsString::from("-v"),
+                dir.clone().into_os_string(),
+                file.clone().into_os_string(),
+                new.clone().into_os_string(),
+            ];
+            let mut output = Vec::new();
+            run(args, &mut output).unwrap();
+            let output = String::from_utf8(output).unwrap();
 
-/// Touches a file at the given path, mimicking the behaviour of the Unix `touch` command.
-/// If the file does not exist, it is created. In either case, the file's access and
-/// modification times are updated to the current time.
-fn touch_file<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
-    let path = path.as_ref();
-    if !path.exists() {
-        // Create the file if it does not exist.
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
+            for path in touched {
+                assert!(
+                    path.exists() && read_times(path).1 != old,
+                    "{} {:?}",
+                    kind,
+                    path
+                );
+                assert!(output.contains(&format!("touched {}", path.display())));
+            }
+            for path in skipped {
+                assert!(
+                    !path.exists() || read_times(path).1 == old,
+                    "{} {:?}",
+                    kind,
+                    path
+                );
+                assert!(output.contains(&format!("skipped {}", path.display())));
+            }
+        }
+        fs::remove_dir_all(&base).unwrap();
     }
-    // Update the file's access and modification times to now.
-    let now = FileTime::now();
-    set_file_times(path, now, now)
-}
 
-/// Runs the application logic.
-///
-/// # Arguments
-///
-/// * `args` - A vector of command line arguments (excluding the program name).
-/// * `writer` - A mutable reference to a writer for standard output.
-fn run<W: Write>(args: Vec<String>, mut writer: W) -> std::io::Result<()> {
-    // If no arguments are provided, print the version and a short summary.
-    if args.is_empty() {
-        writeln!(writer, "mdtouch  {}", BUILD_DATETIME)?;
-        writeln!(
-            writer,
-            "A tool to update file timestamps or create empty files, mimicking the Unix touch command."
-        )?;
-        return Ok(());
+    #[cfg(unix)]
+    #[test]
+    fn test_run_owner_applies_to_created_files_only() {
+        use std::os::unix::fs::MetadataExt;
+
+        let err = run(
+            vec![OsString::from("--owner-existing"), OsString::from("x")],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("requires --owner"), "{}", err);
+        let err = run(
+            vec![
+                OsString::from("--owner"),
+                OsString::from("mdtouch-no-such-user"),
+                OsString::from("x"),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid --owner"), "{}", err);
+
+        // Handing files to another user needs root.
+        // SAFETY: geteuid has no preconditions.
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let existing = base.join("existing.txt");
+        let created = base.join("created.txt");
+        fs::write(&existing, b"").unwrap();
+        let args = |extra: &[&str]| {
+            let mut args: Vec<OsString> = vec!["--owner".into(), "65534".into()];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(existing.clone().into_os_string());
+            args.push(created.clone().into_os_string());
+            args
+        };
+
+        run(args(&[]), Vec::new()).unwrap();
+        assert_eq!(fs::metadata(&existing).unwrap().uid(), 0);
+        assert_eq!(fs::metadata(&created).unwrap().uid(), 65534);
+
+        run(args(&["--owner-existing"]), Vec::new()).unwrap();
+        assert_eq!(fs::metadata(&existing).unwrap().uid(), 65534);
+        fs::remove_dir_all(&base).unwrap();
     }
 
-    // If any argument is a help flag, display help and exit.
-    if args.iter().any(|arg| arg == "-h" || arg == "-?") {
-        writeln!(writer, "{}", help_message())?;
-        return Ok(());
+    #[test]
+    fn test_run_exec() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join("copies")).unwrap();
+        let names = ["plain.txt", "with space.txt", "it's.txt"];
+        for name in names {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let operands = || names.iter().map(|name| dir.join(name).into_os_string());
+        let log = dir.join("log.txt");
+        let run_with = |command: String, shell: bool, extra: &[&str]| {
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            if shell {
+                args.push("--exec-shell".into());
+            }
+            args.extend(["--exec".into(), command.into()]);
+            args.extend(operands());
+            run(args, Vec::new())
+        };
+
+        // Once for each file touched, but not for one -c left missing.
+        let echo = format!("echo {{}} >> \"{}\"", log.display());
+        let mut missing = vec!["-c", "--jobs", "2"];
+        let absent = dir.join("absent.txt");
+        missing.push(absent.to_str().unwrap());
+        run_with(echo, true, &missing).unwrap();
+        let logged = fs::read_to_string(&log).unwrap();
+        assert_eq!(logged.lines().count(), 3, "{}", logged);
+        for name in names {
+            assert!(logged.contains(name), "{}", logged);
+        }
+        assert!(!absent.exists());
+
+        // Without a shell, each path reaches the program as one argument, spaces and all.
+        let copies = dir.join("copies");
+        let copy = if cfg!(windows) {
+            format!("cmd /c copy {{}} \"{}\"", copies.display())
+        } else {
+            format!("cp {{}} \"{}\"", copies.display())
+        };
+        run_with(copy, false, &[]).unwrap();
+        for name in names {
+            assert!(copies.join(name).exists(), "{} was not copied", name);
+        }
+
+        let err = run_with("exit 3".to_string(), true, &[]).unwrap_err();
+        assert!(err.to_string().contains("exited with status 3"), "{}", err);
+        let err = run_with("echo 'open".to_string(), false, &[]).unwrap_err();
+        assert!(err.to_string().contains("unclosed quote"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
     }
 
-    // Process each file argument.
-    for filename in args {
-        if let Err(e) = touch_file(&filename) {
-            // In the main loop, we print to stderr usually, but here we propagate the error
-            // so main can handle it.
-            // However, to mimic the original behavior of printing "Error touching ...",
-            // we will format the error into a new Error.
-            return Err(std::io::Error::other(format!(
-                "Error touching {}: {}",
-                filename, e
-            )));
+    #[test]
+    fn test_run_max_files() {
+        let dir = unique_temp_file();
+        fs::create_dir_all(dir.join("tree")).unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(dir.join("tree").join(name), b"").unwrap();
         }
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let run_with = |extra: &[&str]| {
+            for name in ["a", "b", "c"] {
+                set_file_times(dir.join("tree").join(name), old, old).unwrap();
+            }
+            let mut args: Vec<OsString> = extra.iter().map(OsString::from).collect();
+            args.push(dir.join("tree").into_os_string());
+            run(args, Vec::new())
+        };
+
+        // The directory and its three files, counted once -R has expanded it.
+        let err = run_with(&["-R", "--max-files", "3"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("4 files is more than --max-files allows (3)"),
+            "{}",
+            err
+        );
+        assert_eq!(read_times(&dir.join("tree").join("a")), (old, old));
+        let err = run_with(&["-R", "--max-files=3", "--preflight"]).unwrap_err();
+        assert!(err.to_string().contains("(3)"), "{}", err);
+
+        run_with(&["-R", "--max-files", "4"]).unwrap();
+        assert_ne!(read_times(&dir.join("tree").join("a")).1, old);
+        assert!(run_with(&["--max-files", "0"]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
     }
-    Ok(())
-}
 
-fn main() -> ExitCode {
-    let args: Vec<String> = env::args().skip(1).collect();
+    #[test]
+    fn test_run_copy_security() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let reference = base.join("reference.txt");
+        let created = base.join("created.txt");
+        fs::write(&reference, b"").unwrap();
 
-    if let Err(e) = run(args, std::io::stdout()) {
-        eprintln!("{}", e);
-        return ExitCode::FAILURE;
+        let result = run(
+            vec![
+                OsString::from("--copy-security"),
+                created.clone().into_os_string(),
+            ],
+            Vec::new(),
+        );
+        let err = result.unwrap_err();
+        if cfg!(windows) {
+            assert!(err.to_string().contains("requires -r"), "{}", err);
+        } else {
+            assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+            assert!(!created.exists());
+            fs::remove_dir_all(&base).unwrap();
+            return;
+        }
+
+        run(
+            vec![
+                OsString::from("--copy-security"),
+                OsString::from("-r"),
+                reference.clone().into_os_string(),
+                created.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert!(created.exists());
+        fs::remove_dir_all(&base).unwrap();
     }
-    ExitCode::SUCCESS
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
-    use std::fs;
-    use std::path::PathBuf;
-    use std::thread;
-    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    #[test]
+    fn test_run_preflight_touches_nothing() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let existing = base.join("existing.txt");
+        let new = base.join("new.txt");
+        fs::write(&existing, b"").unwrap();
+        set_file_times(&existing, FileTime::zero(), FileTime::zero()).unwrap();
 
-    /// Generates a unique temporary file path in the system's temporary directory.
-    fn unique_temp_file() -> PathBuf {
-        let mut path = env::temp_dir();
-        // Use the current time in nanoseconds to generate a unique file name.
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        path.push(format!("mdtouch_test_{}.tmp", nanos));
-        path
+        let args = vec![
+            OsString::from("--preflight"),
+            OsString::from("-d"),
+            OsString::from("2024-01-01"),
+            existing.clone().into_os_string(),
+            new.clone().into_os_string(),
+        ];
+        let mut output = Vec::new();
+        run(args, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("2 operand(s) checked"), "{}", output);
+        assert!(!new.exists());
+        assert_eq!(read_times(&existing), (FileTime::zero(), FileTime::zero()));
+        fs::remove_dir_all(&base).unwrap();
     }
 
     #[test]
-    fn test_touch_new_file() {
-        let path = unique_temp_file();
-        // Ensure the file does not exist.
-        if path.exists() {
-            fs::remove_file(&path).expect("Failed to remove pre-existing test file.");
+    fn test_run_preflight_reports_every_problem() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let args = vec![
+            OsString::from("--preflight"),
+            OsString::from("-d"),
+            OsString::from("not a date"),
+            OsString::from("--retries=2"),
+            OsString::from("--files-from"),
+            base.join("missing-list.txt").into_os_string(),
+            base.join("no-such-dir").join("file.txt").into_os_string(),
+            with_separator(&base.join("missing-dir")),
+        ];
+        let err = run(args, Vec::new()).unwrap_err().to_string();
+        for expected in [
+            "Error reading file list",
+            "not a date",
+            "--retries requires --on-error=retry",
+            "parent directory does not exist",
+            "use --mkdir",
+        ] {
+            assert!(err.contains(expected), "missing {:?} in {}", expected, err);
         }
+        assert_eq!(fs::read_dir(&base).unwrap().count(), 0);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_run_preflight_fail_if_changes() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let existing = base.join("existing.txt");
+        let new = base.join("new.txt");
+        fs::write(&existing, b"").unwrap();
+        let time = FileTime::from_unix_time(1_704_067_200, 0);
+        set_file_times(&existing, time, time).unwrap();
+        let args = |extra: &[&Path]| {
+            let mut args: Vec<OsString> = ["--preflight", "--fail-if-changes", "-v", "-d"]
+                .iter()
+                .map(OsString::from)
+                .collect();
+            args.push("@1704067200".into());
+            args.extend(extra.iter().map(|path| path.as_os_str().to_owned()));
+            args
+        };
+
+        let mut output = Vec::new();
+        run(args(&[&existing]), &mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("1 operand(s) checked"));
+
+        let mut output = Vec::new();
+        let err = run(args(&[&existing, &new]), &mut output).unwrap_err();
+        assert!(err.to_string().contains("1 of 2 operand(s)"), "{}", err);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("would create"), "{}", output);
+        assert!(!output.contains("existing.txt"), "{}", output);
+        assert!(!new.exists());
+
+        set_file_times(&existing, FileTime::zero(), FileTime::zero()).unwrap();
+        let err = run(args(&[&existing]), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("not up to date"), "{}", err);
+        assert_eq!(read_times(&existing), (FileTime::zero(), FileTime::zero()));
+
+        let err = run(
+            vec![OsString::from("--fail-if-changes"), OsString::from("x")],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("requires --preflight"), "{}", err);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_run_preflight_decides_as_the_run_does() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let time = FileTime::from_unix_time(1_704_067_200, 0);
+        let preflight = |extra: &[&str], path: &Path| {
+            let mut args: Vec<OsString> = ["--preflight", "--fail-if-changes", "-d", "@1704067200"]
+                .iter()
+                .chain(extra)
+                .map(OsString::from)
+                .collect();
+            args.push(path.as_os_str().to_owned());
+            run(args, Vec::new())
+        };
+
+        // Emptying it changes it, though it has the times already.
+        let full = base.join("full.txt");
+        fs::write(&full, b"data").unwrap();
+        set_file_times(&full, time, time).unwrap();
+        preflight(&[], &full).unwrap();
+        assert!(preflight(&["--empty"], &full).is_err());
+        assert_eq!(fs::read(&full).unwrap(), b"data");
+
+        // Done in an earlier run, so left alone; the checkpoint is only read.
+        let done = base.join("done.txt");
+        fs::write(&done, b"").unwrap();
+        let checkpoint = base.join("progress.txt");
+        assert!(preflight(&[], &done).is_err());
+        let listed = checkpoint.to_str().unwrap();
+        preflight(&["--checkpoint", listed], &done).unwrap_err();
+        assert!(!checkpoint.exists());
+        fs::write(
+            &checkpoint,
+            format!("{}\n", normalize_path(&done).display()),
+        )
+        .unwrap();
+        preflight(&["--checkpoint", listed], &done).unwrap();
+
+        // Its content hasn't changed since it was touched, so it isn't touched again.
+        let hashed = base.join("hashed.txt");
+        fs::write(&hashed, b"data").unwrap();
+        let args = ["--if-content-changed", "-d", "@1704067200"];
+        let mut args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        args.push(hashed.clone().into_os_string());
+        run(args, Vec::new()).unwrap();
+        set_file_times(&hashed, FileTime::zero(), FileTime::zero()).unwrap();
+        assert!(preflight(&[], &hashed).is_err());
+        preflight(&["--if-content-changed"], &hashed).unwrap();
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_run_expands_environment_variables() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        env::set_var("MDTOUCH_TEST_VARS_DIR", &base);
+        let operand = |text: &str| OsString::from(text.replace('/', std::path::MAIN_SEPARATOR_STR));
+
+        run(
+            vec![
+                OsString::from("--dollar-vars"),
+                operand("${MDTOUCH_TEST_VARS_DIR}/braced.txt"),
+                operand("$MDTOUCH_TEST_VARS_DIR/bare.txt"),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert!(base.join("braced.txt").exists());
+        assert!(base.join("bare.txt").exists());
+
+        // Under --strict-env an undefined variable stops the run before anything is touched.
+        let err = run(
+            vec![
+                OsString::from("--dollar-vars"),
+                OsString::from("--strict-env"),
+                operand("$MDTOUCH_TEST_VARS_DIR/first.txt"),
+                operand("$MDTOUCH_TEST_VARS_UNDEFINED/second.txt"),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
         assert!(
-            !path.exists(),
-            "Test file should not exist before touching."
+            err.to_string().contains("'MDTOUCH_TEST_VARS_UNDEFINED'"),
+            "{}",
+            err
         );
+        assert!(!base.join("first.txt").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
 
-        touch_file(&path).expect("Failed to touch new file.");
+    #[test]
+    fn test_run_audit_log_records_each_modification() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let file = base.join("file.txt");
+        let log = base.join("audit.log");
+        let args = |date: &str| {
+            vec![
+                OsString::from("--audit-log"),
+                log.clone().into_os_string(),
+                OsString::from("-d"),
+                OsString::from(date),
+                file.clone().into_os_string(),
+            ]
+        };
+        run(args("@1000000000"), Vec::new()).unwrap();
+        run(args("@2000000000"), Vec::new()).unwrap();
 
-        assert!(path.exists(), "File should exist after touching.");
+        let contents = fs::read_to_string(&log).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", contents);
+        for line in &lines {
+            assert!(
+                line.starts_with("{\"time\":\"") && line.ends_with('}'),
+                "{}",
+                line
+            );
+        }
+        assert!(lines[0].contains("\"outcome\":\"created\",\"old_atime\":null"));
+        assert!(lines[0].contains("\"new_mtime\":\"2001-09-09T01:46:40.000000000Z\""));
+        assert!(lines[1].contains("\"outcome\":\"updated\""));
+        assert!(lines[1].contains("\"old_mtime\":\"2001-09-09T01:46:40.000000000Z\""));
+        assert!(lines[1].contains("\"new_mtime\":\"2033-05-18T03:33:20.000000000Z\""));
 
-        // Clean up
-        fs::remove_file(&path).expect("Failed to remove test file.");
+        // A log that can't be opened is only a warning: the file is still touched.
+        let args = vec![
+            OsString::from("--audit-log"),
+            base.clone().into_os_string(),
+            OsString::from("-d"),
+            OsString::from("@1000000000"),
+            file.clone().into_os_string(),
+        ];
+        run(args, Vec::new()).unwrap();
+        assert_eq!(
+            read_times(&file).1,
+            FileTime::from_unix_time(1_000_000_000, 0)
+        );
+        fs::remove_dir_all(&base).unwrap();
     }
 
     #[test]
-    fn test_touch_existing_file_updates_modification_time() {
-        let path = unique_temp_file();
-        // Create the file initially with some content.
-        fs::write(&path, b"initial content").expect("Failed to create test file.");
+    fn test_run_checkpoint_resumes_after_failure() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let checkpoint = base.join("progress.txt");
+        let first = base.join("first.txt");
+        let blocked = base.join("missing").join("second.txt");
+        let args = || {
+            vec![
+                OsString::from("--checkpoint"),
+                checkpoint.clone().into_os_string(),
+                OsString::from("-v"),
+                first.clone().into_os_string(),
+                blocked.clone().into_os_string(),
+            ]
+        };
 
-        // Set the modification time to a fixed point in the past.
-        let past = FileTime::from_unix_time(1_000_000, 0); // Arbitrary time in the past.
-        set_file_times(&path, past, past).expect("Failed to set file times.");
+        // The first run gets only as far as the first file.
+        assert!(run(args(), Vec::new()).is_err());
+        let listed = fs::read_to_string(&checkpoint).unwrap();
+        assert_eq!(listed, format!("{}\n", first.display()));
 
-        let metadata_before = fs::metadata(&path).expect("Failed to get metadata.");
-        let mod_time_before = metadata_before
-            .modified()
-            .expect("Failed to get modified time.");
+        // Once the cause is fixed, a rerun skips the finished file and cleans up.
+        set_file_times(&first, FileTime::zero(), FileTime::zero()).unwrap();
+        fs::create_dir(base.join("missing")).unwrap();
+        let mut output = Vec::new();
+        run(args(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!(
+            "skipped {} (done in an earlier run)",
+            first.display()
+        )));
+        assert!(output.contains(&format!("touched {}", blocked.display())));
+        assert_eq!(read_times(&first).1, FileTime::zero());
+        assert!(blocked.exists());
+        assert!(!checkpoint.exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
 
-        // Sleep briefly to ensure that the system clock advances.
-        thread::sleep(Duration::from_secs(1));
+    #[test]
+    fn test_run_print0_errors_to_file() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let list = base.join("failed.lst");
+        let bad = base.join("missing").join("a file.txt");
+        let good = base.join("good.txt");
+        let args = vec![
+            OsString::from(format!("--print0-errors={}", list.display())),
+            bad.clone().into_os_string(),
+            good.clone().into_os_string(),
+        ];
+        let err = run(args, Vec::new()).unwrap_err();
+        // The messages still go to standard error when the list goes to a file.
+        assert!(err.to_string().contains("Error touching"), "{}", err);
+        assert_eq!(
+            fs::read(&list).unwrap(),
+            format!("{}\0", bad.display()).into_bytes()
+        );
+        assert!(good.exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
 
-        touch_file(&path).expect("Failed to touch existing file.");
+    #[test]
+    fn test_run_exclusive_fails_for_existing_files() {
+        let base = unique_temp_file();
+        fs::create_dir(&base).unwrap();
+        let existing = base.join("taken.txt");
+        let fresh = base.join("free.txt");
+        fs::write(&existing, b"").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        set_file_times(&existing, old, old).unwrap();
 
-        let metadata_after = fs::metadata(&path).expect("Failed to get metadata.");
-        let mod_time_after = metadata_after
-            .modified()
-            .expect("Failed to get modified time.");
+        let args = |paths: &[&PathBuf]| {
+            let mut args = vec![OsString::from("--exclusive")];
+            args.extend(paths.iter().map(|path| path.as_os_str().to_os_string()));
+            args
+        };
+        let err = run(args(&[&existing, &fresh]), Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            err.to_string(),
+            format!("Not creating {}: it already exists", existing.display())
+        );
+        assert!(fresh.exists());
+        assert_eq!(read_times(&existing).1, old);
 
-        assert!(
-            mod_time_after > mod_time_before,
-            "Modification time should be updated."
+        // Any other failure is not reported as a lost claim.
+        let missing = missing_parent_path("claim.txt");
+        let err = run(args(&[&existing, &missing]), Vec::new()).unwrap_err();
+        assert_ne!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        let mut conflicting = args(&[&fresh]);
+        conflicting.push(OsString::from("-c"));
+        assert!(run(conflicting, Vec::new())
+            .unwrap_err()
+            .to_string()
+            .contains("--exclusive cannot be combined with -c"));
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_run_mirror_from_copies_times_by_relative_path() {
+        let base = unique_temp_file();
+        let (source, copy) = (base.join("source"), base.join("copy"));
+        for root in [&source, &copy] {
+            fs::create_dir_all(root.join("sub")).unwrap();
+            fs::write(root.join("top.txt"), b"").unwrap();
+            fs::write(root.join("sub/deep.txt"), b"").unwrap();
+        }
+        fs::write(copy.join("extra.txt"), b"").unwrap();
+        let at = |seconds| FileTime::from_unix_time(seconds, 0);
+        set_file_times(source.join("top.txt"), at(1_000_000_000), at(1_000_000_100)).unwrap();
+        set_file_times(
+            source.join("sub/deep.txt"),
+            at(1_100_000_000),
+            at(1_100_000_100),
+        )
+        .unwrap();
+        set_file_times(copy.join("extra.txt"), at(900_000_000), at(900_000_000)).unwrap();
+
+        run(
+            vec![
+                OsString::from("-R"),
+                OsString::from("--mirror-from"),
+                source.clone().into_os_string(),
+                copy.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            read_times(&copy.join("top.txt")),
+            (at(1_000_000_000), at(1_000_000_100))
         );
+        assert_eq!(
+            read_times(&copy.join("sub/deep.txt")),
+            (at(1_100_000_000), at(1_100_000_100))
+        );
+        // A file with no counterpart is skipped, not failed.
+        assert_eq!(read_times(&copy.join("extra.txt")).1, at(900_000_000));
 
-        // Clean up
-        fs::remove_file(&path).expect("Failed to remove test file.");
+        // The mapping needs a single destination root.
+        let err = run(
+            vec![
+                OsString::from("--mirror-from"),
+                source.clone().into_os_string(),
+                copy.join("top.txt").into_os_string(),
+                copy.join("extra.txt").into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("exactly one destination operand"));
+        fs::remove_dir_all(&base).unwrap();
     }
 
     #[test]
-    fn test_help_message_contains_usage() {
-        let help = help_message();
+    fn test_run_lockfile_reports_the_holder() {
+        let path = unique_temp_file();
+        let args = || vec![OsString::from("--lockfile"), path.clone().into_os_string()];
+        run(args(), Vec::new()).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents.lines().next(),
+            Some(std::process::id().to_string().as_str())
+        );
+
+        let err = run(args(), Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
         assert!(
-            help.contains("Usage:"),
-            "Help message should contain 'Usage:'"
+            err.to_string().starts_with(&format!(
+                "Not locking {}: locked by process {}",
+                path.display(),
+                std::process::id()
+            )),
+            "{}",
+            err
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+        let mut stealing = args();
+        stealing.extend([OsString::from("--steal-stale"), OsString::from("1x")]);
+        assert!(run(stealing, Vec::new()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_filetime_round_trips_tick_for_tick() {
+        let path = unique_temp_file();
+        let ticks: u64 = 132_223_104_001_234_567;
+        run(
+            vec![
+                OsString::from("--filetime"),
+                OsString::from(ticks.to_string()),
+                path.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+
+        let (atime, mtime) = read_times(&path);
+        for time in [atime, mtime] {
+            let read_back = (time.unix_seconds() + 11_644_473_600) as u64 * 10_000_000
+                + u64::from(time.nanoseconds()) / 100;
+            assert_eq!(read_back, ticks);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_times_sets_each_slot() {
+        let path = unique_temp_file();
+        fs::write(&path, b"").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        set_file_times(&path, old, old).unwrap();
+
+        let times = |value: &str| {
+            run(
+                vec![
+                    OsString::from(format!("--times={}", value)),
+                    path.clone().into_os_string(),
+                ],
+                Vec::new(),
+            )
+        };
+        times("@1700000000,-,-").unwrap();
+        assert_eq!(
+            read_times(&path),
+            (FileTime::from_unix_time(1_700_000_000, 0), old)
+        );
+        times("-,@1600000000,").unwrap();
+        assert_eq!(
+            read_times(&path),
+            (
+                FileTime::from_unix_time(1_700_000_000, 0),
+                FileTime::from_unix_time(1_600_000_000, 0)
+            )
         );
+
+        let err = times("@1700000000,@1600000000").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = run(
+            vec![
+                OsString::from("--times=-,-,-"),
+                OsString::from("-m"),
+                path.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("--times"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_date_format() {
+        let path = unique_temp_file();
+        let touch = |extra: &[&str]| {
+            let mut args: Vec<OsString> = vec!["--date-format=%d.%m.%Y %H:%M".into()];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(path.clone().into_os_string());
+            run(args, Vec::new())
+        };
+        let expected = parse_date("2025-02-03 10:00", ParseContext::default()).unwrap();
+        touch(&["-d", "03.02.2025 10:00"]).unwrap();
+        assert_eq!(read_times(&path), (expected, expected));
+        touch(&["--times", "-,04.02.2025 10:00,-"]).unwrap();
+        assert_eq!(
+            read_times(&path).1.unix_seconds(),
+            expected.unix_seconds() + 86_400
+        );
+
+        let err = touch(&["-d", "2025-02-03 10:00"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
         assert!(
-            help.contains("-h"),
-            "Help message should mention '-h' option"
+            err.to_string().contains("--date-format '%d.%m.%Y %H:%M'"),
+            "{}",
+            err
         );
+        let err = touch(&[]).unwrap_err();
         assert!(
-            help.contains("-?"),
-            "Help message should mention '-?' option"
+            err.to_string().contains("requires -d or --times"),
+            "{}",
+            err
         );
+        let err = run(
+            vec!["--date-format=%d.%q".into(), path.clone().into_os_string()],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown specifier %q"), "{}", err);
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_run_no_args() {
-        let mut output = Vec::new();
-        let result = run(vec![], &mut output);
-        assert!(result.is_ok());
-        let output_str = String::from_utf8(output).unwrap();
-        assert!(output_str.contains("mdtouch"));
-        assert!(output_str.contains("A tool to update file timestamps"));
+    fn test_run_diff_trees_exit_status() {
+        let dir = unique_temp_file();
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        for root in [&first, &second] {
+            fs::create_dir_all(root).unwrap();
+            fs::write(root.join("file.txt"), b"").unwrap();
+            let time = FileTime::from_unix_time(1_700_000_000, 0);
+            set_file_times(root.join("file.txt"), time, time).unwrap();
+        }
+        let diff = |extra: &[&str]| {
+            let mut args = vec![OsString::from("--diff-trees"), OsString::from("--fuzz=1s")];
+            args.extend(extra.iter().map(OsString::from));
+            args.extend([
+                first.clone().into_os_string(),
+                second.clone().into_os_string(),
+            ]);
+            let mut output = Vec::new();
+            let result = run(args, &mut output);
+            (result, String::from_utf8(output).unwrap())
+        };
+
+        let (result, output) = diff(&[]);
+        assert!(result.is_ok(), "{}", output);
+        assert!(output.starts_with("1 identical, 0 different"), "{}", output);
+
+        fs::write(second.join("extra.txt"), b"").unwrap();
+        let (result, output) = diff(&["--json"]);
+        let err = result.unwrap_err();
+        assert!(err.to_string().is_empty());
+        assert!(
+            output.starts_with("{\"path\":\"extra.txt\",\"status\":\"only_in_second\"}"),
+            "{}",
+            output
+        );
+
+        let only_one = run(
+            vec![
+                OsString::from("--diff-trees"),
+                first.clone().into_os_string(),
+            ],
+            Vec::new(),
+        );
+        assert_eq!(
+            only_one.unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        let fuzz_alone = run(
+            vec![
+                OsString::from("--fuzz=2s"),
+                first.join("file.txt").into_os_string(),
+            ],
+            Vec::new(),
+        );
+        assert!(fuzz_alone.is_err());
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_run_help_arg() {
+    fn test_run_self_test() {
+        let dir = env::temp_dir().join(format!("mdtouch_self_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
         let mut output = Vec::new();
-        let result = run(vec!["-h".to_string()], &mut output);
-        assert!(result.is_ok());
-        let output_str = String::from_utf8(output).unwrap();
-        assert!(output_str.contains("Usage:"));
+        run(
+            vec![OsString::from("--self-test"), dir.clone().into_os_string()],
+            &mut output,
+        )
+        .unwrap();
+        let report = String::from_utf8(output).unwrap();
+        let field = |name: &str| {
+            report
+                .lines()
+                .find_map(|line| line.trim_start().strip_prefix(name))
+                .unwrap_or_else(|| panic!("no {} in {}", name, report))
+                .trim()
+                .to_string()
+        };
+        assert!(field("modification time:").starts_with("supported, kept to"));
+        assert!(field("access time:").starts_with("supported, kept to"));
+        assert!(!field("creation time:").is_empty());
+        assert!(!field("last-access updates:").is_empty());
+        assert_eq!(field("result:"), "modification times can be set");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        assert!(run(
+            vec![
+                OsString::from("--self-test"),
+                dir.join("missing").into_os_string()
+            ],
+            Vec::new()
+        )
+        .is_err());
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_run_touch_file() {
+    fn test_run_probe_touches_nothing() {
         let path = unique_temp_file();
-        let path_str = path.to_str().unwrap().to_string();
+        fs::write(&path, b"").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        set_file_times(&path, old, old).unwrap();
+
         let mut output = Vec::new();
+        run(
+            vec![
+                OsString::from("--probe"),
+                OsString::from("--json"),
+                path.clone().into_os_string(),
+            ],
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("{\"path\":"), "{}", output);
+        assert!(output.contains("\"filesystem\":"), "{}", output);
+        assert_eq!(output.lines().count(), 1);
+        assert_eq!(read_times(&path), (old, old));
 
-        let result = run(vec![path_str], &mut output);
-        assert!(result.is_ok());
-        assert!(path.exists());
+        assert!(run(vec![OsString::from("--probe")], Vec::new()).is_err());
+        assert!(run(
+            vec![OsString::from("--json"), path.clone().into_os_string()],
+            Vec::new()
+        )
+        .is_err());
+        fs::remove_file(&path).unwrap();
+    }
 
-        // Cleanup
-        fs::remove_file(path).unwrap();
+    #[test]
+    fn test_run_temp() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let temp = |extra: &[&str]| {
+            let mut args: Vec<OsString> = vec!["--temp".into()];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(dir.clone().into_os_string());
+            let mut output = Vec::new();
+            run(args, &mut output).map(|_| String::from_utf8(output).unwrap())
+        };
+        let output = temp(&[
+            "--prefix",
+            "nightly-",
+            "--suffix",
+            ".csv",
+            "-d",
+            "@1000000000",
+            "-v",
+        ])
+        .unwrap();
+        let path = PathBuf::from(output.strip_suffix('\n').unwrap());
+        assert!(!output.trim_end().contains('\n'), "{}", output);
+        assert!(path.is_absolute() && path.starts_with(normalize_path(&dir)));
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(
+            name.starts_with("nightly-") && name.ends_with(".csv"),
+            "{}",
+            name
+        );
+        let expected = FileTime::from_unix_time(1_000_000_000, 0);
+        assert_eq!(read_times(&path), (expected, expected));
+        let other = temp(&["--prefix", "nightly-", "--suffix", ".csv"]).unwrap();
+        assert_ne!(other, output);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let err = temp(&["-c"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = run(
+            vec![
+                "--suffix".into(),
+                ".log".into(),
+                dir.clone().into_os_string(),
+            ],
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("require --temp"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_run_error_handling() {
-        // We use a directory path which cannot be created as a file
-        // path variable removed as it was unused
-        let mut output = Vec::new();
+    fn test_run_tz() {
+        let path = unique_temp_file();
+        let touch_in = |zone: &str, date: &str| {
+            let args = vec![
+                OsString::from(format!("--tz={}", zone)),
+                "-d".into(),
+                date.into(),
+                path.clone().into_os_string(),
+            ];
+            run(args, Vec::new()).map(|_| read_times(&path).1)
+        };
+        let expected = parse_date("2024-02-03T15:30:00Z", ParseContext::default()).unwrap();
+        assert_eq!(touch_in("-05:00", "2024-02-03 10:30").unwrap(), expected);
+        // A zone in the date itself still wins.
+        assert_eq!(touch_in("+09:00", "2024-02-03 15:30Z").unwrap(), expected);
+        if cfg!(feature = "tzdb") && TimeZone::from_name("America/New_York").is_ok() {
+            assert_eq!(
+                touch_in("America/New_York", "2024-02-03 10:30").unwrap(),
+                expected
+            );
+            let summer = parse_date("2024-07-03T14:30:00Z", ParseContext::default()).unwrap();
+            assert_eq!(
+                touch_in("America/New_York", "2024-07-03 10:30").unwrap(),
+                summer
+            );
+        }
+        let err = touch_in("Mars/Olympus_Mons", "2024-02-03 10:30").unwrap_err();
+        assert!(err.to_string().contains("Mars/Olympus_Mons"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
 
-        // Trying to 'touch' an existing directory typically updates its timestamp on Unix,
-        // but OpenOptions(...).create(true).write(true).open(dir) fails on Windows with "Access is denied"
-        // or "is a directory" depending on the OS.
-        // Let's force an error by using a path with a non-existent parent directory.
-        // E.g. temp_dir / "non_existent_dir" / "file.txt"
+    #[test]
+    fn test_run_unique() {
+        let dir = unique_temp_file();
+        fs::create_dir(&dir).unwrap();
+        let report = dir.join("report.txt");
+        let unique = |extra: &[&str]| {
+            let mut args: Vec<OsString> = vec!["--unique".into()];
+            args.extend(extra.iter().map(OsString::from));
+            args.push(report.clone().into_os_string());
+            let mut output = Vec::new();
+            run(args, &mut output).map(|_| String::from_utf8(output).unwrap())
+        };
+        let names = ["report.txt", "report (2).txt", "report (3).txt"];
+        for name in names {
+            let output = unique(&["-d", "@1000000000"]).unwrap();
+            assert_eq!(output, format!("{}\n", dir.join(name).display()));
+        }
+        for name in names {
+            let expected = FileTime::from_unix_time(1_000_000_000, 0);
+            assert_eq!(read_times(&dir.join(name)), (expected, expected));
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
 
-        let mut bad_path = env::temp_dir();
-        bad_path.push("non_existent_dir_xyz_123");
-        bad_path.push("file.txt");
+        let output = unique(&["--unique-pattern", "{stem}_{n}{ext}"]).unwrap();
+        assert_eq!(output, format!("{}\n", dir.join("report_2.txt").display()));
+        let err = unique(&["--unique-pattern", "{stem}{ext}"]).unwrap_err();
+        assert!(err.to_string().contains("no {n}"), "{}", err);
+        let err = unique(&["--temp"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_save_and_restore_times_across_an_edit() {
+        let path = unique_temp_file();
+        fs::write(&path, b"before").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 500_000_000);
+        set_file_times(&path, old, old).unwrap();
+        let with = |option: &str| vec![OsString::from(option), path.clone().into_os_string()];
+
+        let mut output = Vec::new();
+        let mut args = with("--save-times");
+        args.insert(0, OsString::from("-v"));
+        run(args, &mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("saved times of "));
+        assert_eq!(read_times(&path), (old, old));
+        let err = run(with("--save-times"), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("already saved"), "{}", err);
 
-        let bad_path_str = bad_path.to_str().unwrap().to_string();
+        fs::write(&path, b"after").unwrap();
+        assert_ne!(read_times(&path).1, old);
+        run(with("--restore-times"), Vec::new()).unwrap();
+        assert_eq!(read_times(&path), (old, old));
+        assert!(!saved::sidecar(&path).exists());
+        let err = run(with("--restore-times"), Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("Error restoring times"), "{}", err);
 
-        let result = run(vec![bad_path_str], &mut output);
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err.to_string().contains("Error touching"));
+        let mut both = with("--save-times");
+        both.insert(0, OsString::from("--restore-times"));
+        assert!(run(both, Vec::new()).is_err());
+        fs::remove_file(&path).unwrap();
     }
 }
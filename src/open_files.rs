@@ -0,0 +1,201 @@
+//! Noticing files another process has open for writing (`--warn-if-open`).
+//!
+//! Times set on a file another process is writing to may not last: the next write, or the
+//! flush when it closes the file, sets the modification time again. Before such a file is
+//! touched, a warning names who has it open. Nothing more comes of it: the file is touched
+//! all the same, and a probe that fails is taken to have found nothing.
+//!
+//! On Linux the open files of every process are read from `/proc` once, when the
+//! [`OpenFiles`] snapshot is taken, and each file is then looked up in it by its device
+//! and inode; this names the process and tells writers from readers, though only for
+//! processes of the same user unless run as root. A file another process opens after the
+//! snapshot is not noticed. On Windows each file is opened without sharing it with
+//! anyone, which a sharing violation refuses if any other process has it open; that
+//! includes readers too, so the warning there says only that the file is in use.
+//! Elsewhere nothing is found.
+//!
+//! Only regular files are probed.
+
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// What is known of the files other processes have open for writing.
+#[derive(Debug, Default)]
+pub struct OpenFiles {
+    /// The processes writing to each file, keyed by its device and inode.
+    #[cfg(target_os = "linux")]
+    writers: HashMap<(u64, u64), Vec<u32>>,
+}
+
+impl OpenFiles {
+    /// Reads which files other processes have open for writing, where that can be read
+    /// all at once.
+    pub fn snapshot() -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            OpenFiles {
+                writers: read_writers(),
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            OpenFiles::default()
+        }
+    }
+
+    /// Who else has the file at `path` open for writing, such as `process 4242 (make)`,
+    /// if anyone can be found to.
+    pub fn open_for_writing(&self, path: &Path) -> Option<String> {
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        self.probe(path, &metadata)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn probe(&self, _path: &Path, metadata: &fs::Metadata) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let pids = self.writers.get(&(metadata.dev(), metadata.ino()))?;
+        Some(
+            pids.iter()
+                .map(|&pid| describe(pid))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+
+    #[cfg(windows)]
+    fn probe(&self, path: &Path, _metadata: &fs::Metadata) -> Option<String> {
+        use std::os::windows::fs::OpenOptionsExt;
+        use windows_sys::Win32::Foundation::ERROR_SHARING_VIOLATION;
+
+        // Sharing with nobody fails if anyone else has it open, reading or writing.
+        match fs::OpenOptions::new().read(true).share_mode(0).open(path) {
+            Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION as i32) => {
+                Some("another process (which may only be reading it)".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    fn probe(&self, _path: &Path, _metadata: &fs::Metadata) -> Option<String> {
+        None
+    }
+}
+
+/// The processes other than this one writing to each regular file, by its device and
+/// inode.
+#[cfg(target_os = "linux")]
+fn read_writers() -> HashMap<(u64, u64), Vec<u32>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let own = std::process::id();
+    let mut writers: HashMap<_, Vec<u32>> = HashMap::new();
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return writers;
+    };
+    for entry in processes.flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) if pid != own => pid,
+            _ => continue,
+        };
+        // Other users' processes can't be looked into.
+        let Ok(descriptors) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for descriptor in descriptors.flatten() {
+            // The descriptor's link leads to the open file itself, even one since renamed.
+            let Ok(open) = fs::metadata(descriptor.path()) else {
+                continue;
+            };
+            if !open.is_file() || !writes(pid, &descriptor.file_name().to_string_lossy()) {
+                continue;
+            }
+            let pids = writers.entry((open.dev(), open.ino())).or_default();
+            if !pids.contains(&pid) {
+                pids.push(pid);
+            }
+        }
+    }
+    writers
+}
+
+/// Whether process `pid` opened its descriptor `fd` for writing, as its flags in
+/// `/proc/PID/fdinfo/FD` say, in octal.
+#[cfg(target_os = "linux")]
+fn writes(pid: u32, fd: &str) -> bool {
+    let Ok(info) = fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, fd)) else {
+        return false;
+    };
+    info.lines()
+        .find_map(|line| line.strip_prefix("flags:"))
+        .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+        .is_some_and(|flags| matches!(flags & 0o3, 0o1 | 0o2))
+}
+
+/// Process `pid`, with its name if it can be read.
+#[cfg(target_os = "linux")]
+fn describe(pid: u32) -> String {
+    match fs::read_to_string(format!("/proc/{}/comm", pid)) {
+        Ok(name) => format!("process {} ({})", pid, name.trim_end()),
+        Err(_) => format!("process {}", pid),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_for_writing() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_open_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("log.txt");
+        fs::write(&path, b"").unwrap();
+        let open = OpenFiles::snapshot();
+        assert_eq!(open.open_for_writing(&path), None);
+        assert_eq!(open.open_for_writing(&dir), None);
+
+        // A child writing to it, as its standard output.
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .stdout(fs::File::options().append(true).open(&path).unwrap())
+            .spawn()
+            .unwrap();
+        let found = OpenFiles::snapshot().open_for_writing(&path);
+        // Found through its inode, so under another name too.
+        let renamed = dir.join("renamed.txt");
+        fs::rename(&path, &renamed).unwrap();
+        let found_renamed = OpenFiles::snapshot().open_for_writing(&renamed);
+        fs::rename(&renamed, &path).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let expected = Some(format!("process {} (sleep)", child.id()));
+        assert_eq!(found, expected, "{}", path.display());
+        assert_eq!(found_renamed, expected);
+        // The snapshot taken before the child started knows nothing of it.
+        assert_eq!(open.open_for_writing(&path), None);
+
+        // One only reading it is no matter.
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .stdin(fs::File::open(&path).unwrap())
+            .spawn()
+            .unwrap();
+        let found = OpenFiles::snapshot().open_for_writing(&path);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(found, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! Setting the owner of touched files (`--owner`).
+//!
+//! On Windows the owner is a SID, given either in its string form (`S-1-5-21-...`) or as an
+//! account name that `LookupAccountNameW` resolves. Making another account the owner needs
+//! `SeRestorePrivilege`, which elevated administrators hold but have disabled, so it is
+//! switched on first where the token has it. On Unix the owner is a user name or a numeric
+//! user ID, and changing it needs root.
+
+use std::io;
+use std::path::Path;
+
+/// An account resolved from the `--owner` argument, ready to be applied to files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Owner {
+    /// The account's SID, held in `u32` words so that it is suitably aligned.
+    #[cfg(windows)]
+    sid: Vec<u32>,
+    /// The account's user ID.
+    #[cfg(unix)]
+    uid: u32,
+}
+
+/// Resolves a SID string or account name to the account it names.
+#[cfg(windows)]
+pub fn resolve(name: &str) -> io::Result<Owner> {
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Authorization::ConvertStringSidToSidW;
+    use windows_sys::Win32::Security::{GetLengthSid, LookupAccountNameW, PSID};
+
+    let wide_name = to_wide(name.as_ref());
+    if name
+        .get(..4)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("S-1-"))
+    {
+        // SAFETY: the string is NUL-terminated, and the SID the call allocates is copied
+        // out before it is freed.
+        unsafe {
+            let mut sid: PSID = std::ptr::null_mut();
+            if ConvertStringSidToSidW(wide_name.as_ptr(), &mut sid) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let len = GetLengthSid(sid) as usize;
+            let mut words = vec![0u32; len.div_ceil(4)];
+            std::ptr::copy_nonoverlapping(sid.cast::<u8>(), words.as_mut_ptr().cast(), len);
+            LocalFree(sid);
+            return Ok(Owner { sid: words });
+        }
+    }
+
+    let (mut sid_len, mut domain_len, mut kind) = (0u32, 0u32, 0);
+    // SAFETY: the first call only reports the buffer sizes, and the second gets buffers of
+    // those sizes.
+    unsafe {
+        LookupAccountNameW(
+            std::ptr::null(),
+            wide_name.as_ptr(),
+            std::ptr::null_mut(),
+            &mut sid_len,
+            std::ptr::null_mut(),
+            &mut domain_len,
+            &mut kind,
+        );
+        if sid_len == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut words = vec![0u32; (sid_len as usize).div_ceil(4)];
+        let mut domain = vec![0u16; domain_len as usize];
+        if LookupAccountNameW(
+            std::ptr::null(),
+            wide_name.as_ptr(),
+            words.as_mut_ptr().cast(),
+            &mut sid_len,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut kind,
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Owner { sid: words })
+    }
+}
+
+/// Resolves a user name or numeric user ID to the account it names.
+#[cfg(unix)]
+pub fn resolve(name: &str) -> io::Result<Owner> {
+    if let Ok(uid) = name.parse() {
+        return Ok(Owner { uid });
+    }
+    let c_name = std::ffi::CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "name contains a NUL"))?;
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: every pointer refers to a live local of the expected type, and the
+        // buffer's length is passed along with it.
+        let (status, found) = unsafe {
+            let mut entry: libc::passwd = std::mem::zeroed();
+            let mut result = std::ptr::null_mut();
+            let status = libc::getpwnam_r(
+                c_name.as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            );
+            (status, (!result.is_null()).then_some(entry.pw_uid))
+        };
+        match (status, found) {
+            (libc::ERANGE, _) => buffer.resize(buffer.len() * 2, 0),
+            (0, Some(uid)) => return Ok(Owner { uid }),
+            (0, None) => {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no such user"));
+            }
+            (code, _) => return Err(io::Error::from_raw_os_error(code)),
+        }
+    }
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn resolve(_name: &str) -> io::Result<Owner> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file owners are not supported on this platform",
+    ))
+}
+
+/// Makes `owner` the owner of the file at `path`.
+#[cfg(windows)]
+pub fn set_owner(path: &Path, owner: &Owner) -> io::Result<()> {
+    use windows_sys::Win32::Foundation::{
+        ERROR_ACCESS_DENIED, ERROR_INVALID_OWNER, ERROR_PRIVILEGE_NOT_HELD,
+    };
+    use windows_sys::Win32::Security::Authorization::{SetNamedSecurityInfoW, SE_FILE_OBJECT};
+    use windows_sys::Win32::Security::OWNER_SECURITY_INFORMATION;
+
+    static PRIVILEGES: std::sync::Once = std::sync::Once::new();
+    PRIVILEGES.call_once(enable_owner_privileges);
+
+    let wide_path = to_wide(path.as_os_str());
+    // SAFETY: the path is NUL-terminated and the SID outlives the call, which only reads it.
+    let status = unsafe {
+        SetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            owner.sid.as_ptr() as *mut _,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    match status {
+        0 => Ok(()),
+        ERROR_ACCESS_DENIED | ERROR_INVALID_OWNER | ERROR_PRIVILEGE_NOT_HELD => {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "cannot set the owner: {} (need SeRestorePrivilege; run elevated)",
+                    io::Error::from_raw_os_error(status as i32)
+                ),
+            ))
+        }
+        _ => Err(io::Error::from_raw_os_error(status as i32)),
+    }
+}
+
+/// Makes `owner` the owner of the file at `path`.
+#[cfg(unix)]
+pub fn set_owner(path: &Path, owner: &Owner) -> io::Result<()> {
+    std::os::unix::fs::chown(path, Some(owner.uid), None).map_err(|e| {
+        if e.raw_os_error() == Some(libc::EPERM) {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("cannot set the owner: {} (need root or CAP_CHOWN)", e),
+            )
+        } else {
+            e
+        }
+    })
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn set_owner(_path: &Path, _owner: &Owner) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file owners are not supported on this platform",
+    ))
+}
+
+/// Switches on the privileges that let an owner other than the caller be set. Failure is
+/// ignored: `SetNamedSecurityInfoW` then reports what is missing.
+#[cfg(windows)]
+pub fn enable_owner_privileges() {
+    use windows_sys::Win32::Foundation::{CloseHandle, LUID};
+    use windows_sys::Win32::Security::{
+        AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED,
+        SE_RESTORE_NAME, SE_TAKE_OWNERSHIP_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    // SAFETY: every pointer refers to a live local of the expected type, and the token
+    // handle is closed before returning.
+    unsafe {
+        let mut token = std::ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES, &mut token) == 0 {
+            return;
+        }
+        for name in [SE_RESTORE_NAME, SE_TAKE_OWNERSHIP_NAME] {
+            let mut luid: LUID = std::mem::zeroed();
+            if LookupPrivilegeValueW(std::ptr::null(), name, &mut luid) == 0 {
+                continue;
+            }
+            let privileges = TOKEN_PRIVILEGES {
+                PrivilegeCount: 1,
+                Privileges: [LUID_AND_ATTRIBUTES {
+                    Luid: luid,
+                    Attributes: SE_PRIVILEGE_ENABLED,
+                }],
+            };
+            AdjustTokenPrivileges(
+                token,
+                0,
+                &privileges,
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+        }
+        CloseHandle(token);
+    }
+}
+
+/// Converts a string to the NUL-terminated UTF-16 form Windows APIs take.
+#[cfg(windows)]
+fn to_wide(text: &std::ffi::OsStr) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    text.encode_wide().chain(std::iter::once(0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("mdtouch_owner_{}_{}", name, std::process::id()));
+        fs::write(&path, b"").unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_owner_to_current_user() {
+        use std::os::unix::fs::MetadataExt;
+
+        // SAFETY: geteuid has no preconditions.
+        let uid = unsafe { libc::geteuid() };
+        let path = scratch("current");
+        let owner = resolve(&uid.to_string()).unwrap();
+        set_owner(&path, &owner).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().uid(), uid);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_names() {
+        assert_eq!(resolve("root").unwrap(), Owner { uid: 0 });
+        assert_eq!(resolve("1234").unwrap(), Owner { uid: 1234 });
+        let err = resolve("mdtouch-no-such-user").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_set_owner_to_current_user() {
+        use windows_sys::Win32::Foundation::LocalFree;
+        use windows_sys::Win32::Security::Authorization::{GetNamedSecurityInfoW, SE_FILE_OBJECT};
+        use windows_sys::Win32::Security::{EqualSid, OWNER_SECURITY_INFORMATION};
+
+        let user = std::env::var("USERNAME").unwrap();
+        let path = scratch("current");
+        let owner = resolve(&user).unwrap();
+        set_owner(&path, &owner).unwrap();
+
+        let wide_path = to_wide(path.as_os_str());
+        // SAFETY: the output pointers are live locals, and the descriptor the call
+        // allocates (which the owner SID points into) is freed after the comparison.
+        unsafe {
+            let mut sid = std::ptr::null_mut();
+            let mut descriptor = std::ptr::null_mut();
+            let status = GetNamedSecurityInfoW(
+                wide_path.as_ptr(),
+                SE_FILE_OBJECT,
+                OWNER_SECURITY_INFORMATION,
+                &mut sid,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut descriptor,
+            );
+            assert_eq!(status, 0);
+            assert_ne!(EqualSid(sid, owner.sid.as_ptr() as *mut _), 0);
+            LocalFree(descriptor);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_resolve_sid_string() {
+        // The well-known SID of the local Administrators group.
+        assert!(resolve("S-1-5-32-544").is_ok());
+        assert!(resolve("S-1-bogus").is_err());
+        assert!(resolve("mdtouch-no-such-account").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+//! Reading the link timestamp embedded in a Windows PE image (`--reference-pe`).
+//!
+//! The DOS header's `e_lfanew` field, at offset 0x3C, gives the offset of the `PE\0\0`
+//! signature. The COFF file header follows it, and its `TimeDateStamp` field, four bytes
+//! in, holds the link time as seconds since the Unix epoch.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use mdtouch::FileTime;
+
+/// Offset of `e_lfanew` in the DOS header.
+const PE_OFFSET_FIELD: u64 = 0x3C;
+
+/// Reads the link timestamp of the PE image at `path`.
+pub fn read_timestamp(path: &Path) -> io::Result<FileTime> {
+    let mut file = File::open(path)?;
+    let seconds = timestamp(&mut file).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => invalid(&format!("not a valid PE image: {}", e)),
+        io::ErrorKind::UnexpectedEof => invalid("not a valid PE image: headers are truncated"),
+        _ => e,
+    })?;
+    Ok(FileTime::from_unix_time(i64::from(seconds), 0))
+}
+
+/// Reads the COFF `TimeDateStamp` from a PE image.
+fn timestamp<R: Read + Seek>(image: &mut R) -> io::Result<u32> {
+    let mut magic = [0u8; 2];
+    image.read_exact(&mut magic)?;
+    if &magic != b"MZ" {
+        return Err(invalid("missing MZ signature"));
+    }
+    image.seek(SeekFrom::Start(PE_OFFSET_FIELD))?;
+    let pe_offset = read_u32(image)?;
+
+    image.seek(SeekFrom::Start(u64::from(pe_offset)))?;
+    let mut signature = [0u8; 4];
+    image.read_exact(&mut signature)?;
+    if &signature != b"PE\0\0" {
+        return Err(invalid("missing PE signature"));
+    }
+    // Skip Machine and NumberOfSections.
+    image.seek(SeekFrom::Current(4))?;
+    read_u32(image)
+}
+
+/// Reads a little-endian `u32`.
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Returns the error for an image whose headers are malformed.
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Builds the headers of a minimal PE image with the given link timestamp.
+    fn image(stamp: u32) -> Vec<u8> {
+        let mut bytes = vec![0u8; 0x80];
+        bytes[..2].copy_from_slice(b"MZ");
+        bytes[0x3C..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        bytes[0x40..0x44].copy_from_slice(b"PE\0\0");
+        bytes[0x44..0x46].copy_from_slice(&0x8664u16.to_le_bytes());
+        bytes[0x48..0x4C].copy_from_slice(&stamp.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_timestamp_reads_coff_header() {
+        let mut cursor = Cursor::new(image(0x6543_2100));
+        assert_eq!(timestamp(&mut cursor).unwrap(), 0x6543_2100);
+    }
+
+    #[test]
+    fn test_timestamp_rejects_non_pe_data() {
+        let mut not_mz = image(1);
+        not_mz[0] = b'X';
+        let mut not_pe = image(1);
+        not_pe[0x40] = b'X';
+        let mut bad_offset = image(1);
+        bad_offset[0x3C..0x40].copy_from_slice(&0xFFFFu32.to_le_bytes());
+
+        for bytes in [not_mz, not_pe, bad_offset, b"MZ".to_vec(), Vec::new()] {
+            let err = timestamp(&mut Cursor::new(bytes)).unwrap_err();
+            assert!(matches!(
+                err.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_timestamp_from_file() {
+        let path = std::env::temp_dir().join(format!("mdtouch_pe_{}.txt", std::process::id()));
+        std::fs::write(&path, b"just text").unwrap();
+        let err = read_timestamp(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("not a valid PE image"));
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, image(1_700_000_000)).unwrap();
+        assert_eq!(
+            read_timestamp(&path).unwrap(),
+            FileTime::from_unix_time(1_700_000_000, 0)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
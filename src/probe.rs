@@ -0,0 +1,342 @@
+//! Reporting how a file's volume keeps timestamps (`--probe`).
+//!
+//! A probe reads, and never changes, three things that explain most surprises with
+//! timestamps: the filesystem the file lives on, the resolution that filesystem stores
+//! times at (so a time set to the nanosecond may read back rounded), and whether access
+//! times are updated at all. On Windows the last is the system-wide
+//! `NtfsDisableLastAccessUpdate` setting; on Linux it is the mount's `atime` option.
+//! The resolution is the one the filesystem's on-disk format allows, from a table of
+//! common filesystems.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use mdtouch::normalize_path;
+
+use crate::audit::json_string;
+
+/// What a probe found out about one file's volume. Anything that could not be found
+/// out is `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    /// The file probed, as given.
+    pub path: PathBuf,
+    /// The filesystem's name, such as `NTFS` or `ext4`.
+    pub filesystem: Option<String>,
+    /// How finely modification and access times are stored, in nanoseconds.
+    pub resolution: Option<(u64, u64)>,
+    /// Whether reading a file updates its access time.
+    pub last_access: Option<String>,
+}
+
+/// Probes the volume holding `path`. A path that does not exist yet is probed through
+/// the nearest directory above it that does.
+pub fn probe(path: &Path) -> io::Result<Probe> {
+    let absolute = normalize_path(path);
+    let existing = absolute
+        .ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file or directory"))?;
+    let (filesystem, last_access) = volume_details(existing);
+    Ok(Probe {
+        path: path.to_path_buf(),
+        resolution: filesystem.as_deref().and_then(resolution_of),
+        filesystem,
+        last_access,
+    })
+}
+
+impl Probe {
+    /// Formats the probe for reading.
+    pub fn to_text(&self) -> String {
+        let unknown = || "unknown".to_string();
+        let resolution = match self.resolution {
+            Some((modified, accessed)) if modified == accessed => format_duration(modified),
+            Some((modified, accessed)) => format!(
+                "{} (access times: {})",
+                format_duration(modified),
+                format_duration(accessed)
+            ),
+            None => unknown(),
+        };
+        format!(
+            "{}\n  filesystem:           {}\n  timestamp resolution: {}\n  last-access updates:  {}\n",
+            self.path.display(),
+            self.filesystem.clone().unwrap_or_else(unknown),
+            resolution,
+            self.last_access.clone().unwrap_or_else(unknown),
+        )
+    }
+
+    /// Formats the probe as one line of JSON, with `null` for anything unknown.
+    pub fn to_json(&self) -> String {
+        let text = |value: &Option<String>| match value {
+            Some(value) => json_string(value),
+            None => "null".to_string(),
+        };
+        let number = |value: Option<u64>| match value {
+            Some(value) => value.to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"path\":{},\"filesystem\":{},\"modified_resolution_ns\":{},\"accessed_resolution_ns\":{},\"last_access_updates\":{}}}\n",
+            json_string(&self.path.to_string_lossy()),
+            text(&self.filesystem),
+            number(self.resolution.map(|resolution| resolution.0)),
+            number(self.resolution.map(|resolution| resolution.1)),
+            text(&self.last_access),
+        )
+    }
+}
+
+/// The resolution of modification and access times on a filesystem, in nanoseconds.
+pub fn resolution_of(filesystem: &str) -> Option<(u64, u64)> {
+    const SECOND: u64 = 1_000_000_000;
+    match filesystem.to_ascii_lowercase().as_str() {
+        "ntfs" | "ntfs3" | "refs" => Some((100, 100)),
+        // FAT keeps modification times to 2 seconds and only the date of the last access.
+        "fat" | "fat12" | "fat16" | "fat32" | "vfat" | "msdos" => {
+            Some((2 * SECOND, 86_400 * SECOND))
+        }
+        "exfat" => Some((10_000_000, 2 * SECOND)),
+        "ext4" | "xfs" | "btrfs" | "tmpfs" | "zfs" | "f2fs" | "apfs" => Some((1, 1)),
+        "ext2" | "ext3" | "hfs" => Some((SECOND, SECOND)),
+        _ => None,
+    }
+}
+
+/// Formats a duration given in nanoseconds in its largest whole unit.
+pub fn format_duration(nanos: u64) -> String {
+    const UNITS: [(u64, &str); 5] = [
+        (86_400_000_000_000, " day"),
+        (1_000_000_000, " s"),
+        (1_000_000, " ms"),
+        (1_000, " us"),
+        (1, " ns"),
+    ];
+    let (size, unit) = UNITS
+        .into_iter()
+        .find(|&(size, _)| nanos.is_multiple_of(size))
+        .unwrap_or((1, " ns"));
+    format!("{}{}", nanos / size, unit)
+}
+
+/// Reads the filesystem name and the last-access setting for the volume holding the
+/// existing path `path`.
+#[cfg(windows)]
+fn volume_details(path: &Path) -> (Option<String>, Option<String>) {
+    (volume_filesystem(path), last_access_setting())
+}
+
+/// The name of the filesystem on the volume holding `path`.
+#[cfg(windows)]
+fn volume_filesystem(path: &Path) -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW};
+
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut root = vec![0u16; wide_path.len().max(261)];
+    let mut name = [0u16; 261];
+    // SAFETY: both strings are NUL-terminated, and every buffer's length is passed along
+    // with it.
+    unsafe {
+        if GetVolumePathNameW(wide_path.as_ptr(), root.as_mut_ptr(), root.len() as u32) == 0
+            || GetVolumeInformationW(
+                root.as_ptr(),
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                name.as_mut_ptr(),
+                name.len() as u32,
+            ) == 0
+        {
+            return None;
+        }
+    }
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    Some(String::from_utf16_lossy(&name[..len]))
+}
+
+/// Describes the system-wide `NtfsDisableLastAccessUpdate` setting.
+#[cfg(windows)]
+fn last_access_setting() -> Option<String> {
+    use windows_sys::Win32::System::Registry::{
+        RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD,
+    };
+
+    let wide = |text: &str| -> Vec<u16> { text.encode_utf16().chain(Some(0)).collect() };
+    let key = wide(r"SYSTEM\CurrentControlSet\Control\FileSystem");
+    let value_name = wide("NtfsDisableLastAccessUpdate");
+    let mut value = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    // SAFETY: the names are NUL-terminated and the value buffer is a DWORD whose size is
+    // passed along with it.
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key.as_ptr(),
+            value_name.as_ptr(),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            (&mut value as *mut u32).cast(),
+            &mut size,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    // The high bit says whether Windows manages the setting itself, by volume size.
+    let managed = if value & 0x8000_0000 != 0 {
+        "system managed"
+    } else {
+        "user managed"
+    };
+    let state = if value & 1 != 0 {
+        "disabled"
+    } else {
+        "enabled"
+    };
+    Some(format!("{} ({})", state, managed))
+}
+
+/// Reads the filesystem type and `atime` mount option for the mount holding `path`.
+#[cfg(target_os = "linux")]
+fn volume_details(path: &Path) -> (Option<String>, Option<String>) {
+    let Ok(path) = path.canonicalize() else {
+        return (None, None);
+    };
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return (None, None);
+    };
+    match find_mount(&mounts, &path) {
+        Some((filesystem, options)) => (Some(filesystem), Some(atime_mode(&options))),
+        None => (None, None),
+    }
+}
+
+/// Finds the mount holding `path` in the contents of `/proc/self/mountinfo`, returning
+/// its filesystem type and mount options.
+#[cfg(target_os = "linux")]
+pub fn find_mount(mounts: &str, path: &Path) -> Option<(String, String)> {
+    let mut best: Option<(PathBuf, String, String)> = None;
+    for line in mounts.lines() {
+        // id parent major:minor root mount-point options [optional...] - type source super
+        let Some((before, after)) = line.split_once(" - ") else {
+            continue;
+        };
+        let fields: Vec<&str> = before.split(' ').collect();
+        let (Some(mount_point), Some(options)) = (fields.get(4), fields.get(5)) else {
+            continue;
+        };
+        let mount_point = PathBuf::from(unescape_mount_field(mount_point));
+        let Some(filesystem) = after.split(' ').next() else {
+            continue;
+        };
+        let longer = best
+            .as_ref()
+            .is_none_or(|(found, _, _)| mount_point.as_os_str().len() >= found.as_os_str().len());
+        if path.starts_with(&mount_point) && longer {
+            best = Some((mount_point, filesystem.to_string(), options.to_string()));
+        }
+    }
+    best.map(|(_, filesystem, options)| (filesystem, options))
+}
+
+/// Decodes the octal escapes (`\040` for a space) in a mountinfo field.
+#[cfg(target_os = "linux")]
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+        match escape
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok())
+        {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 4;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Describes how a mount with the given options updates access times.
+#[cfg(target_os = "linux")]
+fn atime_mode(options: &str) -> String {
+    let options: Vec<&str> = options.split(',').collect();
+    if options.contains(&"noatime") {
+        "disabled for this mount (noatime)".to_string()
+    } else if options.contains(&"relatime") {
+        "only when older than the modification time or a day old (relatime)".to_string()
+    } else {
+        "enabled for this mount (strictatime)".to_string()
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn volume_details(_path: &Path) -> (Option<String>, Option<String>) {
+    (None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_missing_file_uses_its_directory() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join(format!("mdtouch_probe_{}.tmp", std::process::id()));
+        let probe = probe(&missing).unwrap();
+        assert_eq!(probe.path, missing);
+        assert!(!missing.exists());
+        #[cfg(any(windows, target_os = "linux"))]
+        assert!(probe.filesystem.is_some());
+    }
+
+    #[test]
+    fn test_formats() {
+        let probe = Probe {
+            path: PathBuf::from("stick/photo.jpg"),
+            filesystem: Some("vfat".to_string()),
+            resolution: resolution_of("vfat"),
+            last_access: None,
+        };
+        assert_eq!(
+            probe.to_text(),
+            "stick/photo.jpg\n  filesystem:           vfat\n  timestamp resolution: 2 s (access times: 1 day)\n  last-access updates:  unknown\n"
+        );
+        assert_eq!(
+            probe.to_json(),
+            "{\"path\":\"stick/photo.jpg\",\"filesystem\":\"vfat\",\"modified_resolution_ns\":2000000000,\"accessed_resolution_ns\":86400000000000,\"last_access_updates\":null}\n"
+        );
+        assert_eq!(format_duration(100), "100 ns");
+        assert_eq!(format_duration(10_000_000), "10 ms");
+        assert_eq!(resolution_of("NTFS"), Some((100, 100)));
+        assert_eq!(resolution_of("fuse.sshfs"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_find_mount_takes_the_deepest() {
+        let mounts = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+40 22 0:35 / /mnt/usb\\040stick rw,noatime - vfat /dev/sdb1 rw
+41 22 0:36 / /mnt/usb rw - tmpfs tmpfs rw
+";
+        let found = |path: &str| find_mount(mounts, Path::new(path)).unwrap();
+        assert_eq!(found("/home/a.txt").0, "ext4");
+        assert_eq!(found("/mnt/usb stick/a.jpg").0, "vfat");
+        assert_eq!(found("/mnt/usb/a.jpg").0, "tmpfs");
+        assert_eq!(atime_mode(&found("/home/a.txt").1), atime_mode("relatime"));
+        assert!(atime_mode("rw,noatime").contains("noatime"));
+        assert!(atime_mode("rw").contains("strictatime"));
+    }
+}
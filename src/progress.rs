@@ -0,0 +1,164 @@
+//! The progress counter on standard error (`--progress`).
+//!
+//! The counter reads `processed X/Y`, or just `processed X` when the operands come from
+//! a list or a recursive walk and their number isn't known up front. It is updated a
+//! few times a second at most, however fast files go by, and once more at the end,
+//! followed by a summary line. On a terminal `--progress=line` rewrites the counter in
+//! place; anywhere else, and with `--progress=lines`, every update is a line of its own.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How the counter is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressStyle {
+    /// A new line for every update (`--progress`, `--progress=lines`).
+    Lines,
+    /// One line rewritten in place on a terminal (`--progress=line`).
+    Line,
+}
+
+impl ProgressStyle {
+    /// Parses the value of `--progress=STYLE`.
+    pub fn parse(value: &str) -> io::Result<Self> {
+        match value {
+            "lines" => Ok(ProgressStyle::Lines),
+            "line" => Ok(ProgressStyle::Line),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --progress style '{}' (expected line or lines)",
+                    value
+                ),
+            )),
+        }
+    }
+}
+
+/// What became of one operand, as far as the summary is concerned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tally {
+    Touched,
+    Skipped,
+    Failed,
+}
+
+/// A running count of the operands processed.
+#[derive(Debug)]
+pub struct Progress<W: Write> {
+    out: W,
+    in_place: bool,
+    total: Option<usize>,
+    counts: [usize; 3],
+    last_shown: Option<Instant>,
+}
+
+impl<W: Write> Progress<W> {
+    /// Starts counting towards `total`, if known, writing to `out`. The counter is only
+    /// rewritten in place with [`ProgressStyle::Line`] when `out` is a terminal.
+    pub fn new(style: ProgressStyle, total: Option<usize>, out: W, terminal: bool) -> Self {
+        Progress {
+            out,
+            in_place: style == ProgressStyle::Line && terminal,
+            total,
+            counts: [0; 3],
+            last_shown: None,
+        }
+    }
+
+    /// Counts one more operand, and shows the count if it hasn't been shown lately.
+    pub fn record(&mut self, tally: Tally) -> io::Result<()> {
+        self.counts[tally as usize] += 1;
+        let interval = if self.in_place {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_secs(1)
+        };
+        if self
+            .last_shown
+            .is_none_or(|shown| shown.elapsed() >= interval)
+        {
+            self.last_shown = Some(Instant::now());
+            self.show()?;
+        }
+        Ok(())
+    }
+
+    /// Shows the final count, then the summary on a line of its own.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.show()?;
+        if self.in_place {
+            writeln!(self.out)?;
+        }
+        let [touched, skipped, failed] = self.counts;
+        writeln!(
+            self.out,
+            "done: {} touched, {} skipped, {} failed",
+            touched, skipped, failed
+        )?;
+        self.out.flush()
+    }
+
+    /// Writes the counter.
+    fn show(&mut self) -> io::Result<()> {
+        let processed: usize = self.counts.iter().sum();
+        let count = match self.total {
+            Some(total) => format!("processed {}/{}", processed, total),
+            None => format!("processed {}", processed),
+        };
+        if self.in_place {
+            // The count only grows, so each rewrite covers the one before it.
+            write!(self.out, "\r{}", count)?;
+        } else {
+            writeln!(self.out, "{}", count)?;
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_three(style: ProgressStyle, total: Option<usize>, terminal: bool) -> String {
+        let mut out = Vec::new();
+        let mut progress = Progress::new(style, total, &mut out, terminal);
+        for tally in [Tally::Touched, Tally::Skipped, Tally::Failed] {
+            progress.record(tally).unwrap();
+        }
+        progress.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_lines_style_writes_whole_lines() {
+        assert_eq!(
+            count_three(ProgressStyle::Lines, Some(3), true),
+            "processed 1/3\nprocessed 3/3\ndone: 1 touched, 1 skipped, 1 failed\n"
+        );
+        assert_eq!(
+            count_three(ProgressStyle::Lines, None, false),
+            "processed 1\nprocessed 3\ndone: 1 touched, 1 skipped, 1 failed\n"
+        );
+    }
+
+    #[test]
+    fn test_line_style_rewrites_in_place_on_a_terminal_only() {
+        // The summary follows the counter on a line of its own.
+        assert_eq!(
+            count_three(ProgressStyle::Line, Some(3), true),
+            "\rprocessed 1/3\rprocessed 3/3\ndone: 1 touched, 1 skipped, 1 failed\n"
+        );
+        assert_eq!(
+            count_three(ProgressStyle::Line, Some(3), false),
+            count_three(ProgressStyle::Lines, Some(3), false)
+        );
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(ProgressStyle::parse("line").unwrap(), ProgressStyle::Line);
+        assert_eq!(ProgressStyle::parse("lines").unwrap(), ProgressStyle::Lines);
+        assert!(ProgressStyle::parse("bar").is_err());
+    }
+}
@@ -0,0 +1,594 @@
+//! Regular expressions for selecting paths (`--regex`).
+//!
+//! The syntax is the common core of Perl-style expressions: literals, `.`, classes such as
+//! `[a-z_]` or `[^0-9]`, the escapes `\d`, `\w`, `\s` and their negations `\D`, `\W` and
+//! `\S` (ASCII only), anchors `^` and `$`, groups `(...)` and `(?:...)`, alternation `|`,
+//! and the repetitions `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`. A pattern matches if it
+//! matches anywhere in the text, so anchor it to match the whole text.
+//!
+//! Patterns are compiled to a small program run as a Thompson NFA, so matching takes time
+//! in proportion to the length of the text whatever the pattern, with no backtracking.
+
+use std::fmt;
+use std::io;
+
+/// The most a counted repetition may repeat, to keep programs small.
+const MAX_REPEAT: u32 = 1000;
+
+/// A compiled regular expression.
+#[derive(Clone)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+/// A set of characters, as inclusive ranges.
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn of(ranges: &[(char, char)], negated: bool) -> Self {
+        Class {
+            negated,
+            ranges: ranges.to_vec(),
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(low, high)| low <= c && c <= high) != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+/// The parsed form of a pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Literal(char),
+    /// `.`: any character but a newline.
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+/// One instruction of a compiled program.
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Carry on at both targets.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    /// Compiles `pattern`, failing with a message that says what is wrong and where.
+    pub fn new(pattern: &str) -> io::Result<Self> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            position: 0,
+        };
+        let node = parser
+            .alternation()
+            .and_then(|node| match parser.peek() {
+                Some(')') => Err(parser.error("unopened group")),
+                _ => Ok(node),
+            })
+            .map_err(|(position, reason)| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid --regex '{}': {} (at character {})",
+                        pattern,
+                        reason,
+                        position + 1
+                    ),
+                )
+            })?;
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program,
+        })
+    }
+
+    /// Returns whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for position in 0..=chars.len() {
+            // A match may start anywhere, so a new thread starts at every position.
+            if self.add(&mut current, 0, position, chars.len()) {
+                return true;
+            }
+            let Some(&c) = chars.get(position) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current.list {
+                let accepts = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => c != '\n',
+                    Inst::Class(class) => class.contains(c),
+                    _ => false,
+                };
+                if accepts && self.add(&mut next, pc + 1, position + 1, chars.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Adds the thread at `pc` to `threads`, following jumps and assertions at once.
+    /// Returns whether it reached a match.
+    fn add(&self, threads: &mut Threads, pc: usize, position: usize, len: usize) -> bool {
+        if !threads.insert(pc) {
+            return false;
+        }
+        match self.program[pc] {
+            Inst::Match => true,
+            Inst::Jump(target) => self.add(threads, target, position, len),
+            Inst::Split(first, second) => {
+                self.add(threads, first, position, len) || self.add(threads, second, position, len)
+            }
+            Inst::Start => position == 0 && self.add(threads, pc + 1, position, len),
+            Inst::End => position == len && self.add(threads, pc + 1, position, len),
+            _ => {
+                threads.list.push(pc);
+                false
+            }
+        }
+    }
+}
+
+/// The threads alive at one position: those waiting on a character, in order, and every
+/// instruction already visited there.
+struct Threads {
+    list: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Threads {
+            list: Vec::new(),
+            seen: vec![false; size],
+        }
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        !std::mem::replace(&mut self.seen[pc], true)
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+/// Appends the instructions for `node` to `program`.
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Literal(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+        Node::Alternate(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 == branches.len() {
+                    compile(branch, program);
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(branch, program);
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where a pattern went wrong, and why.
+type ParseResult<T> = Result<T, (usize, String)>;
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        self.position += usize::from(found);
+        found
+    }
+
+    fn error(&self, reason: &str) -> (usize, String) {
+        (self.position.min(self.chars.len()), reason.to_string())
+    }
+
+    /// Branches separated by `|`, up to the end or a closing `)`.
+    fn alternation(&mut self) -> ParseResult<Node> {
+        let mut branches = vec![self.concatenation()?];
+        while self.eat('|') {
+            branches.push(self.concatenation()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap_or(Node::Empty),
+            _ => Node::Alternate(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> ParseResult<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repetitions(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap_or(Node::Empty),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> ParseResult<Node> {
+        let start = self.position;
+        let c = self.next().ok_or_else(|| self.error("unexpected end"))?;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err(self.error("unsupported group flag; only (?:...) is known"));
+                }
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return Err((start, "unclosed group".to_string()));
+                }
+                node
+            }
+            '[' => Node::Class(self.class(start)?),
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => return Err((start, "repetition with nothing to repeat".into())),
+            '{' => {
+                self.position = start;
+                if self.counted().is_some() {
+                    return Err((start, "repetition with nothing to repeat".into()));
+                }
+                self.position = start + 1;
+                Node::Literal('{')
+            }
+            c => Node::Literal(c),
+        })
+    }
+
+    /// Applies any repetitions following `atom`.
+    fn repetitions(&mut self, mut atom: Node) -> ParseResult<Node> {
+        let mut repeated = false;
+        loop {
+            let start = self.position;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.counted() {
+                    // Already read past the closing brace.
+                    Some(Ok((min, max))) => (min, max),
+                    Some(Err(reason)) => return Err((start, reason)),
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+            if self.position == start {
+                self.position += 1;
+            }
+            if repeated || matches!(atom, Node::Start | Node::End) {
+                return Err((start, "repetition of something that cannot repeat".into()));
+            }
+            // A lazy repetition matches the same texts.
+            self.eat('?');
+            repeated = true;
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+            };
+        }
+    }
+
+    /// Reads a counted repetition such as `{2,5}` at the current position, consuming it.
+    /// Returns `None`, consuming nothing, when the brace does not start one: then it is a
+    /// literal `{`.
+    #[allow(clippy::type_complexity)]
+    fn counted(&mut self) -> Option<Result<(u32, Option<u32>), String>> {
+        let rest: String = self.chars[self.position..].iter().collect();
+        let body = rest.strip_prefix('{')?.split_once('}')?.0.to_string();
+        let number = |text: &str| -> Option<u32> {
+            (!text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| text.parse().unwrap_or(u32::MAX))
+        };
+        let (min, max) = match body.split_once(',') {
+            None => (number(&body)?, number(&body)),
+            Some((min, "")) => (number(min)?, None),
+            Some((min, max)) => (number(min)?, Some(number(max)?)),
+        };
+        self.position += body.chars().count() + 2;
+        Some(if max.is_some_and(|max| max < min) {
+            Err("repetition range is backwards".into())
+        } else if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            Err(format!("repetition count over {}", MAX_REPEAT))
+        } else {
+            Ok((min, max))
+        })
+    }
+
+    /// Reads an escape after `\`, outside a class.
+    fn escape(&mut self) -> ParseResult<Node> {
+        match self.class_escape()? {
+            Escaped::Char(c) => Ok(Node::Literal(c)),
+            Escaped::Class(class) => Ok(Node::Class(class)),
+        }
+    }
+
+    fn class_escape(&mut self) -> ParseResult<Escaped> {
+        let c = self
+            .next()
+            .ok_or_else(|| self.error("pattern ends with a lone backslash"))?;
+        Ok(match c {
+            'd' => Escaped::Class(Class::of(DIGIT, false)),
+            'D' => Escaped::Class(Class::of(DIGIT, true)),
+            'w' => Escaped::Class(Class::of(WORD, false)),
+            'W' => Escaped::Class(Class::of(WORD, true)),
+            's' => Escaped::Class(Class::of(SPACE, false)),
+            'S' => Escaped::Class(Class::of(SPACE, true)),
+            'n' => Escaped::Char('\n'),
+            't' => Escaped::Char('\t'),
+            'r' => Escaped::Char('\r'),
+            c if c.is_ascii_alphanumeric() => {
+                self.position -= 1;
+                return Err(self.error(&format!("unknown escape \\{}", c)));
+            }
+            c => Escaped::Char(c),
+        })
+    }
+
+    /// Reads a class after `[` up to its `]`.
+    fn class(&mut self, start: usize) -> ParseResult<Class> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self
+                .next()
+                .ok_or_else(|| (start, "unclosed character class".to_string()))?;
+            // A `]` first in the class stands for itself.
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = match c {
+                '\\' => match self.class_escape()? {
+                    Escaped::Char(c) => c,
+                    Escaped::Class(class) => {
+                        ranges.extend(complemented(&class));
+                        continue;
+                    }
+                },
+                c => c,
+            };
+            if self.peek() != Some('-') || self.chars.get(self.position + 1) == Some(&']') {
+                ranges.push((low, low));
+                continue;
+            }
+            self.position += 1;
+            let range_start = self.position;
+            let high = match self.next() {
+                Some('\\') => match self.class_escape()? {
+                    Escaped::Char(c) => c,
+                    Escaped::Class(_) => {
+                        return Err((range_start, "class escape cannot end a range".into()))
+                    }
+                },
+                Some(c) => c,
+                None => return Err((start, "unclosed character class".into())),
+            };
+            if high < low {
+                return Err((range_start, "class range is backwards".into()));
+            }
+            ranges.push((low, high));
+        }
+        Ok(Class { negated, ranges })
+    }
+}
+
+/// What an escape stands for.
+enum Escaped {
+    Char(char),
+    Class(Class),
+}
+
+/// The ranges a class escape covers, with `\D` and the like turned into the ranges they
+/// leave, so they can go inside a class.
+fn complemented(class: &Class) -> Vec<(char, char)> {
+    if !class.negated {
+        return class.ranges.clone();
+    }
+    let mut ranges = Vec::new();
+    let mut next = Some('\0');
+    for &(low, high) in &class.ranges {
+        if let Some(from) = next.filter(|&from| from < low) {
+            ranges.push((from, char_before(low)));
+        }
+        next = char::from_u32(high as u32 + 1).or_else(|| char::from_u32(0xE000));
+    }
+    if let Some(from) = next {
+        ranges.push((from, char::MAX));
+    }
+    ranges
+}
+
+/// The character before `c`, which must not be NUL; surrogates are stepped over.
+fn char_before(c: char) -> char {
+    char::from_u32(c as u32 - 1).unwrap_or('\u{D7FF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literals_classes_and_escapes() {
+        assert!(matches("bar", "foobarbaz"));
+        assert!(!matches("bar", "foobaz"));
+        assert!(matches(r"\d{4}\.log", "app_2024.log"));
+        assert!(!matches(r"\d{4}\.log", "app_2024xlog"));
+        assert!(matches("[a-c_]x", "_x"));
+        assert!(!matches("[^a-c]x", "bx"));
+        assert!(matches(r"[\d.]+", "1.2"));
+        assert!(matches(r"[\D]", "a"));
+        assert!(!matches(r"^[\D]+$", "a1"));
+        assert!(matches(r"\w+\s\S", "ab c"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("a.c", "abc"));
+        assert!(!matches("a.c", "a\nc"));
+        assert!(matches(r"a\+b\\", r"a+b\"));
+        assert!(matches("x{", "x{"));
+    }
+
+    #[test]
+    fn test_anchors_and_alternation() {
+        let rotated = Regex::new(r"^(foo|bar)_\d{4}\.log$").unwrap();
+        assert!(rotated.is_match("foo_2024.log"));
+        assert!(rotated.is_match("bar_0001.log"));
+        assert!(!rotated.is_match("baz_2024.log"));
+        assert!(!rotated.is_match("logs/foo_2024.log"));
+        assert!(!rotated.is_match("foo_2024.log.gz"));
+        assert!(!rotated.is_match("foo_202.log"));
+        assert!(matches("(?:a|)b$", "b"));
+        assert!(matches("^$", ""));
+        assert!(!matches("^$", "a"));
+        assert!(matches("a|^b", "cab"));
+    }
+
+    #[test]
+    fn test_repetitions() {
+        assert!(matches("^a{2,3}$", "aaa"));
+        assert!(!matches("^a{2,3}$", "aaaa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2}$", "a"));
+        assert!(matches("^(ab)*c$", "ababc"));
+        assert!(matches("^(ab)+?c$", "abc"));
+        assert!(!matches("^(ab)+c$", "c"));
+        assert!(matches("^colou?r$", "color"));
+        assert!(matches("^(a*)*$", "aaaa"));
+        // A pattern that backtracking engines take exponential time over.
+        let text = format!("{}b", "a".repeat(40));
+        assert!(!matches("^(a|aa)*$", &text));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for (pattern, reason) in [
+            ("(foo", "unclosed group (at character 1)"),
+            ("foo)", "unopened group (at character 4)"),
+            ("[a-", "unclosed character class"),
+            ("[z-a]", "class range is backwards"),
+            ("*a", "nothing to repeat"),
+            ("a**", "cannot repeat"),
+            ("a{3,2}", "backwards"),
+            ("a{2000}", "over 1000"),
+            (r"\q", r"unknown escape \q"),
+            ("a\\", "lone backslash"),
+            ("(?i)a", "unsupported group flag"),
+        ] {
+            let err = Regex::new(pattern).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains(reason), "{}: {}", pattern, err);
+        }
+    }
+}
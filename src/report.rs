@@ -0,0 +1,341 @@
+//! Reports rendered from a template file once the run is over (`--report-format`).
+//!
+//! A template is text with placeholders in braces. The lines between a `{#files}` line and
+//! a `{/files}` line are written once for each operand, in the order they were dealt with;
+//! the rest is written once, before and after them. Each marker must be on a line of its
+//! own, and there is at most one such section. For example:
+//!
+//! ```text
+//! Touch report
+//! {#files}
+//! {outcome}: {path} {time}{reason}
+//! {/files}
+//! {total} files: {created} created, {updated} updated, {skipped} skipped, {failed} failed
+//! ```
+//!
+//! Inside the section, `{path}` is the operand as `-v` shows it, `{outcome}` one of
+//! `created`, `updated`, `unchanged`, `skipped` or `failed`, `{time}` the time applied in
+//! ISO 8601 (the modification time, or the access time when only that was set), and
+//! `{reason}` why it was skipped or failed. Each is empty where it doesn't apply. The
+//! counts `{total}`, `{created}`, `{updated}`, `{unchanged}`, `{skipped}` and `{failed}`
+//! can be used anywhere. `{{` and `}}` stand for a brace.
+//!
+//! A template with an unknown placeholder, a stray brace or a section that is misplaced
+//! or never closed is refused, naming its line, before anything is touched.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use mdtouch::date::format_iso8601;
+use mdtouch::FileTime;
+
+/// What became of one operand, as the report tells it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Created,
+    Updated,
+    Unchanged,
+    Skipped,
+    Failed,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Created => "created",
+            Outcome::Updated => "updated",
+            Outcome::Unchanged => "unchanged",
+            Outcome::Skipped => "skipped",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// One operand, as the report tells it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The operand, as `-v` shows it.
+    pub path: String,
+    pub outcome: Outcome,
+    /// The time applied, if it was touched.
+    pub time: Option<FileTime>,
+    /// Why it was skipped or failed, or nothing.
+    pub reason: String,
+}
+
+/// A placeholder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Path,
+    Outcome,
+    Time,
+    Reason,
+    Total,
+    Count(Outcome),
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "path" => Field::Path,
+            "outcome" => Field::Outcome,
+            "time" => Field::Time,
+            "reason" => Field::Reason,
+            "total" => Field::Total,
+            "created" => Field::Count(Outcome::Created),
+            "updated" => Field::Count(Outcome::Updated),
+            "unchanged" => Field::Count(Outcome::Unchanged),
+            "skipped" => Field::Count(Outcome::Skipped),
+            "failed" => Field::Count(Outcome::Failed),
+            _ => return None,
+        })
+    }
+
+    /// Whether it tells about one operand, so only has a value inside the section.
+    fn per_file(self) -> bool {
+        matches!(
+            self,
+            Field::Path | Field::Outcome | Field::Time | Field::Reason
+        )
+    }
+}
+
+/// A run of text, or a placeholder.
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+/// A parsed report template.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Template {
+    /// What comes before the section, or the whole template if it has none.
+    head: Vec<Piece>,
+    /// The section written for each operand.
+    each: Vec<Piece>,
+    /// What comes after the section.
+    tail: Vec<Piece>,
+}
+
+impl Template {
+    /// Reads and parses the template in the file at `path`.
+    pub fn read(path: &Path) -> io::Result<Template> {
+        Template::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the template `text`.
+    pub fn parse(text: &str) -> io::Result<Template> {
+        let mut template = Template::default();
+        // The line the section was opened on, while it is open, and whether it was closed.
+        let (mut opened, mut closed) = (None, false);
+        for (index, line) in text
+            .trim_start_matches('\u{feff}')
+            .split_inclusive('\n')
+            .enumerate()
+        {
+            let number = index + 1;
+            let error = |reason: String| invalid(number, reason);
+            match line.trim() {
+                "{#files}" if opened.is_some() || closed => {
+                    return Err(error("there can only be one {#files} section".to_string()))
+                }
+                "{#files}" => opened = Some(number),
+                "{/files}" if opened.is_none() => {
+                    return Err(error("{/files} with no {#files} before it".to_string()))
+                }
+                "{/files}" => (opened, closed) = (None, true),
+                _ => {
+                    let pieces = match (opened, closed) {
+                        (Some(_), _) => &mut template.each,
+                        (None, false) => &mut template.head,
+                        (None, true) => &mut template.tail,
+                    };
+                    parse_line(line, opened.is_some(), pieces).map_err(error)?;
+                }
+            }
+        }
+        match opened {
+            Some(number) => Err(invalid(
+                number,
+                "this {#files} section is never closed with {/files}".to_string(),
+            )),
+            None => Ok(template),
+        }
+    }
+
+    /// Renders the report of `records`.
+    pub fn render(&self, records: &[Record]) -> String {
+        let mut report = String::new();
+        render_pieces(&self.head, None, records, &mut report);
+        for record in records {
+            render_pieces(&self.each, Some(record), records, &mut report);
+        }
+        render_pieces(&self.tail, None, records, &mut report);
+        report
+    }
+}
+
+/// Renders `pieces` onto `report`, for `record` inside the section.
+fn render_pieces(
+    pieces: &[Piece],
+    record: Option<&Record>,
+    records: &[Record],
+    report: &mut String,
+) {
+    for piece in pieces {
+        let field = match piece {
+            Piece::Text(text) => {
+                report.push_str(text);
+                continue;
+            }
+            Piece::Field(field) => *field,
+        };
+        let _ = match (field, record) {
+            (Field::Total, _) => write!(report, "{}", records.len()),
+            (Field::Count(outcome), _) => write!(
+                report,
+                "{}",
+                records
+                    .iter()
+                    .filter(|record| record.outcome == outcome)
+                    .count()
+            ),
+            // Parsing keeps the others inside the section.
+            (_, None) => Ok(()),
+            (Field::Path, Some(record)) => write!(report, "{}", record.path),
+            (Field::Outcome, Some(record)) => write!(report, "{}", record.outcome.name()),
+            (Field::Time, Some(record)) => match record.time {
+                Some(time) => write!(report, "{}", format_iso8601(time)),
+                None => Ok(()),
+            },
+            (Field::Reason, Some(record)) => write!(report, "{}", record.reason),
+        };
+    }
+}
+
+/// Parses one line of a template, inside the section or not, onto `pieces`. Fails with
+/// what is wrong with it.
+fn parse_line(line: &str, in_section: bool, pieces: &mut Vec<Piece>) -> Result<(), String> {
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err("a } that closes nothing (write }} for a brace)".to_string()),
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if !closed {
+                    return Err("a { that is never closed (write {{ for a brace)".to_string());
+                }
+                let field = match Field::parse(&name) {
+                    Some(field) => field,
+                    None if name == "#files" || name == "/files" => {
+                        return Err(format!("{{{}}} must be on a line of its own", name))
+                    }
+                    None => return Err(format!("unknown placeholder {{{}}}", name)),
+                };
+                if field.per_file() && !in_section {
+                    return Err(format!(
+                        "{{{}}} can only be used between {{#files}} and {{/files}}",
+                        name
+                    ));
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Field(field));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(())
+}
+
+/// The error for line `number` of a template, for the reason given.
+fn invalid(number: usize, reason: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("line {}: {}", number, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, outcome: Outcome, time: Option<i64>, reason: &str) -> Record {
+        Record {
+            path: path.to_string(),
+            outcome,
+            time: time.map(|seconds| FileTime::from_unix_time(seconds, 0)),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse(
+            "Report {{v1}}\n  {#files}\n- {outcome} {path}{time}{reason}\n{/files}\n{total} files, {failed} failed\n",
+        )
+        .unwrap();
+        let records = [
+            record("a.txt", Outcome::Created, Some(1_700_000_000), ""),
+            record("b.txt", Outcome::Skipped, None, "read-only"),
+            record("c.txt", Outcome::Failed, None, "Permission denied"),
+        ];
+        assert_eq!(
+            template.render(&records),
+            "Report {v1}\n- created a.txt2023-11-14T22:13:20.000000000Z\n- skipped b.txtread-only\n- failed c.txtPermission denied\n3 files, 1 failed\n"
+        );
+        // With no section, it is written once.
+        let template = Template::parse("{updated} updated").unwrap();
+        assert_eq!(template.render(&records), "0 updated");
+    }
+
+    #[test]
+    fn test_malformed_templates_are_refused() {
+        for (text, error) in [
+            (
+                "{#files}\n{path}\n",
+                "line 1: this {#files} section is never closed",
+            ),
+            ("{/files}\n", "line 1: {/files} with no {#files} before it"),
+            (
+                "{#files}\n{/files}\n{#files}\n{/files}\n",
+                "line 3: there can only be one",
+            ),
+            (
+                "ok\n{#files} {path}\n",
+                "line 2: {#files} must be on a line of its own",
+            ),
+            ("{size}", "line 1: unknown placeholder {size}"),
+            ("{path}", "line 1: {path} can only be used between"),
+            ("{total", "line 1: a { that is never closed"),
+            ("total}", "line 1: a } that closes nothing"),
+        ] {
+            let err = Template::parse(text).unwrap_err();
+            assert!(err.to_string().starts_with(error), "{}: {}", text, err);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Keeping a file's times across an edit (`--save-times`, `--restore-times`).
+//!
+//! Saving writes the file's access and modification times, and on Windows its creation
+//! time, to a sidecar file next to it, named after it with `.mdtouch-times` added. Restoring
+//! applies them to the file again and deletes the sidecar. Each time is written as Unix
+//! seconds with nine digits of nanoseconds, so it comes back exactly as it was:
+//!
+//! ```text
+//! atime 1704067200.000000000
+//! mtime 1704067200.250000000
+//! created 1704060000.000000000
+//! ```
+//!
+//! Saving again before restoring is refused, so the times from before the first edit
+//! aren't lost by saving over them.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use filetime::FileTime;
+
+/// The name added to a file's own to name its sidecar.
+const SIDECAR_EXTENSION: &str = ".mdtouch-times";
+
+/// The times saved for a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SavedTimes {
+    pub atime: FileTime,
+    pub mtime: FileTime,
+    /// Only saved on Windows, the one place it can be restored.
+    pub created: Option<FileTime>,
+}
+
+/// The sidecar holding the saved times of `path`.
+pub fn sidecar(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Saves the times of the file at `path` to its sidecar, returning them.
+pub fn save(path: &Path) -> io::Result<SavedTimes> {
+    let metadata = fs::metadata(path)?;
+    let times = SavedTimes {
+        atime: FileTime::from_last_access_time(&metadata),
+        mtime: FileTime::from_last_modification_time(&metadata),
+        created: if cfg!(windows) {
+            FileTime::from_creation_time(&metadata)
+        } else {
+            None
+        },
+    };
+    let sidecar = sidecar(path);
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&sidecar)
+        .and_then(|mut file| io::Write::write_all(&mut file, format_times(&times).as_bytes()));
+    match written {
+        Ok(()) => Ok(times),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "times already saved in {}; restore them with --restore-times, or delete it",
+                sidecar.display()
+            ),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Applies the times saved in the sidecar of `path` to it, then deletes the sidecar.
+pub fn restore(path: &Path) -> io::Result<SavedTimes> {
+    let sidecar = sidecar(path);
+    let text = match fs::read_to_string(&sidecar) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no saved times in {} (save them first with --save-times)",
+                    sidecar.display()
+                ),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+    let times = parse_times(&text).map_err(|reason| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid saved times in {}: {}", sidecar.display(), reason),
+        )
+    })?;
+    filetime::set_file_times(path, times.atime, times.mtime)?;
+    if let Some(created) = times.created.filter(|_| cfg!(windows)) {
+        mdtouch::set_creation_time(path, created)?;
+    }
+    fs::remove_file(&sidecar)?;
+    Ok(times)
+}
+
+/// Writes the times in the sidecar format.
+fn format_times(times: &SavedTimes) -> String {
+    let line = |name: &str, time: FileTime| {
+        format!(
+            "{} {}.{:09}\n",
+            name,
+            time.unix_seconds(),
+            time.nanoseconds()
+        )
+    };
+    let mut text = line("atime", times.atime) + &line("mtime", times.mtime);
+    if let Some(created) = times.created {
+        text += &line("created", created);
+    }
+    text
+}
+
+/// Reads the times back from the sidecar format.
+fn parse_times(text: &str) -> Result<SavedTimes, String> {
+    let (mut atime, mut mtime, mut created) = (None, None, None);
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let (name, value) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("expected a name and a time in '{}'", line))?;
+        let time = parse_time(value.trim()).ok_or_else(|| format!("bad time '{}'", value))?;
+        let slot = match name {
+            "atime" => &mut atime,
+            "mtime" => &mut mtime,
+            "created" => &mut created,
+            _ => return Err(format!("unknown time '{}'", name)),
+        };
+        *slot = Some(time);
+    }
+    match (atime, mtime) {
+        (Some(atime), Some(mtime)) => Ok(SavedTimes {
+            atime,
+            mtime,
+            created,
+        }),
+        _ => Err("expected both an atime and an mtime".to_string()),
+    }
+}
+
+/// Reads `SECONDS.NANOSECONDS`.
+fn parse_time(value: &str) -> Option<FileTime> {
+    let (seconds, nanoseconds) = value.split_once('.')?;
+    if nanoseconds.len() != 9 || !nanoseconds.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(FileTime::from_unix_time(
+        seconds.parse().ok()?,
+        nanoseconds.parse().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_round_trips() {
+        let times = SavedTimes {
+            atime: FileTime::from_unix_time(1_704_067_200, 0),
+            mtime: FileTime::from_unix_time(1_704_067_200, 250_000_000),
+            created: Some(FileTime::from_unix_time(-5, 7)),
+        };
+        let text = format_times(&times);
+        assert_eq!(
+            text,
+            "atime 1704067200.000000000\nmtime 1704067200.250000000\ncreated -5.000000007\n"
+        );
+        assert_eq!(parse_times(&text), Ok(times));
+        let without_created = SavedTimes {
+            created: None,
+            ..times
+        };
+        assert_eq!(
+            parse_times(&format_times(&without_created)),
+            Ok(without_created)
+        );
+        assert!(parse_times("atime 1.000000000\n").is_err());
+        assert!(parse_times("atime 1.0\nmtime 1.000000000\n").is_err());
+        assert!(parse_times("ctime 1.000000000\n").is_err());
+    }
+
+    #[test]
+    fn test_save_then_restore() {
+        let path = std::env::temp_dir().join(format!("mdtouch_saved_{}.txt", std::process::id()));
+        fs::write(&path, b"before").unwrap();
+        let old = FileTime::from_unix_time(1_500_000_000, 123_000_000);
+        filetime::set_file_times(&path, old, old).unwrap();
+
+        save(&path).unwrap();
+        assert!(sidecar(&path).exists());
+        let err = save(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        fs::write(&path, b"after").unwrap();
+        let restored = restore(&path).unwrap();
+        assert_eq!((restored.atime, restored.mtime), (old, old));
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), old);
+        assert!(!sidecar(&path).exists());
+        let err = restore(&path).unwrap_err();
+        assert!(err.to_string().contains("--save-times"), "{}", err);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! Giving new files the permissions of the reference file (`--copy-security`).
+//!
+//! Only Windows has security descriptors to copy. The reference's owner, group and DACL
+//! are read with `GetNamedSecurityInfoW` and applied to the new file with
+//! `SetNamedSecurityInfoW`. Inherited entries in the DACL are not copied as they are: the
+//! new file inherits from its own directory instead, as any file created there would.
+//! Setting an owner other than the caller needs `SeRestorePrivilege`; without it, the
+//! owner and group are left as created and only the DACL is copied.
+
+use std::io;
+use std::path::Path;
+
+/// Copies the security descriptor of `reference` to the file at `path`.
+#[cfg(windows)]
+pub fn copy_security(reference: &Path, path: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{
+        LocalFree, ERROR_ACCESS_DENIED, ERROR_INVALID_OWNER, ERROR_PRIVILEGE_NOT_HELD,
+    };
+    use windows_sys::Win32::Security::Authorization::{
+        GetNamedSecurityInfoW, SetNamedSecurityInfoW, SE_FILE_OBJECT,
+    };
+    use windows_sys::Win32::Security::{
+        DACL_SECURITY_INFORMATION, GROUP_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION,
+    };
+
+    static PRIVILEGES: std::sync::Once = std::sync::Once::new();
+    PRIVILEGES.call_once(crate::owner::enable_owner_privileges);
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (wide_reference, wide_path) = (wide(reference), wide(path));
+    let everything =
+        OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION;
+    // SAFETY: the paths are NUL-terminated, the output pointers are live locals, and the
+    // owner, group and DACL point into the descriptor, which is freed only after they have
+    // been applied.
+    unsafe {
+        let (mut owner, mut group) = (std::ptr::null_mut(), std::ptr::null_mut());
+        let mut dacl = std::ptr::null_mut();
+        let mut descriptor = std::ptr::null_mut();
+        let status = GetNamedSecurityInfoW(
+            wide_reference.as_ptr(),
+            SE_FILE_OBJECT,
+            everything,
+            &mut owner,
+            &mut group,
+            &mut dacl,
+            std::ptr::null_mut(),
+            &mut descriptor,
+        );
+        if status != 0 {
+            return Err(io::Error::from_raw_os_error(status as i32));
+        }
+        let mut status = SetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            everything,
+            owner,
+            group,
+            dacl,
+            std::ptr::null(),
+        );
+        if matches!(
+            status,
+            ERROR_ACCESS_DENIED | ERROR_INVALID_OWNER | ERROR_PRIVILEGE_NOT_HELD
+        ) {
+            status = SetNamedSecurityInfoW(
+                wide_path.as_ptr(),
+                SE_FILE_OBJECT,
+                DACL_SECURITY_INFORMATION,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                dacl,
+                std::ptr::null(),
+            );
+        }
+        LocalFree(descriptor);
+        match status {
+            0 => Ok(()),
+            _ => Err(io::Error::from_raw_os_error(status as i32)),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn copy_security(_reference: &Path, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "security descriptors are only supported on Windows",
+    ))
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_copy_security_between_files() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_security_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (reference, path) = (dir.join("reference.txt"), dir.join("new.txt"));
+        fs::write(&reference, b"").unwrap();
+        fs::write(&path, b"").unwrap();
+        copy_security(&reference, &path).unwrap();
+        assert!(copy_security(&dir.join("missing.txt"), &path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! Finding out what a directory's filesystem does with the times it is given
+//! (`--self-test`).
+//!
+//! Where `--probe` looks the filesystem up in a table, a self-test asks it: a scratch
+//! file is created in the directory, each of its times is set through the same calls a
+//! touch makes, at 100 ns, 1 ms, 1 s and 2 s precision, and read back. The finest
+//! precision that reads back unchanged is the one the filesystem keeps. Whether access
+//! times are updated is found out the same way, by reading the file after setting its
+//! access time far back. The scratch file is removed afterwards, whatever happened.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use mdtouch::{FileTime, TouchOptions};
+
+use crate::probe::format_duration;
+
+/// The precisions each time is tried at, finest first, in nanoseconds.
+const PRECISIONS: [u64; 4] = [100, 1_000_000, 1_000_000_000, 2_000_000_000];
+
+/// The whole second, in September 2020, the times tried are near. Every filesystem with
+/// times can hold it, FAT's 1980 to 2107 included.
+const BASE_SECONDS: i64 = 1_600_000_000;
+
+/// One of the times a file keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeKind {
+    Modification,
+    Access,
+    Creation,
+}
+
+impl TimeKind {
+    fn name(self) -> &'static str {
+        match self {
+            TimeKind::Modification => "modification time",
+            TimeKind::Access => "access time",
+            TimeKind::Creation => "creation time",
+        }
+    }
+}
+
+/// How setting one time went.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Support {
+    /// The time could be set, and read back unchanged to the precision given, in
+    /// nanoseconds.
+    Kept(u64),
+    /// The time could be set, but never read back unchanged, not even to 2 seconds.
+    Rounded,
+    /// Setting or reading the time failed, for the reason given.
+    Unsupported(String),
+}
+
+/// What a self-test found out about one directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTest {
+    /// The directory tested, as given.
+    pub dir: PathBuf,
+    /// The filesystem's name, where it could be found out.
+    pub filesystem: Option<String>,
+    /// How each time fared, in the order of [`TimeKind`].
+    pub times: Vec<(TimeKind, Support)>,
+    /// Whether reading the file updated its access time, where that could be tried.
+    pub last_access_updates: Option<bool>,
+}
+
+impl SelfTest {
+    /// Whether modification times can be set at all, which is what a touch needs.
+    pub fn passed(&self) -> bool {
+        self.times.iter().any(|(kind, support)| {
+            *kind == TimeKind::Modification && matches!(support, Support::Kept(_))
+        })
+    }
+
+    /// Formats the report for reading.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\n  filesystem:           {}\n",
+            self.dir.display(),
+            self.filesystem.as_deref().unwrap_or("unknown")
+        );
+        for (kind, support) in &self.times {
+            let found = match support {
+                Support::Kept(100) => "supported, kept to 100 ns or finer".to_string(),
+                Support::Kept(precision) => {
+                    format!("supported, kept to {}", format_duration(*precision))
+                }
+                Support::Rounded => "supported, but not kept even to 2 s".to_string(),
+                Support::Unsupported(reason) => format!("not supported ({})", reason),
+            };
+            text.push_str(&format!("  {:<22}{}\n", format!("{}:", kind.name()), found));
+        }
+        let last_access = match self.last_access_updates {
+            Some(true) => "appear enabled (reading the file updated its access time)",
+            Some(false) => "appear disabled (reading the file left its access time alone)",
+            None => "unknown",
+        };
+        text.push_str(&format!("  last-access updates:  {}\n", last_access));
+        text.push_str(if self.passed() {
+            "  result:               modification times can be set\n"
+        } else {
+            "  result:               modification times cannot be set\n"
+        });
+        text
+    }
+}
+
+/// Tests the filesystem holding `dir` with a scratch file, which is removed again before
+/// returning.
+pub fn self_test(dir: &Path) -> io::Result<SelfTest> {
+    let scratch =
+        mdtouch::create_temp_file(dir, ".mdtouch-self-test-", ".tmp", &TouchOptions::now())?;
+    let report = test_file(dir, &scratch);
+    let removed = fs::remove_file(&scratch);
+    let report = report?;
+    removed?;
+    Ok(report)
+}
+
+/// Runs the tests on the scratch file `path` in `dir`.
+fn test_file(dir: &Path, path: &Path) -> io::Result<SelfTest> {
+    // Some filesystems skip the access time of a file with nothing in it.
+    fs::write(path, b"mdtouch self-test\n")?;
+    let times = [TimeKind::Modification, TimeKind::Access, TimeKind::Creation]
+        .into_iter()
+        .map(|kind| (kind, test_time(path, kind)))
+        .collect();
+    Ok(SelfTest {
+        dir: dir.to_path_buf(),
+        filesystem: crate::probe::probe(dir)
+            .ok()
+            .and_then(|probe| probe.filesystem),
+        times,
+        last_access_updates: last_access_updates(path),
+    })
+}
+
+/// Finds the finest precision `kind` of time is kept to on the file at `path`.
+fn test_time(path: &Path, kind: TimeKind) -> Support {
+    for precision in PRECISIONS {
+        let time = time_at(precision);
+        let read = set_time(path, kind, time).and_then(|()| read_time(path, kind));
+        match read {
+            Ok(read) if read == time => return Support::Kept(precision),
+            Ok(_) => {}
+            Err(e) => return Support::Unsupported(e.to_string()),
+        }
+    }
+    Support::Rounded
+}
+
+/// A time that is a whole multiple of `precision` but of no coarser one tried, so it only
+/// reads back unchanged where times are kept at least that finely.
+fn time_at(precision: u64) -> FileTime {
+    match precision {
+        100 => FileTime::from_unix_time(BASE_SECONDS + 1, 123_456_700),
+        1_000_000 => FileTime::from_unix_time(BASE_SECONDS + 1, 123_000_000),
+        1_000_000_000 => FileTime::from_unix_time(BASE_SECONDS + 1, 0),
+        _ => FileTime::from_unix_time(BASE_SECONDS + 2, 0),
+    }
+}
+
+/// Sets one time of the file at `path`, as a touch would.
+fn set_time(path: &Path, kind: TimeKind, time: FileTime) -> io::Result<()> {
+    let options = TouchOptions {
+        no_create: true,
+        ..TouchOptions::default()
+    };
+    match kind {
+        TimeKind::Modification => mdtouch::touch_file(
+            path,
+            &TouchOptions {
+                mtime: Some(time),
+                ..options
+            },
+        )
+        .map(|_| ()),
+        TimeKind::Access => mdtouch::touch_file(
+            path,
+            &TouchOptions {
+                atime: Some(time),
+                ..options
+            },
+        )
+        .map(|_| ()),
+        TimeKind::Creation => mdtouch::set_creation_time(path, time),
+    }
+}
+
+/// Reads one time of the file at `path` back.
+fn read_time(path: &Path, kind: TimeKind) -> io::Result<FileTime> {
+    let metadata = fs::metadata(path)?;
+    match kind {
+        TimeKind::Modification => Ok(FileTime::from_last_modification_time(&metadata)),
+        TimeKind::Access => Ok(FileTime::from_last_access_time(&metadata)),
+        TimeKind::Creation => FileTime::from_creation_time(&metadata).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "creation times cannot be read here",
+            )
+        }),
+    }
+}
+
+/// Whether reading the file at `path` updates its access time. The access time is set
+/// well before the modification time first, which even Linux's `relatime` updates.
+fn last_access_updates(path: &Path) -> Option<bool> {
+    let old = FileTime::from_unix_time(BASE_SECONDS - 86_400 * 365, 0);
+    set_time(path, TimeKind::Access, old).ok()?;
+    let mut contents = Vec::new();
+    fs::File::open(path).ok()?.read_to_end(&mut contents).ok()?;
+    Some(read_time(path, TimeKind::Access).ok()? != old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_times_tried_only_fit_their_precision() {
+        for (index, precision) in PRECISIONS.into_iter().enumerate() {
+            let time = time_at(precision);
+            let nanos = time.unix_seconds() as u128 * 1_000_000_000 + time.nanoseconds() as u128;
+            assert_eq!(nanos % precision as u128, 0);
+            for coarser in &PRECISIONS[index + 1..] {
+                assert_ne!(nanos % *coarser as u128, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_text() {
+        let test = SelfTest {
+            dir: PathBuf::from("stick"),
+            filesystem: Some("vfat".to_string()),
+            times: vec![
+                (TimeKind::Modification, Support::Kept(2_000_000_000)),
+                (TimeKind::Access, Support::Rounded),
+                (
+                    TimeKind::Creation,
+                    Support::Unsupported("not here".to_string()),
+                ),
+            ],
+            last_access_updates: None,
+        };
+        assert!(test.passed());
+        assert_eq!(
+            test.to_text(),
+            "stick\n  filesystem:           vfat\n  modification time:    supported, kept to 2 s\n  access time:          supported, but not kept even to 2 s\n  creation time:        not supported (not here)\n  last-access updates:  unknown\n  result:               modification times can be set\n"
+        );
+    }
+}
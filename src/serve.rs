@@ -0,0 +1,735 @@
+//! Touching files on request from other processes (`--serve`, `--send`).
+//!
+//! A build that touches thousands of files one process at a time spends most of its time
+//! starting processes. `--serve NAME` instead listens on a named pipe (a Unix domain
+//! socket elsewhere) and touches files in-process for any number of concurrent clients.
+//! Each request is one line of JSON, and each gets one line of JSON in reply:
+//!
+//! ```text
+//! {"path":"C:\\out\\app.stamp","mtime":"2025-02-03T10:00:00Z"}
+//! {"ok":true,"path":"C:\\out\\app.stamp"}
+//! ```
+//!
+//! A request names a `path` and may give `atime`, `mtime` or `time` (both), each in any
+//! form `-d` accepts or as seconds since the epoch; without any of them both times become
+//! the current time, and a time left out is otherwise unchanged. `"no_create":true` skips
+//! missing files, as `-c` does. A failed request gets `"ok":false` and an `error`.
+//! `{"quit":true}` stops the server, as does Ctrl+C: it stops accepting connections,
+//! finishes the requests in progress and exits.
+//!
+//! `--send NAME` is the client, so scripts need no pipe code of their own.
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use mdtouch::{parse_date, touch_file, FileTime, ParseContext, TimeSource, TouchOptions};
+
+use crate::audit::json_string;
+
+/// Set once the server is to stop accepting connections.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// Requests read but not yet answered.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// How long a client keeps trying to reach a server that isn't listening yet.
+const CONNECT_PATIENCE: Duration = Duration::from_secs(5);
+
+/// A JSON value in a request. Requests are flat objects, so there are no arrays or
+/// nested objects.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+/// Parses a flat JSON object into its members, in order.
+fn parse_object(text: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut chars = text.trim().chars().peekable();
+    let mut members = Vec::new();
+    let skip_space = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    if chars.next() != Some('{') {
+        return Err("expected a JSON object".to_string());
+    }
+    skip_space(&mut chars);
+    if chars.next_if_eq(&'}').is_none() {
+        loop {
+            skip_space(&mut chars);
+            if chars.next() != Some('"') {
+                return Err("expected a member name".to_string());
+            }
+            let name = parse_string(&mut chars)?;
+            skip_space(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("expected ':' after \"{}\"", name));
+            }
+            skip_space(&mut chars);
+            let value = match chars.next() {
+                Some('"') => Value::String(parse_string(&mut chars)?),
+                Some(c) if c == '-' || c.is_ascii_digit() => {
+                    let mut number = c.to_string();
+                    while let Some(c) =
+                        chars.next_if(|c| "+-.eE".contains(*c) || c.is_ascii_digit())
+                    {
+                        number.push(c);
+                    }
+                    Value::Number(
+                        number
+                            .parse()
+                            .map_err(|_| format!("bad number {}", number))?,
+                    )
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let mut word = c.to_string();
+                    while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+                        word.push(c);
+                    }
+                    match word.as_str() {
+                        "true" => Value::Bool(true),
+                        "false" => Value::Bool(false),
+                        "null" => Value::Null,
+                        _ => return Err(format!("unexpected {}", word)),
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "expected a string, number or literal for \"{}\"",
+                        name
+                    ))
+                }
+            };
+            members.push((name, value));
+            skip_space(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err("expected ',' or '}'".to_string()),
+            }
+        }
+    }
+    skip_space(&mut chars);
+    match chars.next() {
+        None => Ok(members),
+        Some(_) => Err("unexpected text after the object".to_string()),
+    }
+}
+
+/// Parses the rest of a JSON string whose opening quote has been read.
+fn parse_string(chars: &mut impl Iterator<Item = char>) -> Result<String, String> {
+    let mut text = String::new();
+    let mut pending_high = None;
+    loop {
+        let c = chars.next().ok_or("unterminated string")?;
+        let c = match c {
+            '"' => return Ok(text),
+            '\\' => match chars.next().ok_or("unterminated string")? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let unit = u16::from_str_radix(&hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == 4)
+                        .ok_or("bad \\u escape")?;
+                    // A surrogate pair arrives as two escapes.
+                    if (0xD800..0xDC00).contains(&unit) {
+                        pending_high = Some(unit);
+                        continue;
+                    }
+                    let units = match pending_high.take() {
+                        Some(high) => vec![high, unit],
+                        None => vec![unit],
+                    };
+                    text.push_str(&String::from_utf16(&units).map_err(|_| "bad \\u escape")?);
+                    continue;
+                }
+                c @ ('"' | '\\' | '/') => c,
+                _ => return Err("bad escape".to_string()),
+            },
+            c => c,
+        };
+        if pending_high.take().is_some() {
+            return Err("bad \\u escape".to_string());
+        }
+        text.push(c);
+    }
+}
+
+/// Reads one time member of a request: a date string or seconds since the epoch.
+fn time_value(value: &Value, now: FileTime) -> Result<FileTime, String> {
+    match value {
+        Value::String(text) => {
+            let context = ParseContext {
+                now,
+                ..ParseContext::default()
+            };
+            parse_date(text, context).map_err(|e| e.to_string())
+        }
+        Value::Number(seconds) if seconds.is_finite() => {
+            let whole = seconds.floor();
+            let nanos = ((seconds - whole) * 1e9) as u32;
+            Ok(FileTime::from_unix_time(
+                whole as i64,
+                nanos.min(999_999_999),
+            ))
+        }
+        _ => Err("times must be date strings or seconds since the epoch".to_string()),
+    }
+}
+
+/// Handles one request line, returning the reply line (without its newline). "Now" is
+/// read from `clock`.
+fn handle(line: &str, clock: &dyn TimeSource) -> String {
+    let members = match parse_object(line) {
+        Ok(members) => members,
+        Err(e) => return format!("{{\"ok\":false,\"error\":{}}}", json_string(&e)),
+    };
+    if member(&members, "quit") == Some(&Value::Bool(true)) {
+        stop();
+        return "{\"ok\":true,\"quit\":true}".to_string();
+    }
+    let Some(Value::String(path)) = member(&members, "path") else {
+        return "{\"ok\":false,\"error\":\"expected a \\\"path\\\" string\"}".to_string();
+    };
+    let result = request_options(&members, clock)
+        .and_then(|options| touch_file(Path::new(path), &options).map_err(|e| e.to_string()));
+    match result {
+        Ok(_) => format!("{{\"ok\":true,\"path\":{}}}", json_string(path)),
+        Err(e) => format!(
+            "{{\"ok\":false,\"path\":{},\"error\":{}}}",
+            json_string(path),
+            json_string(&e)
+        ),
+    }
+}
+
+/// The value of a request's member `name`, if it has one.
+fn member<'a>(members: &'a [(String, Value)], name: &str) -> Option<&'a Value> {
+    members
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+/// Works out how to touch the file a request names, with "now" read from `clock`.
+fn request_options(
+    members: &[(String, Value)],
+    clock: &dyn TimeSource,
+) -> Result<TouchOptions, String> {
+    let now = clock.now();
+    let names = ["time", "atime", "mtime"];
+    if names.iter().all(|name| member(members, name).is_none()) {
+        return Ok(TouchOptions {
+            no_create: member(members, "no_create") == Some(&Value::Bool(true)),
+            ..TouchOptions::now_from(clock)
+        });
+    }
+    // A time given as null is left unchanged.
+    let time = |name: &str| match member(members, name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => time_value(value, now).map(Some),
+    };
+    let both = time("time")?;
+    Ok(TouchOptions {
+        atime: time("atime")?.or(both),
+        mtime: time("mtime")?.or(both),
+        no_create: member(members, "no_create") == Some(&Value::Bool(true)),
+        ..TouchOptions::default()
+    })
+}
+
+/// Formats the request line that touches `path` (already absolute) the way the options
+/// say.
+pub fn request_line(path: &Path, options: &TouchOptions) -> String {
+    let mut line = format!("{{\"path\":{}", json_string(&path.to_string_lossy()));
+    for (name, time) in [("atime", options.atime), ("mtime", options.mtime)] {
+        if let Some(time) = time {
+            let iso = mdtouch::date::format_iso8601(time);
+            line.push_str(&format!(",\"{}\":{}", name, json_string(&iso)));
+        }
+    }
+    if options.atime.is_none() && options.mtime.is_none() {
+        // Leaving both out would mean "now" to the server.
+        line.push_str(",\"time\":null");
+    }
+    if options.no_create {
+        line.push_str(",\"no_create\":true");
+    }
+    line.push('}');
+    line
+}
+
+/// Serves one client until it hangs up.
+fn serve_client<C>(connection: C, clock: &dyn TimeSource)
+where
+    for<'c> &'c C: io::Read + Write,
+{
+    let mut reader = BufReader::new(&connection);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        let reply = handle(&line, clock);
+        let sent = (&connection).write_all(format!("{}\n", reply).as_bytes());
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+/// Listens on `name` until told to quit, touching files for every client. "Now" is read
+/// from `clock`.
+pub fn serve(name: &OsStr, clock: Arc<dyn TimeSource>) -> io::Result<()> {
+    let listener = platform::Listener::bind(name)?;
+    platform::on_interrupt(stop);
+    while !STOPPING.load(Ordering::SeqCst) {
+        match listener.accept()? {
+            Some(connection) => {
+                let clock = Arc::clone(&clock);
+                thread::spawn(move || serve_client(connection, &*clock));
+            }
+            None => continue,
+        }
+    }
+    drop(listener);
+    // Idle clients are dropped, but a request being handled is seen through.
+    while IN_FLIGHT.load(Ordering::SeqCst) > 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// Makes the server stop accepting connections.
+fn stop() {
+    STOPPING.store(true, Ordering::SeqCst);
+    platform::wake();
+}
+
+/// Sends each request line to the server on `name`, one at a time, and writes each
+/// reply line to `writer`. Returns how many requests failed.
+pub fn send<W: Write>(
+    name: &OsStr,
+    requests: impl IntoIterator<Item = io::Result<String>>,
+    mut writer: W,
+) -> io::Result<usize> {
+    let connection = connect(name)?;
+    let mut reader = BufReader::new(&connection);
+    let mut failed = 0;
+    let mut reply = String::new();
+    for request in requests {
+        let request = request?;
+        if request.trim().is_empty() {
+            continue;
+        }
+        (&connection).write_all(format!("{}\n", request.trim()).as_bytes())?;
+        reply.clear();
+        if reader.read_line(&mut reply)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the server closed the connection",
+            ));
+        }
+        if !reply.starts_with("{\"ok\":true") {
+            failed += 1;
+        }
+        writer.write_all(reply.as_bytes())?;
+    }
+    Ok(failed)
+}
+
+/// Connects to the server on `name`, waiting a little for one that is still starting.
+fn connect(name: &OsStr) -> io::Result<platform::Connection> {
+    let started = std::time::Instant::now();
+    loop {
+        match platform::connect(name) {
+            Ok(connection) => return Ok(connection),
+            Err(e) if started.elapsed() < CONNECT_PATIENCE && platform::not_ready(&e) => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Error connecting to {}: {}", name.to_string_lossy(), e),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+
+    pub type Connection = UnixStream;
+
+    /// A Unix domain socket, removed again when the server stops.
+    pub struct Listener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl Listener {
+        pub fn bind(name: &OsStr) -> io::Result<Self> {
+            let path = PathBuf::from(name);
+            let listener = match UnixListener::bind(&path) {
+                // A socket left behind by a server that died can be reused; one that
+                // still answers belongs to a running server.
+                Err(e)
+                    if e.kind() == io::ErrorKind::AddrInUse
+                        && UnixStream::connect(&path).is_err() =>
+                {
+                    std::fs::remove_file(&path)?;
+                    UnixListener::bind(&path)
+                }
+                result => result,
+            }
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Error listening on {}: {}", path.display(), e),
+                )
+            })?;
+            Ok(Listener { listener, path })
+        }
+
+        /// Waits a little for a client, so that a stop is noticed promptly.
+        pub fn accept(&self) -> io::Result<Option<Connection>> {
+            let mut poll = libc::pollfd {
+                fd: self.listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: poll reads and writes only the one pollfd passed.
+            if unsafe { libc::poll(&mut poll, 1, 100) } <= 0 {
+                return Ok(None);
+            }
+            match self.listener.accept() {
+                Ok((stream, _)) => Ok(Some(stream)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    pub fn connect(name: &OsStr) -> io::Result<Connection> {
+        UnixStream::connect(name)
+    }
+
+    /// Whether a failed connection means the server isn't listening yet.
+    pub fn not_ready(e: &io::Error) -> bool {
+        matches!(
+            e.kind(),
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+        )
+    }
+
+    /// The accept loop polls, so there is nothing to wake.
+    pub fn wake() {}
+
+    /// Calls `stop` on SIGINT or SIGTERM.
+    pub fn on_interrupt(stop: fn()) {
+        static STOP: std::sync::OnceLock<fn()> = std::sync::OnceLock::new();
+        extern "C" fn handler(_signal: libc::c_int) {
+            // Only an atomic store happens in `stop` on Unix, which is signal-safe.
+            if let Some(stop) = STOP.get() {
+                stop();
+            }
+        }
+        let _ = STOP.set(stop);
+        // SAFETY: the handler only stores to an atomic.
+        unsafe {
+            libc::signal(libc::SIGINT, handler as *const () as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, handler as *const () as libc::sighandler_t);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::{OsStr, OsString};
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{FromRawHandle, OwnedHandle};
+    use std::sync::OnceLock;
+
+    use windows_sys::Win32::Foundation::{
+        GetLastError, BOOL, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE, TRUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    pub type Connection = File;
+
+    /// The pipe being served, for waking the accept loop.
+    static PIPE: OnceLock<OsString> = OnceLock::new();
+
+    /// A bare name such as `mdtouch` means `\\.\pipe\mdtouch`.
+    fn pipe_name(name: &OsStr) -> OsString {
+        if name.to_string_lossy().starts_with(r"\\") {
+            name.to_os_string()
+        } else {
+            let mut full = OsString::from(r"\\.\pipe\");
+            full.push(name);
+            full
+        }
+    }
+
+    /// A named pipe, with a new instance created for each client.
+    pub struct Listener {
+        name: Vec<u16>,
+    }
+
+    impl Listener {
+        pub fn bind(name: &OsStr) -> io::Result<Self> {
+            let name = pipe_name(name);
+            let _ = PIPE.set(name.clone());
+            Ok(Listener {
+                name: name.encode_wide().chain(Some(0)).collect(),
+            })
+        }
+
+        /// Waits for the next client. A stop wakes the wait by connecting itself.
+        pub fn accept(&self) -> io::Result<Option<Connection>> {
+            // SAFETY: the name is NUL-terminated and outlives the call.
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    self.name.as_ptr(),
+                    PIPE_ACCESS_DUPLEX,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                    PIPE_UNLIMITED_INSTANCES,
+                    64 * 1024,
+                    64 * 1024,
+                    0,
+                    std::ptr::null(),
+                )
+            };
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the handle was just created and is owned by nothing else.
+            let pipe = File::from(unsafe { OwnedHandle::from_raw_handle(handle) });
+            // SAFETY: the handle is open; no OVERLAPPED, so the call blocks.
+            let connected = unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) } != 0
+                || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+            Ok(connected.then_some(pipe))
+        }
+    }
+
+    pub fn connect(name: &OsStr) -> io::Result<Connection> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pipe_name(name))
+    }
+
+    /// Whether a failed connection means the server isn't listening yet, or is between
+    /// pipe instances.
+    pub fn not_ready(e: &io::Error) -> bool {
+        e.kind() == io::ErrorKind::NotFound || e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32)
+    }
+
+    /// Wakes the accept loop by connecting to it.
+    pub fn wake() {
+        if let Some(name) = PIPE.get() {
+            let _ = OpenOptions::new().read(true).write(true).open(name);
+        }
+    }
+
+    /// Calls `stop` on Ctrl+C, Ctrl+Break or the console closing.
+    pub fn on_interrupt(stop: fn()) {
+        static STOP: OnceLock<fn()> = OnceLock::new();
+        unsafe extern "system" fn handler(event: u32) -> BOOL {
+            match (event, STOP.get()) {
+                (CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT, Some(stop)) => {
+                    stop();
+                    TRUE
+                }
+                _ => 0,
+            }
+        }
+        let _ = STOP.set(stop);
+        // SAFETY: the handler runs on its own thread and only touches statics.
+        unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) };
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::ffi::OsStr;
+    use std::fs::File;
+    use std::io;
+
+    pub type Connection = File;
+
+    pub struct Listener;
+
+    impl Listener {
+        pub fn bind(_name: &OsStr) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--serve is not supported on this platform",
+            ))
+        }
+
+        pub fn accept(&self) -> io::Result<Option<Connection>> {
+            Ok(None)
+        }
+    }
+
+    pub fn connect(_name: &OsStr) -> io::Result<Connection> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--send is not supported on this platform",
+        ))
+    }
+
+    pub fn not_ready(_e: &io::Error) -> bool {
+        false
+    }
+
+    pub fn wake() {}
+
+    pub fn on_interrupt(_stop: fn()) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_object() {
+        assert_eq!(
+            parse_object(
+                r#" { "path" : "a\"b\\c\u00e9\ud83d\ude00", "n": -1.5e1, "c":true, "x":null } "#
+            ),
+            Ok(vec![
+                ("path".to_string(), Value::String("a\"b\\cé😀".to_string())),
+                ("n".to_string(), Value::Number(-15.0)),
+                ("c".to_string(), Value::Bool(true)),
+                ("x".to_string(), Value::Null),
+            ])
+        );
+        assert_eq!(parse_object("{}"), Ok(vec![]));
+        for bad in [
+            "",
+            "[]",
+            "{\"a\"}",
+            "{\"a\":1,}",
+            "{\"a\":\"x}",
+            "{\"a\":1} x",
+            "{\"a\":nope}",
+        ] {
+            assert!(parse_object(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_handle_touches_and_reports() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_serve_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.stamp");
+        let clock = mdtouch::FixedClock(FileTime::from_unix_time(1_000_000_000, 0));
+        let options = TouchOptions {
+            atime: None,
+            mtime: Some(FileTime::from_unix_time(1_700_000_000, 500)),
+            ..TouchOptions::default()
+        };
+        let request = request_line(&path, &options);
+        assert_eq!(
+            handle(&request, &clock),
+            format!(
+                "{{\"ok\":true,\"path\":{}}}",
+                json_string(&path.to_string_lossy())
+            )
+        );
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            FileTime::from_last_modification_time(&metadata),
+            FileTime::from_unix_time(1_700_000_000, 500)
+        );
+
+        // Seconds since the epoch work too, and -c style requests skip missing files.
+        let epoch = format!(
+            "{{\"path\":{},\"time\":86400.25}}",
+            json_string(&path.to_string_lossy())
+        );
+        assert!(handle(&epoch, &clock).starts_with("{\"ok\":true"));
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            FileTime::from_last_access_time(&metadata),
+            FileTime::from_unix_time(86_400, 250_000_000)
+        );
+
+        // "now", given or implied by giving no times, is the server's clock.
+        let now = format!(
+            "{{\"path\":{},\"mtime\":\"now\"}}",
+            json_string(&path.to_string_lossy())
+        );
+        assert!(handle(&now, &clock).starts_with("{\"ok\":true"));
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), clock.0);
+        let bare = format!("{{\"path\":{}}}", json_string(&path.to_string_lossy()));
+        assert!(handle(&bare, &clock).starts_with("{\"ok\":true"));
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_access_time(&metadata), clock.0);
+        let missing = TouchOptions {
+            no_create: true,
+            ..TouchOptions::now()
+        };
+        let reply = handle(&request_line(&dir.join("missing"), &missing), &clock);
+        assert!(reply.starts_with("{\"ok\":true"), "{}", reply);
+        assert!(!dir.join("missing").exists());
+
+        for bad in [
+            "nonsense",
+            "{\"mtime\":\"now\"}",
+            "{\"path\":\"x\",\"mtime\":\"someday\"}",
+        ] {
+            assert!(handle(bad, &clock).starts_with("{\"ok\":false"), "{}", bad);
+        }
+        let reply = handle(
+            &request_line(&dir.join("no dir").join("x"), &TouchOptions::now()),
+            &clock,
+        );
+        assert!(reply.contains("\"error\":"), "{}", reply);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
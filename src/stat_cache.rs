@@ -0,0 +1,144 @@
+//! Reading each operand's metadata once per run (`--stat-cache`).
+//!
+//! Several checks look at an operand before it is touched: `--apply-to` for whether it is
+//! a directory, `--attr-filter` and `--skip-readonly` for its attributes, and
+//! `--report-unchanged` and `--audit-log` for its times. Each of them reads the metadata on
+//! its own, so combining them costs one read apiece. With the cache, the first read is
+//! kept and the others are answered from it.
+//!
+//! Entries are keyed by the operand's absolute path with `.` and `..` taken out, worked
+//! out without asking the filesystem: resolving links to a canonical path would itself cost
+//! more than the read being saved. Metadata read through a link and of the link itself are
+//! kept apart. An operand's entry is dropped once it has been touched, since its times are
+//! no longer what was read, so the next read of it goes to the disk again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// What a read gave: the metadata, or enough of the error to give it again.
+type Entry = Result<fs::Metadata, (Option<i32>, io::ErrorKind)>;
+
+/// Metadata read during a run, shared by the checks made on each operand.
+#[derive(Debug, Default)]
+pub struct StatCache {
+    /// `None` when caching is off, so every lookup reads from the disk.
+    entries: Option<Mutex<HashMap<(PathBuf, bool), Entry>>>,
+    lookups: AtomicUsize,
+    reads: AtomicUsize,
+}
+
+impl StatCache {
+    /// A cache that keeps what it reads when `enabled`, or otherwise reads every time.
+    pub fn new(enabled: bool) -> Self {
+        StatCache {
+            entries: enabled.then(Mutex::default),
+            ..StatCache::default()
+        }
+    }
+
+    /// The metadata of `path`, following links as [`fs::metadata`] does.
+    pub fn metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+        self.lookup(path, true)
+    }
+
+    /// The metadata of `path` itself, as [`fs::symlink_metadata`] reads it.
+    pub fn symlink_metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+        self.lookup(path, false)
+    }
+
+    fn lookup(&self, path: &Path, follow: bool) -> io::Result<fs::Metadata> {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let read = || {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            if follow {
+                fs::metadata(path)
+            } else {
+                fs::symlink_metadata(path)
+            }
+        };
+        let Some(entries) = &self.entries else {
+            return read();
+        };
+        let key = (mdtouch::normalize_path(path), follow);
+        if let Some(entry) = entries.lock().unwrap().get(&key) {
+            return entry.clone().map_err(rebuild_error);
+        }
+        // Read without holding the lock, so other workers are not kept waiting on the disk.
+        let result = read();
+        let entry = match &result {
+            Ok(metadata) => Ok(metadata.clone()),
+            Err(e) => Err((e.raw_os_error(), e.kind())),
+        };
+        entries.lock().unwrap().insert(key, entry);
+        result
+    }
+
+    /// Drops what was read about `path`, once it has been changed.
+    pub fn forget(&self, path: &Path) {
+        if let Some(entries) = &self.entries {
+            let path = mdtouch::normalize_path(path);
+            let mut entries = entries.lock().unwrap();
+            entries.remove(&(path.clone(), true));
+            entries.remove(&(path, false));
+        }
+    }
+
+    /// How many times metadata was asked for.
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    /// How many of those were read from the disk.
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+/// Gives a cached error again, with its OS error code where it had one.
+fn rebuild_error((code, kind): (Option<i32>, io::ErrorKind)) -> io::Error {
+    match code {
+        Some(code) => io::Error::from_raw_os_error(code),
+        None => kind.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_once_until_forgotten() {
+        let path = std::env::temp_dir().join(format!("mdtouch_stat_cache_{}", std::process::id()));
+        fs::write(&path, b"").unwrap();
+        let cache = StatCache::new(true);
+        assert!(cache.metadata(&path).is_ok());
+        let dotted = path
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(path.file_name().unwrap());
+        assert!(cache.metadata(&dotted).is_ok());
+        assert!(cache.symlink_metadata(&path).is_ok());
+        assert_eq!((cache.lookups(), cache.reads()), (3, 2));
+
+        fs::remove_file(&path).unwrap();
+        assert!(cache.metadata(&path).is_ok());
+        cache.forget(&path);
+        let err = cache.metadata(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            cache.metadata(&path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!((cache.lookups(), cache.reads()), (6, 3));
+
+        let uncached = StatCache::new(false);
+        assert!(uncached.metadata(&path).is_err());
+        assert!(uncached.metadata(&path).is_err());
+        assert_eq!((uncached.lookups(), uncached.reads()), (2, 2));
+    }
+}
@@ -0,0 +1,270 @@
+//! Creating files under names nothing has yet: a uniquely named temporary file, as
+//! `mktemp` does (`--temp`), and a numbered copy of a name that is taken (`--unique`).
+//!
+//! A temporary file's name is the prefix, a random part and the suffix. A numbered name
+//! follows a pattern, `report (2).txt` by default. Either way the file is created with
+//! the same single open as [`TouchOptions::exclusive`], so it is never one that was
+//! already there: a name that is taken is given up for the next one, as many times as it
+//! takes within reason.
+
+use std::collections::hash_map::RandomState;
+use std::ffi::{OsStr, OsString};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{is_separator, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{create_new, flush_if_asked, normalize_path, set_handle_times, TouchOptions};
+
+/// How the name of a file is numbered when it is taken, unless told otherwise: `{stem}`
+/// is the name without its extension, `{ext}` the extension with its dot, and `{n}` the
+/// number, counting from 2.
+pub const UNIQUE_PATTERN: &str = "{stem} ({n}){ext}";
+
+/// The highest number [`create_unique_file`] tries before giving up.
+const UNIQUE_LIMIT: u32 = 9999;
+
+/// How many taken names to go through before giving up. With 48 random bits a name is
+/// taken only by another process that happened on the same one, so running out means
+/// something is wrong with the directory rather than that it is busy.
+const ATTEMPTS: usize = 1000;
+
+/// Creates an empty file in `dir` named `prefix`, a random part, then `suffix`, sets its
+/// times from `options`, and returns its absolute path. Only the times and `flush` are
+/// taken from `options`: the file is always new.
+pub fn create_temp_file(
+    dir: &Path,
+    prefix: &str,
+    suffix: &str,
+    options: &TouchOptions,
+) -> io::Result<PathBuf> {
+    if prefix.chars().chain(suffix.chars()).any(is_separator) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the prefix and suffix of a temporary file cannot contain a path separator",
+        ));
+    }
+    let candidates =
+        (0..ATTEMPTS).map(|_| dir.join(format!("{}{}{}", prefix, random_part(), suffix)));
+    match create_first_free(candidates, options)? {
+        Some(path) => Ok(normalize_path(&path)),
+        None => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("no free name found after {} tries", ATTEMPTS),
+        )),
+    }
+}
+
+/// Creates an empty file at `path`, or if something is there already, at the first free
+/// name numbered by `pattern` (see [`UNIQUE_PATTERN`]) in the same directory, and sets
+/// its times from `options`. Returns the path it was created at, in the form `path` was
+/// given in. Only the times and `flush` are taken from `options`.
+pub fn create_unique_file(
+    path: &Path,
+    pattern: &str,
+    options: &TouchOptions,
+) -> io::Result<PathBuf> {
+    check_unique_pattern(pattern)?;
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no file name to number",
+        ));
+    };
+    let name = Path::new(name);
+    let (stem, ext) = (name.file_stem().unwrap_or_default(), name.extension());
+    let numbered =
+        (2..=UNIQUE_LIMIT).map(|n| path.with_file_name(numbered_name(pattern, stem, ext, n)));
+    create_first_free(std::iter::once(path.to_path_buf()).chain(numbered), options)?.ok_or_else(
+        || {
+            io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("every name numbered up to {} is taken", UNIQUE_LIMIT),
+            )
+        },
+    )
+}
+
+/// Expands `pattern` for the number `n`. The pattern is read once, left to right, so braces
+/// in the stem or extension are never taken for more of it.
+fn numbered_name(pattern: &str, stem: &OsStr, ext: Option<&OsStr>, n: u32) -> OsString {
+    let mut name = OsString::new();
+    let mut rest = pattern;
+    while let Some(at) = rest.find('{') {
+        name.push(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("{stem}") {
+            name.push(stem);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{ext}") {
+            if let Some(ext) = ext {
+                name.push(".");
+                name.push(ext);
+            }
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{n}") {
+            name.push(n.to_string());
+            rest = after;
+        } else {
+            name.push("{");
+            rest = &rest[1..];
+        }
+    }
+    name.push(rest);
+    name
+}
+
+/// Checks a pattern for [`create_unique_file`]: it must number the name, and stay in the
+/// same directory.
+fn check_unique_pattern(pattern: &str) -> io::Result<()> {
+    let reason = if !pattern.contains("{n}") {
+        "it has no {n} for the number"
+    } else if pattern.chars().any(is_separator) {
+        "it cannot contain a path separator"
+    } else {
+        return Ok(());
+    };
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid --unique-pattern '{}': {}", pattern, reason),
+    ))
+}
+
+/// Creates the first of `candidates` that does not exist yet, or returns `None` if every
+/// one of them does.
+fn create_first_free(
+    candidates: impl Iterator<Item = PathBuf>,
+    options: &TouchOptions,
+) -> io::Result<Option<PathBuf>> {
+    for path in candidates {
+        let file = match create_new(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        set_handle_times(&file, options.atime, options.mtime)?;
+        flush_if_asked(&file, &path, options, true)?;
+        return Ok(Some(path));
+    }
+    Ok(None)
+}
+
+/// Twelve random hexadecimal digits. The standard library's hasher keys are random, and
+/// a counter keeps calls in the same instant apart.
+fn random_part() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(std::process::id());
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos()),
+    );
+    format!("{:012x}", hasher.finish() & 0xFFFF_FFFF_FFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::fs;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mdtouch_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parallel_temp_files_are_unique() {
+        let dir = scratch_dir("temp_parallel");
+        let options = TouchOptions::now();
+        let paths: Vec<PathBuf> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..25)
+                            .map(|_| create_temp_file(&dir, "build-", ".log", &options).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        assert_eq!(paths.iter().collect::<HashSet<_>>().len(), 200);
+        for path in &paths {
+            assert!(path.is_absolute() && path.is_file(), "{}", path.display());
+            let name = path.file_name().unwrap().to_string_lossy();
+            assert!(
+                name.starts_with("build-") && name.ends_with(".log"),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 200);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_numbered_name() {
+        let name = |pattern, stem, ext: Option<&str>| {
+            numbered_name(pattern, OsStr::new(stem), ext.map(OsStr::new), 2)
+        };
+        assert_eq!(
+            name(UNIQUE_PATTERN, "report", Some("txt")),
+            "report (2).txt"
+        );
+        assert_eq!(name(UNIQUE_PATTERN, "Makefile", None), "Makefile (2)");
+        assert_eq!(name("{n}-{stem}{ext}.{x}", "a", Some("b")), "2-a.b.{x}");
+        // What the stem and extension hold is never expanded.
+        assert_eq!(
+            name(UNIQUE_PATTERN, "a{n}", Some("{ext}")),
+            "a{n} (2).{ext}"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::{OsStrExt, OsStringExt};
+            let stem = OsStr::from_bytes(b"caf\xe9");
+            assert_eq!(
+                numbered_name(UNIQUE_PATTERN, stem, Some(OsStr::new("txt")), 3).into_vec(),
+                b"caf\xe9 (3).txt"
+            );
+        }
+    }
+
+    #[test]
+    fn test_taken_names_are_retried() {
+        let dir = scratch_dir("temp_taken");
+        let taken = dir.join("taken");
+        fs::write(&taken, b"keep").unwrap();
+        let old = crate::FileTime::from_unix_time(1_000_000_000, 0);
+        let options = TouchOptions {
+            atime: Some(old),
+            mtime: Some(old),
+            ..TouchOptions::default()
+        };
+        let created = create_first_free([taken.clone(), dir.join("free")].into_iter(), &options)
+            .unwrap()
+            .unwrap();
+        assert_eq!(created, dir.join("free"));
+        assert_eq!(fs::read(&taken).unwrap(), b"keep");
+        let metadata = fs::metadata(&created).unwrap();
+        assert_eq!(crate::FileTime::from_last_modification_time(&metadata), old);
+
+        assert_eq!(
+            create_first_free(std::iter::once(taken), &options).unwrap(),
+            None
+        );
+        assert!(create_temp_file(&dir, "a/b", "", &options).is_err());
+        assert!(create_temp_file(&dir.join("missing"), "", "", &options).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
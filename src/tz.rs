@@ -0,0 +1,479 @@
+//! Named time zones for civil times (`--tz`).
+//!
+//! A zone is either a fixed offset, written as a zone is in a date (`UTC`, `+05:30`), or
+//! with the `tzdb` feature, an IANA name such as `America/New_York`. Named zones are read
+//! from the system's zoneinfo database (`$TZDIR`, or `/usr/share/zoneinfo` and the other
+//! usual places), in the TZif format the `tzdata` package installs. The offset at an
+//! instant comes from the file's transitions, and past the last of them from the POSIX
+//! rule at its end, so daylight saving time is followed for any year.
+//!
+//! Each zone is read once per process and kept for as long as it runs, which keeps
+//! [`TimeZone`] `Copy` like the rest of a [`ParseContext`](crate::ParseContext).
+
+use crate::date::{parse_zone, ParseError};
+
+/// A time zone civil times can be read in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeZone {
+    rules: Rules,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rules {
+    /// Always this many seconds east of UTC.
+    Fixed(i32),
+    #[cfg(feature = "tzdb")]
+    Named(&'static tzdb::Zone),
+}
+
+impl TimeZone {
+    /// A zone that is always `seconds` east of UTC.
+    pub fn fixed(seconds: i32) -> Self {
+        TimeZone {
+            rules: Rules::Fixed(seconds),
+        }
+    }
+
+    /// Finds the zone called `name`: a fixed offset (`UTC`, `+05:30`, `-0800`) or, with the
+    /// `tzdb` feature, a name from the zoneinfo database.
+    pub fn from_name(name: &str) -> Result<Self, ParseError> {
+        if let Some(offset) = parse_zone(name) {
+            return Ok(TimeZone::fixed(offset));
+        }
+        #[cfg(feature = "tzdb")]
+        {
+            tzdb::load(name)
+                .map(|zone| TimeZone {
+                    rules: Rules::Named(zone),
+                })
+                .map_err(|reason| ParseError::new("time zone", name, reason))
+        }
+        #[cfg(not(feature = "tzdb"))]
+        Err(ParseError::new(
+            "time zone",
+            name,
+            "named zones need mdtouch built with the tzdb feature; give an offset such as \
+             -05:00 instead",
+        ))
+    }
+
+    /// The offset from UTC, in seconds east, in force at the instant `unix_seconds`.
+    #[cfg_attr(not(feature = "tzdb"), allow(unused_variables))]
+    pub fn offset_at(&self, unix_seconds: i64) -> i32 {
+        match self.rules {
+            Rules::Fixed(offset) => offset,
+            #[cfg(feature = "tzdb")]
+            Rules::Named(zone) => zone.offset_at(unix_seconds),
+        }
+    }
+}
+
+#[cfg(feature = "tzdb")]
+mod tzdb {
+    use std::collections::HashMap;
+    use std::path::{Component, Path, PathBuf};
+    use std::sync::{Mutex, OnceLock};
+
+    use crate::date::{civil_from_days, days_from_civil, days_in_month, is_leap_year};
+
+    /// Where the zoneinfo database is looked for when `TZDIR` is not set.
+    const DATABASES: [&str; 4] = [
+        "/usr/share/zoneinfo",
+        "/usr/lib/zoneinfo",
+        "/usr/share/lib/zoneinfo",
+        "/etc/zoneinfo",
+    ];
+
+    /// A zone read from the database.
+    #[derive(Debug, PartialEq)]
+    pub struct Zone {
+        /// The offset before the first transition.
+        initial: i32,
+        /// Each instant the offset changes, with the offset from then on.
+        transitions: Vec<(i64, i32)>,
+        /// The rule after the last transition, if the file has one.
+        rule: Option<PosixRule>,
+    }
+
+    impl Zone {
+        pub fn offset_at(&self, unix_seconds: i64) -> i32 {
+            let after = self
+                .transitions
+                .partition_point(|&(at, _)| at <= unix_seconds);
+            match (&self.rule, after) {
+                (Some(rule), after) if after == self.transitions.len() => {
+                    rule.offset_at(unix_seconds)
+                }
+                (_, 0) => self.initial,
+                (_, after) => self.transitions[after - 1].1,
+            }
+        }
+    }
+
+    /// Reads the zone called `name` from the database, or gives the one read before.
+    pub fn load(name: &str) -> Result<&'static Zone, String> {
+        static LOADED: OnceLock<Mutex<HashMap<String, &'static Zone>>> = OnceLock::new();
+
+        let loaded = LOADED.get_or_init(Mutex::default);
+        if let Some(zone) = loaded.lock().unwrap().get(name) {
+            return Ok(zone);
+        }
+        let relative = Path::new(name);
+        if name.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err("not a zone name".to_string());
+        }
+        let databases: Vec<PathBuf> = match std::env::var_os("TZDIR") {
+            Some(dir) => vec![PathBuf::from(dir)],
+            None => DATABASES.iter().map(PathBuf::from).collect(),
+        };
+        let Some(database) = databases.iter().find(|dir| dir.is_dir()) else {
+            return Err("no time zone database found; set TZDIR to its directory".to_string());
+        };
+        let path = database.join(relative);
+        let data =
+            std::fs::read(&path).map_err(|_| format!("no such zone in {}", database.display()))?;
+        let zone =
+            parse_tzif(&data).ok_or_else(|| format!("{} is not a TZif file", path.display()))?;
+        let zone: &'static Zone = Box::leak(Box::new(zone));
+        loaded.lock().unwrap().insert(name.to_string(), zone);
+        Ok(zone)
+    }
+
+    /// Reads big-endian values from the front of a byte slice.
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+            if count > self.0.len() {
+                return None;
+            }
+            let (taken, rest) = self.0.split_at(count);
+            self.0 = rest;
+            Some(taken)
+        }
+
+        fn count(&mut self) -> Option<usize> {
+            let bytes = self.take(4)?;
+            Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+        }
+
+        /// A transition time, four bytes wide in a version 1 block and eight after it.
+        fn time(&mut self, wide: bool) -> Option<i64> {
+            Some(if wide {
+                i64::from_be_bytes(self.take(8)?.try_into().ok()?)
+            } else {
+                i64::from(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+            })
+        }
+    }
+
+    /// Parses a TZif file (RFC 8536). Files of version 2 and later repeat the data with
+    /// 64-bit times and end with a POSIX rule; that copy is the one read when present.
+    pub(super) fn parse_tzif(data: &[u8]) -> Option<Zone> {
+        let mut reader = Reader(data);
+        let (zone, version) = parse_block(&mut reader, false)?;
+        if version < b'2' {
+            return Some(zone);
+        }
+        let (mut zone, _) = parse_block(&mut reader, true)?;
+        let footer = reader.0.strip_prefix(b"\n")?;
+        let end = footer.iter().position(|&b| b == b'\n')?;
+        let rule = std::str::from_utf8(&footer[..end]).ok()?;
+        if !rule.is_empty() {
+            zone.rule = Some(PosixRule::parse(rule)?);
+        }
+        Some(zone)
+    }
+
+    /// Parses one header and the data block after it.
+    fn parse_block(reader: &mut Reader, wide: bool) -> Option<(Zone, u8)> {
+        if reader.take(4)? != b"TZif" {
+            return None;
+        }
+        let version = reader.take(16)?[0];
+        let ut_count = reader.count()?;
+        let std_count = reader.count()?;
+        let leap_count = reader.count()?;
+        let time_count = reader.count()?;
+        let type_count = reader.count()?;
+        let char_count = reader.count()?;
+        let times = (0..time_count)
+            .map(|_| reader.time(wide))
+            .collect::<Option<Vec<_>>>()?;
+        let indices = reader.take(time_count)?.to_vec();
+        let offsets = (0..type_count)
+            .map(|_| {
+                let entry = reader.take(6)?;
+                Some(i32::from_be_bytes(entry[..4].try_into().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let time_size = if wide { 8 } else { 4 };
+        reader.take(char_count + leap_count * (time_size + 4) + std_count + ut_count)?;
+        let transitions = times
+            .into_iter()
+            .zip(indices)
+            .map(|(at, index)| Some((at, *offsets.get(usize::from(index))?)))
+            .collect::<Option<Vec<_>>>()?;
+        let zone = Zone {
+            initial: *offsets.first()?,
+            transitions,
+            rule: None,
+        };
+        Some((zone, version))
+    }
+
+    /// A POSIX `TZ` rule, such as `EST5EDT,M3.2.0,M11.1.0`: a standard offset, and
+    /// optionally a daylight saving offset with the days it starts and ends each year.
+    #[derive(Debug, PartialEq)]
+    pub(super) struct PosixRule {
+        /// The standard offset, in seconds east of UTC.
+        standard: i32,
+        daylight: Option<Daylight>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Daylight {
+        /// The daylight saving offset, in seconds east of UTC.
+        offset: i32,
+        /// The day it starts, and the local standard time of day it starts at.
+        start: (RuleDay, i32),
+        /// The day it ends, and the local daylight saving time of day it ends at.
+        end: (RuleDay, i32),
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum RuleDay {
+        /// `Jn`: day 1 to 365, never counting February 29.
+        Julian(u32),
+        /// `n`: day 0 to 365, counting February 29 in leap years.
+        Zero(u32),
+        /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` of month `m`, where week 5 is
+        /// the last.
+        Month(u32, u32, u32),
+    }
+
+    impl RuleDay {
+        /// The day this falls on in `year`, in days since 1970-01-01.
+        fn in_year(&self, year: i64) -> i64 {
+            let new_year = days_from_civil(year, 1, 1);
+            match *self {
+                RuleDay::Julian(day) => {
+                    new_year + i64::from(day) - 1 + i64::from(is_leap_year(year) && day >= 60)
+                }
+                RuleDay::Zero(day) => new_year + i64::from(day),
+                RuleDay::Month(month, week, weekday) => {
+                    let first = days_from_civil(year, month, 1);
+                    // 1970-01-01 was a Thursday.
+                    let first_weekday = (first + 4).rem_euclid(7);
+                    let mut day = first
+                        + (i64::from(weekday) - first_weekday).rem_euclid(7)
+                        + 7 * (i64::from(week) - 1);
+                    while day >= first + i64::from(days_in_month(year, month)) {
+                        day -= 7;
+                    }
+                    day
+                }
+            }
+        }
+    }
+
+    impl PosixRule {
+        pub(super) fn parse(text: &str) -> Option<Self> {
+            let mut rest = text;
+            skip_name(&mut rest)?;
+            let standard = -parse_offset(&mut rest)?;
+            if rest.is_empty() {
+                return Some(PosixRule {
+                    standard,
+                    daylight: None,
+                });
+            }
+            skip_name(&mut rest)?;
+            let offset = if rest.starts_with([',', ';']) || rest.is_empty() {
+                standard + 3_600
+            } else {
+                -parse_offset(&mut rest)?
+            };
+            // Without dates, the United States rules are the usual default.
+            let (start, end) = match rest.strip_prefix(',') {
+                Some(dates) => {
+                    rest = dates;
+                    let start = parse_transition(&mut rest)?;
+                    rest = rest.strip_prefix(',')?;
+                    (start, parse_transition(&mut rest)?)
+                }
+                None => (
+                    (RuleDay::Month(3, 2, 0), 7_200),
+                    (RuleDay::Month(11, 1, 0), 7_200),
+                ),
+            };
+            if !rest.is_empty() {
+                return None;
+            }
+            Some(PosixRule {
+                standard,
+                daylight: Some(Daylight { offset, start, end }),
+            })
+        }
+
+        pub(super) fn offset_at(&self, unix_seconds: i64) -> i32 {
+            let Some(daylight) = &self.daylight else {
+                return self.standard;
+            };
+            let local = unix_seconds + i64::from(self.standard);
+            let (year, _, _) = civil_from_days(local.div_euclid(86_400));
+            let instant = |(day, time): &(RuleDay, i32), offset: i32| {
+                day.in_year(year) * 86_400 + i64::from(*time) - i64::from(offset)
+            };
+            let start = instant(&daylight.start, self.standard);
+            let end = instant(&daylight.end, daylight.offset);
+            let in_daylight = if start < end {
+                (start..end).contains(&unix_seconds)
+            } else {
+                // Southern hemisphere: daylight saving time spans the new year.
+                !(end..start).contains(&unix_seconds)
+            };
+            if in_daylight {
+                daylight.offset
+            } else {
+                self.standard
+            }
+        }
+    }
+
+    /// Skips a zone abbreviation: three or more letters, or anything in `<` and `>`.
+    fn skip_name(rest: &mut &str) -> Option<()> {
+        let end = match rest.strip_prefix('<') {
+            Some(quoted) => quoted.find('>')? + 2,
+            None => rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len()),
+        };
+        if end < 3 {
+            return None;
+        }
+        *rest = &rest[end..];
+        Some(())
+    }
+
+    /// Reads `[+-]hh[:mm[:ss]]` as seconds. A POSIX offset counts west of UTC, and a
+    /// transition time may run past 24 hours.
+    fn parse_offset(rest: &mut &str) -> Option<i32> {
+        let (sign, unsigned) = match rest.strip_prefix('-') {
+            Some(unsigned) => (-1, unsigned),
+            None => (1, rest.strip_prefix('+').unwrap_or(rest)),
+        };
+        let end = unsigned
+            .find(|c: char| !c.is_ascii_digit() && c != ':')
+            .unwrap_or(unsigned.len());
+        let fields: Vec<&str> = unsigned[..end].split(':').collect();
+        if fields.len() > 3
+            || fields
+                .iter()
+                .any(|field| field.is_empty() || field.len() > 3)
+        {
+            return None;
+        }
+        let seconds = fields
+            .iter()
+            .zip([3_600, 60, 1])
+            .map(|(field, scale)| Some(field.parse::<i32>().ok()? * scale))
+            .sum::<Option<i32>>()?;
+        *rest = &unsigned[end..];
+        Some(sign * seconds)
+    }
+
+    /// Reads a day and an optional `/time`, which is 02:00 without one.
+    fn parse_transition(rest: &mut &str) -> Option<(RuleDay, i32)> {
+        let end = rest.find([',', '/']).unwrap_or(rest.len());
+        let (day, after) = rest.split_at(end);
+        let number = |text: &str| text.parse::<u32>().ok();
+        let day = if let Some(day) = day.strip_prefix('J') {
+            RuleDay::Julian(number(day).filter(|day| (1..=365).contains(day))?)
+        } else if let Some(fields) = day.strip_prefix('M') {
+            let mut fields = fields.split('.').map(number);
+            let (month, week, weekday) = (fields.next()??, fields.next()??, fields.next()??);
+            if fields.next().is_some()
+                || !(1..=12).contains(&month)
+                || !(1..=5).contains(&week)
+                || weekday > 6
+            {
+                return None;
+            }
+            RuleDay::Month(month, week, weekday)
+        } else {
+            RuleDay::Zero(number(day).filter(|day| *day <= 365)?)
+        };
+        *rest = after;
+        let time = match rest.strip_prefix('/') {
+            Some(time) => {
+                *rest = time;
+                parse_offset(rest)?
+            }
+            None => 7_200,
+        };
+        Some((day, time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_zones() {
+        assert_eq!(TimeZone::from_name("+05:30").unwrap().offset_at(0), 19_800);
+        assert_eq!(TimeZone::from_name("UTC").unwrap(), TimeZone::fixed(0));
+        assert!(TimeZone::from_name("../../etc/passwd").is_err());
+    }
+
+    #[cfg(not(feature = "tzdb"))]
+    #[test]
+    fn test_named_zones_need_the_feature() {
+        let err = TimeZone::from_name("America/New_York").unwrap_err();
+        assert!(err.reason().contains("tzdb"), "{}", err);
+    }
+
+    #[cfg(feature = "tzdb")]
+    #[test]
+    fn test_posix_rules() {
+        use tzdb::PosixRule;
+
+        let new_york = PosixRule::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        // 2024-03-10 06:59:59Z is 01:59:59 EST, and a second later is 03:00 EDT.
+        assert_eq!(new_york.offset_at(1_710_053_999), -18_000);
+        assert_eq!(new_york.offset_at(1_710_054_000), -14_400);
+        // 2024-11-03 05:59:59Z is 01:59:59 EDT, and a second later is 01:00 EST.
+        assert_eq!(new_york.offset_at(1_730_613_599), -14_400);
+        assert_eq!(new_york.offset_at(1_730_613_600), -18_000);
+
+        // Daylight saving time across the new year, ending at 03:00 local.
+        let sydney = PosixRule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.offset_at(1_704_067_200), 39_600);
+        assert_eq!(sydney.offset_at(1_719_792_000), 36_000);
+
+        let quoted = PosixRule::parse("<+0530>-5:30").unwrap();
+        assert_eq!(quoted.offset_at(0), 19_800);
+        assert!(PosixRule::parse("EST").is_none());
+        assert!(PosixRule::parse("EST5EDT,M13.1.0,M11.1.0").is_none());
+    }
+
+    #[cfg(feature = "tzdb")]
+    #[test]
+    fn test_database_zone() {
+        // Only where the system has a zoneinfo database to read.
+        let Ok(zone) = TimeZone::from_name("America/New_York") else {
+            return;
+        };
+        // 1990, 2024 and 2100 are read from transitions or from the closing rule.
+        assert_eq!(zone.offset_at(631_152_000), -18_000);
+        assert_eq!(zone.offset_at(1_720_000_000), -14_400);
+        assert_eq!(zone.offset_at(4_102_444_800), -18_000);
+        assert_eq!(zone.offset_at(4_102_444_800 + 180 * 86_400), -14_400);
+        assert!(TimeZone::from_name("America/Nowhere").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Environment-variable expansion in operands.
+//!
+//! cmd.exe expands `%VAR%` on the command line, but PowerShell, the Task Scheduler and
+//! programs that start mdtouch directly pass it through unchanged. On Windows, `%VAR%` in an
+//! operand is therefore expanded by mdtouch itself. With `--dollar-vars`, `$VAR` and `${VAR}`
+//! are expanded as well, on any platform. A `%` or `$` that does not start a well-formed
+//! reference is kept as written, as is a reference to a variable that is not defined
+//! (`--strict-env` turns that into an error).
+
+use std::env;
+
+/// Expands the environment variables referenced in `text`, leaving references to
+/// undefined variables as written.
+pub fn expand_env_vars(text: &str, dollar: bool) -> String {
+    expand_with(text, cfg!(windows), dollar, |name| env::var(name).ok()).0
+}
+
+/// Returns the names of the variables referenced in `text` that are not defined.
+pub fn undefined_vars(text: &str, dollar: bool) -> Vec<String> {
+    expand_with(text, cfg!(windows), dollar, |name| env::var(name).ok()).1
+}
+
+/// Expands `%NAME%` references (if `percent`) and `$NAME`/`${NAME}` references (if
+/// `dollar`) using `lookup`. Returns the expanded text and the names `lookup` did not know.
+fn expand_with<F>(text: &str, percent: bool, dollar: bool, lookup: F) -> (String, Vec<String>)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(text.len());
+    let mut undefined = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c| (percent && c == '%') || (dollar && c == '$')) {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = if rest.starts_with('%') {
+            percent_reference(rest)
+        } else {
+            dollar_reference(rest)
+        };
+        match reference {
+            Some((name, len)) => {
+                match lookup(name) {
+                    Some(value) => expanded.push_str(&value),
+                    None => {
+                        expanded.push_str(&rest[..len]);
+                        undefined.push(name.to_string());
+                    }
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                expanded.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    (expanded, undefined)
+}
+
+/// Parses a `%NAME%` reference at the start of `text`, returning the name and the length
+/// of the whole reference. The name can't be empty or contain a path separator, so the
+/// `%` signs in a name like `100%\50%` are left alone.
+fn percent_reference(text: &str) -> Option<(&str, usize)> {
+    let end = text[1..].find('%')? + 1;
+    let name = &text[1..end];
+    if name.is_empty() || name.contains(['\\', '/']) {
+        return None;
+    }
+    Some((name, end + 1))
+}
+
+/// Parses a `$NAME` or `${NAME}` reference at the start of `text`, returning the name and
+/// the length of the whole reference. A bare name is a letter or underscore followed by
+/// letters, digits and underscores, as in POSIX shells.
+fn dollar_reference(text: &str) -> Option<(&str, usize)> {
+    if let Some(braced) = text.strip_prefix("${") {
+        let end = braced.find('}')?;
+        let name = &braced[..end];
+        return (!name.is_empty()).then_some((name, end + 3));
+    }
+    let name_len = text[1..]
+        .char_indices()
+        .find(|&(i, c)| !(c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())))
+        .map_or(text.len() - 1, |(i, _)| i);
+    (name_len > 0).then_some((&text[1..=name_len], name_len + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "TEMP" => Some(r"C:\Temp".to_string()),
+            "ProgramFiles(x86)" => Some(r"C:\Program Files (x86)".to_string()),
+            "HOME" => Some("/home/me".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn expand(text: &str, percent: bool, dollar: bool) -> (String, Vec<String>) {
+        expand_with(text, percent, dollar, lookup)
+    }
+
+    #[test]
+    fn test_percent_references() {
+        assert_eq!(
+            expand(r"%TEMP%\file.txt", true, false).0,
+            r"C:\Temp\file.txt"
+        );
+        assert_eq!(
+            expand(r"%ProgramFiles(x86)%\app", true, false).0,
+            r"C:\Program Files (x86)\app"
+        );
+        assert_eq!(expand(r"%EMPTY%\*", true, false).0, r"\*");
+        // Stray or malformed percent signs are literal.
+        for literal in ["100%", "%%", r"50%\x%", "a%b"] {
+            assert_eq!(expand(literal, true, false), (literal.to_string(), vec![]));
+        }
+        // Without percent expansion, references are left alone.
+        assert_eq!(expand("%TEMP%", false, false).0, "%TEMP%");
+    }
+
+    #[test]
+    fn test_dollar_references() {
+        assert_eq!(expand("$HOME/notes", false, true).0, "/home/me/notes");
+        assert_eq!(expand("${HOME}x", false, true).0, "/home/mex");
+        assert_eq!(expand("$HOMEx", false, true).1, vec!["HOMEx"]);
+        for literal in ["$", "$1", "${", "${}", "cost$"] {
+            assert_eq!(expand(literal, false, true), (literal.to_string(), vec![]));
+        }
+        assert_eq!(expand("$HOME", true, false).0, "$HOME");
+    }
+
+    #[test]
+    fn test_undefined_references_are_kept_and_reported() {
+        let (expanded, undefined) = expand(r"%NOPE%\${ALSO_NOPE}\%TEMP%", true, true);
+        assert_eq!(expanded, r"%NOPE%\${ALSO_NOPE}\C:\Temp");
+        assert_eq!(undefined, vec!["NOPE", "ALSO_NOPE"]);
+    }
+}
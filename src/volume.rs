@@ -0,0 +1,345 @@
+//! Adapting to the filesystem each operand lives on.
+//!
+//! Before a file is touched, the filesystem of the volume holding it is looked up, once
+//! per directory. Times that filesystem can't store at all, such as a 1970 date on FAT,
+//! are refused with a message saying so, instead of failing in the OS or being clamped
+//! without a word; times it will store less precisely than given are noted once per
+//! volume, and also allowed for when `--report-unchanged` asks whether a file already has
+//! its times. The lookup goes through [`Detect`], so the adaptation can be tested for any
+//! filesystem without the media.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use mdtouch::date::format_iso8601;
+use mdtouch::FileTime;
+
+use crate::probe::{format_duration, resolution_of};
+
+/// A volume and its filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    /// Tells volumes apart: the volume serial number on Windows, the device ID elsewhere.
+    pub id: u64,
+    /// The filesystem's name, such as `NTFS` or `vfat`.
+    pub filesystem: String,
+}
+
+/// Finds out which volume a directory is on.
+pub trait Detect: Sync {
+    /// The volume holding the existing directory `dir`, if it can be found out.
+    fn detect(&self, dir: &Path) -> Option<Volume>;
+}
+
+/// Asks the OS: `GetVolumeInformationByHandleW` on Windows, the device ID and
+/// `/proc/self/mountinfo` on Linux. Elsewhere nothing is detected.
+#[derive(Debug, Default)]
+pub struct SystemDetector {
+    /// The mount table, read the first time it is needed.
+    #[cfg(target_os = "linux")]
+    mounts: std::sync::OnceLock<Option<String>>,
+}
+
+impl Detect for SystemDetector {
+    #[cfg(windows)]
+    fn detect(&self, dir: &Path) -> Option<Volume> {
+        use std::os::windows::fs::OpenOptionsExt;
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Storage::FileSystem::{
+            GetVolumeInformationByHandleW, FILE_FLAG_BACKUP_SEMANTICS, FILE_READ_ATTRIBUTES,
+        };
+
+        let dir = std::fs::OpenOptions::new()
+            .access_mode(FILE_READ_ATTRIBUTES)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(dir)
+            .ok()?;
+        let mut serial = 0u32;
+        let mut name = [0u16; 261];
+        // SAFETY: the handle is open for the duration of the call, and the name buffer's
+        // length is passed along with it.
+        let found = unsafe {
+            GetVolumeInformationByHandleW(
+                dir.as_raw_handle(),
+                std::ptr::null_mut(),
+                0,
+                &mut serial,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                name.as_mut_ptr(),
+                name.len() as u32,
+            )
+        };
+        if found == 0 {
+            return None;
+        }
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        Some(Volume {
+            id: u64::from(serial),
+            filesystem: String::from_utf16_lossy(&name[..len]),
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn detect(&self, dir: &Path) -> Option<Volume> {
+        use std::os::unix::fs::MetadataExt;
+
+        let id = std::fs::metadata(dir).ok()?.dev();
+        let mounts = self
+            .mounts
+            .get_or_init(|| std::fs::read_to_string("/proc/self/mountinfo").ok())
+            .as_deref()?;
+        let (filesystem, _) = crate::probe::find_mount(mounts, &dir.canonicalize().ok()?)?;
+        Some(Volume { id, filesystem })
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    fn detect(&self, _dir: &Path) -> Option<Volume> {
+        None
+    }
+}
+
+/// The volumes met during a run, looked up once per directory.
+#[derive(Debug)]
+pub struct Volumes<D> {
+    detector: D,
+    /// Keyed by the directory as spelled, which hashes much faster than a `Path`.
+    by_dir: Mutex<HashMap<OsString, Option<Arc<Volume>>>>,
+    /// The volumes already handed out.
+    met: Mutex<HashSet<u64>>,
+}
+
+impl<D: Detect> Volumes<D> {
+    pub fn new(detector: D) -> Self {
+        Volumes {
+            detector,
+            by_dir: Mutex::new(HashMap::new()),
+            met: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The volume holding `path`, found through its directory, and whether this is the
+    /// first time that volume has come up.
+    pub fn lookup(&self, path: &Path) -> Option<(Arc<Volume>, bool)> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let cached = self.by_dir.lock().unwrap().get(dir.as_os_str()).cloned();
+        let volume = match cached {
+            Some(volume) => volume?,
+            None => {
+                let volume = self.detector.detect(dir).map(Arc::new);
+                self.by_dir
+                    .lock()
+                    .unwrap()
+                    .insert(dir.as_os_str().to_os_string(), volume.clone());
+                volume?
+            }
+        };
+        let first = self.met.lock().unwrap().insert(volume.id);
+        Some((volume, first))
+    }
+}
+
+/// The earliest and latest times FAT and exFAT can store: 1980-01-01 and 2107-12-31.
+const FAT_RANGE: (i64, i64) = (315_532_800, 4_354_819_199);
+
+/// Checks that `filesystem` can store the access, modification and creation times
+/// about to be set. Fails with a message naming a time it can't store at all, and
+/// otherwise returns a note for each time it will store less precisely than given.
+pub fn check_times(filesystem: &str, times: [Option<FileTime>; 3]) -> Result<Vec<String>, String> {
+    let name = filesystem.to_ascii_lowercase();
+    let fat = matches!(
+        name.as_str(),
+        "fat" | "fat12" | "fat16" | "fat32" | "vfat" | "msdos" | "exfat"
+    );
+    let resolution = resolution_of(filesystem).map(|(modified, accessed)| {
+        let created = match name.as_str() {
+            "ntfs" | "ntfs3" | "refs" => 100,
+            _ if fat => 10_000_000,
+            _ => modified,
+        };
+        [accessed, modified, created]
+    });
+    let mut notes = Vec::new();
+    for (index, label) in ["access", "modification", "creation"]
+        .into_iter()
+        .enumerate()
+    {
+        let Some(time) = times[index] else {
+            continue;
+        };
+        if fat && !(FAT_RANGE.0..=FAT_RANGE.1).contains(&time.unix_seconds()) {
+            return Err(format!(
+                "{} cannot store times before 1980 or after 2107, such as the {} time {}",
+                filesystem,
+                label,
+                format_iso8601(time)
+            ));
+        }
+        let Some(step) = resolution.map(|resolution| resolution[index]) else {
+            continue;
+        };
+        let nanos =
+            i128::from(time.unix_seconds()) * 1_000_000_000 + i128::from(time.nanoseconds());
+        if nanos.rem_euclid(i128::from(step)) != 0 {
+            notes.push(format!(
+                "{} keeps {} times to {}, so finer times are rounded",
+                filesystem,
+                label,
+                format_duration(step)
+            ));
+        }
+    }
+    Ok(notes)
+}
+
+/// Returns whether a file already has the access and modification times about to be set,
+/// as far as `filesystem` (when known) keeps them: a time it would round is taken as set
+/// when it falls in the same step. Times not being set always count as set.
+pub fn already_set(
+    filesystem: Option<&str>,
+    times: [Option<FileTime>; 2],
+    current: [FileTime; 2],
+) -> bool {
+    let resolution = filesystem
+        .and_then(resolution_of)
+        .map_or([1, 1], |(modified, accessed)| [accessed, modified]);
+    let nanos = |time: FileTime| {
+        i128::from(time.unix_seconds()) * 1_000_000_000 + i128::from(time.nanoseconds())
+    };
+    times
+        .iter()
+        .zip(current)
+        .zip(resolution)
+        .all(|((time, current), step)| {
+            time.is_none_or(|time| {
+                let step = i128::from(step);
+                nanos(time).div_euclid(step) == nanos(current).div_euclid(step)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Puts every directory whose name starts with `usb` on a FAT32 volume, and the
+    /// rest on NTFS, counting the lookups.
+    #[derive(Debug, Default)]
+    struct FakeDetector {
+        lookups: AtomicUsize,
+    }
+
+    impl Detect for FakeDetector {
+        fn detect(&self, dir: &Path) -> Option<Volume> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            let name = dir.file_name()?.to_str()?;
+            Some(if name.starts_with("usb") {
+                Volume {
+                    id: 2,
+                    filesystem: "FAT32".to_string(),
+                }
+            } else {
+                Volume {
+                    id: 1,
+                    filesystem: "NTFS".to_string(),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_lookup_caches_per_directory_and_reports_new_volumes() {
+        let volumes = Volumes::new(FakeDetector::default());
+        let (first, new) = volumes.lookup(Path::new("usb1/a.txt")).unwrap();
+        assert_eq!((first.filesystem.as_str(), new), ("FAT32", true));
+        // Same directory: no new lookup. Same volume through another directory: looked up,
+        // but not new.
+        assert!(!volumes.lookup(Path::new("usb1/b.txt")).unwrap().1);
+        assert!(!volumes.lookup(Path::new("usb2/c.txt")).unwrap().1);
+        assert_eq!(volumes.detector.lookups.load(Ordering::Relaxed), 2);
+        let (other, new) = volumes.lookup(Path::new("data/d.txt")).unwrap();
+        assert_eq!((other.filesystem.as_str(), new), ("NTFS", true));
+        // A bare file name is looked up through the current directory.
+        assert!(volumes.lookup(Path::new("e.txt")).is_none());
+    }
+
+    #[test]
+    fn test_check_times_per_filesystem() {
+        let at = |seconds, nanos| Some(FileTime::from_unix_time(seconds, nanos));
+        let even = at(1_700_000_000, 0);
+        let odd = at(1_700_000_001, 500_000_000);
+        let before_fat = at(0, 0);
+
+        // NTFS and ext4 store anything at full precision.
+        assert_eq!(check_times("NTFS", [odd, odd, odd]), Ok(vec![]));
+        assert_eq!(check_times("ext4", [before_fat, odd, None]), Ok(vec![]));
+        // FAT refuses times outside its range, whichever one it is.
+        for filesystem in ["FAT32", "vfat", "exFAT"] {
+            let err = check_times(filesystem, [None, before_fat, None]).unwrap_err();
+            assert!(err.contains("before 1980"), "{}", err);
+            assert!(
+                err.contains("modification time 1970-01-01T00:00:00"),
+                "{}",
+                err
+            );
+            assert!(check_times(filesystem, [None, None, at(4_354_819_200, 0)]).is_err());
+        }
+        // FAT keeps modification times to 2 s and access times to the day.
+        assert_eq!(
+            check_times("FAT32", [None, odd, None]),
+            Ok(vec![
+                "FAT32 keeps modification times to 2 s, so finer times are rounded".to_string()
+            ])
+        );
+        let notes = check_times("FAT32", [even, even, even]).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].contains("access times to 1 day"), "{}", notes[0]);
+        // exFAT keeps modification and creation times to 10 ms.
+        assert_eq!(check_times("exFAT", [None, even, even]), Ok(vec![]));
+        assert_eq!(
+            check_times("exFAT", [None, at(1_700_000_000, 5), None])
+                .unwrap()
+                .len(),
+            1
+        );
+        // Nothing is known about other filesystems' precision.
+        assert_eq!(check_times("smbfs", [odd, odd, odd]), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_already_set_within_the_filesystem_precision() {
+        let at = |seconds, nanos| FileTime::from_unix_time(seconds, nanos);
+        let wanted = at(1_700_000_001, 500_000_000);
+        // FAT would store 1_700_000_000, so a file with that time already has it.
+        let stored = at(1_700_000_000, 0);
+        assert!(already_set(
+            Some("FAT32"),
+            [None, Some(wanted)],
+            [stored, stored]
+        ));
+        assert!(!already_set(
+            Some("NTFS"),
+            [None, Some(wanted)],
+            [stored, stored]
+        ));
+        assert!(!already_set(None, [None, Some(wanted)], [stored, stored]));
+        assert!(already_set(
+            None,
+            [Some(wanted), Some(wanted)],
+            [wanted, wanted]
+        ));
+        assert!(!already_set(
+            Some("FAT32"),
+            [None, Some(wanted)],
+            [stored, at(1_699_999_999, 0)]
+        ));
+        // With nothing being set, any file is as it should be.
+        assert!(already_set(Some("ext4"), [None, None], [stored, wanted]));
+    }
+}
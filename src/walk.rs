@@ -0,0 +1,580 @@
+//! Recursive expansion of directory operands (`-R`).
+//!
+//! A directory operand stands for itself followed by everything below it, depth first. By
+//! default the walk does not descend through symbolic links or junctions, which could lead
+//! out of the tree or round in a loop; a link is listed like any other entry. With
+//! `--follow-links` it does, but never into a directory it has already walked, so a link
+//! back up the tree is not followed round. Entries an attribute filter excludes
+//! (`--skip-hidden`, `--skip-system`) are left out, and so is everything below them. With
+//! `--one-file-system`, so are directories on another volume than the operand, such as a
+//! drive mounted into a folder, and with `--exclude`, entries matching a pattern. Unless
+//! `--allow-system` is given, entries inside a system directory are held back and
+//! reported, and nothing below them is walked.
+//!
+//! Entries come in the order the filesystem lists them, which differs between filesystems.
+//! With `--sorted` each directory is read whole and its entries listed by name instead,
+//! which holds every entry of the directories on the current path in memory.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::attrs::{self, AttrFilter};
+use crate::exclude::Excludes;
+use crate::guard::SystemGuard;
+
+/// What the walk finds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Found {
+    /// A path at or below the operand.
+    Path(PathBuf),
+    /// A link to a directory, reported just before the link itself is listed.
+    Link(PathBuf, Crossing),
+    /// A directory, or a link to one, on another volume than the operand. It is left out,
+    /// along with everything below it.
+    OtherVolume(PathBuf),
+    /// An entry inside the given system directory. It is left out, along with everything
+    /// below it.
+    Blocked(PathBuf, PathBuf),
+}
+
+/// The order the entries of a directory are listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// As the filesystem lists them.
+    #[default]
+    Listed,
+    /// By name, in exact case.
+    Name,
+    /// By name ignoring case, as Windows sorts them, with names that differ only in case
+    /// in exact-case order.
+    NameIgnoringCase,
+}
+
+/// The entries of a directory being read.
+#[derive(Debug)]
+enum Entries {
+    // Boxed, as a ReadDir is large on Windows.
+    Listed(Box<fs::ReadDir>),
+    Sorted(std::vec::IntoIter<io::Result<fs::DirEntry>>),
+}
+
+impl Entries {
+    /// Starts listing the entries read from `read_dir` in `order`. Errors reading them come
+    /// after the entries when sorted.
+    fn new(read_dir: fs::ReadDir, order: Order) -> Self {
+        let ignore_case = match order {
+            Order::Listed => return Entries::Listed(Box::new(read_dir)),
+            Order::Name => false,
+            Order::NameIgnoringCase => true,
+        };
+        let (mut entries, errors): (Vec<_>, Vec<_>) = read_dir.partition(Result::is_ok);
+        entries.sort_by_cached_key(|entry| {
+            let name = entry
+                .as_ref()
+                .map(fs::DirEntry::file_name)
+                .unwrap_or_default();
+            let folded = ignore_case.then(|| name.to_string_lossy().to_lowercase());
+            (folded, name)
+        });
+        entries.extend(errors);
+        Entries::Sorted(entries.into_iter())
+    }
+}
+
+impl Iterator for Entries {
+    type Item = io::Result<fs::DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Entries::Listed(read_dir) => read_dir.next(),
+            Entries::Sorted(entries) => entries.next(),
+        }
+    }
+}
+
+/// What became of a link to a directory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Crossing {
+    /// It was not followed, as links aren't by default.
+    NotFollowed,
+    /// It was followed into the given directory.
+    Followed(PathBuf),
+    /// It leads to the given directory, which the walk has already been through.
+    Walked(PathBuf),
+}
+
+/// The paths at and below one operand, listed as the walk reaches them.
+///
+/// Directories are read one at a time as the walk descends, so only the directories on the
+/// current path are held open. A directory that can't be read yields its error in place of
+/// its entries, and the walk carries on with the rest of the tree.
+#[derive(Debug)]
+pub struct Walk {
+    /// The operand itself, until it has been listed.
+    root: Option<PathBuf>,
+    /// The operand, which `--exclude` patterns are matched below.
+    base: PathBuf,
+    /// The directories being read, innermost last.
+    stack: Vec<(PathBuf, Entries)>,
+    /// The order entries are listed in.
+    order: Order,
+    /// An error opening a directory, to be listed straight after the directory.
+    error: Option<(PathBuf, io::Error)>,
+    /// The attributes entries below the operand must have or lack.
+    filter: Option<AttrFilter>,
+    /// The patterns entries below the operand must not match.
+    excludes: Option<Excludes>,
+    /// The system directories entries below the operand must not be in.
+    guard: Option<SystemGuard>,
+    /// Whether to descend through links to directories.
+    follow_links: bool,
+    /// The directories walked so far, in canonical form, when following links.
+    walked: HashSet<PathBuf>,
+    /// A link just reported, to be listed next.
+    link: Option<PathBuf>,
+    /// Tells which volume a directory is on, when the walk stays on the operand's.
+    volume_of: Option<fn(&Path) -> Option<u64>>,
+    /// The volume the operand is on, if known.
+    root_volume: Option<u64>,
+}
+
+impl Walk {
+    /// Starts a walk at `root`, which is listed first.
+    pub fn new(root: PathBuf) -> Self {
+        Walk {
+            base: root.clone(),
+            root: Some(root),
+            stack: Vec::new(),
+            order: Order::Listed,
+            error: None,
+            filter: None,
+            excludes: None,
+            guard: None,
+            follow_links: false,
+            walked: HashSet::new(),
+            link: None,
+            volume_of: None,
+            root_volume: None,
+        }
+    }
+
+    /// Leaves out directories on another volume than the operand (`--one-file-system`).
+    pub fn one_file_system(self, one_file_system: bool) -> Self {
+        if one_file_system {
+            self.staying_on_volume(volume_id)
+        } else {
+            self
+        }
+    }
+
+    /// Leaves out directories that `volume_of` places on another volume than the operand.
+    fn staying_on_volume(mut self, volume_of: fn(&Path) -> Option<u64>) -> Self {
+        self.volume_of = Some(volume_of);
+        self
+    }
+
+    /// Whether the directory `dir` is on the operand's volume, or can't be told apart.
+    fn on_root_volume(&self, dir: &Path) -> bool {
+        self.volume_of
+            .is_none_or(|volume_of| same_volume(self.root_volume, volume_of(dir)))
+    }
+
+    /// Lists the entries of each directory in `order` (`--sorted`).
+    pub fn ordered(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Descends through links to directories too (`--follow-links`).
+    pub fn following_links(mut self, follow_links: bool) -> Self {
+        self.follow_links = follow_links;
+        self
+    }
+
+    /// Leaves out the entries below the operand that `filter` excludes, and everything
+    /// below them. The operand itself is always listed.
+    pub fn filtered(mut self, filter: Option<AttrFilter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Leaves out the entries below the operand that `excludes` matches, and everything
+    /// below them (`--exclude`). The operand itself is always listed.
+    pub fn excluding(mut self, excludes: Option<Excludes>) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// Holds back the entries below the operand that lie inside a directory `guard`
+    /// protects, and everything below them. The operand itself is always listed.
+    pub fn guarded(mut self, guard: Option<SystemGuard>) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// Starts reading the directory `dir`, unless following links has already led through
+    /// it. Returns whether it is being read.
+    fn enter(&mut self, dir: &Path) -> bool {
+        if self.follow_links {
+            let canonical = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+            if !self.walked.insert(canonical) {
+                return false;
+            }
+        }
+        match fs::read_dir(dir) {
+            Ok(entries) => self
+                .stack
+                .push((dir.to_path_buf(), Entries::new(entries, self.order))),
+            Err(e) => self.error = Some((dir.to_path_buf(), e)),
+        }
+        true
+    }
+
+    /// Says what becomes of the link `path` to a directory, descending through it when
+    /// following links.
+    fn cross(&mut self, path: &Path) -> Crossing {
+        if !self.follow_links {
+            return Crossing::NotFollowed;
+        }
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.enter(path) {
+            Crossing::Followed(target)
+        } else {
+            Crossing::Walked(target)
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<Found, (PathBuf, io::Error)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(link) = self.link.take() {
+            return Some(Ok(Found::Path(link)));
+        }
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        if let Some(root) = self.root.take() {
+            if fs::metadata(&root).is_ok_and(|metadata| metadata.is_dir()) {
+                self.root_volume = self.volume_of.and_then(|volume_of| volume_of(&root));
+                self.enter(&root);
+            }
+            return Some(Ok(Found::Path(root)));
+        }
+        loop {
+            let (dir, entries) = self.stack.last_mut()?;
+            match entries.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(Err(e)) => return Some(Err((dir.clone(), e))),
+                Some(Ok(entry)) => {
+                    let path = entry.path();
+                    if let Some(filter) = &self.filter {
+                        let excluded = entry.metadata().is_ok_and(|metadata| {
+                            !filter.matches(attrs::attributes(&path, &metadata))
+                        });
+                        if excluded {
+                            continue;
+                        }
+                    }
+                    if let Some(excludes) = &self.excludes {
+                        if excludes.excludes(&path, &self.base) {
+                            continue;
+                        }
+                    }
+                    if let Some(protected) =
+                        self.guard.as_ref().and_then(|guard| guard.blocks(&path))
+                    {
+                        return Some(Ok(Found::Blocked(path, protected.to_path_buf())));
+                    }
+                    // file_type does not follow links, so a linked directory is told
+                    // apart from a real one.
+                    let Ok(kind) = entry.file_type() else {
+                        return Some(Ok(Found::Path(path)));
+                    };
+                    let dir_link = kind.is_symlink()
+                        && fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir());
+                    if (kind.is_dir() || dir_link) && !self.on_root_volume(&path) {
+                        return Some(Ok(Found::OtherVolume(path)));
+                    }
+                    if kind.is_dir() {
+                        self.enter(&path);
+                    } else if dir_link {
+                        let crossing = self.cross(&path);
+                        // The link itself is listed next.
+                        self.link = Some(path.clone());
+                        return Some(Ok(Found::Link(path, crossing)));
+                    }
+                    return Some(Ok(Found::Path(path)));
+                }
+            }
+        }
+    }
+}
+
+/// Whether a directory on volume `dir` is on the operand's volume `root`. A volume that
+/// can't be found out is taken to be the same, since there is nothing to tell them apart.
+fn same_volume(root: Option<u64>, dir: Option<u64>) -> bool {
+    match (root, dir) {
+        (Some(root), Some(dir)) => root == dir,
+        _ => true,
+    }
+}
+
+/// The volume serial number of the directory `dir`, from `GetFileInformationByHandle`.
+#[cfg(windows)]
+fn volume_id(dir: &Path) -> Option<u64> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS,
+        FILE_READ_ATTRIBUTES,
+    };
+
+    let dir = fs::OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(dir)
+        .ok()?;
+    // SAFETY: all-zero is a valid BY_HANDLE_FILE_INFORMATION, filled in by the call.
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    // SAFETY: the handle is open for the duration of the call.
+    let found = unsafe { GetFileInformationByHandle(dir.as_raw_handle(), &mut info) };
+    (found != 0).then_some(u64::from(info.dwVolumeSerialNumber))
+}
+
+/// The device ID of the filesystem holding the directory `dir`.
+#[cfg(unix)]
+fn volume_id(dir: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(dir).ok().map(|metadata| metadata.dev())
+}
+
+/// Nothing tells volumes apart here.
+#[cfg(not(any(windows, unix)))]
+fn volume_id(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// The paths a walk lists, failing on any error.
+    fn paths(walk: Walk) -> Vec<PathBuf> {
+        walk.map(Result::unwrap)
+            .filter_map(|found| match found {
+                Found::Path(path) => Some(path),
+                Found::Link(..) | Found::OtherVolume(_) | Found::Blocked(..) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_lists_the_whole_tree() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_walk_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("top.txt"), b"").unwrap();
+        fs::write(dir.join("a/b/deep.txt"), b"").unwrap();
+
+        let found = paths(Walk::new(dir.clone()));
+        assert_eq!(found[0], dir);
+        let expected: BTreeSet<PathBuf> = ["", "a", "a/b", "a/b/deep.txt", "top.txt"]
+            .iter()
+            .map(|sub| {
+                if sub.is_empty() {
+                    dir.clone()
+                } else {
+                    dir.join(sub)
+                }
+            })
+            .collect();
+        assert_eq!(found.iter().cloned().collect::<BTreeSet<_>>(), expected);
+        // Every directory comes before what is inside it.
+        let position = |path: &Path| found.iter().position(|p| p == path).unwrap();
+        assert!(position(&dir.join("a")) < position(&dir.join("a/b/deep.txt")));
+
+        // A file operand is just itself.
+        let file = paths(Walk::new(dir.join("top.txt")));
+        assert_eq!(file, vec![dir.join("top.txt")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_does_not_descend_through_links() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_walk_link_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tree")).unwrap();
+        fs::create_dir_all(dir.join("elsewhere")).unwrap();
+        fs::write(dir.join("elsewhere/outside.txt"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.join("elsewhere"), dir.join("tree/link")).unwrap();
+
+        let found: Vec<Found> = Walk::new(dir.join("tree")).map(Result::unwrap).collect();
+        assert_eq!(
+            found,
+            vec![
+                Found::Path(dir.join("tree")),
+                Found::Link(dir.join("tree/link"), Crossing::NotFollowed),
+                Found::Path(dir.join("tree/link")),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Lays out `tree` with a link `tree/outputs` to the sibling `build`, and a link
+    /// `build/up` back to `tree`, made by `link(target, path)`.
+    fn linked_trees(name: &str, link: fn(&Path, &Path)) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mdtouch_walk_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tree")).unwrap();
+        fs::create_dir_all(dir.join("build/bin")).unwrap();
+        fs::write(dir.join("build/bin/app.exe"), b"").unwrap();
+        link(&dir.join("build"), &dir.join("tree/outputs"));
+        link(&dir.join("tree"), &dir.join("build/up"));
+        dir
+    }
+
+    /// Checks a walk of `tree` from [`linked_trees`] with and without following links.
+    fn check_following(dir: &Path) {
+        let tree = dir.join("tree");
+        assert_eq!(
+            paths(Walk::new(tree.clone())),
+            vec![tree.clone(), tree.join("outputs")]
+        );
+
+        let found: Vec<Found> = Walk::new(tree.clone())
+            .following_links(true)
+            .map(Result::unwrap)
+            .collect();
+        let build = fs::canonicalize(dir.join("build")).unwrap();
+        assert!(found.contains(&Found::Link(
+            tree.join("outputs"),
+            Crossing::Followed(build)
+        )));
+        assert!(found.contains(&Found::Path(tree.join("outputs/bin/app.exe"))));
+        // The link back up leads to where the walk started, so it stops there.
+        assert!(found.contains(&Found::Link(
+            tree.join("outputs/up"),
+            Crossing::Walked(fs::canonicalize(&tree).unwrap())
+        )));
+        assert!(!found
+            .iter()
+            .any(|found| *found == Found::Path(tree.join("outputs/up/outputs"))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_follows_links_once() {
+        let dir = linked_trees("follow", |target, path| {
+            std::os::unix::fs::symlink(target, path).unwrap()
+        });
+        check_following(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_walk_follows_junctions_once() {
+        let dir = linked_trees("junction", |target, path| {
+            let status = std::process::Command::new("cmd")
+                .arg("/c")
+                .arg("mklink")
+                .arg("/J")
+                .arg(path)
+                .arg(target)
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        });
+        check_following(&dir);
+        // A junction to the same volume is no mount point.
+        let found = paths(
+            Walk::new(dir.join("tree"))
+                .following_links(true)
+                .one_file_system(true),
+        );
+        assert!(found.contains(&dir.join("tree/outputs/bin/app.exe")));
+        // Removing the tree removes the junctions, not what they lead to.
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_same_volume() {
+        assert!(same_volume(Some(7), Some(7)));
+        assert!(!same_volume(Some(7), Some(8)));
+        // Without both serials there is nothing to go on.
+        assert!(same_volume(None, Some(8)));
+        assert!(same_volume(Some(7), None));
+    }
+
+    #[test]
+    fn test_walk_leaves_out_other_volumes() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_walk_xdev_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tree/mnt/inside")).unwrap();
+        fs::create_dir_all(dir.join("tree/local")).unwrap();
+        /// Puts directories named `mnt` on volume 2 and everything else on volume 1.
+        fn volume_of(dir: &Path) -> Option<u64> {
+            Some(if dir.ends_with("mnt") { 2 } else { 1 })
+        }
+
+        let found: Vec<Found> = Walk::new(dir.join("tree"))
+            .staying_on_volume(volume_of)
+            .map(Result::unwrap)
+            .collect();
+        assert!(found.contains(&Found::OtherVolume(dir.join("tree/mnt"))));
+        assert!(found.contains(&Found::Path(dir.join("tree/local"))));
+        assert!(!found.iter().any(
+            |found| matches!(found, Found::Path(path) if path.starts_with(dir.join("tree/mnt")))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_walk_holds_back_system_directories() {
+        let dir = std::env::temp_dir().join(format!("mdtouch_walk_guard_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tree/system/deep")).unwrap();
+        fs::create_dir_all(dir.join("tree/local")).unwrap();
+        let guard = SystemGuard::new(vec![dir.join("tree/system")], Vec::new());
+        let protected = fs::canonicalize(dir.join("tree/system")).unwrap();
+
+        let found: Vec<Found> = Walk::new(dir.join("tree"))
+            .guarded(Some(guard.clone()))
+            .map(Result::unwrap)
+            .collect();
+        assert!(found.contains(&Found::Blocked(dir.join("tree/system"), protected)));
+        assert!(found.contains(&Found::Path(dir.join("tree/local"))));
+        assert!(!found.iter().any(
+            |found| matches!(found, Found::Path(path) if path.starts_with(dir.join("tree/system")))
+        ));
+        // Named outright, the operand is walked whatever it is.
+        let found = paths(Walk::new(dir.join("tree/system")).guarded(Some(guard)));
+        assert_eq!(found[0], dir.join("tree/system"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_one_file_system_follows_links_on_the_same_volume() {
+        let dir = linked_trees("xdev_link", |target, path| {
+            std::os::unix::fs::symlink(target, path).unwrap()
+        });
+        let found = paths(
+            Walk::new(dir.join("tree"))
+                .following_links(true)
+                .one_file_system(true),
+        );
+        assert!(found.contains(&dir.join("tree/outputs/bin/app.exe")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+//! Touching files for as long as another process runs (`--while-pid`, `--every`).
+//!
+//! The operands are touched once, then again every interval, until the watched process
+//! ends. The wait between touches is a wait on the process itself, with the interval as
+//! its timeout, so the loop ends as soon as the process does rather than at the next
+//! touch: on Windows through a handle to the process, on Linux through a pidfd. Other
+//! Unix systems check every 100 ms whether the process is still there.
+
+use std::io;
+use std::time::Duration;
+
+use crate::lock;
+
+/// Interval used by `--while-pid` when `--every` is not given.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parses an `--every` interval: a number of seconds, optionally followed by `s`, `m`,
+/// `h` or `d`, and not zero.
+pub fn parse_interval(text: &str) -> io::Result<Duration> {
+    lock::parse_age(text)
+        .ok()
+        .filter(|interval| !interval.is_zero())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid --every interval '{}' (expected a number followed by s, m, h or d)",
+                    text
+                ),
+            )
+        })
+}
+
+/// A running process being waited on.
+#[derive(Debug)]
+pub struct Process(platform::Process);
+
+impl Process {
+    /// Starts watching the process with ID `pid`. Returns `None` if there is no such
+    /// process running, or it can't be watched from here.
+    pub fn open(pid: u32) -> Option<Process> {
+        platform::Process::open(pid).map(Process)
+    }
+
+    /// Waits up to `timeout` for the process to end, and says whether it has.
+    pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        self.0.wait(timeout)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::time::Duration;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, WaitForSingleObject, INFINITE, PROCESS_SYNCHRONIZE,
+    };
+
+    #[derive(Debug)]
+    pub struct Process(HANDLE);
+
+    impl Process {
+        pub fn open(pid: u32) -> Option<Process> {
+            // SAFETY: plain call; the handle is checked before use.
+            let handle = unsafe { OpenProcess(PROCESS_SYNCHRONIZE, 0, pid) };
+            (!handle.is_null()).then_some(Process(handle))
+        }
+
+        pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+            let millis = u32::try_from(timeout.as_millis())
+                .map_or(INFINITE - 1, |millis| millis.min(INFINITE - 1));
+            // SAFETY: the handle stays open until the process is dropped.
+            match unsafe { WaitForSingleObject(self.0, millis) } {
+                WAIT_OBJECT_0 => Ok(true),
+                WAIT_TIMEOUT => Ok(false),
+                _ => Err(io::Error::last_os_error()),
+            }
+        }
+    }
+
+    impl Drop for Process {
+        fn drop(&mut self) {
+            // SAFETY: the handle was opened by `open` and is closed only here.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug)]
+    pub struct Process {
+        pid: libc::pid_t,
+        /// A pidfd for the process, which becomes readable when it ends.
+        #[cfg(target_os = "linux")]
+        pidfd: Option<std::os::fd::OwnedFd>,
+    }
+
+    impl Process {
+        pub fn open(pid: u32) -> Option<Process> {
+            let pid = libc::pid_t::try_from(pid).ok().filter(|&pid| pid > 0)?;
+            if !running(pid) {
+                return None;
+            }
+            Some(Process {
+                pid,
+                #[cfg(target_os = "linux")]
+                pidfd: open_pidfd(pid),
+            })
+        }
+
+        pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+            #[cfg(target_os = "linux")]
+            if let Some(pidfd) = &self.pidfd {
+                return wait_pidfd(pidfd, timeout);
+            }
+            // Without a pidfd, look every so often.
+            let deadline = Instant::now() + timeout;
+            loop {
+                if !running(self.pid) {
+                    return Ok(true);
+                }
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Ok(false);
+                }
+                std::thread::sleep(left.min(Duration::from_millis(100)));
+            }
+        }
+    }
+
+    /// Whether the process exists, even if it belongs to someone else.
+    fn running(pid: libc::pid_t) -> bool {
+        // SAFETY: signal 0 only checks that the process exists and may be signalled.
+        let alive = unsafe { libc::kill(pid, 0) } == 0;
+        alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    /// Opens a pidfd for `pid`, on kernels that have them.
+    #[cfg(target_os = "linux")]
+    fn open_pidfd(pid: libc::pid_t) -> Option<std::os::fd::OwnedFd> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: pidfd_open takes a process ID and flags, and returns a new descriptor
+        // that is owned from here on.
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        let fd = i32::try_from(fd).ok().filter(|&fd| fd >= 0)?;
+        // SAFETY: the descriptor was just opened and nothing else owns it.
+        Some(unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Waits up to `timeout` for the pidfd to become readable, which it does once the
+    /// process has ended.
+    #[cfg(target_os = "linux")]
+    fn wait_pidfd(pidfd: &std::os::fd::OwnedFd, timeout: Duration) -> io::Result<bool> {
+        use std::os::fd::AsRawFd;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let millis = i32::try_from(left.as_millis()).unwrap_or(i32::MAX);
+            let mut poll = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd is passed, with its count.
+            match unsafe { libc::poll(&mut poll, 1, millis) } {
+                -1 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+                0 if left.is_zero() => return Ok(false),
+                0 => {}
+                _ => return Ok(true),
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, unix)))]
+mod platform {
+    use std::io;
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    pub struct Process;
+
+    impl Process {
+        pub fn open(_pid: u32) -> Option<Process> {
+            None
+        }
+
+        pub fn wait(&self, _timeout: Duration) -> io::Result<bool> {
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_interval("5").unwrap(), Duration::from_secs(5));
+        for bad in ["0", "0s", "", "2x", "-1"] {
+            let err = parse_interval(bad).unwrap_err();
+            assert!(err.to_string().contains("--every"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_watching_a_missing_process() {
+        // Process IDs are far below this everywhere.
+        assert!(Process::open(u32::MAX - 1).is_none());
+        assert!(Process::open(0).is_none());
+    }
+
+    #[test]
+    fn test_waiting_times_out_while_the_process_runs() {
+        let process = Process::open(std::process::id()).unwrap();
+        assert!(!process.wait(Duration::from_millis(50)).unwrap());
+    }
+}
@@ -0,0 +1,427 @@
+//! Reading entry times from a zip archive (`--from-zip`).
+//!
+//! Only the central directory is read, with a look at an entry's local header when the
+//! central directory has no precise time for it, so the archive's contents are never
+//! decompressed. An entry's time comes from the first of these it has:
+//!
+//! * the NTFS extra field (`0x000a`), in 100 ns ticks;
+//! * the extended timestamp extra field (`0x5455`, "UT"), in whole seconds;
+//! * the DOS date and time every entry has, in local time to 2 seconds.
+//!
+//! Zip64 archives, whose central directory may lie beyond 4 GiB, are read too.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use mdtouch::date::{days_from_civil, filetime_from_ticks};
+use mdtouch::{FileTime, ParseContext};
+
+/// One entry of an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The entry's name, with `/` separating its components. Directories end with `/`.
+    pub name: String,
+    /// When the entry was last modified.
+    pub modified: FileTime,
+    /// Whether the time came from the DOS date and time, so is only good to 2 seconds.
+    pub dos_time: bool,
+}
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+
+/// Reads the entries of the archive at `path`, in central directory order. DOS times are
+/// read as local time by `context`.
+pub fn read_entries(path: &Path, context: ParseContext) -> io::Result<Vec<Entry>> {
+    let mut file = File::open(path)?;
+    let (offset, size) = find_central_directory(&mut file)?;
+    let size = usize::try_from(size).map_err(|_| invalid("central directory too large"))?;
+    let mut directory = vec![0; size];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut directory)?;
+
+    let mut entries = Vec::new();
+    let mut rest = &directory[..];
+    while rest.len() >= 4 && u32_at(rest, 0) == CENTRAL_HEADER {
+        if rest.len() < 46 {
+            return Err(invalid("truncated central directory"));
+        }
+        let flags = u16_at(rest, 8);
+        let (time, date) = (u16_at(rest, 12), u16_at(rest, 14));
+        let name_len = usize::from(u16_at(rest, 28));
+        let extra_len = usize::from(u16_at(rest, 30));
+        let comment_len = usize::from(u16_at(rest, 32));
+        let mut header_offset = u64::from(u32_at(rest, 42));
+        let end = 46 + name_len + extra_len + comment_len;
+        if rest.len() < end {
+            return Err(invalid("truncated central directory"));
+        }
+        let name = decode_name(&rest[46..46 + name_len], flags & 0x800 != 0);
+        let extra = &rest[46 + name_len..46 + name_len + extra_len];
+        if header_offset == 0xFFFF_FFFF {
+            header_offset = zip64_header_offset(rest, extra).unwrap_or(header_offset);
+        }
+        let mut modified = precise_time(extra);
+        if modified.is_none() {
+            modified = local_extra(&mut file, header_offset)
+                .ok()
+                .and_then(|extra| precise_time(&extra));
+        }
+        entries.push(Entry {
+            name,
+            dos_time: modified.is_none(),
+            modified: modified.unwrap_or_else(|| dos_time(date, time, context)),
+        });
+        rest = &rest[end..];
+    }
+    Ok(entries)
+}
+
+/// Finds the offset and size of the central directory from the end of central directory
+/// record, or its Zip64 counterpart.
+fn find_central_directory(file: &mut File) -> io::Result<(u64, u64)> {
+    let len = file.seek(SeekFrom::End(0))?;
+    // The record is 22 bytes, followed by a comment of up to 65535.
+    let tail_len = len.min(22 + 65_535);
+    let mut tail = vec![0; tail_len as usize];
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    file.read_exact(&mut tail)?;
+    let at = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(&tail, at) == END_OF_CENTRAL_DIRECTORY)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let record = &tail[at..];
+    let size = u64::from(u32_at(record, 12));
+    let offset = u64::from(u32_at(record, 16));
+    if size != 0xFFFF_FFFF && offset != 0xFFFF_FFFF {
+        return Ok((offset, size));
+    }
+
+    // A Zip64 archive: the locator just before the record says where the real one is.
+    let locator_at = (len - tail_len + at as u64)
+        .checked_sub(20)
+        .ok_or_else(|| invalid("missing Zip64 locator"))?;
+    let mut locator = [0; 20];
+    file.seek(SeekFrom::Start(locator_at))?;
+    file.read_exact(&mut locator)?;
+    if u32_at(&locator, 0) != ZIP64_END_LOCATOR {
+        return Err(invalid("missing Zip64 locator"));
+    }
+    let mut record = [0; 56];
+    file.seek(SeekFrom::Start(u64_at(&locator, 8)))?;
+    file.read_exact(&mut record)?;
+    if u32_at(&record, 0) != ZIP64_END_OF_CENTRAL_DIRECTORY {
+        return Err(invalid("missing Zip64 end of central directory"));
+    }
+    Ok((u64_at(&record, 48), u64_at(&record, 40)))
+}
+
+/// The local header offset from an entry's Zip64 extra field. The field holds the sizes
+/// first, but only those the central header marks as too large for it.
+fn zip64_header_offset(header: &[u8], extra: &[u8]) -> Option<u64> {
+    let data = extra_fields(extra).find(|&(id, _)| id == 0x0001)?.1;
+    let skip = [24, 20]
+        .iter()
+        .filter(|&&at| u32_at(header, at) == 0xFFFF_FFFF)
+        .count()
+        * 8;
+    (data.len() >= skip + 8).then(|| u64_at(data, skip))
+}
+
+/// Reads the extra field of the local header at `offset`.
+fn local_extra(file: &mut File, offset: u64) -> io::Result<Vec<u8>> {
+    let mut header = [0; 30];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut header)?;
+    if u32_at(&header, 0) != LOCAL_HEADER {
+        return Err(invalid("bad local header"));
+    }
+    let name_len = i64::from(u16_at(&header, 26));
+    let mut extra = vec![0; usize::from(u16_at(&header, 28))];
+    file.seek(SeekFrom::Current(name_len))?;
+    file.read_exact(&mut extra)?;
+    Ok(extra)
+}
+
+/// The modification time from the NTFS or extended timestamp extra fields, if either is
+/// there.
+fn precise_time(extra: &[u8]) -> Option<FileTime> {
+    let ntfs = extra_fields(extra)
+        .find(|&(id, _)| id == 0x000a)
+        .and_then(|(_, data)| {
+            // Four reserved bytes, then tagged attributes; tag 1 holds the times.
+            let mut rest = data.get(4..)?;
+            while rest.len() >= 4 {
+                let (tag, size) = (u16_at(rest, 0), usize::from(u16_at(rest, 2)));
+                let value = rest.get(4..4 + size)?;
+                if tag == 1 && size >= 8 {
+                    // One Windows would not take is left to the other fields.
+                    return filetime_from_ticks(u64_at(value, 0));
+                }
+                rest = &rest[4 + size..];
+            }
+            None
+        });
+    ntfs.or_else(|| {
+        let (_, data) = extra_fields(extra).find(|&(id, _)| id == 0x5455)?;
+        // A flags byte, then the modification time if its bit is set.
+        (data.len() >= 5 && data[0] & 1 != 0)
+            .then(|| FileTime::from_unix_time(i64::from(u32_at(data, 1) as i32), 0))
+    })
+}
+
+/// The `(id, data)` pairs of an extra field.
+fn extra_fields(mut extra: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if extra.len() < 4 {
+            return None;
+        }
+        let (id, size) = (u16_at(extra, 0), usize::from(u16_at(extra, 2)));
+        let data = extra.get(4..4 + size)?;
+        extra = &extra[4 + size..];
+        Some((id, data))
+    })
+}
+
+/// Converts a DOS date and time, which has no zone, read as local time by `context`.
+fn dos_time(date: u16, time: u16, context: ParseContext) -> FileTime {
+    let year = 1980 + i64::from(date >> 9);
+    let month = u32::from((date >> 5) & 0xF).clamp(1, 12);
+    let day = u32::from(date & 0x1F).max(1);
+    let seconds = i64::from(time >> 11) * 3600
+        + i64::from((time >> 5) & 0x3F) * 60
+        + i64::from(time & 0x1F) * 2;
+    let civil = days_from_civil(year, month, day) * 86_400 + seconds;
+    FileTime::from_unix_time(context.local_to_utc(civil), 0)
+}
+
+/// Decodes an entry name: UTF-8 when flagged as such, and otherwise code page 437,
+/// the zip format's original encoding.
+fn decode_name(bytes: &[u8], utf8: bool) -> String {
+    if utf8 || bytes.is_ascii() {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    const HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0..=0x7F => char::from(byte),
+            _ => HIGH.chars().nth(usize::from(byte - 0x80)).unwrap_or('?'),
+        })
+        .collect()
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn utc() -> ParseContext {
+        ParseContext {
+            utc: true,
+            ..ParseContext::default()
+        }
+    }
+
+    /// An extra field's id and data.
+    type Field = (u16, Vec<u8>);
+
+    /// Writes an archive of empty, stored entries at `start` in a new file at `path`, each with
+    /// the DOS time 2021-03-04 05:06:08 and the given extra fields. Starting at 4 GiB or later
+    /// makes it a Zip64 archive, with the rest of the file left as a hole.
+    fn write_archive(path: &Path, start: u64, entries: &[(&str, Vec<Field>)]) {
+        let mut file = File::create(path).unwrap();
+        file.seek(SeekFrom::Start(start)).unwrap();
+        let mut writer = ::zip::ZipWriter::new(file);
+        let time = ::zip::DateTime::from_date_and_time(2021, 3, 4, 5, 6, 8).unwrap();
+        for (name, fields) in entries {
+            let mut options = ::zip::write::FullFileOptions::default()
+                .compression_method(::zip::CompressionMethod::Stored)
+                .last_modified_time(time)
+                .large_file(start > u64::from(u32::MAX));
+            for (id, data) in fields {
+                options
+                    .add_extra_data(*id, data.clone().into(), false)
+                    .unwrap();
+            }
+            if name.ends_with('/') {
+                writer.add_directory(*name, options).unwrap();
+            } else {
+                writer.start_file(*name, options).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+    }
+
+    /// Renames the UT field of `name` in the central directory of the archive at `start` in
+    /// `path`, so its time has to come from the local header.
+    fn hide_central_ut(path: &Path, start: u64, name: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mut archive = Vec::new();
+        file.seek(SeekFrom::Start(start)).unwrap();
+        file.read_to_end(&mut archive).unwrap();
+        // The last copy of the name is the central directory's.
+        let at = archive
+            .windows(name.len())
+            .rposition(|window| window == name.as_bytes())
+            .unwrap();
+        let mut field = at + name.len();
+        let end = field + usize::from(u16_at(&archive, at - 16));
+        while field < end {
+            if u16_at(&archive, field) == 0x5455 {
+                archive[field..field + 2].copy_from_slice(&0xfffeu16.to_le_bytes());
+            }
+            field += 4 + usize::from(u16_at(&archive, field + 2));
+        }
+        file.seek(SeekFrom::Start(start)).unwrap();
+        file.write_all(&archive).unwrap();
+    }
+
+    fn ut(seconds: u32) -> Field {
+        let mut data = vec![1];
+        data.extend(seconds.to_le_bytes());
+        (0x5455, data)
+    }
+
+    fn ntfs(ticks: u64) -> Field {
+        let mut data = vec![0, 0, 0, 0, 1, 0, 24, 0];
+        data.extend(ticks.to_le_bytes());
+        data.extend([0; 16]);
+        (0x000a, data)
+    }
+
+    #[test]
+    fn test_entry_times_from_each_source() {
+        let dos = FileTime::from_unix_time(1_614_834_368, 0);
+        let ticks: u64 = 116_444_736_000_000_000 + 1_700_000_000 * 10_000_000 + 1_234_567;
+        let path = std::env::temp_dir().join(format!("mdtouch_zip_{}.zip", std::process::id()));
+        write_archive(
+            &path,
+            0,
+            &[
+                ("dir/", Vec::new()),
+                ("dir/plain.txt", Vec::new()),
+                ("unix.txt", vec![ut(1_600_000_000)]),
+                ("ntfs.txt", vec![ntfs(ticks)]),
+                ("huge.txt", vec![ntfs(u64::MAX)]),
+                ("local.txt", vec![ut(1_650_000_000)]),
+            ],
+        );
+        hide_central_ut(&path, 0, "local.txt");
+
+        let entries = read_entries(&path, utc()).unwrap();
+        let found: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.modified, entry.dos_time))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("dir/", dos, true),
+                ("dir/plain.txt", dos, true),
+                (
+                    "unix.txt",
+                    FileTime::from_unix_time(1_600_000_000, 0),
+                    false
+                ),
+                (
+                    "ntfs.txt",
+                    FileTime::from_unix_time(1_700_000_000, 123_456_700),
+                    false
+                ),
+                ("huge.txt", dos, true),
+                (
+                    "local.txt",
+                    FileTime::from_unix_time(1_650_000_000, 0),
+                    false
+                ),
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // The archive starts 4 GiB into a sparse file; Windows would write out the hole.
+    #[cfg(unix)]
+    #[test]
+    fn test_zip64_archive() {
+        let path = std::env::temp_dir().join(format!("mdtouch_zip64_{}.zip", std::process::id()));
+        let start = 1 << 32;
+        write_archive(
+            &path,
+            start,
+            &[
+                ("plain.txt", Vec::new()),
+                ("unix.txt", vec![ut(1_600_000_000)]),
+                ("local.txt", vec![ut(1_650_000_000)]),
+            ],
+        );
+        hide_central_ut(&path, start, "local.txt");
+
+        let entries = read_entries(&path, utc()).unwrap();
+        let found: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.modified, entry.dos_time))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "plain.txt",
+                    FileTime::from_unix_time(1_614_834_368, 0),
+                    true
+                ),
+                (
+                    "unix.txt",
+                    FileTime::from_unix_time(1_600_000_000, 0),
+                    false
+                ),
+                (
+                    "local.txt",
+                    FileTime::from_unix_time(1_650_000_000, 0),
+                    false
+                ),
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        let path = std::env::temp_dir().join(format!("mdtouch_notzip_{}", std::process::id()));
+        std::fs::write(&path, b"just some text, not a zip").unwrap();
+        let err = read_entries(&path, utc()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_name() {
+        assert_eq!(decode_name(b"plain/name.txt", false), "plain/name.txt");
+        assert_eq!(decode_name(b"caf\x82.txt", false), "café.txt");
+        assert_eq!(decode_name("café.txt".as_bytes(), true), "café.txt");
+    }
+}
@@ -1,5 +1,10 @@
 use std::env;
-use std::process::Command;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn test_main_binary_runs() {
@@ -61,3 +66,698 @@ fn test_main_binary_runs() {
     let stderr = String::from_utf8(output_err.stderr).unwrap();
     assert!(stderr.contains("Error touching"));
 }
+
+/// Locates the compiled mdtouch binary next to the test executable, if it exists.
+fn binary_path() -> Option<PathBuf> {
+    let mut bin_path = env::current_exe().expect("Failed to get current exe path");
+    bin_path.pop(); // Remove the test executable name.
+    if bin_path.file_name().and_then(|s| s.to_str()) == Some("deps") {
+        bin_path.pop();
+    }
+    bin_path.push(format!("mdtouch{}", env::consts::EXE_SUFFIX));
+    bin_path.exists().then_some(bin_path)
+}
+
+/// Creates a fresh, empty scratch directory for a single test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let mut dir = env::temp_dir();
+    dir.push(format!("mdtouch_it_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("Failed to create scratch directory");
+    dir
+}
+
+#[test]
+fn test_failed_to_round_trips_through_files_from() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("failed_to");
+    let missing = dir.join("missing dir");
+    let first = missing.join("first file.txt");
+    let second = missing.join("second file.txt");
+    let good = dir.join("good file.txt");
+    let list = dir.join("failed list.txt");
+
+    // The parent directory does not exist yet, so both spaced paths fail.
+    let output = Command::new(&bin_path)
+        .arg("--failed-to")
+        .arg(&list)
+        .arg(&first)
+        .arg(&good)
+        .arg(&second)
+        .output()
+        .expect("Failed to execute binary");
+    assert!(!output.status.success());
+    assert!(good.exists());
+
+    let listed = fs::read_to_string(&list).expect("Failed list should be written");
+    let expected = format!("{}\n{}\n", first.display(), second.display());
+    assert_eq!(listed, expected);
+
+    // Fix the cause and retry exactly the failures.
+    fs::create_dir(&missing).unwrap();
+    let retry = Command::new(&bin_path)
+        .arg("--files-from")
+        .arg(&list)
+        .output()
+        .expect("Failed to execute binary");
+    assert!(retry.status.success());
+    assert!(first.exists());
+    assert!(second.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_failed_to_null_separated() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("failed_to_null");
+    let bad = dir.join("missing dir").join("a file.txt");
+    let list = dir.join("failed.lst");
+
+    let output = Command::new(&bin_path)
+        .args(["-0", "--failed-to"])
+        .arg(&list)
+        .arg(&bad)
+        .output()
+        .expect("Failed to execute binary");
+    assert!(!output.status.success());
+    assert_eq!(
+        fs::read_to_string(&list).unwrap(),
+        format!("{}\0", bad.display())
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_touch_current_directory() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("dot");
+    let before = fs::metadata(&dir).unwrap().modified().unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let output = Command::new(&bin_path)
+        .arg(".")
+        .current_dir(&dir)
+        .output()
+        .expect("Failed to execute binary");
+
+    assert!(output.status.success());
+    let after = fs::metadata(&dir).unwrap().modified().unwrap();
+    assert!(after > before, "Directory mtime should advance.");
+    assert!(dir.is_dir());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_gnu_compatibility_table() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("gnu_compat");
+    fs::write(dir.join("exists.txt"), b"").unwrap();
+    let try_help = "Try 'touch --help' for more information.\n";
+
+    // Arguments after --posix, the exact standard error, and the exit code.
+    let cases: &[(&[&str], String, i32)] = &[
+        (&[], format!("touch: missing file operand\n{}", try_help), 1),
+        (&["new.txt"], String::new(), 0),
+        (&["-c", "not-created.txt"], String::new(), 0),
+        (&["-am", "-t", "202501020304.05", "exists.txt"], String::new(), 0),
+        (&["--time=mtime", "-r", "exists.txt", "new.txt"], String::new(), 0),
+        (
+            &["missing/file.txt"],
+            "touch: cannot touch 'missing/file.txt': No such file or directory\n".to_string(),
+            1,
+        ),
+        (
+            &["ok.txt", "missing/a", "missing/b"],
+            "touch: cannot touch 'missing/a': No such file or directory\n\
+             touch: cannot touch 'missing/b': No such file or directory\n"
+                .to_string(),
+            1,
+        ),
+        (
+            &["--json", "x"],
+            format!("touch: unrecognized option '--json'\n{}", try_help),
+            1,
+        ),
+        (&["-v", "x"], format!("touch: invalid option -- 'v'\n{}", try_help), 1),
+        (
+            &["x", "-d"],
+            format!("touch: option requires an argument -- 'd'\n{}", try_help),
+            1,
+        ),
+        (
+            &["x", "--date"],
+            format!("touch: option '--date' requires an argument\n{}", try_help),
+            1,
+        ),
+        (
+            &["-d", "next blursday", "x"],
+            "touch: invalid date format 'next blursday'\n".to_string(),
+            1,
+        ),
+        (
+            &["-r", "nope.txt", "x"],
+            "touch: failed to get attributes of 'nope.txt': No such file or directory\n"
+                .to_string(),
+            1,
+        ),
+        (
+            &["--no", "x"],
+            format!(
+                "touch: option '--no' is ambiguous; possibilities: '--no-create' '--no-dereference'\n{}",
+                try_help
+            ),
+            1,
+        ),
+        (
+            &["-t", "202501020304", "-d", "2025-01-02", "x"],
+            format!(
+                "touch: cannot specify times from more than one source\n{}",
+                try_help
+            ),
+            1,
+        ),
+    ];
+
+    for (args, stderr, code) in cases {
+        let output = Command::new(&bin_path)
+            .arg("--posix")
+            .args(*args)
+            .current_dir(&dir)
+            .env_remove("MDTOUCH_COMPAT")
+            .output()
+            .expect("Failed to execute binary");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            *stderr,
+            "stderr for {:?}",
+            args
+        );
+        assert_eq!(
+            output.status.code(),
+            Some(*code),
+            "exit code for {:?}",
+            args
+        );
+        assert!(output.stdout.is_empty(), "stdout for {:?}", args);
+    }
+    assert!(dir.join("new.txt").exists());
+    assert!(!dir.join("not-created.txt").exists());
+
+    // The environment variable selects the same mode, so there is no banner either.
+    let output = Command::new(&bin_path)
+        .current_dir(&dir)
+        .env("MDTOUCH_COMPAT", "gnu")
+        .output()
+        .expect("Failed to execute binary");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!("touch: missing file operand\n{}", try_help)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_gnu_defaults_when_named_touch() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("named_touch");
+    let run_as = |name: &str| {
+        let copy = dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX));
+        fs::copy(&bin_path, &copy).unwrap();
+        Command::new(&copy)
+            .current_dir(&dir)
+            .env_remove("MDTOUCH_COMPAT")
+            .output()
+            .expect("Failed to execute binary")
+    };
+
+    // With no arguments GNU touch has no banner, only a usage error.
+    let output = run_as("Touch");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "touch: missing file operand\nTry 'touch --help' for more information.\n"
+    );
+
+    // Under any other name mdtouch is itself.
+    let output = run_as("mdtouch-copy");
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("mdtouch"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Marks a file read-only or hidden. Outside Windows a hidden file is one whose name
+/// starts with `.`, so only read-only needs setting.
+#[cfg(windows)]
+fn set_attributes(path: &std::path::Path, readonly: bool, hidden: bool) {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        SetFileAttributesW, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_NORMAL, FILE_ATTRIBUTE_READONLY,
+    };
+
+    let mut attributes = 0;
+    if readonly {
+        attributes |= FILE_ATTRIBUTE_READONLY;
+    }
+    if hidden {
+        attributes |= FILE_ATTRIBUTE_HIDDEN;
+    }
+    if attributes == 0 {
+        attributes = FILE_ATTRIBUTE_NORMAL;
+    }
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    // SAFETY: the path is NUL-terminated.
+    assert_ne!(unsafe { SetFileAttributesW(wide.as_ptr(), attributes) }, 0);
+}
+
+#[cfg(not(windows))]
+fn set_attributes(path: &std::path::Path, readonly: bool, _hidden: bool) {
+    let mut permissions = fs::metadata(path).unwrap().permissions();
+    permissions.set_readonly(readonly);
+    fs::set_permissions(path, permissions).unwrap();
+}
+
+#[test]
+fn test_recursive_attr_filter() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("attr_filter");
+    let plain = dir.join("plain.txt");
+    let readonly = dir.join("sub").join("readonly.txt");
+    let hidden = dir.join("sub").join(".hidden");
+    fs::create_dir(dir.join("sub")).unwrap();
+    for file in [&plain, &readonly, &hidden] {
+        fs::write(file, b"").unwrap();
+    }
+    set_attributes(&readonly, true, false);
+    set_attributes(&hidden, false, true);
+
+    let output = Command::new(&bin_path)
+        .args(["-R", "-v", "--attr-filter", "-R", "--attr-filter=-H"])
+        .arg(&dir)
+        .output()
+        .expect("Failed to execute binary");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for path in [&dir, &dir.join("sub"), &plain] {
+        assert!(
+            stdout.contains(&format!("touched {}\n", path.display())),
+            "{}",
+            stdout
+        );
+    }
+    for path in [&readonly, &hidden] {
+        assert!(
+            stdout.contains(&format!(
+                "skipped {} (attributes do not match --attr-filter)",
+                path.display()
+            )),
+            "{}",
+            stdout
+        );
+    }
+
+    set_attributes(&readonly, false, false);
+    set_attributes(&hidden, false, false);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_print0_errors_round_trips_through_stdin() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("print0_errors");
+    let missing = dir.join("missing dir");
+    let first = missing.join("first.txt");
+    let second = missing.join("second.txt");
+    let good = dir.join("good.txt");
+
+    let output = Command::new(&bin_path)
+        .arg("--print0-errors")
+        .arg(&first)
+        .arg(&good)
+        .arg(&second)
+        .output()
+        .expect("Failed to execute binary");
+    assert!(!output.status.success());
+    // Standard error holds nothing but the list.
+    let expected = format!("{}\0{}\0", first.display(), second.display());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), expected);
+
+    fs::create_dir(&missing).unwrap();
+    let mut retry = Command::new(&bin_path)
+        .args(["--null", "--files-from", "-"])
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute binary");
+    retry
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(expected.as_bytes())
+        .unwrap();
+    assert!(retry.wait().unwrap().success());
+    assert!(first.exists() && second.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_print0_lists_only_modified_files() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("print0");
+    let tree = dir.join("tree");
+    fs::create_dir_all(tree.join("sub dir")).unwrap();
+    for name in ["a.txt", "with space.txt", "sub dir/b.txt", "locked.txt"] {
+        fs::write(tree.join(name), b"").unwrap();
+    }
+    let locked = tree.join("locked.txt");
+    let writable = fs::metadata(&locked).unwrap().permissions();
+    let mut readonly = writable.clone();
+    readonly.set_readonly(true);
+    fs::set_permissions(&locked, readonly).unwrap();
+    let failing = dir.join("missing dir").join("file.txt");
+
+    let output = Command::new(&bin_path)
+        .args(["-v", "-R", "--print0", "--skip-readonly", "--jobs", "4"])
+        .arg(&tree)
+        .arg(&failing)
+        .output()
+        .expect("Failed to execute binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with('\0'), "{:?}", stdout);
+    let listed: std::collections::BTreeSet<PathBuf> =
+        stdout.split_terminator('\0').map(PathBuf::from).collect();
+    let expected = ["", "a.txt", "with space.txt", "sub dir", "sub dir/b.txt"]
+        .iter()
+        .map(|name| {
+            if name.is_empty() {
+                tree.clone()
+            } else {
+                tree.join(name)
+            }
+        })
+        .collect();
+    assert_eq!(listed, expected);
+    // What -v would have written to standard output went to standard error.
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("touched "), "{}", stderr);
+    assert!(stderr.contains("skipped "), "{}", stderr);
+    assert!(stderr.contains("Error touching"), "{}", stderr);
+
+    fs::set_permissions(&locked, writable).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_serve_and_send() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("serve");
+    let name = if cfg!(windows) {
+        format!(r"\\.\pipe\mdtouch-it-{}", std::process::id())
+    } else {
+        dir.join("mdtouch.sock").to_string_lossy().into_owned()
+    };
+    let mut server = Command::new(&bin_path)
+        .args(["--serve", &name])
+        .spawn()
+        .expect("Failed to start server");
+
+    // Two clients at once, each with a batch of files. They wait for the server to listen.
+    let expected = std::time::UNIX_EPOCH + Duration::from_secs(981_173_106);
+    let clients: Vec<_> = ["first", "second"]
+        .iter()
+        .map(|batch| {
+            let paths: Vec<PathBuf> = (0..20)
+                .map(|i| dir.join(format!("{} {}.stamp", batch, i)))
+                .collect();
+            let client = Command::new(&bin_path)
+                .args(["--send", &name, "-d", "2001-02-03T04:05:06Z"])
+                .args(&paths)
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("Failed to start client");
+            (paths, client)
+        })
+        .collect();
+    for (paths, client) in clients {
+        let output = client.wait_with_output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout.lines().count(), paths.len(), "{}", stdout);
+        assert!(stdout.lines().all(|line| line.starts_with("{\"ok\":true")));
+        for path in paths {
+            assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), expected);
+        }
+    }
+
+    // A bad request fails the client; quitting stops the server.
+    let mut client = Command::new(&bin_path)
+        .args(["--send", &name])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start client");
+    client
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"{\"path\":\"x\",\"mtime\":\"someday\"}\n{\"quit\":true}\n")
+        .unwrap();
+    let output = client.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("{\"ok\":false"), "{}", stdout);
+    assert!(stdout.contains("\"quit\":true"), "{}", stdout);
+
+    let started = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            break status;
+        }
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "Server did not stop"
+        );
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert!(status.success());
+    if !cfg!(windows) {
+        assert!(
+            !PathBuf::from(&name).exists(),
+            "The socket should be removed"
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_while_pid_exits_after_the_process() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("while_pid");
+    let heartbeat = dir.join("heartbeat.txt");
+    let mut child = if cfg!(windows) {
+        Command::new("ping")
+            .args(["-n", "2", "127.0.0.1"])
+            .stdout(Stdio::null())
+            .spawn()
+    } else {
+        Command::new("sleep").arg("1").spawn()
+    }
+    .expect("Failed to start the process to watch");
+    let pid = child.id().to_string();
+    let mut watchdog = Command::new(&bin_path)
+        .args(["--while-pid", &pid, "--every", "1"])
+        .arg(&heartbeat)
+        .spawn()
+        .expect("Failed to start watchdog");
+
+    // The watchdog keeps going while the process runs, and stops soon after it ends.
+    child.wait().unwrap();
+    let mut status = None;
+    for _ in 0..50 {
+        status = watchdog.try_wait().unwrap();
+        if status.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let Some(status) = status else {
+        let _ = watchdog.kill();
+        panic!("the watchdog kept running after the process ended");
+    };
+    assert!(status.success());
+    assert!(heartbeat.exists());
+
+    // A process that is gone already gets the file touched once.
+    fs::remove_file(&heartbeat).unwrap();
+    let status = Command::new(&bin_path)
+        .args(["--while-pid", &pid])
+        .arg(&heartbeat)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(heartbeat.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctrl_c_stops_between_operands() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("ctrl_c");
+    let (first, second) = (dir.join("first.txt"), dir.join("second.txt"));
+    // Operands read from a pipe come as slowly as they are written, so the run is still
+    // going when it is interrupted.
+    let mut child = Command::new(&bin_path)
+        .args(["--files-from", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mdtouch");
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "{}", first.display()).unwrap();
+    stdin.flush().unwrap();
+    for _ in 0..50 {
+        if first.exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert!(first.exists());
+
+    let pid = i32::try_from(child.id()).unwrap();
+    // SAFETY: plain call with the child's process ID.
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGINT) }, 0);
+    thread::sleep(Duration::from_millis(200));
+    writeln!(stdin, "{}", second.display()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(130));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cancelled after 1 operand"), "{}", stderr);
+    assert!(!second.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Needs someone to accept the UAC prompt, so it only runs when asked for with
+/// `cargo test -- --ignored` from a prompt that is not elevated.
+#[cfg(windows)]
+#[test]
+#[ignore]
+fn test_elevate_relaunches_and_passes_back_the_status() {
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("elevate");
+    let file = dir.join("elevated.txt");
+    let status = Command::new(&bin_path)
+        .arg("--elevate")
+        .arg(&file)
+        .current_dir(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(file.exists());
+
+    // A failure in the elevated run is this run's failure too.
+    let status = Command::new(&bin_path)
+        .arg("--elevate")
+        .arg(dir.join("missing").join("file.txt"))
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The peak resident set size of process `pid`, in kilobytes, while it is still running.
+#[cfg(target_os = "linux")]
+fn peak_resident_set_kb(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_streams_a_million_line_list() {
+    use std::io::{BufRead, BufReader};
+
+    let Some(bin_path) = binary_path() else {
+        eprintln!("Could not find mdtouch binary; skipping.");
+        return;
+    };
+    let dir = scratch_dir("million");
+    let path = dir.join("touched.txt");
+    fs::write(&path, b"").unwrap();
+    let list = dir.join("list.txt");
+    fs::write(&list, format!("{}\n", path.display()).repeat(1_000_000)).unwrap();
+
+    // Held as PathBufs, the list would take upwards of 60MB; streamed, the whole process
+    // stays within a few. It blocks while its output is unread, so it is still there to
+    // be measured until the last lines.
+    let mut child = Command::new(&bin_path)
+        .arg("-v")
+        .arg("--files-from")
+        .arg(&list)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mdtouch");
+    let mut lines = 0;
+    let mut peak = 0;
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        line.unwrap();
+        lines += 1;
+        if lines % 50_000 == 0 {
+            peak = peak_resident_set_kb(child.id()).map_or(peak, |kb| peak.max(kb));
+        }
+    }
+    assert!(child.wait().unwrap().success());
+    assert_eq!(lines, 1_000_000);
+    assert!(peak > 0);
+    assert!(peak < 32 * 1024, "peak resident set was {}kB", peak);
+    fs::remove_dir_all(&dir).unwrap();
+}